@@ -2,14 +2,15 @@
 #![feature(generators)]
 
 use futures::stream::StreamExt; // Provides friendly methods for streams
-use reqwest::{Client, Response};
+use reqwest::Client;
 use scraper::{Html, Selector}; // Used to parse Responses with CSS selectors
 use scrappy_do::{
     handle,
-    util::{get_unique_element, parse_attr},
-    wrap, Callback, Spider,
+    util::{get_unique_element, Pagination},
+    wrap, ScrapedResponse, Spider,
 };
 use slog::{info, Logger};
+use std::num::NonZeroUsize;
 
 // This is the `Item` eg what we are trying to create from the web pages
 #[derive(Debug)]
@@ -20,7 +21,7 @@ struct Quote {
 }
 
 #[handle(item = Quote)]
-fn parse_quotes(client: Client, response: Response, context: u8, logger: Logger) {
+fn parse_quotes(client: Client, response: ScrapedResponse, context: usize, logger: Logger) {
     // We grab the URL first because grabbing the body consumes the response
     let url = response.url().clone();
 
@@ -60,25 +61,15 @@ fn parse_quotes(client: Client, response: Response, context: u8, logger: Logger)
             });
         }
 
-        // Grab the link to the next page
-        let next_selector = Selector::parse(".next a").unwrap();
-        parse_attr(&mut fragment.select(&next_selector), "href").ok()
+        // Follow the link to the next page, we only want to scrape the first 2 pages of quotes
+        Pagination::default()
+            .max_pages(NonZeroUsize::new(2).unwrap())
+            .follow(wrap!(parse_quotes), &client, &fragment, &url, context)
     };
 
-    // We only want to scrape the first 2 pages of quotes
-    if context < 2 {
-        if let Some(link) = next_page {
-            info!(logger, "Found next page"; "link" => &link);
-            let callback = Callback::new(
-                wrap!(parse_quotes),
-                client
-                    .get(url.join(&link).unwrap().as_str())
-                    .build()
-                    .unwrap(),
-                context + 1,
-            );
-            yield callback;
-        }
+    if let Some(callback) = next_page {
+        info!(logger, "Found next page"; "link" => callback.target().url().as_str());
+        yield callback;
     }
 
     // We yield the results last so that the next callback can start being processed
@@ -90,12 +81,11 @@ fn parse_quotes(client: Client, response: Response, context: u8, logger: Logger)
 // Sets up multithreaded runtime
 #[tokio::main]
 async fn main() {
-    // Build the HTTP client
-    let client = reqwest::Client::builder().build().unwrap();
-
-    // Build the spider passing None as the logger because we don't feel like configuring slog. A
-    // logger compatible with the `log` crate will be created and passed to the handler's functions.
-    let spider = Spider::new(client.clone(), None);
+    // Build the spider along with its HTTP client. No logger is set because we don't feel like
+    // configuring slog, a logger compatible with the `log` crate will be created and passed to the
+    // handler's functions.
+    let spider = Spider::builder().build().unwrap();
+    let client = spider.client().clone();
 
     let items = spider
         // A web requires an initial address, handler, and context in order to be created. All
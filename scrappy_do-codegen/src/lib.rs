@@ -3,7 +3,7 @@ use quote::{quote, ToTokens};
 use syn::{
     parse::{Parse, ParseStream},
     visit_mut::VisitMut,
-    Block, Expr, ExprPath, FnArg, ItemFn, Result, Signature, Token, Type,
+    Block, DeriveInput, Expr, ExprPath, FnArg, ItemFn, Pat, Result, Signature, Token, Type,
 };
 
 macro_rules! error {
@@ -18,6 +18,7 @@ macro_rules! error {
 mod kw {
     syn::custom_keyword!(item);
     syn::custom_keyword!(context);
+    syn::custom_keyword!(json);
 }
 
 // Parses `= <value>` in `<name> = <value>` and returns value and span of name-value pair.
@@ -70,6 +71,7 @@ fn parse_value(
 
 struct HandleArgs {
     item_ty: Type,
+    json_ty: Option<Type>,
 }
 
 struct ConvertYields;
@@ -91,11 +93,15 @@ impl VisitMut for ConvertYields {
 impl Parse for HandleArgs {
     fn parse(input: ParseStream) -> Result<Self> {
         let mut item_ty = None;
+        let mut json_ty = None;
 
         while !input.is_empty() {
             if input.peek(kw::item) {
                 let i: kw::item = input.parse()?;
                 item_ty = Some(parse_value(input, &i, item_ty.is_some())?.0);
+            } else if input.peek(kw::json) {
+                let j: kw::json = input.parse()?;
+                json_ty = Some(parse_value(input, &j, json_ty.is_some())?.0);
             } else {
                 let token = input.parse::<TokenStream>()?;
                 return Err(error!(token, "unexpected argument: {}", token));
@@ -103,7 +109,7 @@ impl Parse for HandleArgs {
         }
 
         match item_ty {
-            Some(item_ty) => Ok(Self { item_ty }),
+            Some(item_ty) => Ok(Self { item_ty, json_ty }),
             None => {
                 let token = input.parse::<TokenStream>()?;
                 Err(error!(token, "missing defined item"))
@@ -112,14 +118,21 @@ impl Parse for HandleArgs {
     }
 }
 
-/// Attribute to generate a handler function.
+/// Attribute to generate a handler function, or an error handler function taking the failed
+/// request and its error instead of the response.
 ///
 /// # Required Arguments:
 /// - `item`: The struct type the handler scrapes.
 ///
+/// # Optional Arguments:
+/// - `json`: Deserialize the response body into this type and hand it to the handler instead of
+///   the response. Responses that can't be deserialized fail the handler, the error goes to the
+///   errback of the callback if it has one.
+///
 /// # Example
 /// ```ignore
 /// #[handle(item = u16)]
+/// #[handle(item = u16, json = ApiPage)]
 /// ```
 #[proc_macro_attribute]
 pub fn handle(
@@ -171,19 +184,73 @@ fn convert_fn_signature(sig: Signature, item_ty: Type, context_ty: Type) -> Sign
     }
 }
 
-fn impl_handle(args: TokenStream, ast: ItemFn) -> Result<TokenStream> {
-    let HandleArgs { item_ty } = syn::parse2(args)?;
-    let context_arg = match ast.sig.inputs.len() {
-        // this is a struct method (self + client, context, respone, and logger)
-        5 => &ast.sig.inputs[3],
-        // this is a bare function
-        _ => &ast.sig.inputs[2]
+// Replaces the response argument with a `ScrapedResponse` and deserializes it into the argument
+// at the start of the block. Responses that can't be deserialized fail the handler, with the
+// context handed back for the errback.
+fn convert_json_response(ast: &mut ItemFn, response_idx: usize, json_ty: Type) -> Result<()> {
+    let mut replace_arg = |idx: usize, name: Pat| match &mut ast.sig.inputs[idx] {
+        FnArg::Typed(pat_type) => Ok(std::mem::replace(pat_type.pat.as_mut(), name)),
+        FnArg::Receiver(arg) => Err(error!(arg, "unexpected argument")),
+    };
+    let response_pat = replace_arg(response_idx, syn::parse_quote!(__json_response))?;
+    let context_pat = replace_arg(response_idx + 1, syn::parse_quote!(__json_context))?;
+    if let FnArg::Typed(pat_type) = &mut ast.sig.inputs[response_idx] {
+        *pat_type.ty = syn::parse_quote!(scrappy_do::ScrapedResponse);
+    }
+
+    let block = &ast.block;
+    *ast.block = syn::parse_quote! {
+        {
+            let #response_pat: #json_ty = match __json_response.json::<#json_ty>().await {
+                Ok(value) => value,
+                Err(err) => {
+                    __yield_ind
+                        .send(scrappy_do::Indeterminate::Failed(err.into(), __json_context))
+                        .await
+                        .expect("live receiver");
+                    return;
+                }
+            };
+            let #context_pat = __json_context;
+            #block
+        }
+    };
+    Ok(())
+}
+
+// Whether `arg` is the self argument of a method, like `self: Box<Self>`.
+fn is_self(arg: &FnArg) -> bool {
+    match arg {
+        FnArg::Receiver(_) => true,
+        FnArg::Typed(pat_type) => {
+            matches!(pat_type.pat.as_ref(), Pat::Ident(ident) if ident.ident == "self")
+        }
+    }
+}
+
+fn impl_handle(args: TokenStream, mut ast: ItemFn) -> Result<TokenStream> {
+    let HandleArgs { item_ty, json_ty } = syn::parse2(args)?;
+    // struct methods take self first
+    let receiver = ast.sig.inputs.first().is_some_and(is_self) as usize;
+    // handlers take the client, response, context, and logger, error handlers take the client,
+    // request, error, context, and logger
+    let (response_idx, context_idx) = match ast.sig.inputs.len() - receiver {
+        5 => (receiver + 1, receiver + 3),
+        _ => (receiver + 1, receiver + 2),
     };
+    if let Some(json_ty) = json_ty {
+        if context_idx != response_idx + 1 {
+            return Err(error!(
+                ast.sig,
+                "error handlers have no response to deserialize"
+            ));
+        }
+        convert_json_response(&mut ast, response_idx, json_ty)?;
+    }
+    let context_arg = &ast.sig.inputs[context_idx];
     let context_ty = match &context_arg {
-            FnArg::Typed(pat_type) => Ok(pat_type.ty.clone()),
-            FnArg::Receiver(arg) => {
-                Err(error!(arg, "unexpected argument"))
-            }
+        FnArg::Typed(pat_type) => Ok(pat_type.ty.clone()),
+        FnArg::Receiver(arg) => Err(error!(arg, "unexpected argument")),
     }?;
 
     let mut block = ast.block;
@@ -224,3 +291,28 @@ fn impl_wrap(ast: &ExprPath) -> proc_macro::TokenStream {
     };
     gen.into()
 }
+
+/// Derives the `ScrapeItem` marker trait, the type must also implement `Debug`, `Send`, and
+/// `serde::Serialize`.
+///
+/// # Example
+/// ```ignore
+/// #[derive(Debug, Serialize, ScrapeItem)]
+/// struct Quote {
+///     text: String,
+/// }
+/// ```
+#[proc_macro_derive(ScrapeItem)]
+pub fn derive_scrape_item(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let ast: DeriveInput = match syn::parse(input) {
+        Ok(ast) => ast,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+
+    let gen = quote! {
+        impl #impl_generics scrappy_do::ScrapeItem for #name #ty_generics #where_clause {}
+    };
+    gen.into()
+}
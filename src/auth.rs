@@ -0,0 +1,396 @@
+use crate::spider::{matches_host, ClientFactory};
+use crate::util::{Form, FormField};
+use reqwest::cookie::{CookieStore, Jar};
+use reqwest::header::{HeaderValue, InvalidHeaderValue, AUTHORIZATION, COOKIE};
+use reqwest::Request;
+use scraper::Html;
+use slog::{info, warn, Logger};
+use std::fmt;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::OnceCell;
+use url::Url;
+
+/// Authenticates the requests of a crawl to the hosts matching patterns, so handlers of
+/// multi-site crawls don't carry the secrets of every site. A `*` in a pattern stands for any
+/// sequence of characters, the first matching pattern wins.
+///
+/// Credentials are only added to requests that don't set the `Authorization` header, or the
+/// session cookies, themselves.
+///
+/// ```
+/// use scrappy_do::{CredentialStore, FormLogin};
+/// use url::Url;
+///
+/// # fn example() -> Result<(), reqwest::header::InvalidHeaderValue> {
+/// let credentials = CredentialStore::new()
+///     .basic("intranet.example.com", "crawler", "hunter2")?
+///     .bearer("api.example.com", "9f86d081884c7d65")?
+///     .form_login(
+///         "*.shop.example.com",
+///         FormLogin::new(Url::parse("https://shop.example.com/login").unwrap())
+///             .id("login")
+///             .field("username", "crawler")
+///             .field("password", "hunter2")
+///             .success_marker("Sign out"),
+///     );
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CredentialStore {
+    // Checked in order, the first matching pattern wins
+    entries: Vec<(String, Credential)>,
+}
+
+impl CredentialStore {
+    /// Create a store without any credentials.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Authenticate the requests to hosts matching `pattern` with HTTP basic authentication.
+    ///
+    /// # Errors
+    /// Fails if the credentials can't be sent in a header, like when they contain control
+    /// characters.
+    pub fn basic<U: Into<String>, P: Into<String>>(
+        self,
+        pattern: &str,
+        username: U,
+        password: P,
+    ) -> Result<Self, InvalidHeaderValue> {
+        let encoded = base64::encode(format!("{}:{}", username.into(), password.into()));
+        self.authorization(pattern, format!("Basic {}", encoded))
+    }
+
+    /// Authenticate the requests to hosts matching `pattern` with a bearer token.
+    ///
+    /// # Errors
+    /// Fails if `token` can't be sent in a header, like when it contains control characters.
+    pub fn bearer<T: Into<String>>(
+        self,
+        pattern: &str,
+        token: T,
+    ) -> Result<Self, InvalidHeaderValue> {
+        self.authorization(pattern, format!("Bearer {}", token.into()))
+    }
+
+    /// Authenticate the requests to hosts matching `pattern` with the session cookies of a
+    /// [FormLogin](FormLogin), logged in before the first of them is sent.
+    pub fn form_login(self, pattern: &str, login: FormLogin) -> Self {
+        self.add(pattern, Credential::Form(login))
+    }
+
+    fn authorization(self, pattern: &str, value: String) -> Result<Self, InvalidHeaderValue> {
+        let mut value = HeaderValue::from_str(&value)?;
+        value.set_sensitive(true);
+        Ok(self.add(pattern, Credential::Header(value)))
+    }
+
+    fn add(mut self, pattern: &str, credential: Credential) -> Self {
+        self.entries
+            .push((pattern.to_ascii_lowercase(), credential));
+        self
+    }
+}
+
+#[derive(Clone)]
+enum Credential {
+    // The value of the `Authorization` header
+    Header(HeaderValue),
+    Form(FormLogin),
+}
+
+// Sensitive header values are printed as `Sensitive`, which keeps the secrets out of the logs
+impl fmt::Debug for Credential {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Credential::Header(value) => f.debug_tuple("Header").field(value).finish(),
+            Credential::Form(login) => f
+                .debug_struct("Form")
+                .field("url", &login.url.as_str())
+                .finish(),
+        }
+    }
+}
+
+/// Logs in to a site by submitting its login form, the session cookies it sets then
+/// authenticate the requests given to the [CredentialStore](CredentialStore).
+///
+/// The login page is fetched and its form submitted with a client configured like the clients of
+/// the crawl, proxies, user agent and local address included, but keeping the cookies set along
+/// the way, redirects included, to itself. A failed login is logged once and the
+/// requests go out without the session.
+#[derive(Clone)]
+pub struct FormLogin {
+    url: Url,
+    id: Option<String>,
+    name: Option<String>,
+    fields: Vec<FormField>,
+    success_marker: Option<String>,
+}
+
+impl FormLogin {
+    /// Log in through the form of the page at `url`.
+    pub fn new(url: Url) -> Self {
+        Self {
+            url,
+            id: None,
+            name: None,
+            fields: Vec::new(),
+            success_marker: None,
+        }
+    }
+
+    /// The ID of the login form, when the page has several forms.
+    pub fn id<S: Into<String>>(mut self, id: S) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// The name of the login form, when the page has several forms.
+    pub fn name<S: Into<String>>(mut self, name: S) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Submit `value` as the form field `name`, like the username or the password. The other
+    /// fields of the form, like CSRF tokens, are submitted with the values of the page.
+    pub fn field<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.fields.push(FormField::new(name, value));
+        self
+    }
+
+    /// Only consider the login successful when the page it lands on contains `marker`, like a
+    /// sign out link. Otherwise any successful status is.
+    pub fn success_marker<S: Into<String>>(mut self, marker: S) -> Self {
+        self.success_marker = Some(marker.into());
+        self
+    }
+
+    async fn log_in(&self, client_builder: &ClientFactory) -> Result<Arc<Jar>, LoginError> {
+        let jar = Arc::new(Jar::default());
+        // The crawl decodes its responses itself, the pages of the login are read here
+        let client = client_builder()
+            .cookie_provider(jar.clone())
+            .gzip(true)
+            .brotli(true)
+            .deflate(true)
+            .build()?;
+        let page = client
+            .get(self.url.clone())
+            .send()
+            .await?
+            .error_for_status()?;
+        let page_url = page.url().clone();
+        let form = self.form(&page.text().await?)?;
+        let request = form.generate_request(&client, page_url)?;
+        let landing = client.execute(request).await?.error_for_status()?;
+        if let Some(marker) = &self.success_marker {
+            if !landing.text().await?.contains(marker.as_str()) {
+                return Err(LoginError::Rejected);
+            }
+        }
+        Ok(jar)
+    }
+
+    // The document isn't `Send`, it is dropped before the form is submitted
+    fn form(&self, page: &str) -> Result<Form, LoginError> {
+        let mut builder = Form::builder().body(Html::parse_document(page));
+        if let Some(id) = &self.id {
+            builder = builder.id(id.clone());
+        }
+        if let Some(name) = &self.name {
+            builder = builder.name(name.clone());
+        }
+        builder
+            .fields(&mut self.fields.clone())
+            .build()
+            .ok_or(LoginError::MissingForm)
+    }
+}
+
+impl fmt::Debug for FormLogin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FormLogin")
+            .field("url", &self.url.as_str())
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+#[derive(Error, Debug)]
+enum LoginError {
+    #[error("the login request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("the login form wasn't found on the page")]
+    MissingForm,
+    #[error("the page reached after logging in doesn't contain the success marker")]
+    Rejected,
+}
+
+// The session of a form login, `None` once the login failed
+type Session = OnceCell<Option<Arc<Jar>>>;
+
+/// The credentials of a running crawl.
+pub(crate) struct Credentials {
+    // The sessions of form logins, logged in by the first request needing them
+    entries: Vec<(String, Credential, Session)>,
+    // Configures the clients form logins log in with
+    client_builder: ClientFactory,
+}
+
+impl Credentials {
+    pub(crate) fn new(store: CredentialStore, client_builder: ClientFactory) -> Self {
+        Self {
+            client_builder,
+            entries: store
+                .entries
+                .into_iter()
+                .map(|(pattern, credential)| (pattern, credential, Session::new()))
+                .collect(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Authenticate `request` with the credentials of its host, logging in first if needed.
+    /// `stored` holds the cookies the crawl's clients keep, which the client won't add once the
+    /// request has a `Cookie` header.
+    pub(crate) async fn apply(&self, request: &mut Request, stored: Option<&Jar>, logger: &Logger) {
+        let host = request.url().host_str().unwrap_or_default();
+        let (credential, session) = match self
+            .entries
+            .iter()
+            .find(|(pattern, _, _)| matches_host(pattern, host))
+        {
+            Some((_, credential, session)) => (credential, session),
+            None => return,
+        };
+        match credential {
+            Credential::Header(value) => {
+                if !request.headers().contains_key(AUTHORIZATION) {
+                    request.headers_mut().insert(AUTHORIZATION, value.clone());
+                }
+            }
+            Credential::Form(login) => {
+                let session = session
+                    .get_or_init(|| async {
+                        match login.log_in(&self.client_builder).await {
+                            Ok(jar) => {
+                                info!(logger, "Logged in"; "url" => %login.url);
+                                Some(jar)
+                            }
+                            Err(err) => {
+                                warn!(logger, "Failed to log in"; "url" => %login.url, "error" => %err);
+                                None
+                            }
+                        }
+                    })
+                    .await;
+                if let Some(session) = session {
+                    attach_session(request, session, stored);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("entries", &self.entries)
+            .finish()
+    }
+}
+
+/// Add the session cookies to the `Cookie` header of `request`, keeping the cookies it already
+/// has, or else the stored ones.
+fn attach_session(request: &mut Request, session: &Jar, stored: Option<&Jar>) {
+    let session = match session.cookies(request.url()) {
+        Some(session) => session,
+        None => return,
+    };
+    let existing = match request.headers().get(COOKIE) {
+        Some(cookies) => Some(cookies.clone()),
+        None => stored.and_then(|jar| jar.cookies(request.url())),
+    };
+    let existing = existing
+        .as_ref()
+        .and_then(|cookies| cookies.to_str().ok())
+        .unwrap_or_default();
+    let mut cookies: Vec<&str> = existing
+        .split(';')
+        .map(str::trim)
+        .filter(|cookie| !cookie.is_empty())
+        .collect();
+    let name = |cookie: &str| cookie.split('=').next().unwrap_or_default().to_string();
+    let names: Vec<String> = cookies.iter().map(|cookie| name(cookie)).collect();
+    let session = session.to_str().unwrap_or_default();
+    cookies.extend(
+        session
+            .split(';')
+            .map(str::trim)
+            .filter(|cookie| !cookie.is_empty() && !names.contains(&name(cookie))),
+    );
+    if let Ok(header) = HeaderValue::from_str(&cookies.join("; ")) {
+        request.headers_mut().insert(COOKIE, header);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Client;
+    use std::collections::HashMap;
+    use url::form_urlencoded;
+
+    #[test]
+    fn submits_the_inputs_of_the_page_by_name() {
+        let page = r#"<form id="login" action="/session" method="post">
+            <input type="hidden" name="csrf_token" value="f3a9">
+            <input id="login-email" name="email" value="">
+            <input id="remember" value="on">
+            <input name="password" type="password">
+        </form>"#;
+        let login = FormLogin::new(Url::parse("https://example.com/login").unwrap())
+            .id("login")
+            .field("email", "crawler@example.com")
+            .field("password", "hunter2");
+        let request = login
+            .form(page)
+            .unwrap()
+            .generate_request(&Client::new(), login.url.clone())
+            .unwrap();
+        let body = request.body().and_then(|body| body.as_bytes()).unwrap();
+        let submitted: HashMap<String, String> =
+            form_urlencoded::parse(body).into_owned().collect();
+        let expected = [
+            ("csrf_token", "f3a9"),
+            ("email", "crawler@example.com"),
+            ("password", "hunter2"),
+        ];
+        assert_eq!(
+            submitted,
+            expected
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect()
+        );
+        assert_eq!(request.url().as_str(), "https://example.com/session");
+    }
+
+    #[test]
+    fn rejects_invalid_credentials() {
+        assert!(CredentialStore::new()
+            .bearer("api.example.com", "9f86\nd081")
+            .is_err());
+        assert!(CredentialStore::new()
+            .basic("intranet.example.com", "crawler", "hunter2")
+            .is_ok());
+    }
+}
@@ -0,0 +1,129 @@
+use crate::intern;
+use crate::throttle::Gate;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Adapts the delay between the requests to each domain to its response latency, to crawl as
+/// fast as a site comfortably serves without tuning the delays by hand.
+///
+/// The delay of a domain aims at `target_concurrency` requests in flight to it on average: it is
+/// the latency of its responses divided by the target concurrency. Every response moves the
+/// delay halfway to that target, within the minimum and maximum delays. A slow response raises
+/// the delay right away, and the responses that aren't successful, like the requests that fail,
+/// never lower it.
+///
+/// ```
+/// use scrappy_do::AutoThrottle;
+/// use std::time::Duration;
+///
+/// // About 2 requests in flight per domain, spaced out by 100 milliseconds to a minute
+/// let throttle = AutoThrottle::new()
+///     .start_delay(Duration::from_secs(1))
+///     .delay_range(Duration::from_millis(100), Duration::from_secs(60))
+///     .target_concurrency(2.0);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AutoThrottle {
+    start_delay: Duration,
+    min_delay: Duration,
+    max_delay: Duration,
+    target_concurrency: f64,
+}
+
+impl Default for AutoThrottle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AutoThrottle {
+    /// Start with a 5 second delay, kept between no delay and a minute, for a single request in
+    /// flight per domain.
+    pub fn new() -> Self {
+        Self {
+            start_delay: Duration::from_secs(5),
+            min_delay: Duration::ZERO,
+            max_delay: Duration::from_secs(60),
+            target_concurrency: 1.0,
+        }
+    }
+
+    /// Space out the first requests to a domain by `start_delay`, until its latency is known.
+    pub fn start_delay(mut self, start_delay: Duration) -> Self {
+        self.start_delay = start_delay;
+        self
+    }
+
+    /// Keep the delays between `min_delay` and `max_delay`.
+    pub fn delay_range(mut self, min_delay: Duration, max_delay: Duration) -> Self {
+        self.min_delay = min_delay;
+        self.max_delay = max_delay.max(min_delay);
+        self
+    }
+
+    /// Aim at `target_concurrency` requests in flight to each domain on average. Values below 1
+    /// wait longer than the latency between requests.
+    ///
+    /// # Panics
+    /// Panics if `target_concurrency` isn't positive.
+    pub fn target_concurrency(mut self, target_concurrency: f64) -> Self {
+        assert!(
+            target_concurrency > 0.0,
+            "the target concurrency must be positive"
+        );
+        self.target_concurrency = target_concurrency;
+        self
+    }
+
+    fn clamp(&self, delay: Duration) -> Duration {
+        delay.clamp(self.min_delay, self.max_delay)
+    }
+}
+
+/// The delays of the domains of a running crawl.
+#[derive(Debug)]
+pub(crate) struct AutoThrottles {
+    throttle: AutoThrottle,
+    domains: Mutex<HashMap<Arc<str>, Arc<Gate>>>,
+}
+
+impl AutoThrottles {
+    pub(crate) fn new(throttle: AutoThrottle) -> Self {
+        Self {
+            throttle,
+            domains: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The gate spacing out the requests to `domain`.
+    pub(crate) fn gate(&self, domain: &str) -> Arc<Gate> {
+        let mut domains = self.domains.lock().expect("auto throttle lock");
+        if let Some(gate) = domains.get(domain) {
+            return gate.clone();
+        }
+        let gate = Arc::new(Gate::permanent(
+            self.throttle.clamp(self.throttle.start_delay),
+        ));
+        domains.insert(intern::host(domain), gate.clone());
+        gate
+    }
+
+    /// Adapt the delay of `domain` to a response received after `latency`, or a request failing
+    /// after it. Only successful responses lower the delay.
+    ///
+    /// # Returns
+    /// The new delay of the domain.
+    pub(crate) fn observe(&self, domain: &str, latency: Duration, success: bool) -> Duration {
+        let gate = self.gate(domain);
+        let current = gate.delay();
+        let target = latency.div_f64(self.throttle.target_concurrency);
+        let mut delay = ((current + target) / 2).max(target);
+        if !success {
+            delay = delay.max(current);
+        }
+        let delay = self.throttle.clamp(delay);
+        gate.set_delay(delay);
+        delay
+    }
+}
@@ -0,0 +1,267 @@
+use crate::download::DownloadError;
+use crate::intern;
+use reqwest::StatusCode;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Backs off from the domains that look temporarily unavailable: requests that time out or fail
+/// to connect, and responses with a server error status like `503 Service Unavailable`.
+///
+/// After a number of consecutive failures the domain is paused for a cool-down period: its
+/// queued callbacks wait without being dropped, and without spending their retries against the
+/// outage, while the other domains keep being crawled. The cool-down doubles every time the
+/// domain fails again after a pause, up to a maximum, and is reset by the first successful
+/// response. The callback that failed is retried, without its handler seeing the error page.
+///
+/// ```
+/// use reqwest::StatusCode;
+/// use scrappy_do::Backoff;
+/// use std::num::NonZeroUsize;
+/// use std::time::Duration;
+///
+/// let backoff = Backoff::new()
+///     .failures(NonZeroUsize::new(5).unwrap())
+///     .cool_down(Duration::from_secs(10), Duration::from_secs(600))
+///     .status(StatusCode::INTERNAL_SERVER_ERROR)
+///     .max_retries(5);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    failures: NonZeroUsize,
+    cool_down: Duration,
+    max_cool_down: Duration,
+    statuses: Vec<StatusCode>,
+    max_retries: usize,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Backoff {
+    /// Pause a domain after 3 consecutive failures, for 30 seconds at first and up to 10
+    /// minutes. Timeouts, connection failures and the `502`, `503` and `504` statuses are
+    /// failures, and a failed callback is retried up to 3 times.
+    pub fn new() -> Self {
+        Self {
+            failures: NonZeroUsize::new(3).expect("non zero"),
+            cool_down: Duration::from_secs(30),
+            max_cool_down: Duration::from_secs(600),
+            statuses: vec![
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            max_retries: 3,
+        }
+    }
+
+    /// Pause a domain after `failures` consecutive failures.
+    pub fn failures(mut self, failures: NonZeroUsize) -> Self {
+        self.failures = failures;
+        self
+    }
+
+    /// Pause a failing domain for `cool_down`, doubled every time the domain fails again after a
+    /// pause, up to `max_cool_down`.
+    pub fn cool_down(mut self, cool_down: Duration, max_cool_down: Duration) -> Self {
+        self.cool_down = cool_down;
+        self.max_cool_down = max_cool_down;
+        self
+    }
+
+    /// Treat responses with `status` as failures as well.
+    pub fn status(mut self, status: StatusCode) -> Self {
+        self.statuses.push(status);
+        self
+    }
+
+    /// Give up on a callback that failed more than `max_retries` times, counting it as a failed
+    /// request.
+    pub fn max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+}
+
+/// The failing domains of a running crawl.
+#[derive(Debug)]
+pub(crate) struct Backoffs {
+    backoff: Backoff,
+    domains: Mutex<HashMap<Arc<str>, Failing>>,
+}
+
+#[derive(Debug, Default)]
+struct Failing {
+    // Since the last successful response, or the last pause
+    consecutive: usize,
+    // The number of pauses since the last successful response
+    pauses: u32,
+    paused_until: Option<Instant>,
+}
+
+impl Backoffs {
+    pub(crate) fn new(backoff: Backoff) -> Self {
+        Self {
+            backoff,
+            domains: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn max_retries(&self) -> usize {
+        self.backoff.max_retries
+    }
+
+    /// Describes why the request failed, if the error is a failure of the domain.
+    pub(crate) fn check_error(&self, error: &DownloadError) -> Option<String> {
+        match error {
+            DownloadError::Request(err) if err.is_timeout() || err.is_connect() => {
+                Some(err.to_string())
+            }
+            DownloadError::Outlier(_) => Some(error.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Describes why the response is a failure of the domain, judging by its status.
+    pub(crate) fn check_status(&self, status: StatusCode) -> Option<String> {
+        self.backoff
+            .statuses
+            .contains(&status)
+            .then(|| format!("the response status is {}", status))
+    }
+
+    /// Record a failure of `domain`.
+    ///
+    /// # Returns
+    /// How long the domain is paused for, if the failure paused it.
+    pub(crate) fn failed(&self, domain: &str) -> Option<Duration> {
+        let mut domains = self.domains.lock().expect("backoffs lock");
+        let failing = domains.entry(intern::host(domain)).or_default();
+        let now = Instant::now();
+        // Requests sent before the pause don't count against the domain again
+        if failing.paused_until.is_some_and(|until| until > now) {
+            return None;
+        }
+        failing.consecutive += 1;
+        if failing.consecutive < self.backoff.failures.get() {
+            return None;
+        }
+        let cool_down = self
+            .backoff
+            .cool_down
+            .saturating_mul(2u32.saturating_pow(failing.pauses))
+            .min(self.backoff.max_cool_down);
+        failing.consecutive = 0;
+        failing.pauses = failing.pauses.saturating_add(1);
+        failing.paused_until = Some(now + cool_down);
+        Some(cool_down)
+    }
+
+    /// Record a successful response of `domain`.
+    pub(crate) fn succeeded(&self, domain: &str) {
+        self.domains.lock().expect("backoffs lock").remove(domain);
+    }
+
+    /// The time until which `domain` is paused, if it is.
+    pub(crate) fn paused_until(&self, domain: &str) -> Option<Instant> {
+        let domains = self.domains.lock().expect("backoffs lock");
+        domains
+            .get(domain)
+            .and_then(|failing| failing.paused_until)
+            .filter(|until| *until > Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::download::connect_error;
+
+    fn backoffs() -> Backoffs {
+        Backoffs::new(
+            Backoff::new()
+                .failures(NonZeroUsize::new(2).unwrap())
+                .cool_down(Duration::from_secs(10), Duration::from_secs(25)),
+        )
+    }
+
+    // Let the pause of `domain` run out
+    fn expire(backoffs: &Backoffs, domain: &str) {
+        let mut domains = backoffs.domains.lock().unwrap();
+        domains.get_mut(domain).unwrap().paused_until = Some(Instant::now());
+    }
+
+    #[test]
+    fn pauses_after_consecutive_failures() {
+        let backoffs = backoffs();
+        assert_eq!(backoffs.failed("example.com"), None);
+        assert!(backoffs.paused_until("example.com").is_none());
+        assert_eq!(
+            backoffs.failed("example.com"),
+            Some(Duration::from_secs(10))
+        );
+        assert!(backoffs.paused_until("example.com").is_some());
+        assert!(backoffs.paused_until("other.com").is_none());
+        // The failures of the requests sent before the pause don't count
+        assert_eq!(backoffs.failed("example.com"), None);
+        assert_eq!(backoffs.failed("example.com"), None);
+    }
+
+    #[test]
+    fn doubles_the_cool_down_up_to_the_maximum() {
+        let backoffs = backoffs();
+        let mut cool_downs = Vec::new();
+        for _ in 0..3 {
+            backoffs.failed("example.com");
+            cool_downs.extend(backoffs.failed("example.com"));
+            expire(&backoffs, "example.com");
+        }
+        assert_eq!(cool_downs, [10, 20, 25].map(Duration::from_secs).to_vec());
+    }
+
+    #[test]
+    fn resets_on_success() {
+        let backoffs = backoffs();
+        backoffs.failed("example.com");
+        backoffs.succeeded("example.com");
+        assert_eq!(backoffs.failed("example.com"), None);
+        assert_eq!(
+            backoffs.failed("example.com"),
+            Some(Duration::from_secs(10))
+        );
+        backoffs.succeeded("example.com");
+        assert!(backoffs.paused_until("example.com").is_none());
+        backoffs.failed("example.com");
+        assert_eq!(
+            backoffs.failed("example.com"),
+            Some(Duration::from_secs(10))
+        );
+    }
+
+    #[test]
+    fn recognizes_failures() {
+        let backoffs = Backoffs::new(Backoff::new().status(StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(backoffs
+            .check_status(StatusCode::SERVICE_UNAVAILABLE)
+            .is_some());
+        assert!(backoffs
+            .check_status(StatusCode::INTERNAL_SERVER_ERROR)
+            .is_some());
+        assert!(backoffs.check_status(StatusCode::NOT_FOUND).is_none());
+        let outlier = DownloadError::Outlier(Duration::from_secs(5));
+        assert!(backoffs.check_error(&outlier).is_some());
+        let banned = DownloadError::Banned("captcha".to_string());
+        assert!(backoffs.check_error(&banned).is_none());
+    }
+
+    #[tokio::test]
+    async fn recognizes_connection_failures() {
+        let err = connect_error().await;
+        assert!(backoffs().check_error(&err).is_some());
+    }
+}
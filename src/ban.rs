@@ -0,0 +1,182 @@
+use crate::intern;
+use regex::Regex;
+use reqwest::{Response, StatusCode};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// Recognizes the responses of sites blocking the crawler, like `403` pages, captcha
+/// interstitials, or redirects to a challenge page.
+///
+/// Once a response of a domain looks like a ban, the domain is paused for a cool-down period:
+/// its callbacks wait in the queue while the other domains keep being crawled. The callback
+/// receiving the ban is retried after the cool-down, without its handler seeing the ban page.
+/// Bans are counted in [CrawlStats::bans](crate::CrawlStats::bans).
+///
+/// ```
+/// use regex::Regex;
+/// use reqwest::StatusCode;
+/// use scrappy_do::BanDetector;
+/// use std::time::Duration;
+///
+/// let detector = BanDetector::new()
+///     .status(StatusCode::FORBIDDEN)
+///     .status(StatusCode::TOO_MANY_REQUESTS)
+///     .body_marker("Access Denied")
+///     .redirect_to(Regex::new("/(captcha|challenge)").unwrap())
+///     .cool_down(Duration::from_secs(300))
+///     .rotate_identity(true);
+/// ```
+#[derive(Debug, Clone)]
+pub struct BanDetector {
+    statuses: Vec<StatusCode>,
+    body_markers: Vec<String>,
+    redirects: Vec<Regex>,
+    cool_down: Duration,
+    rotate_identity: bool,
+    max_retries: usize,
+}
+
+impl Default for BanDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BanDetector {
+    /// Create a detector that doesn't recognize anything yet, with a 60 second cool-down and up
+    /// to 3 retries of a banned callback.
+    pub fn new() -> Self {
+        Self {
+            statuses: Vec::new(),
+            body_markers: Vec::new(),
+            redirects: Vec::new(),
+            cool_down: Duration::from_secs(60),
+            rotate_identity: false,
+            max_retries: 3,
+        }
+    }
+
+    /// Treat responses with `status` as bans.
+    pub fn status(mut self, status: StatusCode) -> Self {
+        self.statuses.push(status);
+        self
+    }
+
+    /// Treat text responses whose body contains `marker` as bans. Text responses are buffered in
+    /// memory before reaching the handler.
+    pub fn body_marker<S: Into<String>>(mut self, marker: S) -> Self {
+        self.body_markers.push(marker.into());
+        self
+    }
+
+    /// Treat requests redirected to a URL matching `pattern` as bans.
+    pub fn redirect_to(mut self, pattern: Regex) -> Self {
+        self.redirects.push(pattern);
+        self
+    }
+
+    /// Pause a banned domain for `cool_down`.
+    pub fn cool_down(mut self, cool_down: Duration) -> Self {
+        self.cool_down = cool_down;
+        self
+    }
+
+    /// Send the requests to a banned domain through the next client of the
+    /// [web](crate::WebBuilder::clients), when it has several.
+    pub fn rotate_identity(mut self, rotate_identity: bool) -> Self {
+        self.rotate_identity = rotate_identity;
+        self
+    }
+
+    /// Give up on a callback banned more than `max_retries` times, counting it as a failed
+    /// request.
+    pub fn max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+}
+
+/// The bans of a running crawl.
+#[derive(Debug)]
+pub(crate) struct Bans {
+    detector: BanDetector,
+    // The time until which each banned domain is paused
+    paused: Mutex<HashMap<Arc<str>, Instant>>,
+}
+
+impl Bans {
+    pub(crate) fn new(detector: BanDetector) -> Self {
+        Self {
+            detector,
+            paused: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn rotate_identity(&self) -> bool {
+        self.detector.rotate_identity
+    }
+
+    pub(crate) fn max_retries(&self) -> usize {
+        self.detector.max_retries
+    }
+
+    /// Whether the bodies of text responses have to be checked.
+    pub(crate) fn checks_body(&self) -> bool {
+        !self.detector.body_markers.is_empty()
+    }
+
+    /// Describes why the response to a request for `requested` is a ban, judging by its status
+    /// and URL.
+    pub(crate) fn check_response(&self, requested: &Url, response: &Response) -> Option<String> {
+        if self.detector.statuses.contains(&response.status()) {
+            return Some(format!("the response status is {}", response.status()));
+        }
+        if response.url() != requested {
+            if let Some(pattern) = self
+                .detector
+                .redirects
+                .iter()
+                .find(|pattern| pattern.is_match(response.url().as_str()))
+            {
+                return Some(format!(
+                    "the request was redirected to {}, matching {}",
+                    response.url(),
+                    pattern
+                ));
+            }
+        }
+        None
+    }
+
+    /// Describes why `body` is a ban page.
+    pub(crate) fn check_body(&self, body: &str) -> Option<String> {
+        self.detector
+            .body_markers
+            .iter()
+            .find(|marker| body.contains(marker.as_str()))
+            .map(|marker| format!("the body contains {:?}", marker))
+    }
+
+    /// Pause `domain` for the cool-down period.
+    pub(crate) fn pause(&self, domain: &str) {
+        let until = Instant::now() + self.detector.cool_down;
+        let mut paused = self.paused.lock().expect("bans lock");
+        let entry = paused.entry(intern::host(domain)).or_insert(until);
+        *entry = (*entry).max(until);
+    }
+
+    /// The time until which `domain` is paused, if it is.
+    pub(crate) fn paused_until(&self, domain: &str) -> Option<Instant> {
+        let mut paused = self.paused.lock().expect("bans lock");
+        match paused.get(domain) {
+            Some(until) if *until > Instant::now() => Some(*until),
+            Some(_) => {
+                paused.remove(domain);
+                None
+            }
+            None => None,
+        }
+    }
+}
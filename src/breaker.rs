@@ -0,0 +1,198 @@
+use crate::download::DownloadError;
+use crate::intern;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// Stops requesting the hosts that can't be reached. After a number of consecutive requests to a
+/// host and port failed to resolve the name of the host or to connect, the circuit of the host
+/// opens: its queued callbacks fail right away instead of each waiting out the connection
+/// timeout, which keeps dead domains from dominating the tail latency of broad crawls. The
+/// [errback](crate::Callback::errback) of a failed callback gets a
+/// [CircuitOpen](crate::DownloadError::CircuitOpen) error.
+///
+/// The failed callbacks are counted in [CrawlStats::failed_requests](crate::CrawlStats) and, when
+/// [coverage](crate::WebBuilder::coverage) is tracked, listed as failed in the
+/// [CoverageReport](crate::CoverageReport). There is no dead-letter queue: the callbacks without
+/// an errback are only logged, give them one to keep them, for instance to request them again in
+/// a later crawl. The circuit stays open for the rest of the crawl,
+/// unless a [retry delay](CircuitBreaker::retry_after) lets a request through to check whether
+/// the host is back. The other callbacks of the host keep failing while that request is made.
+///
+/// ```
+/// use scrappy_do::CircuitBreaker;
+/// use std::num::NonZeroUsize;
+/// use std::time::Duration;
+///
+/// let breaker = CircuitBreaker::new(NonZeroUsize::new(5).unwrap())
+///     .retry_after(Duration::from_secs(600));
+/// ```
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    failures: NonZeroUsize,
+    retry_after: Option<Duration>,
+}
+
+impl CircuitBreaker {
+    /// Open the circuit of a host after `failures` consecutive connection failures.
+    pub fn new(failures: NonZeroUsize) -> Self {
+        Self {
+            failures,
+            retry_after: None,
+        }
+    }
+
+    /// Let a single request through `retry_after` the circuit opened, the others keep failing.
+    /// The circuit closes if the request connects, and opens again for another `retry_after`
+    /// otherwise. Another request is let through if the first one isn't answered within
+    /// `retry_after`.
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = Some(retry_after);
+        self
+    }
+}
+
+/// The circuits of the hosts of a running crawl.
+#[derive(Debug)]
+pub(crate) struct Breakers {
+    breaker: CircuitBreaker,
+    // By host and port
+    hosts: Mutex<HashMap<Arc<str>, Circuit>>,
+}
+
+#[derive(Debug, Default)]
+struct Circuit {
+    consecutive: usize,
+    opened: Option<Instant>,
+    // When the request checking whether the host is back was let through
+    probed: Option<Instant>,
+}
+
+impl Breakers {
+    pub(crate) fn new(breaker: CircuitBreaker) -> Self {
+        Self {
+            breaker,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record a failed request to `url`.
+    ///
+    /// # Returns
+    /// Whether the failure opened the circuit of the host.
+    pub(crate) fn failed(&self, url: &Url, error: &DownloadError) -> bool {
+        match error {
+            DownloadError::Request(err) if err.is_connect() => {}
+            _ => return false,
+        }
+        let mut hosts = self.hosts.lock().expect("breakers lock");
+        let circuit = hosts.entry(intern::host(&host(url))).or_default();
+        if circuit.probed.take().is_some() {
+            // The host is still unreachable
+            circuit.opened = Some(Instant::now());
+            return true;
+        }
+        if circuit.opened.is_some() {
+            return false;
+        }
+        circuit.consecutive += 1;
+        if circuit.consecutive < self.breaker.failures.get() {
+            return false;
+        }
+        circuit.opened = Some(Instant::now());
+        true
+    }
+
+    /// Record a response to a request to `url`.
+    pub(crate) fn succeeded(&self, url: &Url) {
+        self.hosts
+            .lock()
+            .expect("breakers lock")
+            .remove(host(url).as_str());
+    }
+
+    /// Whether the callbacks requesting `url` fail without being requested.
+    pub(crate) fn is_open(&self, url: &Url) -> bool {
+        let mut hosts = self.hosts.lock().expect("breakers lock");
+        let circuit = match hosts.get_mut(host(url).as_str()) {
+            Some(circuit) => circuit,
+            None => return false,
+        };
+        let retry_after = match (circuit.opened, self.breaker.retry_after) {
+            (None, _) => return false,
+            (Some(opened), Some(retry_after)) if opened.elapsed() >= retry_after => retry_after,
+            (Some(_), _) => return true,
+        };
+        // Half open, a single request checks whether the host is back
+        if circuit
+            .probed
+            .is_some_and(|probed| probed.elapsed() < retry_after)
+        {
+            return true;
+        }
+        circuit.probed = Some(Instant::now());
+        false
+    }
+}
+
+/// The host and port of `url`, the circuits are opened by.
+fn host(url: &Url) -> String {
+    format!(
+        "{}:{}",
+        url.host_str().unwrap_or_default(),
+        url.port_or_known_default().unwrap_or_default()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::download::connect_error;
+
+    const RETRY_AFTER: Duration = Duration::from_millis(50);
+
+    fn breakers() -> Breakers {
+        Breakers::new(CircuitBreaker::new(NonZeroUsize::new(2).unwrap()).retry_after(RETRY_AFTER))
+    }
+
+    #[tokio::test]
+    async fn lets_a_single_request_through_once_half_open() {
+        let breakers = breakers();
+        let probe = Url::parse("http://dead.example.com/probe").unwrap();
+        let queued = Url::parse("http://dead.example.com/queued").unwrap();
+        let error = connect_error().await;
+        assert!(!breakers.failed(&probe, &error));
+        assert!(breakers.failed(&probe, &error));
+        assert!(breakers.is_open(&probe));
+
+        std::thread::sleep(RETRY_AFTER);
+        assert!(!breakers.is_open(&probe));
+        assert!(breakers.is_open(&queued));
+        // A failed probe opens the circuit for another delay
+        assert!(breakers.failed(&probe, &error));
+        assert!(breakers.is_open(&queued));
+
+        std::thread::sleep(RETRY_AFTER);
+        assert!(!breakers.is_open(&probe));
+        assert!(breakers.is_open(&queued));
+        breakers.succeeded(&probe);
+        assert!(!breakers.is_open(&queued));
+        assert!(!breakers.is_open(&queued));
+    }
+
+    #[tokio::test]
+    async fn probes_again_when_unanswered() {
+        let breakers = breakers();
+        let url = Url::parse("http://dead.example.com/").unwrap();
+        let error = connect_error().await;
+        breakers.failed(&url, &error);
+        breakers.failed(&url, &error);
+        std::thread::sleep(RETRY_AFTER);
+        assert!(!breakers.is_open(&url));
+        assert!(breakers.is_open(&url));
+        std::thread::sleep(RETRY_AFTER);
+        assert!(!breakers.is_open(&url));
+    }
+}
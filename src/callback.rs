@@ -1,8 +1,34 @@
-use crate::handler::Handler;
-use reqwest::{Client, Request};
-use slog::{trace, Logger};
+use crate::capture::Captured;
+use crate::coverage::{FilterReason, UrlOutcome};
+use crate::download::DownloadError;
+use crate::encoding::{self, ContentCoding};
+use crate::handler::{ErrorHandler, Handler};
+use crate::identity::Affinity;
+use crate::near_duplicate::NearDuplicateAction;
+use crate::pipeline::Source;
+use crate::response::{CallbackInfo, FetchMetrics, ScrapedResponse};
+use crate::router::Unrouted;
+use crate::runtime;
+use crate::scope::ScopeHandle;
+use crate::spider::Shared;
+use crate::stats::RequestTags;
+use crate::throttle::{Branch, Gate, Scheduler};
+use reqwest::{
+    cookie::CookieStore,
+    header::{
+        HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, COOKIE,
+    },
+    Client, Request, Response, ResponseBuilderExt,
+};
+use slog::{debug, trace, warn, Logger};
 use std::fmt::{self, Debug, Display};
-use tokio::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::{
+    sync::mpsc::{channel, Receiver},
+    time::timeout,
+};
+use url::Url;
 
 /// Represents the current calculation state.
 ///
@@ -15,6 +41,10 @@ pub enum Indeterminate<I: Debug, C> {
     Item(I),
     /// A callback to be invoked.
     Callback(Callback<I, C>),
+    /// The handler failed, like a `json` [handler](crate::handle) given a response it couldn't
+    /// deserialize. The failure and the context go to the errback of the callback, if it has
+    /// one, and otherwise count as an error of the handler.
+    Failed(DownloadError, C),
 }
 
 impl<I: Debug, C> From<Callback<I, C>> for Indeterminate<I, C> {
@@ -36,6 +66,20 @@ pub struct Callback<I, C> {
     // A Box to provide type eraser for the handler
     handler: Box<dyn Handler<I, C>>,
     context: C,
+    depth: usize,
+    retries: usize,
+    // Shared by all the callbacks produced by the same response
+    parent: Option<Arc<Url>>,
+    // The throttle of the branch of the crawl the callback belongs to
+    gate: Option<Arc<Gate>>,
+    // Sent along with the cookies stored by the client
+    cookies: Vec<(String, String)>,
+    // Whether the handler is yet to be picked by the URL router of the web
+    unrouted: bool,
+    errback: Option<Box<dyn ErrorHandler<I, C>>>,
+    // The connection-affine chain the callback belongs to
+    affinity: Option<Arc<Affinity>>,
+    priority: i32,
 }
 
 impl<I: Debug, C> Callback<I, C> {
@@ -53,26 +97,726 @@ impl<I: Debug, C> Callback<I, C> {
             handler: Box::new(handler),
             request,
             context,
+            depth: 0,
+            retries: 0,
+            parent: None,
+            gate: None,
+            cookies: Vec::new(),
+            unrouted: false,
+            errback: None,
+            affinity: None,
+            priority: 0,
         }
     }
 
+    /// Construct a `Callback` following a link, whose handler is picked by the
+    /// [UrlRouter](crate::UrlRouter) of the web once the callback is produced, see
+    /// [WebBuilder::url_router](crate::WebBuilder::url_router). The callback is dropped if no
+    /// route matches the URL of `request`.
+    pub fn follow(request: Request, context: C) -> Self
+    where
+        I: 'static,
+    {
+        Self {
+            unrouted: true,
+            ..Self::new(Unrouted, request, context)
+        }
+    }
+
+    /// Send the cookie `name` with the request, along with the cookies stored by the client. It
+    /// replaces a stored cookie of the same name, and isn't stored for later requests. Useful to
+    /// echo the per-flow tokens a site sets only on the requests continuing that flow, see
+    /// [ScrapedResponse::cookie](crate::ScrapedResponse::cookie).
+    ///
+    /// The stored cookies are only known to the crawl for clients built by the
+    /// [SpiderBuilder](crate::SpiderBuilder), with other clients the attached cookies are sent
+    /// instead of the stored ones.
+    pub fn cookie<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.cookies.push((name.into(), value.into()));
+        self
+    }
+
+    /// Hand the failure of the request to `errback` instead of only logging it, once the request
+    /// failed for good: its retries, if any, failed too. The errback gets the request, which must
+    /// be clonable, the error, and the context, and produces items and callbacks like a handler.
+    pub fn errback<E>(mut self, errback: E) -> Self
+    where
+        E: ErrorHandler<I, C> + 'static,
+    {
+        self.errback = Some(Box::new(errback));
+        self
+    }
+
+    /// Start a connection-affine chain, for session-sticky sites that expect the requests of a
+    /// flow over the same connection. The callback, the callbacks its handler produces, and
+    /// theirs, go through the same client identity for each host, and execute one at a time to
+    /// reuse its pooled connection. The sub-requests of their handlers aren't part of the chain.
+    ///
+    /// The callbacks produced within a chain join it, this only starts a new chain.
+    pub fn affine(mut self) -> Self {
+        self.affinity = Some(Arc::new(Affinity::default()));
+        self
+    }
+
+    /// Dispatch the callback ahead of the queued callbacks of a lower priority, for instance to
+    /// fetch the detail pages before following the pagination. Callbacks of a same priority are
+    /// dispatched in the order they were queued. The default priority is 0, and a negative
+    /// priority dispatches the callback after those of the default priority.
+    ///
+    /// The priorities order the callbacks in about half of the task queue, see
+    /// [task_queue_size_bytes](crate::WebBuilder::task_queue_size_bytes). While more callbacks
+    /// are queued, the others wait in the order they were queued until there is room for them.
+    ///
+    /// The callbacks produced by the handler don't inherit the priority, retries keep it.
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
     /// Returns the `Request` that will be processed by the callback execution.
     pub fn target(&self) -> &Request {
         &self.request
     }
 
-    /// Execute the callback with the provided client and logger.
+    /// Returns how many callbacks separate this callback from the start of the crawl.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the priority of the callback, see [with_priority](Callback::with_priority).
+    pub fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    /// The context handed to the handler.
+    pub(crate) fn context(&self) -> &C {
+        &self.context
+    }
+
+    /// The throttle of the branch the callback belongs to.
+    pub(crate) fn gate(&self) -> Option<Arc<Gate>> {
+        self.gate.clone()
+    }
+
+    pub(crate) fn set_gate(&mut self, gate: Option<Arc<Gate>>) {
+        self.gate = gate;
+    }
+
+    /// How many times the callback was retried.
+    pub(crate) fn retries(&self) -> usize {
+        self.retries
+    }
+
+    /// Record that the callback was produced by the handler of the callback requesting `parent`.
+    pub(crate) fn descend_from(&mut self, parent: &Arc<Url>, parent_depth: usize) {
+        self.parent = Some(parent.clone());
+        self.depth = parent_depth + 1;
+    }
+
+    /// The connection-affine chain the callback belongs to, if any.
+    pub(crate) fn affinity(&self) -> Option<Arc<Affinity>> {
+        self.affinity.clone()
+    }
+
+    /// Join the connection-affine chain of the callback that produced this one, unless the
+    /// callback starts a chain of its own.
+    pub(crate) fn join_chain(&mut self, affinity: Option<Arc<Affinity>>) {
+        if self.affinity.is_none() {
+            self.affinity = affinity;
+        }
+    }
+
+    /// Whether the callback follows a link the URL router of the web is yet to pick the handler
+    /// of.
+    pub(crate) fn unrouted(&self) -> bool {
+        self.unrouted
+    }
+
+    /// Hand the response to `handler`, picked by the URL router of the web.
+    pub(crate) fn route(&mut self, handler: Box<dyn Handler<I, C>>) {
+        self.handler = handler;
+        self.unrouted = false;
+    }
+
+    /// The name of the handler processing the response.
+    pub(crate) fn handler_name(&self) -> String {
+        self.handler.to_string()
+    }
+
+    /// Add the attached cookies to the `Cookie` header of the request. The client doesn't add
+    /// the stored cookies to requests that already have the header, so they are added as well.
+    fn attach_cookies(&mut self, shared: &Shared) {
+        let stored = match self.request.headers().get(COOKIE) {
+            Some(cookies) => Some(cookies.clone()),
+            None => shared
+                .cookie_jar
+                .as_ref()
+                .and_then(|jar| jar.cookies(self.request.url())),
+        };
+        let mut cookies: Vec<String> = stored
+            .as_ref()
+            .and_then(|cookies| cookies.to_str().ok())
+            .map(|cookies| {
+                cookies
+                    .split(';')
+                    .map(str::trim)
+                    .filter(|cookie| {
+                        let name = cookie.split('=').next().unwrap_or_default();
+                        !cookie.is_empty()
+                            && !self.cookies.iter().any(|(attached, _)| attached == name)
+                    })
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        cookies.extend(
+            self.cookies
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value)),
+        );
+        match HeaderValue::from_str(&cookies.join("; ")) {
+            Ok(header) => {
+                self.request.headers_mut().insert(COOKIE, header);
+            }
+            Err(_) => {
+                // Invalid cookies are dropped, the request goes out with the stored ones
+                if let Some(stored) = stored {
+                    self.request.headers_mut().insert(COOKIE, stored);
+                }
+            }
+        }
+    }
+
+    /// The domain targeted by the callback.
+    pub(crate) fn domain(&self) -> &str {
+        self.request.url().host_str().unwrap_or_default()
+    }
+
+    /// Execute the callback with the provided client and logger. `branch` holds the throttle
+    /// the handler applies to the callbacks it produces, `scope` lets it queue sub-requests.
+    /// `keep_source` keeps the response for the pipelines given the source of the items.
     pub(crate) async fn run(
-        self,
+        mut self,
         client: Client,
         logger: Logger,
-    ) -> Result<Receiver<Indeterminate<I, C>>, reqwest::Error> {
+        shared: &Shared,
+        branch: Branch,
+        scope: ScopeHandle,
+        keep_source: bool,
+    ) -> Result<Executed<I, C>, DownloadError> {
+        let stats = &shared.stats;
+        if !shared.headers.is_empty() {
+            shared.headers.apply(&mut self.request);
+        }
+        if !shared.credentials.is_empty() {
+            shared
+                .credentials
+                .apply(&mut self.request, shared.cookie_jar.as_deref(), &logger)
+                .await;
+        }
+        if !self.cookies.is_empty() {
+            self.attach_cookies(shared);
+        }
+        // Requests started near the deadline of the crawl give up once it is reached
+        if let Some(deadline) = shared.deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let timeout = self.request.timeout().copied().or(shared.client_timeout);
+            *self.request.timeout_mut() =
+                Some(timeout.map_or(remaining, |timeout| timeout.min(remaining)));
+        }
+        if shared.encodings.forces_identity(self.domain()) {
+            self.request
+                .headers_mut()
+                .insert(ACCEPT_ENCODING, HeaderValue::from_static("identity"));
+        }
+        let tags = RequestTags {
+            domain: self.domain().to_string(),
+            handler: self.handler_name(),
+        };
+        let info = CallbackInfo {
+            url: self.request.url().clone(),
+            method: self.request.method().clone(),
+            headers: self.request.headers().clone(),
+            depth: self.depth,
+            retries: self.retries,
+            parent: self.parent.clone(),
+        };
+        // Kept to retry the callback if the response turns out to be a ban, or a failure
+        let retry_request = match (&shared.bans, &shared.backoffs, &shared.retry_policy) {
+            (None, None, None) => None,
+            _ => self.request.try_clone(),
+        };
+        if let Some(probe) = shared
+            .probes
+            .iter()
+            .find(|probe| probe.matches(&self.request))
+        {
+            let request = probe.request(&self.request);
+            trace!(logger, "Probing resource"; "request" => ?request);
+            match shared
+                .downloader(request.url())
+                .download(client.clone(), request)
+                .await
+            {
+                Ok(resp) => {
+                    if let Some(reason) = probe.reject(&resp) {
+                        debug!(logger, "Skipping probed resource"; "url" => %info.url, "reason" => reason);
+                        stats.record_probe_rejection();
+                        shared.record_coverage(
+                            &info.url,
+                            UrlOutcome::Filtered(FilterReason::ProbeRejected),
+                        );
+                        let (_, empty) = channel(1);
+                        return Ok(Executed::Handled(empty, None, None, None));
+                    }
+                }
+                Err(err) => {
+                    debug!(logger, "The probe failed, fetching the resource anyway"; "url" => %info.url, "error" => %err)
+                }
+            }
+        }
+        // Kept to request the resource uncompressed if the response fails to decode
+        let fallback_request = self.request.try_clone();
+        // Kept to hand the request to the errback if it fails for good
+        let errback_request = self.errback.as_ref().and_then(|_| self.request.try_clone());
         trace!(logger, "Executing request"; "request" => ?self.request);
-        let resp = client.execute(self.request).await?;
+        let started = Instant::now();
+        // The callback is kept whole, for its retry or its errback
+        let request = std::mem::replace(
+            &mut self.request,
+            Request::new(info.method.clone(), info.url.clone()),
+        );
+        let download = shared
+            .downloader(request.url())
+            .download(client.clone(), request);
+        let outcome = match shared
+            .outlier_limit
+            .and_then(|limit| limit.for_domain(stats, &tags.domain))
+        {
+            Some(limit) => timeout(limit, download)
+                .await
+                .unwrap_or(Err(DownloadError::Outlier(limit))),
+            None => download.await,
+        };
+        let resp = match outcome {
+            Ok(resp) => resp,
+            Err(err) => {
+                stats.record_latency(&tags.domain, started.elapsed());
+                if let Some(throttles) = &shared.auto_throttle {
+                    let delay = throttles.observe(&tags.domain, started.elapsed(), false);
+                    trace!(logger, "Adapted the delay of the domain";
+                           "domain" => &tags.domain, "delay" => ?delay);
+                }
+                if let Some(breakers) = &shared.breakers {
+                    if breakers.failed(&info.url, &err) {
+                        warn!(logger, "The host can't be reached, failing its callbacks";
+                              "url" => %info.url, "error" => %err);
+                    }
+                }
+                if let Some(reason) = shared
+                    .backoffs
+                    .as_ref()
+                    .and_then(|backoffs| backoffs.check_error(&err))
+                {
+                    let retry = match retry_request {
+                        Some(request) => Some(Self {
+                            request,
+                            retries: self.retries + 1,
+                            ..self
+                        }),
+                        None => None,
+                    };
+                    let error = DownloadError::Unavailable(reason);
+                    return Ok(Executed::Failed { error, retry });
+                }
+                if let Some(reason) = shared
+                    .retry_policy
+                    .as_ref()
+                    .and_then(|policy| policy.check_error(&err))
+                {
+                    let retry = match retry_request {
+                        Some(request) => Some(Self {
+                            request,
+                            retries: self.retries + 1,
+                            ..self
+                        }),
+                        None => None,
+                    };
+                    let error = DownloadError::Transient(reason);
+                    return Ok(Executed::Failed { error, retry });
+                }
+                return self.fail(client, errback_request, err, shared, logger);
+            }
+        };
+        let mut metrics = FetchMetrics::new(&resp, started.elapsed()).track_connection(stats);
+        if let Some(breakers) = &shared.breakers {
+            breakers.succeeded(&info.url);
+        }
+        if let Some(throttles) = &shared.auto_throttle {
+            let success = resp.status().is_success();
+            let delay = throttles.observe(&tags.domain, metrics.duration(), success);
+            trace!(logger, "Adapted the delay of the domain";
+                   "domain" => &tags.domain, "delay" => ?delay);
+        }
+        stats.record_response(tags, metrics.duration(), resp.content_length());
+        debug!(logger, "Got response headers";
+               "url" => %info.url, "remote_addr" => ?metrics.remote_addr(),
+               "local_addr" => ?metrics.local_addr(),
+               "connection_reused" => ?metrics.connection_reused(),
+               "version" => ?metrics.version());
         trace!(logger, "Got response"; "response" => ?resp);
-        let result = self.handler.handle(client, resp, self.context, logger);
-        Ok(result)
+
+        let decoded = match (decode(resp).await, fallback_request) {
+            (Err(DownloadError::Decode(coding, err)), Some(mut request)) => {
+                warn!(logger, "The response could not be decoded, requesting it uncompressed";
+                    "url" => %info.url, "coding" => ?coding, "error" => %err);
+                stats.record_decode_fallback();
+                shared
+                    .encodings
+                    .fall_back(info.url.host_str().unwrap_or_default());
+                request
+                    .headers_mut()
+                    .insert(ACCEPT_ENCODING, HeaderValue::from_static("identity"));
+                let started = Instant::now();
+                match shared
+                    .downloader(request.url())
+                    .download(client.clone(), request)
+                    .await
+                {
+                    Ok(resp) => {
+                        metrics = FetchMetrics::new(&resp, started.elapsed())
+                            .track_connection(stats)
+                            .identity_fallback();
+                        decode(resp).await
+                    }
+                    Err(err) => Err(err),
+                }
+            }
+            (decoded, _) => decoded,
+        };
+        let mut resp = match decoded {
+            Ok(resp) => resp,
+            Err(err) => return self.fail(client, errback_request, err, shared, logger),
+        };
+
+        if let Some(bans) = &shared.bans {
+            let mut reason = bans.check_response(&info.url, &resp);
+            if reason.is_none() && bans.checks_body() && is_text(&resp) {
+                let (buffered, body) = match buffer(resp).await {
+                    Ok(buffered) => buffered,
+                    Err(err) => return self.fail(client, errback_request, err, shared, logger),
+                };
+                resp = buffered;
+                reason = bans.check_body(&String::from_utf8_lossy(&body));
+            }
+            if let Some(reason) = reason {
+                let domain = info.url.host_str().unwrap_or_default();
+                bans.pause(domain);
+                if bans.rotate_identity() {
+                    shared.identities.rotate(domain);
+                }
+                stats.record_ban();
+                let retry = match retry_request {
+                    Some(request) => Some(Self {
+                        request,
+                        retries: self.retries + 1,
+                        ..self
+                    }),
+                    None => None,
+                };
+                let error = DownloadError::Banned(reason);
+                return Ok(Executed::Failed { error, retry });
+            }
+        }
+
+        if let Some(reason) = shared
+            .backoffs
+            .as_ref()
+            .and_then(|backoffs| backoffs.check_status(resp.status()))
+        {
+            let retry = match retry_request {
+                Some(request) => Some(Self {
+                    request,
+                    retries: self.retries + 1,
+                    ..self
+                }),
+                None => None,
+            };
+            let error = DownloadError::Unavailable(reason);
+            return Ok(Executed::Failed { error, retry });
+        }
+
+        if let Some(reason) = shared
+            .retry_policy
+            .as_ref()
+            .and_then(|policy| policy.check_status(resp.status()))
+        {
+            let retry = match retry_request {
+                Some(request) => Some(Self {
+                    request,
+                    retries: self.retries + 1,
+                    ..self
+                }),
+                None => None,
+            };
+            let error = DownloadError::Transient(reason);
+            return Ok(Executed::Failed { error, retry });
+        }
+
+        let mut near_duplicate = false;
+        if let Some(near_duplicates) = &shared.near_duplicates {
+            if is_text(&resp) {
+                let (buffered, body) = match buffer(resp).await {
+                    Ok(buffered) => buffered,
+                    Err(err) => return self.fail(client, errback_request, err, shared, logger),
+                };
+                resp = buffered;
+                if near_duplicates.check(&String::from_utf8_lossy(&body)) {
+                    near_duplicate = true;
+                    if near_duplicates.action() == NearDuplicateAction::Skip {
+                        debug!(logger, "Skipping near duplicate page"; "url" => %info.url);
+                        stats.record_near_duplicate();
+                        shared.record_coverage(
+                            &info.url,
+                            UrlOutcome::Filtered(FilterReason::NearDuplicate),
+                        );
+                        // Nothing is ever sent, so the handler produces nothing
+                        let (_, empty) = channel(1);
+                        return Ok(Executed::Handled(empty, None, None, None));
+                    }
+                }
+            }
+        }
+
+        // Kept in case the handler produces nothing for the response
+        let mut captured = None;
+        if let Some(capture) = shared.capture.as_ref().filter(|capture| capture.has_room()) {
+            let (buffered, body) = match buffer(resp).await {
+                Ok(buffered) => buffered,
+                Err(err) => return self.fail(client, errback_request, err, shared, logger),
+            };
+            resp = buffered;
+            if capture.fits(body.len()) {
+                captured = Some(Captured {
+                    method: info.method.clone(),
+                    url: info.url.clone(),
+                    request_headers: info.headers.clone(),
+                    status: resp.status(),
+                    headers: resp.headers().clone(),
+                    body,
+                });
+            }
+        }
+
+        let mut source = None;
+        if keep_source {
+            let (buffered, body) = match buffer(resp).await {
+                Ok(buffered) => buffered,
+                Err(err) => return self.fail(client, errback_request, err, shared, logger),
+            };
+            resp = buffered;
+            source = Some(Arc::new(Source {
+                info: info.clone(),
+                url: resp.url().clone(),
+                status: resp.status(),
+                headers: resp.headers().clone(),
+                body,
+            }));
+        }
+
+        shared.record_coverage(&info.url, UrlOutcome::Fetched);
+        let domain = info.url.host_str().unwrap_or_default();
+        let scheduler = Scheduler::new(shared.throttles.clone(), domain, branch, Some(scope));
+        let response = ScrapedResponse::new(
+            resp,
+            info,
+            metrics,
+            near_duplicate,
+            scheduler,
+            Some(shared.stats.clone()),
+            shared.parse_pool.clone(),
+        );
+        for extension in &shared.extensions {
+            extension.response_received(&response);
+        }
+        let fallback = match (self.errback, errback_request) {
+            (Some(errback), Some(request)) => Some(Fallback {
+                client: client.clone(),
+                request,
+                errback,
+            }),
+            _ => None,
+        };
+        let handler = self.handler;
+        let context = self.context;
+        let result = runtime::enter(&shared.runtime, || {
+            handler.handle(client, response, context, logger)
+        });
+        Ok(Executed::Handled(result, captured, source, fallback))
+    }
+
+    /// Hand `error` to the errback of the callback with `request`, the original request, if the
+    /// callback has an errback and its request could be cloned.
+    fn fail(
+        mut self,
+        client: Client,
+        request: Option<Request>,
+        error: DownloadError,
+        shared: &Shared,
+        logger: Logger,
+    ) -> Result<Executed<I, C>, DownloadError> {
+        match request {
+            Some(request) => {
+                self.request = request;
+                self.recover(client, error, shared, logger)
+            }
+            None => Err(error),
+        }
+    }
+
+    /// Hand `error` to the errback of the callback, if it has one, along with the request and the
+    /// context.
+    pub(crate) fn recover(
+        self,
+        client: Client,
+        error: DownloadError,
+        shared: &Shared,
+        logger: Logger,
+    ) -> Result<Executed<I, C>, DownloadError> {
+        let errback = match &self.errback {
+            Some(errback) => errback,
+            None => return Err(error),
+        };
+        warn!(logger, "The request failed, handing it to the errback";
+              "callback" => %self, "errback" => %errback, "error" => %error);
+        let errback = self.errback.expect("errback of the callback");
+        let request = self.request;
+        let context = self.context;
+        let result = runtime::enter(&shared.runtime, || {
+            errback.handle_error(client, request, error, context, logger)
+        });
+        Ok(Executed::Recovered(result))
+    }
+}
+
+/// The errback of a handled callback, kept in case its handler [fails](Indeterminate::Failed).
+pub(crate) struct Fallback<I, C> {
+    client: Client,
+    request: Request,
+    errback: Box<dyn ErrorHandler<I, C>>,
+}
+
+impl<I: Debug, C> Fallback<I, C> {
+    /// Hand `error` to the errback along with the request and `context`.
+    pub(crate) fn recover(
+        self,
+        error: DownloadError,
+        context: C,
+        shared: &Shared,
+        logger: Logger,
+    ) -> Receiver<Indeterminate<I, C>> {
+        warn!(logger, "The handler failed, handing the request to the errback";
+              "errback" => %self.errback, "error" => %error);
+        let (client, request, errback) = (self.client, self.request, self.errback);
+        runtime::enter(&shared.runtime, || {
+            errback.handle_error(client, request, error, context, logger)
+        })
+    }
+}
+
+/// What became of an executed callback.
+pub(crate) enum Executed<I: Debug, C> {
+    /// The handler is producing the contents of the response, which is captured when
+    /// [failures are captured](crate::WebBuilder::capture_failures), and kept as the source of
+    /// the items when a pipeline [asks for it](crate::pipeline::Stage::source). The errback of
+    /// the callback is kept for the failures of the handler.
+    Handled(
+        Receiver<Indeterminate<I, C>>,
+        Option<Captured>,
+        Option<Arc<Source>>,
+        Option<Fallback<I, C>>,
+    ),
+    /// The response was a [ban](DownloadError::Banned), the domain is
+    /// [unavailable](DownloadError::Unavailable), or the request failed
+    /// [transiently](DownloadError::Transient). `retry` repeats the callback, unless its request
+    /// couldn't be cloned.
+    Failed {
+        error: DownloadError,
+        retry: Option<Callback<I, C>>,
+    },
+    /// The request failed for good, the errback of the callback is producing the contents
+    /// instead.
+    Recovered(Receiver<Indeterminate<I, C>>),
+}
+
+/// Whether the response is a page that can be compared to other pages.
+fn is_text(response: &Response) -> bool {
+    match response.headers().get(CONTENT_TYPE) {
+        Some(content_type) => content_type
+            .to_str()
+            .map(|content_type| {
+                let content_type = content_type.to_ascii_lowercase();
+                content_type.starts_with("text/") || content_type.contains("xhtml")
+            })
+            .unwrap_or(false),
+        None => true,
+    }
+}
+
+/// Read the whole body of `response`, returning an equivalent response along with the body.
+async fn buffer(response: Response) -> Result<(Response, bytes::Bytes), DownloadError> {
+    let mut builder = http::Response::builder()
+        .status(response.status())
+        .version(response.version())
+        .url(response.url().clone());
+    for (name, value) in response.headers() {
+        builder = builder.header(name, value);
+    }
+    let body = response.bytes().await?;
+    let buffered = builder.body(body.clone()).expect("valid buffered response");
+    Ok((Response::from(buffered), body))
+}
+
+/// Decode the body of `response` if it was sent with a content coding, the body is buffered in
+/// memory. Responses without a coding, or with a coding that can't be decoded, are returned as
+/// they are. Gzipped XML documents, like sitemaps, are decompressed as well when the server
+/// didn't announce their coding.
+async fn decode(response: Response) -> Result<Response, DownloadError> {
+    let coding = ContentCoding::of(response.headers());
+    let sniff = encoding::may_be_gzipped(response.url(), response.headers());
+    if coding == ContentCoding::Unknown || coding == ContentCoding::Identity && !sniff {
+        return Ok(response);
+    }
+    let mut builder = http::Response::builder()
+        .status(response.status())
+        .version(response.version())
+        .url(response.url().clone());
+    let mut headers = response.headers().clone();
+    let body = response.bytes().await?;
+    let mut decoded = match encoding::decode(&headers, &body) {
+        Some(Ok(decoded)) => decoded,
+        Some(Err(err)) => return Err(DownloadError::Decode(coding, err)),
+        None => body.to_vec(),
+    };
+    if sniff {
+        if let Some(document) = encoding::gunzip_xml(&decoded) {
+            if encoding::is_gzip_type(&headers) {
+                headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/xml"));
+            }
+            decoded = document;
+        }
+    }
+    for (name, value) in &headers {
+        // The headers describe the encoded body
+        if name != CONTENT_ENCODING && name != CONTENT_LENGTH {
+            builder = builder.header(name, value);
+        }
     }
+    let decoded = builder
+        .header(CONTENT_LENGTH, decoded.len())
+        .body(decoded)
+        .expect("valid decoded response");
+    Ok(Response::from(decoded))
 }
 
 impl<I, C> Display for Callback<I, C> {
@@ -0,0 +1,158 @@
+use bytes::Bytes;
+use reqwest::header::HeaderMap;
+use reqwest::{Method, StatusCode};
+use serde_json::json;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use url::Url;
+
+/// Saves the responses a handler produced nothing for, no item and no callback, to a directory.
+/// Those are usually pages the selectors of the handler no longer match, or pages the handler
+/// panicked on.
+///
+/// Each response is written to its own HAR archive, along with the request it answered, so the
+/// failure can be reproduced offline by crawling a [Replay](crate::Replay) of the archive. The
+/// number of archives and the size of the bodies saved are capped, responses with larger bodies
+/// are skipped.
+///
+/// ```
+/// use scrappy_do::FailureCapture;
+///
+/// let capture = FailureCapture::new("debug/empty-pages")
+///     .max_captures(50)
+///     .max_body_bytes(1_000_000);
+/// ```
+#[derive(Debug)]
+pub struct FailureCapture {
+    directory: PathBuf,
+    max_captures: u64,
+    max_body_bytes: usize,
+    captured: AtomicU64,
+}
+
+impl FailureCapture {
+    /// Save the responses to `directory`, which is created if needed. At most 100 responses of
+    /// up to 10MB are saved by default.
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Self {
+        Self {
+            directory: directory.into(),
+            max_captures: 100,
+            max_body_bytes: 10_000_000,
+            captured: AtomicU64::new(0),
+        }
+    }
+
+    /// Save at most `max_captures` responses during the crawl.
+    pub fn max_captures(mut self, max_captures: u64) -> Self {
+        self.max_captures = max_captures;
+        self
+    }
+
+    /// Skip the responses whose body is longer than `max_body_bytes`.
+    pub fn max_body_bytes(mut self, max_body_bytes: usize) -> Self {
+        self.max_body_bytes = max_body_bytes;
+        self
+    }
+
+    /// Whether more responses may be saved.
+    pub(crate) fn has_room(&self) -> bool {
+        self.captured.load(Ordering::Relaxed) < self.max_captures
+    }
+
+    /// Whether a body of `length` bytes may be saved.
+    pub(crate) fn fits(&self, length: usize) -> bool {
+        length <= self.max_body_bytes
+    }
+
+    /// Save the response the handler named `handler` produced nothing for.
+    ///
+    /// # Returns
+    /// Where the response was saved, or `None` once the maximum number of responses was saved.
+    pub(crate) async fn save(
+        &self,
+        handler: &str,
+        captured: &Captured,
+    ) -> io::Result<Option<PathBuf>> {
+        let reserved =
+            self.captured
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |captured| {
+                    (captured < self.max_captures).then_some(captured + 1)
+                });
+        let index = match reserved {
+            Ok(index) => index,
+            Err(_) => return Ok(None),
+        };
+        let path = self.directory.join(file_name(index, handler));
+        tokio::fs::create_dir_all(&self.directory).await?;
+        tokio::fs::write(&path, captured.to_har(handler)).await?;
+        Ok(Some(path))
+    }
+}
+
+/// `<index>-<handler>.har`, keeping the handler name safe to use in a path.
+fn file_name(index: u64, handler: &str) -> String {
+    let handler: String = handler
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("{:04}-{}.har", index, handler)
+}
+
+/// A response buffered in case the handler produces nothing for it.
+#[derive(Debug)]
+pub(crate) struct Captured {
+    pub(crate) method: Method,
+    pub(crate) url: Url,
+    pub(crate) request_headers: HeaderMap,
+    pub(crate) status: StatusCode,
+    pub(crate) headers: HeaderMap,
+    pub(crate) body: Bytes,
+}
+
+impl Captured {
+    /// A HAR archive holding the request and its response.
+    fn to_har(&self, handler: &str) -> Vec<u8> {
+        let headers = |headers: &HeaderMap| {
+            headers
+                .iter()
+                .map(|(name, value)| {
+                    json!({
+                        "name": name.as_str(),
+                        "value": String::from_utf8_lossy(value.as_bytes()),
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+        let mime_type = self
+            .headers
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let har = json!({
+            "log": {
+                "version": "1.2",
+                "creator": {"name": "scrappy_do", "version": env!("CARGO_PKG_VERSION")},
+                "entries": [{
+                    "comment": format!("{} produced nothing", handler),
+                    "request": {
+                        "method": self.method.as_str(),
+                        "url": self.url.as_str(),
+                        "headers": headers(&self.request_headers),
+                    },
+                    "response": {
+                        "status": self.status.as_u16(),
+                        "headers": headers(&self.headers),
+                        "content": {
+                            "size": self.body.len(),
+                            "mimeType": mime_type,
+                            "text": base64::encode(&self.body),
+                            "encoding": "base64",
+                        },
+                    },
+                }],
+            },
+        });
+        serde_json::to_vec_pretty(&har).expect("serializable HAR")
+    }
+}
@@ -0,0 +1,181 @@
+use crate::callback::Indeterminate;
+use crate::handler::Handler;
+use crate::response::ScrapedResponse;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Client;
+use slog::{trace, Logger};
+use std::fmt::{self, Debug, Display};
+use std::sync::Arc;
+use tokio::sync::mpsc::Receiver;
+
+/// Creates a handler for a response.
+type Factory<I, C> = Arc<dyn Fn() -> Box<dyn Handler<I, C>> + Send + Sync>;
+
+/// A handler handing each response to the handler registered for its content type, for crawls
+/// following links to pages, JSON APIs and documents alike.
+///
+/// The content type of a response is read from its `Content-Type` header. When the header is
+/// missing, or is the generic `application/octet-stream`, the content type is guessed from the
+/// extension of the URL. Responses matching no route go to the fallback handler.
+///
+/// ```no_run
+/// # #![feature(generators)]
+/// # use scrappy_do::{handle, wrap, ContentRouter, ScrapedResponse};
+/// # use reqwest::Client;
+/// # use slog::Logger;
+/// #[handle(item = String)]
+/// fn page(client: Client, response: ScrapedResponse, context: (), logger: Logger) {
+///     yield response.text().await.unwrap();
+/// }
+///
+/// #[handle(item = String)]
+/// fn api(client: Client, response: ScrapedResponse, context: (), logger: Logger) {
+///     yield response.text().await.unwrap();
+/// }
+///
+/// #[handle(item = String)]
+/// fn document(client: Client, response: ScrapedResponse, context: (), logger: Logger) {
+///     yield format!("{} bytes", response.bytes().await.unwrap().len());
+/// }
+///
+/// let router = ContentRouter::new(wrap!(page))
+///     .route("application/json", wrap!(api))
+///     .route("application/pdf", wrap!(document))
+///     .route("image/*", wrap!(document));
+/// ```
+///
+/// The router is a handler itself, given to [WebBuilder::handler](crate::WebBuilder::handler) or
+/// to the [callbacks](crate::Callback::new) following links of unknown types.
+pub struct ContentRouter<I, C> {
+    // The content type patterns and their handlers, in the order they were registered
+    routes: Vec<(String, Factory<I, C>)>,
+    fallback: Factory<I, C>,
+    names: Vec<String>,
+}
+
+impl<I: Debug + 'static, C: 'static> ContentRouter<I, C> {
+    /// Route the responses matching no other route to `fallback`.
+    pub fn new<H>(fallback: H) -> Self
+    where
+        H: Handler<I, C> + Clone + 'static,
+    {
+        Self {
+            routes: Vec::new(),
+            names: vec![fallback.to_string()],
+            fallback: factory(fallback),
+        }
+    }
+
+    /// Route the responses whose content type matches `content_type` to `handler`. The pattern
+    /// is a media type like `application/json`, or a type with any subtype like `text/*`, compared
+    /// without the parameters of the content type, like its charset. The first matching route
+    /// is used.
+    pub fn route<H>(mut self, content_type: &str, handler: H) -> Self
+    where
+        H: Handler<I, C> + Clone + 'static,
+    {
+        self.names.push(format!("{} => {}", content_type, handler));
+        self.routes
+            .push((content_type.to_ascii_lowercase(), factory(handler)));
+        self
+    }
+}
+
+fn factory<I: Debug + 'static, C: 'static, H>(handler: H) -> Factory<I, C>
+where
+    H: Handler<I, C> + Clone + 'static,
+{
+    Arc::new(move || Box::new(handler.clone()))
+}
+
+impl<I, C> Clone for ContentRouter<I, C> {
+    fn clone(&self) -> Self {
+        Self {
+            routes: self.routes.clone(),
+            fallback: self.fallback.clone(),
+            names: self.names.clone(),
+        }
+    }
+}
+
+impl<I, C> Debug for ContentRouter<I, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ContentRouter")
+            .field("handlers", &self.names)
+            .finish()
+    }
+}
+
+impl<I, C> Display for ContentRouter<I, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ContentRouter({})", self.names.join(", "))
+    }
+}
+
+impl<I: Debug, C> Handler<I, C> for ContentRouter<I, C> {
+    fn handle(
+        self: Box<Self>,
+        client: Client,
+        response: ScrapedResponse,
+        context: C,
+        logger: Logger,
+    ) -> Receiver<Indeterminate<I, C>> {
+        let content_type = content_type(&response);
+        let handler = self
+            .routes
+            .iter()
+            .find(|(pattern, _)| matches(pattern, &content_type))
+            .map(|(_, factory)| factory())
+            .unwrap_or_else(|| (self.fallback)());
+        trace!(logger, "Routing the response by its content type";
+               "content_type" => &content_type, "handler" => %handler);
+        handler.handle(client, response, context, logger)
+    }
+}
+
+/// The lowercase media type of `response`, without its parameters.
+fn content_type(response: &ScrapedResponse) -> String {
+    let declared = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_ascii_lowercase())
+        .filter(|value| !value.is_empty() && value != "application/octet-stream");
+    match declared {
+        Some(declared) => declared,
+        None => sniff(response.info().url().path()).to_string(),
+    }
+}
+
+/// Guess the media type of a resource from the extension of its path.
+fn sniff(path: &str) -> &'static str {
+    let extension = path
+        .rsplit('/')
+        .next()
+        .and_then(|name| name.rsplit_once('.'))
+        .map(|(_, extension)| extension.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("html") | Some("htm") => "text/html",
+        Some("json") => "application/json",
+        Some("xml") => "application/xml",
+        Some("pdf") => "application/pdf",
+        Some("csv") => "text/csv",
+        Some("txt") => "text/plain",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Whether `content_type` matches `pattern`, a media type or a type followed by `/*`.
+fn matches(pattern: &str, content_type: &str) -> bool {
+    match pattern.strip_suffix("/*") {
+        Some(kind) => content_type
+            .split('/')
+            .next()
+            .is_some_and(|content_kind| content_kind == kind),
+        None => pattern == content_type,
+    }
+}
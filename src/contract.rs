@@ -0,0 +1,253 @@
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// The expected output of a handler for a single response, like "the product pages yield exactly
+/// one item and no callback". Contracts catch selector rot: handlers that silently stop
+/// extracting anything once a site changes its markup.
+///
+/// Contracts given to [WebBuilder::contract](crate::WebBuilder::contract) are checked after
+/// every matching response of a crawl, violations are listed in the
+/// [ContractReport](ContractReport) of the [CrawlSummary](crate::CrawlSummary). A
+/// [contract run](crate::WebBuilder::contract_run) checks each contract once with as few requests
+/// as possible, against the live site or a [Replay](crate::Replay) of a recorded crawl.
+///
+/// ```
+/// use regex::Regex;
+/// use scrappy_do::Contract;
+///
+/// // The name of the handler, as given to `wrap!`
+/// let listing = Contract::new("parse_listing")
+///     .url(Regex::new("/category/").unwrap())
+///     .min_items(1)
+///     .max_callbacks(50);
+/// let product = Contract::new("parse_product").min_items(1).max_items(1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Contract {
+    handler: String,
+    pattern: Option<Regex>,
+    min_items: u64,
+    max_items: Option<u64>,
+    min_callbacks: u64,
+    max_callbacks: Option<u64>,
+}
+
+impl Contract {
+    /// Set expectations for the responses processed by the handler named `handler`, the name of
+    /// the function given to `wrap!` or the [Display](std::fmt::Display) of the handler.
+    pub fn new<S: Into<String>>(handler: S) -> Self {
+        Self {
+            handler: handler.into(),
+            pattern: None,
+            min_items: 0,
+            max_items: None,
+            min_callbacks: 0,
+            max_callbacks: None,
+        }
+    }
+
+    /// Only check the responses to the requests whose URL matches `pattern`.
+    pub fn url(mut self, pattern: Regex) -> Self {
+        self.pattern = Some(pattern);
+        self
+    }
+
+    /// Expect at least `min_items` items per response.
+    pub fn min_items(mut self, min_items: u64) -> Self {
+        self.min_items = min_items;
+        self
+    }
+
+    /// Expect at most `max_items` items per response.
+    pub fn max_items(mut self, max_items: u64) -> Self {
+        self.max_items = Some(max_items);
+        self
+    }
+
+    /// Expect at least `min_callbacks` callbacks per response.
+    pub fn min_callbacks(mut self, min_callbacks: u64) -> Self {
+        self.min_callbacks = min_callbacks;
+        self
+    }
+
+    /// Expect at most `max_callbacks` callbacks per response.
+    pub fn max_callbacks(mut self, max_callbacks: u64) -> Self {
+        self.max_callbacks = Some(max_callbacks);
+        self
+    }
+
+    fn applies(&self, handler: &str, url: &str) -> bool {
+        self.handler == handler
+            && self
+                .pattern
+                .as_ref()
+                .is_none_or(|pattern| pattern.is_match(url))
+    }
+
+    /// Describes the expectation a response producing `items` and `callbacks` breaks.
+    fn check(&self, items: u64, callbacks: u64) -> Option<String> {
+        let check = |what: &str, count: u64, min: u64, max: Option<u64>| {
+            if count < min {
+                Some(format!("at least {} {}", min, what))
+            } else {
+                match max {
+                    Some(max) if count > max => Some(format!("at most {} {}", max, what)),
+                    _ => None,
+                }
+            }
+        };
+        check("items", items, self.min_items, self.max_items).or_else(|| {
+            check(
+                "callbacks",
+                callbacks,
+                self.min_callbacks,
+                self.max_callbacks,
+            )
+        })
+    }
+}
+
+impl Display for Contract {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.pattern {
+            Some(pattern) => write!(f, "{} ({})", self.handler, pattern),
+            None => write!(f, "{}", self.handler),
+        }
+    }
+}
+
+/// A response for which a handler didn't produce what its [Contract](Contract) expects.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ContractViolation {
+    /// The contract, its handler name and URL pattern.
+    pub contract: String,
+    /// The URL of the response.
+    pub url: String,
+    /// The expectation that wasn't met, like `at most 50 callbacks`.
+    pub expected: String,
+    /// The number of items the handler produced.
+    pub items: u64,
+    /// The number of callbacks the handler produced.
+    pub callbacks: u64,
+}
+
+/// The contracts checked during a crawl, found in the [CrawlSummary](crate::CrawlSummary) when
+/// [contracts](crate::WebBuilder::contract) were given.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ContractReport {
+    /// The number of responses checked against a contract.
+    pub checked: u64,
+    /// The responses that broke their contract.
+    pub violations: Vec<ContractViolation>,
+    /// The contracts no response was checked against, in a contract run the handlers the crawl
+    /// never reached.
+    pub unchecked: Vec<String>,
+}
+
+/// The contracts of a running crawl.
+#[derive(Debug)]
+pub(crate) struct Contracts {
+    contracts: Vec<Contract>,
+    contract_run: bool,
+    // Whether a response was checked against each contract, or is about to be in a contract run
+    sampled: Vec<AtomicBool>,
+    // The handlers that executed a callback in a contract run
+    sampled_handlers: Mutex<HashSet<String>>,
+    checked: Vec<AtomicBool>,
+    responses: AtomicU64,
+    violations: Mutex<Vec<ContractViolation>>,
+}
+
+impl Contracts {
+    pub(crate) fn new(contracts: Vec<Contract>, contract_run: bool) -> Self {
+        Self {
+            sampled: contracts.iter().map(|_| AtomicBool::new(false)).collect(),
+            checked: contracts.iter().map(|_| AtomicBool::new(false)).collect(),
+            sampled_handlers: Mutex::new(HashSet::new()),
+            contracts,
+            contract_run,
+            responses: AtomicU64::new(0),
+            violations: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Whether a callback for `handler` requesting `url` is executed. In a contract run, only the
+    /// first callback of each contract, and of each handler, is.
+    pub(crate) fn follow(&self, handler: &str, url: &str) -> bool {
+        if !self.contract_run {
+            return true;
+        }
+        // The handlers without a contract lead to the handlers with one
+        let mut follow = self
+            .sampled_handlers
+            .lock()
+            .expect("contracts lock")
+            .insert(handler.to_string());
+        // Every contract applying to the callback is sampled by it
+        for (contract, sampled) in self.contracts.iter().zip(&self.sampled) {
+            if contract.applies(handler, url) && !sampled.swap(true, Ordering::Relaxed) {
+                follow = true;
+            }
+        }
+        follow
+    }
+
+    /// Check what the handler produced for a response against the contracts applying to it.
+    ///
+    /// # Returns
+    /// The violations found.
+    pub(crate) fn check(
+        &self,
+        handler: &str,
+        url: &str,
+        items: u64,
+        callbacks: u64,
+    ) -> Vec<ContractViolation> {
+        let mut found = Vec::new();
+        let mut applied = false;
+        for (contract, checked) in self.contracts.iter().zip(&self.checked) {
+            if !contract.applies(handler, url) {
+                continue;
+            }
+            applied = true;
+            checked.store(true, Ordering::Relaxed);
+            if let Some(expected) = contract.check(items, callbacks) {
+                found.push(ContractViolation {
+                    contract: contract.to_string(),
+                    url: url.to_string(),
+                    expected,
+                    items,
+                    callbacks,
+                });
+            }
+        }
+        if applied {
+            self.responses.fetch_add(1, Ordering::Relaxed);
+        }
+        if !found.is_empty() {
+            self.violations
+                .lock()
+                .expect("contracts lock")
+                .extend(found.iter().cloned());
+        }
+        found
+    }
+
+    pub(crate) fn report(&self) -> ContractReport {
+        ContractReport {
+            checked: self.responses.load(Ordering::Relaxed),
+            violations: self.violations.lock().expect("contracts lock").clone(),
+            unchecked: self
+                .contracts
+                .iter()
+                .zip(&self.checked)
+                .filter(|(_, checked)| !checked.load(Ordering::Relaxed))
+                .map(|(contract, _)| contract.to_string())
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use crate::intern::Interner;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+/// Why a discovered URL wasn't processed by its handler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum FilterReason {
+    /// The domain exceeded its [latency budget](crate::WebBuilder::domain_latency_budget).
+    LatencyBudget,
+    /// The page was a [near duplicate](crate::WebBuilder::near_duplicates) of an earlier page.
+    NearDuplicate,
+    /// The crawl was stopped, by a pipeline stage or through its handle, before the URL was
+    /// requested.
+    CrawlStopped,
+    /// The URL is assigned to another [Shard](crate::Shard).
+    OtherShard,
+    /// The [Probe](crate::Probe) of the URL rejected the resource.
+    ProbeRejected,
+    /// The crawl was a [contract run](crate::WebBuilder::contract_run), and the contracts of the
+    /// callback were already sampled.
+    ContractRun,
+    /// The URL was past the limits of the [sub-crawl](crate::Scheduler::sub_crawl) that
+    /// discovered it, or the handler of the sub-crawl stopped collecting its items.
+    SubCrawl,
+    /// The URL is disallowed by the robots.txt of its host, see
+    /// [respect_robots_txt](crate::WebBuilder::respect_robots_txt).
+    RobotsTxt,
+    /// The same request was already made, see [dedup](crate::WebBuilder::dedup).
+    Duplicate,
+    /// No route of the [URL router](crate::WebBuilder::url_router) of the web matched the
+    /// [followed](crate::Callback::follow) link.
+    NoRoute,
+    /// The URL was past the [maximum depth](crate::WebBuilder::max_depth) of the crawl.
+    MaxDepth,
+}
+
+/// What became of a discovered URL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "status", content = "reason")]
+pub enum UrlOutcome {
+    /// The URL was never dequeued, the crawl was dropped before it finished.
+    Pending,
+    /// A response was received and handed to the handler.
+    Fetched,
+    /// The request failed, or was banned more times than allowed.
+    Failed,
+    /// The URL was skipped.
+    Filtered(FilterReason),
+}
+
+/// The coverage of a single URL.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UrlCoverage {
+    /// The URL.
+    pub url: String,
+    /// The handler the URL was scheduled for.
+    pub handler: String,
+    /// What became of the URL. When a URL was scheduled several times, the last outcome, but a
+    /// fetched URL stays fetched unless a later request of it fails.
+    #[serde(flatten)]
+    pub outcome: UrlOutcome,
+}
+
+/// Which of the URLs discovered during a crawl were fetched, failed, or were filtered out, to
+/// audit whether the settings of the crawl silently excluded sections of a site. Enabled with
+/// [WebBuilder::coverage](crate::WebBuilder::coverage) and found in the
+/// [CrawlSummary](crate::CrawlSummary).
+///
+/// The report serializes to JSON:
+///
+/// ```no_run
+/// # fn example(summary: scrappy_do::CrawlSummary) -> Result<(), Box<dyn std::error::Error>> {
+/// if let Some(coverage) = summary.coverage {
+///     serde_json::to_writer_pretty(std::fs::File::create("coverage.json")?, &coverage)?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CoverageReport {
+    /// The number of distinct URLs scheduled by the crawl.
+    pub discovered: u64,
+    /// The number of URLs handed to their handler.
+    pub fetched: u64,
+    /// The number of URLs whose request failed.
+    pub failed: u64,
+    /// The number of URLs never dequeued.
+    pub pending: u64,
+    /// The number of URLs filtered out, by reason.
+    pub filtered: BTreeMap<FilterReason, u64>,
+    /// Every discovered URL, in the order they were discovered.
+    pub urls: Vec<UrlCoverage>,
+}
+
+/// Tracks the coverage of a running crawl.
+#[derive(Debug, Default)]
+pub(crate) struct Coverage {
+    urls: Mutex<CoveredUrls>,
+}
+
+#[derive(Debug, Default)]
+struct CoveredUrls {
+    // Indices into `urls`, by URL
+    index: HashMap<Arc<str>, usize>,
+    urls: Vec<CoveredUrl>,
+    handlers: Interner,
+}
+
+/// A [UrlCoverage](UrlCoverage) sharing its URL with the index, and its handler with the other
+/// URLs of the handler.
+#[derive(Debug)]
+struct CoveredUrl {
+    url: Arc<str>,
+    handler: Arc<str>,
+    outcome: UrlOutcome,
+}
+
+impl Coverage {
+    /// Record the scheduling of `url` for `handler`.
+    pub(crate) fn discovered(&self, url: &str, handler: String) {
+        let mut covered = self.urls.lock().expect("coverage lock");
+        let covered = &mut *covered;
+        let handler = covered.handlers.intern(&handler);
+        match covered.index.get(url) {
+            Some(&index) => {
+                let entry = &mut covered.urls[index];
+                entry.handler = handler;
+                // A fetched URL stays fetched, unless it fails the next time
+                if entry.outcome != UrlOutcome::Fetched {
+                    entry.outcome = UrlOutcome::Pending;
+                }
+            }
+            None => {
+                let url: Arc<str> = Arc::from(url);
+                covered.index.insert(url.clone(), covered.urls.len());
+                covered.urls.push(CoveredUrl {
+                    url,
+                    handler,
+                    outcome: UrlOutcome::Pending,
+                });
+            }
+        }
+    }
+
+    /// Record what became of `url`.
+    pub(crate) fn record(&self, url: &str, outcome: UrlOutcome) {
+        let mut covered = self.urls.lock().expect("coverage lock");
+        let covered = &mut *covered;
+        if let Some(&index) = covered.index.get(url) {
+            covered.urls[index].outcome = outcome;
+        }
+    }
+
+    /// Record that `url` was skipped as a duplicate request, unless it was fetched before.
+    pub(crate) fn duplicate(&self, url: &str) {
+        let mut covered = self.urls.lock().expect("coverage lock");
+        let covered = &mut *covered;
+        if let Some(&index) = covered.index.get(url) {
+            let entry = &mut covered.urls[index];
+            if entry.outcome != UrlOutcome::Fetched {
+                entry.outcome = UrlOutcome::Filtered(FilterReason::Duplicate);
+            }
+        }
+    }
+
+    pub(crate) fn report(&self) -> CoverageReport {
+        let covered = self.urls.lock().expect("coverage lock");
+        let mut report = CoverageReport {
+            discovered: covered.urls.len() as u64,
+            ..CoverageReport::default()
+        };
+        for url in &covered.urls {
+            match url.outcome {
+                UrlOutcome::Pending => report.pending += 1,
+                UrlOutcome::Fetched => report.fetched += 1,
+                UrlOutcome::Failed => report.failed += 1,
+                UrlOutcome::Filtered(reason) => *report.filtered.entry(reason).or_insert(0) += 1,
+            }
+            report.urls.push(UrlCoverage {
+                url: url.url.to_string(),
+                handler: url.handler.to_string(),
+                outcome: url.outcome,
+            });
+        }
+        report
+    }
+}
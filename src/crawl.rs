@@ -0,0 +1,301 @@
+use crate::contract::ContractReport;
+use crate::coverage::CoverageReport;
+use crate::pipeline::StageStats;
+use crate::settings::{Settings, Shutdown};
+use crate::stats::{CrawlGauges, CrawlStats, Stats};
+use crate::tracker::{SchedulerDump, Tracker};
+use futures::{Future, Stream};
+use std::fmt::{self, Display, Formatter};
+use std::num::{NonZeroU64, NonZeroUsize};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::{mpsc::Receiver, oneshot};
+use uuid::Uuid;
+
+/// Describes how a crawl went.
+#[derive(Debug, Clone)]
+pub struct CrawlSummary {
+    /// The ID of the crawl.
+    pub run_id: Uuid,
+    /// The statistics collected during the crawl.
+    pub stats: CrawlStats,
+    /// How long the crawl ran.
+    pub duration: Duration,
+    /// The statistics of every pipeline stage, in the order the stages ran.
+    pub pipelines: Vec<StageStats>,
+    /// Why a pipeline stage stopped the crawl, if one did.
+    pub pipeline_failure: Option<String>,
+    /// What became of every discovered URL, when
+    /// [coverage](crate::WebBuilder::coverage) is enabled.
+    pub coverage: Option<CoverageReport>,
+    /// The contracts checked during the crawl, when [contracts](crate::WebBuilder::contract) were
+    /// given.
+    pub contracts: Option<ContractReport>,
+    /// Why the crawl stopped.
+    pub stop_reason: StopReason,
+}
+
+/// Why a crawl stopped. Only a [Finished](StopReason::Finished) crawl went through every URL it
+/// discovered, the others were truncated.
+///
+/// ```no_run
+/// # async fn example(mut crawl: scrappy_do::Crawl<String>) {
+/// use futures::StreamExt;
+/// use scrappy_do::StopReason;
+///
+/// while let Some(item) = crawl.next().await {
+///     println!("{}", item);
+/// }
+/// match crawl.summary().map(|summary| summary.stop_reason) {
+///     Some(StopReason::Finished) => println!("Crawled everything"),
+///     Some(reason) => println!("Truncated: {}", reason),
+///     None => println!("The crawl ended without a summary"),
+/// }
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum StopReason {
+    /// No callback was left to execute.
+    Finished,
+    /// The crawl made its [maximum number of requests](crate::WebBuilder::max_requests).
+    MaxRequests,
+    /// The crawl produced its [maximum number of items](crate::WebBuilder::max_items).
+    MaxItems,
+    /// The crawl ran for its [maximum duration](crate::WebBuilder::max_duration).
+    MaxDuration,
+    /// The crawl was stopped through its handle with
+    /// [graceful_shutdown](CrawlHandle::graceful_shutdown).
+    Shutdown,
+    /// The crawl was stopped through its handle with [abort](CrawlHandle::abort).
+    Aborted,
+    /// A pipeline stage failed the crawl, see
+    /// [pipeline_failure](CrawlSummary::pipeline_failure).
+    PipelineFailure,
+}
+
+impl StopReason {
+    /// Whether the crawl went through every URL it discovered.
+    pub fn is_finished(&self) -> bool {
+        *self == StopReason::Finished
+    }
+}
+
+impl Display for StopReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StopReason::Finished => "no callback was left to execute",
+            StopReason::MaxRequests => "the crawl made its maximum number of requests",
+            StopReason::MaxItems => "the crawl produced its maximum number of items",
+            StopReason::MaxDuration => "the crawl ran for its maximum duration",
+            StopReason::Shutdown => "the crawl was shut down",
+            StopReason::Aborted => "the crawl was aborted",
+            StopReason::PipelineFailure => "a pipeline stage failed the crawl",
+        })
+    }
+}
+
+/// Controls a running crawl. Obtained through [Crawl::handle](Crawl::handle), it can be cloned and
+/// moved to other tasks.
+///
+/// Settings changed through the handle take effect for the callbacks dispatched afterwards,
+/// operators can react to pressure on the target site without restarting the crawl.
+///
+/// The handle also stops the crawl, for instance on ctrl-c:
+///
+/// ```no_run
+/// # async fn example(crawl: scrappy_do::Crawl<String>) {
+/// let handle = crawl.handle();
+/// tokio::spawn(async move {
+///     tokio::signal::ctrl_c().await.unwrap();
+///     // The pages being fetched are still handled, and their items still produced
+///     handle.graceful_shutdown();
+///     handle.await_idle().await;
+/// });
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct CrawlHandle {
+    settings: Arc<Settings>,
+    stats: Arc<Stats>,
+    tracker: Arc<Tracker>,
+}
+
+impl CrawlHandle {
+    /// Returns the current maximum number of concurrent requests.
+    pub fn concurrent_requests(&self) -> usize {
+        self.settings.concurrent_requests()
+    }
+
+    /// Change the maximum number of concurrent requests. When lowering the limit, requests that
+    /// are already executing are allowed to finish. Ignored by
+    /// [deterministic](crate::WebBuilder::deterministic) crawls.
+    pub fn set_concurrent_requests(&self, concurrent_requests: NonZeroUsize) {
+        self.settings
+            .set_concurrent_requests(concurrent_requests.get());
+    }
+
+    /// Returns the current per domain latency budget, see
+    /// [domain_latency_budget](crate::WebBuilder::domain_latency_budget).
+    pub fn domain_latency_budget(&self) -> Option<Duration> {
+        self.settings.domain_latency_budget()
+    }
+
+    /// Change the per domain latency budget. `None` disables it.
+    pub fn set_domain_latency_budget(&self, budget: Option<Duration>) {
+        self.settings.set_domain_latency_budget(budget);
+    }
+
+    /// Returns the current bandwidth of the crawl in bytes per second, see
+    /// [bandwidth](crate::WebBuilder::bandwidth).
+    pub fn bandwidth(&self) -> Option<NonZeroU64> {
+        self.settings.bandwidth()
+    }
+
+    /// Change the bandwidth of the crawl. `None` lifts the limit.
+    pub fn set_bandwidth(&self, bytes_per_second: Option<NonZeroU64>) {
+        self.settings.set_bandwidth(bytes_per_second);
+    }
+
+    /// Returns the statistics collected so far, while the crawl is running. The final statistics
+    /// are in the [CrawlSummary](CrawlSummary).
+    pub fn stats(&self) -> CrawlStats {
+        self.stats.snapshot()
+    }
+
+    /// Stop the crawl once the executing callbacks finished. The callbacks queued, and the ones
+    /// produced meanwhile, are dropped, reported as
+    /// [CrawlStopped](crate::FilterReason::CrawlStopped) by the coverage report. The stream of
+    /// items ends once the crawl stopped.
+    pub fn graceful_shutdown(&self) {
+        self.settings.stop(Shutdown::Draining, StopReason::Shutdown);
+    }
+
+    /// Stop the crawl right away, cancelling the executing callbacks. The queued callbacks are
+    /// dropped like with [graceful_shutdown](CrawlHandle::graceful_shutdown).
+    pub fn abort(&self) {
+        self.settings.stop(Shutdown::Aborted, StopReason::Aborted);
+    }
+
+    /// Why the crawl stopped, or is stopping. `None` while it runs.
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.settings.stop_reason()
+    }
+
+    /// Whether the crawl was asked to stop, gracefully or not.
+    pub fn is_shutting_down(&self) -> bool {
+        self.settings.shutdown() != Shutdown::Running
+    }
+
+    /// Wait until no callback is queued, waiting for its turn, or executing. Once the crawl was
+    /// asked to stop, this is when it stopped.
+    pub async fn await_idle(&self) {
+        self.tracker.idle().await
+    }
+
+    /// Returns the state of the scheduler: the queue of every domain, the callbacks that waited
+    /// the longest in the queue and the executing callbacks with their age. Useful to find what a
+    /// stalled crawl is waiting for. The callbacks are only listed when the crawl tracks them,
+    /// see [WebBuilder::track_callbacks](crate::WebBuilder::track_callbacks).
+    pub fn scheduler_dump(&self) -> SchedulerDump {
+        self.tracker.dump(
+            self.settings.concurrent_requests(),
+            self.stats.gauges().domain_frontier,
+        )
+    }
+}
+
+/// The stream of items produced by a crawl.
+///
+/// Once the stream is exhausted the [CrawlSummary](CrawlSummary) of the crawl is available through
+/// [summary](Crawl::summary).
+#[derive(Debug)]
+pub struct Crawl<I> {
+    run_id: Uuid,
+    items: Receiver<I>,
+    stats: Arc<Stats>,
+    settings: Arc<Settings>,
+    tracker: Arc<Tracker>,
+    pending_summary: Option<oneshot::Receiver<CrawlSummary>>,
+    summary: Option<CrawlSummary>,
+}
+
+impl<I> Crawl<I> {
+    pub(crate) fn new(
+        run_id: Uuid,
+        items: Receiver<I>,
+        stats: Arc<Stats>,
+        settings: Arc<Settings>,
+        tracker: Arc<Tracker>,
+        summary: oneshot::Receiver<CrawlSummary>,
+    ) -> Self {
+        Self {
+            run_id,
+            items,
+            stats,
+            settings,
+            tracker,
+            pending_summary: Some(summary),
+            summary: None,
+        }
+    }
+
+    /// Returns the ID generated for the crawl. Every message logged during the crawl carries it as
+    /// `run_id`.
+    pub fn run_id(&self) -> Uuid {
+        self.run_id
+    }
+
+    /// Returns the current state of the crawl's queues. Useful to tell whether a crawl is bound by
+    /// the network, the handlers, or the consumer of the items.
+    pub fn gauges(&self) -> CrawlGauges {
+        CrawlGauges {
+            queued_items: self.items.len(),
+            ..self.stats.gauges()
+        }
+    }
+
+    /// Returns the statistics collected so far, see [CrawlHandle::stats](CrawlHandle::stats).
+    pub fn stats(&self) -> CrawlStats {
+        self.stats.snapshot()
+    }
+
+    /// Returns a handle to control the crawl while it is running.
+    pub fn handle(&self) -> CrawlHandle {
+        CrawlHandle {
+            settings: self.settings.clone(),
+            stats: self.stats.clone(),
+            tracker: self.tracker.clone(),
+        }
+    }
+
+    /// Returns the summary of the crawl. This is `None` until the stream has been exhausted.
+    pub fn summary(&self) -> Option<&CrawlSummary> {
+        self.summary.as_ref()
+    }
+}
+
+impl<I> Stream for Crawl<I> {
+    type Item = I;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        let this = self.get_mut();
+        match this.items.poll_recv(cx) {
+            Poll::Ready(None) => {}
+            item => return item,
+        }
+        // The item queue closes slightly before the crawl finishes joining its tasks
+        if let Some(pending_summary) = &mut this.pending_summary {
+            match Pin::new(pending_summary).poll(cx) {
+                Poll::Ready(summary) => {
+                    this.summary = summary.ok();
+                    this.pending_summary = None;
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(None)
+    }
+}
@@ -0,0 +1,506 @@
+use crate::crawl::{CrawlHandle, CrawlSummary};
+use crate::spider::{panic_message, Web};
+use crate::stats::CrawlStats;
+use futures::{FutureExt, Sink, StreamExt};
+use slog::{error, info};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt::{Debug, Display};
+use std::num::{NonZeroU64, NonZeroUsize};
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex, MutexGuard};
+use thiserror::Error;
+use tokio::sync::watch;
+
+#[derive(Error, Debug)]
+pub enum CrawlSetError {
+    #[error("a crawl named {0} is already in the set")]
+    Duplicate(String),
+}
+
+/// The state of a crawl of a [CrawlSet](CrawlSet).
+#[derive(Debug, Clone)]
+pub enum CrawlState {
+    /// The crawl is running, or stopping.
+    Running,
+    /// Every item of the crawl was written to its sink.
+    Finished(Box<CrawlSummary>),
+    /// The sink of the crawl returned an error or panicked, or the crawl ended without a summary.
+    /// The crawl was aborted.
+    Failed(String),
+}
+
+impl CrawlState {
+    /// Whether the crawl finished or failed.
+    pub fn is_done(&self) -> bool {
+        !matches!(self, CrawlState::Running)
+    }
+}
+
+/// Limits on the resources used by crawls of a [CrawlSet](CrawlSet), by each crawl with
+/// [quota](CrawlSet::quota) or by all of them together with [limits](CrawlSet::limits).
+///
+/// ```
+/// use scrappy_do::{CrawlSet, Quota};
+/// use std::num::{NonZeroU64, NonZeroUsize};
+///
+/// // Every customer crawl makes up to 8 concurrent requests, downloads up to 1 MB/s, and
+/// // queues up to 16 MB, within 64 concurrent requests and 20 MB/s for the whole service
+/// let crawls = CrawlSet::new()
+///     .quota(
+///         Quota::new()
+///             .concurrent_requests(NonZeroUsize::new(8).unwrap())
+///             .bandwidth(NonZeroU64::new(1_000_000).unwrap())
+///             .buffer_bytes(NonZeroUsize::new(16_000_000).unwrap()),
+///     )
+///     .limits(
+///         Quota::new()
+///             .concurrent_requests(NonZeroUsize::new(64).unwrap())
+///             .bandwidth(NonZeroU64::new(20_000_000).unwrap()),
+///     );
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quota {
+    concurrent_requests: Option<NonZeroUsize>,
+    buffer_bytes: Option<NonZeroUsize>,
+    bandwidth: Option<NonZeroU64>,
+}
+
+impl Quota {
+    /// No limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make at most `concurrent_requests` requests at once.
+    pub fn concurrent_requests(mut self, concurrent_requests: NonZeroUsize) -> Self {
+        self.concurrent_requests = Some(concurrent_requests);
+        self
+    }
+
+    /// Size the queues of callbacks and items after `buffer_bytes` bytes at most, see
+    /// [task_queue_size_bytes](crate::WebBuilder::task_queue_size_bytes) and
+    /// [item_queue_size_bytes](crate::WebBuilder::item_queue_size_bytes).
+    pub fn buffer_bytes(mut self, buffer_bytes: NonZeroUsize) -> Self {
+        self.buffer_bytes = Some(buffer_bytes);
+        self
+    }
+
+    /// Download at most `bytes_per_second` bytes per second, see
+    /// [bandwidth](crate::WebBuilder::bandwidth).
+    pub fn bandwidth(mut self, bytes_per_second: NonZeroU64) -> Self {
+        self.bandwidth = Some(bytes_per_second);
+        self
+    }
+}
+
+/// Runs independent crawls side by side in one process, each one known by a name, for instance a
+/// service running the crawls of its customers.
+///
+/// Every crawl keeps its own concurrency limit, statistics, queues and tasks, and its messages are
+/// logged with its name as `crawl`. The items of a crawl are written to its own sink by a task of
+/// its own: a sink returning an error or panicking, like a callback panicking, only brings down
+/// the crawl it belongs to, and a stalled crawl doesn't hold back the others.
+///
+/// The webs of a same [Spider](crate::Spider) share its clients and its
+/// [host delays](crate::SpiderBuilder::host_delay), give each tenant its own spider so that none
+/// can slow down the others.
+///
+/// ```no_run
+/// # #![feature(generators)]
+/// # use scrappy_do::{handle, wrap, ScrapedResponse};
+/// # use reqwest::Client;
+/// # use slog::Logger;
+/// # #[handle(item = String)]
+/// # fn handler(client: Client, response: ScrapedResponse, context: (), logger: Logger) {}
+/// use scrappy_do::{CrawlSet, CrawlState, Spider};
+///
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let crawls = CrawlSet::new();
+/// for (customer, url) in [("acme", "https://acme.example"), ("globex", "https://globex.example")] {
+///     let spider = Spider::builder().build()?;
+///     let web = spider
+///         .web()
+///         .start(spider.client().get(url).build()?)
+///         .handler(wrap!(handler))
+///         .context(())
+///         .build();
+///     crawls.spawn(customer, web, futures::sink::drain()).await?;
+/// }
+///
+/// for (customer, state) in crawls.wait_all().await {
+///     if let CrawlState::Failed(reason) = state {
+///         eprintln!("The crawl of {} failed: {}", customer, reason);
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CrawlSet {
+    members: Arc<Mutex<Members>>,
+    limits: Quota,
+    quota: Quota,
+}
+
+#[derive(Debug, Default)]
+struct Members {
+    crawls: BTreeMap<String, Member>,
+    // The crawls being started, with the bytes reserved for their queues
+    starting: BTreeMap<String, usize>,
+}
+
+#[derive(Debug)]
+struct Member {
+    handle: CrawlHandle,
+    state: watch::Receiver<CrawlState>,
+    // The concurrency and the bandwidth of the crawl without the limits of the set
+    concurrent_requests: u64,
+    bandwidth: u64,
+    buffer_bytes: usize,
+}
+
+impl CrawlSet {
+    /// An empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit the resources used by all the crawls of the set together. Once a limit is reached,
+    /// it is shared fairly among the running crawls: every crawl gets as much as the others,
+    /// unless it needs less, and what it leaves goes to the others.
+    ///
+    /// The concurrency and the bandwidth are shared again whenever a crawl starts or ends, and
+    /// override the changes made to them through the handles of the crawls. The queues of a
+    /// crawl are sized when it starts, to its share or to what the running crawls left if less,
+    /// and hold at least one callback and one item.
+    pub fn limits(mut self, limits: Quota) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Limit the resources of each crawl spawned with [spawn](CrawlSet::spawn). A web asking for
+    /// more is capped to its quota.
+    pub fn quota(mut self, quota: Quota) -> Self {
+        self.quota = quota;
+        self
+    }
+
+    /// Start crawling `web` under `name`, writing its items to `sink`, within the
+    /// [quota](CrawlSet::quota) of the set.
+    ///
+    /// # Returns
+    /// The handle of the crawl, or an error if a crawl of the set already has this name. Finished
+    /// crawls keep their name until they are [removed](CrawlSet::remove).
+    pub async fn spawn<N, I, C, S>(
+        &self,
+        name: N,
+        web: Web<I, C>,
+        sink: S,
+    ) -> Result<CrawlHandle, CrawlSetError>
+    where
+        N: Into<String>,
+        I: Debug + Send + Unpin + 'static,
+        C: Debug + Send + Unpin + 'static,
+        S: Sink<I> + Send + Unpin + 'static,
+        S::Error: Display,
+    {
+        self.spawn_with_quota(name, web, sink, self.quota).await
+    }
+
+    /// Start crawling `web` under `name` like [spawn](CrawlSet::spawn), within `quota` rather
+    /// than the quota of the set.
+    pub async fn spawn_with_quota<N, I, C, S>(
+        &self,
+        name: N,
+        web: Web<I, C>,
+        sink: S,
+        quota: Quota,
+    ) -> Result<CrawlHandle, CrawlSetError>
+    where
+        N: Into<String>,
+        I: Debug + Send + Unpin + 'static,
+        C: Debug + Send + Unpin + 'static,
+        S: Sink<I> + Send + Unpin + 'static,
+        S::Error: Display,
+    {
+        let name = name.into();
+        let mut web = web.tenant(&name);
+        if let Some(buffer_bytes) = quota.buffer_bytes {
+            web = web.cap_buffers(buffer_bytes.get());
+        }
+        let starting = {
+            let mut members = self.members();
+            if members.crawls.contains_key(&name) || members.starting.contains_key(&name) {
+                return Err(CrawlSetError::Duplicate(name));
+            }
+            if let Some(limit) = self.limits.buffer_bytes {
+                web = web.cap_buffers(members.buffer_share(limit.get()));
+            }
+            members.starting.insert(name.clone(), web.buffer_bytes());
+            Starting {
+                members: &self.members,
+                name: name.clone(),
+            }
+        };
+        let concurrent_requests = quota
+            .concurrent_requests
+            .map_or(web.concurrent_requests(), |quota| {
+                quota.get().min(web.concurrent_requests())
+            }) as u64;
+        let bandwidth = match (quota.bandwidth, web.bandwidth()) {
+            (Some(quota), Some(bandwidth)) => quota.min(bandwidth).get(),
+            (Some(bandwidth), None) | (None, Some(bandwidth)) => bandwidth.get(),
+            (None, None) => u64::MAX,
+        };
+        let buffer_bytes = web.buffer_bytes();
+        let logger = web.logger().clone();
+        let runtime = web.runtime();
+        let mut crawl = web.crawl().await;
+        let handle = crawl.handle();
+
+        let (state_sender, state) = watch::channel(CrawlState::Running);
+        {
+            let mut members = self.members();
+            members.starting.remove(&name);
+            members.crawls.insert(
+                name,
+                Member {
+                    handle: handle.clone(),
+                    state,
+                    concurrent_requests,
+                    bandwidth,
+                    buffer_bytes,
+                },
+            );
+            members.rebalance(&self.limits);
+        }
+        drop(starting);
+
+        let consumer_handle = handle.clone();
+        let set = self.clone();
+        runtime.spawn(Box::pin(async move {
+            let mut sink = sink;
+            let written = AssertUnwindSafe((&mut crawl).map(Ok).forward(&mut sink))
+                .catch_unwind()
+                .await;
+            let state = match written {
+                Ok(Ok(())) => match crawl.summary() {
+                    Some(summary) => CrawlState::Finished(Box::new(summary.clone())),
+                    None => CrawlState::Failed("the crawl ended without a summary".to_string()),
+                },
+                Ok(Err(err)) => CrawlState::Failed(format!("the sink failed: {}", err)),
+                Err(panic) => {
+                    CrawlState::Failed(format!("the sink panicked: {}", panic_message(&*panic)))
+                }
+            };
+            match &state {
+                CrawlState::Failed(reason) => {
+                    consumer_handle.abort();
+                    error!(logger, "The crawl failed"; "error" => reason);
+                }
+                _ => info!(logger, "The crawl finished"),
+            }
+            state_sender.send_replace(state);
+            // The running crawls share what the crawl used
+            set.members().rebalance(&set.limits);
+        }));
+        Ok(handle)
+    }
+
+    fn members(&self) -> MutexGuard<'_, Members> {
+        self.members.lock().expect("crawl set lock")
+    }
+
+    /// Whether a crawl of the set is named `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.members().crawls.contains_key(name)
+    }
+
+    /// Returns the names of the crawls of the set, in order.
+    pub fn names(&self) -> Vec<String> {
+        self.members().crawls.keys().cloned().collect()
+    }
+
+    /// Returns the handle of the crawl named `name`, to control it.
+    pub fn handle(&self, name: &str) -> Option<CrawlHandle> {
+        self.members()
+            .crawls
+            .get(name)
+            .map(|member| member.handle.clone())
+    }
+
+    /// Returns the state of the crawl named `name`.
+    pub fn state(&self, name: &str) -> Option<CrawlState> {
+        self.members()
+            .crawls
+            .get(name)
+            .map(|member| member.state.borrow().clone())
+    }
+
+    /// Returns the statistics collected so far by every crawl of the set, by name.
+    pub fn stats(&self) -> BTreeMap<String, CrawlStats> {
+        self.members()
+            .crawls
+            .iter()
+            .map(|(name, member)| (name.clone(), member.handle.stats()))
+            .collect()
+    }
+
+    /// Wait until the crawl named `name` finished or failed.
+    ///
+    /// # Returns
+    /// The final state of the crawl, or `None` if no crawl of the set is named `name`.
+    pub async fn wait(&self, name: &str) -> Option<CrawlState> {
+        let state = self
+            .members()
+            .crawls
+            .get(name)
+            .map(|member| member.state.clone());
+        Some(wait_done(state?).await)
+    }
+
+    /// Wait until every crawl of the set finished or failed, the ones spawned meanwhile included.
+    ///
+    /// # Returns
+    /// The final state of every crawl, by name.
+    pub async fn wait_all(&self) -> BTreeMap<String, CrawlState> {
+        let mut done = BTreeMap::new();
+        loop {
+            let pending: Vec<_> = self
+                .members()
+                .crawls
+                .iter()
+                .filter(|(name, _)| !done.contains_key(*name))
+                .map(|(name, member)| (name.clone(), member.state.clone()))
+                .collect();
+            if pending.is_empty() {
+                return done;
+            }
+            for (name, state) in pending {
+                done.insert(name, wait_done(state).await);
+            }
+        }
+    }
+
+    /// Remove the crawl named `name` from the set, aborting it if it is still running.
+    ///
+    /// # Returns
+    /// The state of the crawl when it was removed, or `None` if no crawl of the set is named
+    /// `name`.
+    pub fn remove(&self, name: &str) -> Option<CrawlState> {
+        let mut members = self.members();
+        let member = members.crawls.remove(name)?;
+        let state = member.state.borrow().clone();
+        if !state.is_done() {
+            member.handle.abort();
+            members.rebalance(&self.limits);
+        }
+        Some(state)
+    }
+
+    /// Stop every crawl of the set once its executing callbacks finished, see
+    /// [graceful_shutdown](CrawlHandle::graceful_shutdown).
+    pub fn graceful_shutdown(&self) {
+        for member in self.members().crawls.values() {
+            member.handle.graceful_shutdown();
+        }
+    }
+
+    /// Stop every crawl of the set right away, see [abort](CrawlHandle::abort).
+    pub fn abort(&self) {
+        for member in self.members().crawls.values() {
+            member.handle.abort();
+        }
+    }
+}
+
+impl Members {
+    fn running(&self) -> impl Iterator<Item = &Member> {
+        self.crawls
+            .values()
+            .filter(|member| !member.state.borrow().is_done())
+    }
+
+    /// The bytes the queues of a starting crawl may use, out of `limit` for all the crawls.
+    fn buffer_share(&self, limit: usize) -> usize {
+        let used = self
+            .running()
+            .map(|member| member.buffer_bytes)
+            .chain(self.starting.values().copied())
+            .fold(0, usize::saturating_add);
+        let crawls = self.running().count() + self.starting.len() + 1;
+        (limit / crawls).min(limit.saturating_sub(used))
+    }
+
+    /// Share the concurrency and the bandwidth limits among the running crawls.
+    fn rebalance(&self, limits: &Quota) {
+        let running: Vec<_> = self.running().collect();
+        let concurrency = fair_shares(
+            limits.concurrent_requests.map(|limit| limit.get() as u64),
+            running.iter().map(|member| member.concurrent_requests),
+        );
+        let bandwidth = fair_shares(
+            limits.bandwidth.map(NonZeroU64::get),
+            running.iter().map(|member| member.bandwidth),
+        );
+        for ((member, concurrent_requests), bandwidth) in
+            running.into_iter().zip(concurrency).zip(bandwidth)
+        {
+            let concurrent_requests = usize::try_from(concurrent_requests).unwrap_or(usize::MAX);
+            member.handle.set_concurrent_requests(
+                NonZeroUsize::new(concurrent_requests).unwrap_or(NonZeroUsize::MIN),
+            );
+            // Unlimited crawls demand `u64::MAX`
+            member.handle.set_bandwidth(
+                Some(bandwidth)
+                    .filter(|&bandwidth| bandwidth < u64::MAX)
+                    .and_then(NonZeroU64::new),
+            );
+        }
+    }
+}
+
+/// Releases the name and the reservation of a crawl that didn't start.
+struct Starting<'a> {
+    members: &'a Mutex<Members>,
+    name: String,
+}
+
+impl Drop for Starting<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut members) = self.members.lock() {
+            members.starting.remove(&self.name);
+        }
+    }
+}
+
+/// Share `capacity` among `demands` max-min fairly: the smallest demands are met first, and
+/// the others split what they left equally. Every share is at least 1. Without a capacity every
+/// demand is met.
+fn fair_shares<D: Iterator<Item = u64>>(capacity: Option<u64>, demands: D) -> Vec<u64> {
+    let demands: Vec<_> = demands.collect();
+    let capacity = match capacity {
+        Some(capacity) => capacity,
+        None => return demands,
+    };
+    let mut order: Vec<_> = (0..demands.len()).collect();
+    order.sort_by_key(|&index| demands[index]);
+    let mut shares = vec![0; demands.len()];
+    let mut remaining = capacity;
+    for (served, index) in order.into_iter().enumerate() {
+        let share = remaining / (demands.len() - served) as u64;
+        shares[index] = demands[index].min(share).max(1);
+        remaining = remaining.saturating_sub(shares[index]);
+    }
+    shares
+}
+
+/// Wait until the crawl of `state` is done.
+async fn wait_done(mut state: watch::Receiver<CrawlState>) -> CrawlState {
+    match state.wait_for(CrawlState::is_done).await {
+        Ok(done) => done.clone(),
+        // The consumer task was dropped along with its runtime
+        Err(_) => CrawlState::Failed("the crawl was cancelled".to_string()),
+    }
+}
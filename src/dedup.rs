@@ -0,0 +1,168 @@
+#[cfg(feature = "redis")]
+use crate::pipeline::RedisStore;
+use crate::pipeline::{BloomStore, DedupStore, ExactStore, Fnv64};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use reqwest::Request;
+use std::fmt::Debug;
+use std::hash::Hasher;
+use std::sync::Arc;
+
+/// Remembers the fingerprints of the requests already made by a crawl, see
+/// [WebBuilder::dedup](crate::WebBuilder::dedup).
+///
+/// The [stores](crate::pipeline::DedupStore) of the [Dedup](crate::pipeline::Dedup) pipeline
+/// are filters too: an [ExactStore](crate::pipeline::ExactStore) remembers every request, a
+/// [BloomStore](crate::pipeline::BloomStore) bounds the memory used by long crawls at the cost
+/// of skipping a few unseen requests, and both can be saved to resume a crawl later. Wrap the
+/// filter in an `Arc` to keep a handle on it once the crawl is started.
+///
+/// ```no_run
+/// # async fn example(web: scrappy_do::WebBuilder<(), ()>) -> std::io::Result<()> {
+/// use scrappy_do::pipeline::{BloomStore, DedupStore};
+/// use std::fs::File;
+/// use std::sync::Arc;
+///
+/// let seen = Arc::new(BloomStore::open("requests.bin", 10_000_000, 0.001)?);
+/// let web = web.dedup_filter(seen.clone());
+/// // ... crawl with the web, then save the filter for the next run
+/// seen.save(&mut File::create("requests.bin")?)?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait DedupFilter: Send + Sync + Debug {
+    /// Record the fingerprint of a request.
+    ///
+    /// # Returns
+    /// Whether the fingerprint wasn't recorded before, so the request is made. Filters that fail
+    /// to record a fingerprint should return true rather than lose the request.
+    fn insert(&self, fingerprint: u64) -> BoxFuture<'_, bool>;
+}
+
+impl<F: DedupFilter + ?Sized> DedupFilter for Arc<F> {
+    fn insert(&self, fingerprint: u64) -> BoxFuture<'_, bool> {
+        (**self).insert(fingerprint)
+    }
+}
+
+impl DedupFilter for ExactStore {
+    fn insert(&self, fingerprint: u64) -> BoxFuture<'_, bool> {
+        store_insert(self, fingerprint)
+    }
+}
+
+impl DedupFilter for BloomStore {
+    fn insert(&self, fingerprint: u64) -> BoxFuture<'_, bool> {
+        store_insert(self, fingerprint)
+    }
+}
+
+#[cfg(feature = "redis")]
+impl DedupFilter for RedisStore {
+    fn insert(&self, fingerprint: u64) -> BoxFuture<'_, bool> {
+        store_insert(self, fingerprint)
+    }
+}
+
+fn store_insert<S: DedupStore>(store: &S, fingerprint: u64) -> BoxFuture<'_, bool> {
+    DedupStore::insert(store, fingerprint)
+        .map(|new| new.unwrap_or(true))
+        .boxed()
+}
+
+/// The fingerprint of `request`, from its method, its normalized URL and its body. URLs are
+/// normalized by dropping their fragment and sorting their query parameters, the scheme and host
+/// are already lowercase. Streamed bodies aren't part of the fingerprint.
+///
+/// Fingerprints are stable across runs, so filters can be saved and loaded by the next run.
+pub(crate) fn fingerprint(request: &Request) -> u64 {
+    let mut url = request.url().clone();
+    url.set_fragment(None);
+    let mut query: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+    if query.is_empty() {
+        url.set_query(None);
+    } else {
+        query.sort();
+        url.query_pairs_mut().clear().extend_pairs(query);
+    }
+    let mut hasher = Fnv64::default();
+    hasher.write(request.method().as_str().as_bytes());
+    hasher.write(&[0]);
+    hasher.write(url.as_str().as_bytes());
+    if let Some(body) = request.body().and_then(|body| body.as_bytes()) {
+        hasher.write(&[0]);
+        hasher.write(body);
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::{Client, Method};
+
+    fn request(method: Method, url: &str, body: Option<&'static str>) -> Request {
+        let mut builder = Client::new().request(method, url);
+        if let Some(body) = body {
+            builder = builder.body(body);
+        }
+        builder.build().unwrap()
+    }
+
+    fn get(url: &str) -> u64 {
+        fingerprint(&request(Method::GET, url, None))
+    }
+
+    #[test]
+    fn normalizes_the_url() {
+        let page = get("https://example.com/list?page=2&sort=asc");
+        assert_eq!(get("https://example.com/list?sort=asc&page=2"), page);
+        assert_eq!(
+            get("https://example.com/list?page=2&sort=asc#reviews"),
+            page
+        );
+        assert_eq!(get("HTTPS://EXAMPLE.COM/list?page=2&sort=asc"), page);
+        assert_eq!(
+            get("https://example.com/list?"),
+            get("https://example.com/list")
+        );
+        assert_ne!(get("https://example.com/list?page=3&sort=asc"), page);
+        assert_ne!(get("https://example.com/List?page=2&sort=asc"), page);
+    }
+
+    #[test]
+    fn keeps_repeated_query_parameters() {
+        assert_eq!(
+            get("https://example.com/search?tag=b&tag=a"),
+            get("https://example.com/search?tag=a&tag=b")
+        );
+        assert_ne!(
+            get("https://example.com/search?tag=a"),
+            get("https://example.com/search?tag=a&tag=a")
+        );
+    }
+
+    #[test]
+    fn includes_the_method_and_body() {
+        let url = "https://example.com/api";
+        let posted = fingerprint(&request(Method::POST, url, Some("cursor=1")));
+        assert_ne!(fingerprint(&request(Method::GET, url, None)), posted);
+        assert_ne!(fingerprint(&request(Method::POST, url, None)), posted);
+        assert_ne!(
+            fingerprint(&request(Method::POST, url, Some("cursor=2"))),
+            posted
+        );
+        assert_eq!(
+            fingerprint(&request(Method::POST, url, Some("cursor=1"))),
+            posted
+        );
+    }
+
+    #[tokio::test]
+    async fn filters_repeated_fingerprints() {
+        let filter: Box<dyn DedupFilter> = Box::new(ExactStore::default());
+        assert!(filter.insert(1).await);
+        assert!(filter.insert(2).await);
+        assert!(!filter.insert(1).await);
+    }
+}
@@ -0,0 +1,77 @@
+use crate::spider::matches_host;
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use std::net::SocketAddr;
+
+/// Which IP versions are used to reach hosts that have both IPv4 and IPv6 addresses. Some sites
+/// serve different, or broken, content over IPv6.
+///
+/// When both versions are allowed, connections are attempted over the preferred version first
+/// and over the other one if no connection was established within 300ms ("happy eyeballs").
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IpPreference {
+    /// Use the addresses in the order returned by the system resolver.
+    #[default]
+    System,
+    /// Try IPv4 first, falling back to IPv6.
+    PreferIpv4,
+    /// Try IPv6 first, falling back to IPv4.
+    PreferIpv6,
+    /// Only connect over IPv4, hosts without an IPv4 address can't be reached.
+    Ipv4Only,
+    /// Only connect over IPv6, hosts without an IPv6 address can't be reached.
+    Ipv6Only,
+}
+
+impl IpPreference {
+    /// Filter and order the addresses of a host.
+    fn apply(self, mut addresses: Vec<SocketAddr>) -> Vec<SocketAddr> {
+        match self {
+            Self::System => {}
+            // The sorts are stable, the system order is kept within a version
+            Self::PreferIpv4 => addresses.sort_by_key(|address| !address.is_ipv4()),
+            Self::PreferIpv6 => addresses.sort_by_key(|address| !address.is_ipv6()),
+            Self::Ipv4Only => addresses.retain(SocketAddr::is_ipv4),
+            Self::Ipv6Only => addresses.retain(SocketAddr::is_ipv6),
+        }
+        addresses
+    }
+}
+
+/// Resolves host names with the system resolver and applies the IP preference of the host.
+#[derive(Debug)]
+pub(crate) struct PreferenceResolver {
+    default: IpPreference,
+    // The preferences of the hosts matching a pattern
+    domains: Vec<(String, IpPreference)>,
+}
+
+impl PreferenceResolver {
+    pub(crate) fn new(default: IpPreference, domains: Vec<(String, IpPreference)>) -> Self {
+        Self { default, domains }
+    }
+
+    fn preference(&self, host: &str) -> IpPreference {
+        self.domains
+            .iter()
+            .find(|(pattern, _)| matches_host(pattern, host))
+            .map(|(_, preference)| *preference)
+            .unwrap_or(self.default)
+    }
+}
+
+impl Resolve for PreferenceResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        let preference = self.preference(&host);
+        Box::pin(async move {
+            // The port is replaced by the one of the URL
+            let addresses = tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            let addresses = preference.apply(addresses);
+            if addresses.is_empty() {
+                return Err(format!("{} has no address allowed by {:?}", host, preference).into());
+            }
+            Ok(Box::new(addresses.into_iter()) as Addrs)
+        })
+    }
+}
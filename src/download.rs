@@ -0,0 +1,154 @@
+use crate::encoding::ContentCoding;
+use crate::response::JsonError;
+use futures::future::BoxFuture;
+use reqwest::{
+    header::CONTENT_TYPE, Client, Method, Request, Response, ResponseBuilderExt, StatusCode,
+};
+use std::fmt::Debug;
+use std::io::{self, ErrorKind};
+use std::time::Duration;
+use thiserror::Error;
+use url::Url;
+
+#[derive(Error, Debug)]
+pub enum DownloadError {
+    #[error("the request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("no response was recorded for the request (given: {0} {1})")]
+    NotRecorded(Method, Url),
+    #[error("the request was aborted as an outlier after {0:?}")]
+    Outlier(Duration),
+    #[error("the resource could not be read: {0}")]
+    Io(#[from] io::Error),
+    #[error("the FTP server replied unexpectedly: {0}")]
+    Ftp(String),
+    #[error("the domain banned the crawler: {0}")]
+    Banned(String),
+    #[error("the domain is unavailable: {0}")]
+    Unavailable(String),
+    #[error("the request failed transiently: {0}")]
+    Transient(String),
+    #[error("the host can't be reached, its circuit is open: {0}")]
+    CircuitOpen(String),
+    #[error("the {0:?} encoded body could not be decoded: {1}")]
+    Decode(ContentCoding, #[source] io::Error),
+    #[error("the response could not be deserialized: {0}")]
+    Json(#[from] JsonError),
+}
+
+/// Turns a [Request](reqwest::Request) into a [Response](reqwest::Response).
+///
+/// By default requests are sent over the network with the web's client. Other handlers can serve
+/// responses from anywhere else, like the [Replay](crate::Replay) of a recorded crawl.
+pub trait DownloadHandler: Send + Sync + Debug {
+    fn download(
+        &self,
+        client: Client,
+        request: Request,
+    ) -> BoxFuture<'static, Result<Response, DownloadError>>;
+}
+
+/// Sends requests over the network.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HttpDownloadHandler;
+
+impl DownloadHandler for HttpDownloadHandler {
+    fn download(
+        &self,
+        client: Client,
+        request: Request,
+    ) -> BoxFuture<'static, Result<Response, DownloadError>> {
+        Box::pin(async move { Ok(client.execute(request).await?) })
+    }
+}
+
+/// Reads `file://` URLs from the local file system. Directories are listed as plain text, one
+/// entry per line with a trailing `/` for subdirectories. Missing files produce a `404` response.
+///
+/// Not registered by default, pages could otherwise link to arbitrary local files. Register it
+/// with [scheme_handler](crate::WebBuilder::scheme_handler), which is mostly useful to run
+/// handlers against fixtures in tests.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileDownloadHandler;
+
+impl DownloadHandler for FileDownloadHandler {
+    fn download(
+        &self,
+        _client: Client,
+        request: Request,
+    ) -> BoxFuture<'static, Result<Response, DownloadError>> {
+        let url = request.url().clone();
+        Box::pin(async move {
+            let path = url.to_file_path().map_err(|_| {
+                io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("not a local path: {}", url),
+                )
+            })?;
+            let metadata = match tokio::fs::metadata(&path).await {
+                Ok(metadata) => metadata,
+                Err(err) if err.kind() == ErrorKind::NotFound => {
+                    return Ok(local_response(url, StatusCode::NOT_FOUND, Vec::new(), None))
+                }
+                Err(err) => return Err(err.into()),
+            };
+            if metadata.is_dir() {
+                let mut entries = Vec::new();
+                let mut dir = tokio::fs::read_dir(&path).await?;
+                while let Some(entry) = dir.next_entry().await? {
+                    let mut name = entry.file_name().to_string_lossy().into_owned();
+                    if entry.file_type().await?.is_dir() {
+                        name.push('/');
+                    }
+                    entries.push(name);
+                }
+                entries.sort();
+                let listing = entries.join("\n").into_bytes();
+                Ok(local_response(
+                    url,
+                    StatusCode::OK,
+                    listing,
+                    Some("text/plain"),
+                ))
+            } else {
+                let body = tokio::fs::read(&path).await?;
+                Ok(local_response(url, StatusCode::OK, body, None))
+            }
+        })
+    }
+}
+
+/// Build a response for a resource that wasn't fetched over HTTP.
+pub(crate) fn local_response(
+    url: Url,
+    status: StatusCode,
+    body: Vec<u8>,
+    content_type: Option<&str>,
+) -> Response {
+    let mut builder = http::Response::builder().status(status).url(url);
+    if let Some(content_type) = content_type {
+        builder = builder.header(CONTENT_TYPE, content_type);
+    }
+    Response::from(builder.body(body).expect("valid local response"))
+}
+
+/// The error of a request to a local port nothing listens on, for the tests of the failure
+/// checks.
+#[cfg(test)]
+pub(crate) async fn connect_error() -> DownloadError {
+    let address = std::net::TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .expect("local port");
+    // The proxies of the environment would turn the refused connection into a proxy error
+    let client = reqwest::Client::builder()
+        .no_proxy()
+        .build()
+        .expect("client");
+    let err = client
+        .get(format!("http://{}", address))
+        .send()
+        .await
+        .expect_err("refused connection");
+    assert!(err.is_connect(), "not a connect error: {}", err);
+    DownloadError::Request(err)
+}
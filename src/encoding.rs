@@ -0,0 +1,319 @@
+use crate::spider::matches_host;
+use flate2::read::{MultiGzDecoder, ZlibDecoder};
+use reqwest::header::{HeaderMap, CONTENT_ENCODING, CONTENT_TYPE};
+use std::collections::HashSet;
+use std::io::{self, Read};
+use std::sync::Mutex;
+use url::Url;
+
+/// The codings the crawl decodes, as announced in the `Accept-Encoding` header.
+pub(crate) const ACCEPTED_CODINGS: &str = "gzip, deflate, br";
+
+/// The content coding a response was sent with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ContentCoding {
+    /// The body wasn't encoded, or the client decoded it before the crawl saw it.
+    Identity,
+    /// `gzip`, or its `x-gzip` alias.
+    Gzip,
+    /// `deflate`, zlib compressed data.
+    Deflate,
+    /// `br`, Brotli compressed data.
+    Brotli,
+    /// A coding the crawl doesn't decode, the handler receives the encoded body.
+    Unknown,
+}
+
+impl ContentCoding {
+    fn parse(coding: &str) -> Option<Self> {
+        match coding.trim().to_ascii_lowercase().as_str() {
+            "" | "identity" => None,
+            "gzip" | "x-gzip" => Some(Self::Gzip),
+            "deflate" => Some(Self::Deflate),
+            "br" => Some(Self::Brotli),
+            _ => Some(Self::Unknown),
+        }
+    }
+
+    /// The codings listed by `Content-Encoding`, in the order they were applied.
+    fn all(headers: &HeaderMap) -> Vec<Self> {
+        headers
+            .get_all(CONTENT_ENCODING)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(Self::parse)
+            .collect()
+    }
+
+    /// The coding of a response with `headers`. When several codings were applied, the last
+    /// one.
+    pub(crate) fn of(headers: &HeaderMap) -> Self {
+        let codings = Self::all(headers);
+        if codings.contains(&Self::Unknown) {
+            return Self::Unknown;
+        }
+        codings.last().copied().unwrap_or(Self::Identity)
+    }
+}
+
+/// Decode a body sent with `headers`.
+///
+/// # Returns
+/// The decoded body, or `None` when the body doesn't have to, or can't, be decoded.
+pub(crate) fn decode(headers: &HeaderMap, body: &[u8]) -> Option<io::Result<Vec<u8>>> {
+    let codings = ContentCoding::all(headers);
+    if codings.is_empty() || codings.contains(&ContentCoding::Unknown) {
+        return None;
+    }
+    let mut body = body.to_vec();
+    // The codings are undone in the reverse order they were applied
+    for coding in codings.into_iter().rev() {
+        let mut decoded = Vec::new();
+        let read = match coding {
+            ContentCoding::Gzip => MultiGzDecoder::new(&body[..]).read_to_end(&mut decoded),
+            ContentCoding::Deflate => ZlibDecoder::new(&body[..]).read_to_end(&mut decoded),
+            ContentCoding::Brotli => {
+                brotli_decompressor::Decompressor::new(&body[..], 4096).read_to_end(&mut decoded)
+            }
+            ContentCoding::Identity | ContentCoding::Unknown => continue,
+        };
+        if let Err(err) = read {
+            return Some(Err(err));
+        }
+        body = decoded;
+    }
+    Some(Ok(body))
+}
+
+/// The first bytes of gzip data.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// How much of a gzipped body is decompressed to tell whether it is an XML document.
+const SNIFF_LEN: u64 = 1024;
+/// The most a gzipped XML document is decompressed to, the size limit of sitemaps. Larger
+/// documents are left compressed.
+const MAX_GUNZIPPED_LEN: u64 = 50 * 1024 * 1024;
+
+/// The content types servers send gzip files as.
+const GZIP_TYPES: [&str; 6] = [
+    "application/gzip",
+    "application/x-gzip",
+    "application/x-gunzip",
+    "application/gzip-compressed",
+    "application/x-gzip-compressed",
+    "application/octet-stream",
+];
+
+/// The media type of a response with `headers`, lowercased and without its parameters.
+fn media_type(headers: &HeaderMap) -> Option<String> {
+    let content_type = headers.get(CONTENT_TYPE)?.to_str().ok()?;
+    let media_type = content_type.split(';').next().unwrap_or_default();
+    Some(media_type.trim().to_ascii_lowercase())
+}
+
+/// Whether a response with `headers` is sent as a gzip file, or as bytes of an unknown type.
+pub(crate) fn is_gzip_type(headers: &HeaderMap) -> bool {
+    media_type(headers).is_some_and(|media_type| GZIP_TYPES.contains(&media_type.as_str()))
+}
+
+/// Whether the response for `url` with `headers` may be a gzipped document the server didn't
+/// announce with a `Content-Encoding`, like the `.xml.gz` sitemaps served as gzip files, or the
+/// XML documents compressed once more on the fly. Their bodies are sniffed for gzip data.
+///
+/// Only the XML responses and the `.xml.gz` or sitemap files are, other files like archives are
+/// streamed to the handler as they are.
+pub(crate) fn may_be_gzipped(url: &Url, headers: &HeaderMap) -> bool {
+    let path = url.path().to_ascii_lowercase();
+    let file = path.rsplit('/').next().unwrap_or_default();
+    file.ends_with(".xml.gz")
+        || file.starts_with("sitemap") && file.ends_with(".gz")
+        || media_type(headers).is_some_and(|media_type| media_type.contains("xml"))
+}
+
+/// Decompress `body` if it is a gzipped XML document, judging by its first kilobyte once
+/// decompressed. Other gzip files, and the documents decompressing to more than 50 MiB, are
+/// left compressed.
+pub(crate) fn gunzip_xml(body: &[u8]) -> Option<Vec<u8>> {
+    if !body.starts_with(&GZIP_MAGIC) {
+        return None;
+    }
+    let mut prefix = Vec::new();
+    MultiGzDecoder::new(body)
+        .take(SNIFF_LEN)
+        .read_to_end(&mut prefix)
+        .ok()?;
+    let document = prefix.strip_prefix(b"\xef\xbb\xbf").unwrap_or(&prefix);
+    if document.iter().find(|byte| !byte.is_ascii_whitespace()) != Some(&b'<') {
+        return None;
+    }
+    let mut decoded = Vec::new();
+    MultiGzDecoder::new(body)
+        .take(MAX_GUNZIPPED_LEN + 1)
+        .read_to_end(&mut decoded)
+        .ok()?;
+    (decoded.len() as u64 <= MAX_GUNZIPPED_LEN).then_some(decoded)
+}
+
+/// Which hosts are asked for uncompressed responses.
+#[derive(Debug, Default)]
+pub(crate) struct Encodings {
+    // The patterns of the hosts configured to receive uncompressed responses
+    identity: Vec<String>,
+    // The hosts whose compressed responses failed to decode during the crawl
+    fallen_back: Mutex<HashSet<String>>,
+}
+
+impl Encodings {
+    pub(crate) fn new(identity: Vec<String>) -> Self {
+        Self {
+            identity,
+            fallen_back: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Whether `host` has to be asked for uncompressed responses.
+    pub(crate) fn forces_identity(&self, host: &str) -> bool {
+        self.identity
+            .iter()
+            .any(|pattern| matches_host(pattern, host))
+            || self
+                .fallen_back
+                .lock()
+                .expect("encodings lock")
+                .contains(host)
+    }
+
+    /// Ask `host` for uncompressed responses for the rest of the crawl.
+    pub(crate) fn fall_back(&self, host: &str) {
+        self.fallen_back
+            .lock()
+            .expect("encodings lock")
+            .insert(host.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::{GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+    use reqwest::header::HeaderValue;
+    use std::io::Write;
+
+    fn gzip(body: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(body).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn zlib(body: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(body).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn headers(name: reqwest::header::HeaderName, values: &[&'static str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(name.clone(), HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn parses_the_codings() {
+        let coding = |values| ContentCoding::of(&headers(CONTENT_ENCODING, values));
+        assert_eq!(coding(&[]), ContentCoding::Identity);
+        assert_eq!(coding(&["identity"]), ContentCoding::Identity);
+        assert_eq!(coding(&["X-Gzip"]), ContentCoding::Gzip);
+        assert_eq!(coding(&["gzip, br"]), ContentCoding::Brotli);
+        assert_eq!(coding(&["deflate", "gzip"]), ContentCoding::Gzip);
+        assert_eq!(coding(&["gzip, zstd"]), ContentCoding::Unknown);
+    }
+
+    #[test]
+    fn decodes_the_codings_in_reverse() {
+        let body = b"<html>caf\xc3\xa9</html>";
+        let gzipped = headers(CONTENT_ENCODING, &["gzip"]);
+        assert_eq!(decode(&gzipped, &gzip(body)).unwrap().unwrap(), body);
+        let deflated = headers(CONTENT_ENCODING, &["deflate"]);
+        assert_eq!(decode(&deflated, &zlib(body)).unwrap().unwrap(), body);
+        let both = headers(CONTENT_ENCODING, &["deflate", "gzip"]);
+        assert_eq!(decode(&both, &gzip(&zlib(body))).unwrap().unwrap(), body);
+    }
+
+    #[test]
+    fn leaves_unencoded_and_unknown_bodies() {
+        assert!(decode(&HeaderMap::new(), b"plain").is_none());
+        assert!(decode(&headers(CONTENT_ENCODING, &["identity"]), b"plain").is_none());
+        assert!(decode(&headers(CONTENT_ENCODING, &["zstd"]), b"\x28\xb5").is_none());
+    }
+
+    #[test]
+    fn fails_on_corrupt_bodies() {
+        let gzipped = headers(CONTENT_ENCODING, &["gzip"]);
+        assert!(decode(&gzipped, b"not gzip").unwrap().is_err());
+    }
+
+    #[test]
+    fn sniffs_xml_and_sitemap_files_only() {
+        let url = |path| {
+            Url::parse("https://example.com")
+                .unwrap()
+                .join(path)
+                .unwrap()
+        };
+        let none = HeaderMap::new();
+        let gzip_file = headers(CONTENT_TYPE, &["application/x-gzip"]);
+        assert!(may_be_gzipped(&url("/sitemap.xml.GZ"), &none));
+        assert!(may_be_gzipped(&url("/sitemap-2.gz"), &gzip_file));
+        assert!(may_be_gzipped(
+            &url("/feed"),
+            &headers(CONTENT_TYPE, &["Application/RSS+XML; charset=utf-8"])
+        ));
+        assert!(!may_be_gzipped(&url("/release.tar.gz"), &gzip_file));
+        assert!(!may_be_gzipped(
+            &url("/download"),
+            &headers(CONTENT_TYPE, &["application/octet-stream"])
+        ));
+        assert!(!may_be_gzipped(
+            &url("/index.html"),
+            &headers(CONTENT_TYPE, &["text/html"])
+        ));
+    }
+
+    #[test]
+    fn gunzips_xml_only() {
+        let sitemap = b"\xef\xbb\xbf\n  <?xml version=\"1.0\"?><urlset/>";
+        assert_eq!(gunzip_xml(&gzip(sitemap)).unwrap(), sitemap);
+        assert!(gunzip_xml(&gzip(b"PK\x03\x04 archive")).is_none());
+        assert!(gunzip_xml(sitemap).is_none());
+        assert!(gunzip_xml(&gzip(sitemap)[..12]).is_none());
+    }
+
+    #[test]
+    fn sniffs_a_bounded_prefix() {
+        // Only the first kilobyte tells, whatever follows
+        let mut archive = vec![0; 10 * 1024 * 1024];
+        archive[2000] = b'<';
+        assert!(gunzip_xml(&gzip(&archive)).is_none());
+        let mut document = b"<urlset>".to_vec();
+        document.resize(MAX_GUNZIPPED_LEN as usize + 1, b' ');
+        assert!(gunzip_xml(&gzip(&document)).is_none());
+        document.truncate(MAX_GUNZIPPED_LEN as usize);
+        assert_eq!(
+            gunzip_xml(&gzip(&document)).map(|xml| xml.len()),
+            Some(document.len())
+        );
+    }
+
+    #[test]
+    fn falls_back_to_identity() {
+        let encodings = Encodings::new(vec!["*.legacy.example.com".to_string()]);
+        assert!(encodings.forces_identity("www.legacy.example.com"));
+        assert!(!encodings.forces_identity("example.com"));
+        encodings.fall_back("example.com");
+        assert!(encodings.forces_identity("example.com"));
+    }
+}
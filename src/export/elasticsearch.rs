@@ -0,0 +1,274 @@
+use super::{ExportError, Exporter};
+use crate::item::ScrapeItem;
+use futures::future::BoxFuture;
+use reqwest::{header::CONTENT_TYPE, Client, StatusCode};
+use serde::Deserialize;
+use std::fmt::{self, Debug};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use url::Url;
+
+type DocumentId<I> = Box<dyn Fn(&I) -> String + Send + Sync>;
+
+/// Indexes the items into Elasticsearch or OpenSearch with the bulk API.
+///
+/// Items are buffered and sent in batches. Batches, or the items of a batch, rejected with
+/// `429 Too Many Requests` are retried with an exponential backoff. Since
+/// [Web::export](crate::Web::export) waits for every batch, the crawl slows down while the
+/// cluster is pushing back.
+///
+/// ```no_run
+/// # fn example() -> Result<(), url::ParseError> {
+/// use reqwest::Client;
+/// use scrappy_do::export::Elasticsearch;
+/// use scrappy_do::ScrapeItem;
+/// use serde::Serialize;
+///
+/// #[derive(Debug, Serialize, ScrapeItem)]
+/// struct Product {
+///     sku: String,
+/// }
+///
+/// let exporter = Elasticsearch::new(Client::new(), "http://localhost:9200".parse()?, "products-%Y.%m.%d")
+///     .id(|product: &Product| product.sku.clone())
+///     .batch_size(1_000);
+/// # Ok(())
+/// # }
+/// ```
+pub struct Elasticsearch<I> {
+    client: Client,
+    bulk_url: Url,
+    index: String,
+    id: Option<DocumentId<I>>,
+    batch_size: usize,
+    retries: usize,
+    backoff: Duration,
+    // The IDs and serialized documents waiting to be sent
+    pending: Vec<(Option<String>, String)>,
+}
+
+impl<I> Elasticsearch<I> {
+    /// Index into `index` on the cluster at `url`.
+    ///
+    /// `index` may contain the `%Y`, `%m`, and `%d` placeholders, replaced by the UTC year, month,
+    /// and day when a batch is sent, to create daily or monthly indices.
+    pub fn new<S: Into<String>>(client: Client, url: Url, index: S) -> Self {
+        // Keep a path prefix, like a proxy mounting the cluster under `/search/`
+        let mut bulk_url = url;
+        if !bulk_url.path().ends_with('/') {
+            bulk_url.set_path(&format!("{}/", bulk_url.path()));
+        }
+        Self {
+            client,
+            bulk_url: bulk_url.join("_bulk").expect("relative bulk path"),
+            index: index.into(),
+            id: None,
+            batch_size: 500,
+            retries: 5,
+            backoff: Duration::from_millis(500),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Set the ID of the documents with `id`, so items indexed again replace their previous
+    /// version. Without it the cluster generates IDs.
+    pub fn id<F>(mut self, id: F) -> Self
+    where
+        F: Fn(&I) -> String + Send + Sync + 'static,
+    {
+        self.id = Some(Box::new(id));
+        self
+    }
+
+    /// The number of items sent per bulk request. Defaults to 500.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Retry rejected items up to `retries` times, waiting `backoff` before the first retry and
+    /// doubling the wait every time. Defaults to 5 retries after 500ms.
+    pub fn retry(mut self, retries: usize, backoff: Duration) -> Self {
+        self.retries = retries;
+        self.backoff = backoff;
+        self
+    }
+
+    /// The name of the index for a batch sent at `now`.
+    fn index_name(&self, now: SystemTime) -> String {
+        let days = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() / 86_400;
+        let (year, month, day) = civil_date(days as i64);
+        self.index
+            .replace("%Y", &format!("{:04}", year))
+            .replace("%m", &format!("{:02}", month))
+            .replace("%d", &format!("{:02}", day))
+    }
+
+    fn buffer(&mut self, item: &I) -> Result<(), ExportError>
+    where
+        I: ScrapeItem,
+    {
+        let id = self.id.as_ref().map(|id| id(item));
+        self.pending.push((id, serde_json::to_string(item)?));
+        Ok(())
+    }
+
+    /// Send the buffered items, retrying the ones rejected with `429`.
+    async fn flush(&mut self) -> Result<(), ExportError> {
+        let index = self.index_name(SystemTime::now());
+        let mut backoff = self.backoff;
+        let mut attempt = 0;
+        while !self.pending.is_empty() {
+            let mut body = String::new();
+            for (id, doc) in &self.pending {
+                let action = match id {
+                    Some(id) => serde_json::json!({ "index": { "_index": index, "_id": id } }),
+                    None => serde_json::json!({ "index": { "_index": index } }),
+                };
+                body.push_str(&action.to_string());
+                body.push('\n');
+                body.push_str(doc);
+                body.push('\n');
+            }
+            let response = self
+                .client
+                .post(self.bulk_url.clone())
+                .header(CONTENT_TYPE, "application/x-ndjson")
+                .body(body)
+                .send()
+                .await
+                .map_err(backend)?;
+
+            let throttled: Vec<bool> = match response.status() {
+                StatusCode::TOO_MANY_REQUESTS => vec![true; self.pending.len()],
+                status if status.is_success() => {
+                    let body = response.bytes().await.map_err(backend)?;
+                    let bulk: BulkResponse = serde_json::from_slice(&body)?;
+                    if let Some(failure) = bulk.items.iter().find_map(BulkItem::failure) {
+                        return Err(ExportError::Backend(failure));
+                    }
+                    bulk.items
+                        .iter()
+                        .map(|item| item.status() == StatusCode::TOO_MANY_REQUESTS.as_u16())
+                        .collect()
+                }
+                status => {
+                    let body = response.text().await.unwrap_or_default();
+                    return Err(ExportError::Backend(format!(
+                        "the bulk request failed with {}: {}",
+                        status, body
+                    )));
+                }
+            };
+
+            let mut throttled = throttled.into_iter();
+            self.pending.retain(|_| throttled.next().unwrap_or(true));
+            if self.pending.is_empty() {
+                break;
+            }
+            if attempt == self.retries {
+                return Err(ExportError::Backend(format!(
+                    "{} items were still throttled after {} retries",
+                    self.pending.len(),
+                    self.retries
+                )));
+            }
+            attempt += 1;
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+        Ok(())
+    }
+}
+
+impl<I> Debug for Elasticsearch<I> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Elasticsearch")
+            .field("bulk_url", &self.bulk_url.as_str())
+            .field("index", &self.index)
+            .field("batch_size", &self.batch_size)
+            .field("retries", &self.retries)
+            .field("backoff", &self.backoff)
+            .field("pending", &self.pending.len())
+            .finish()
+    }
+}
+
+impl<I: ScrapeItem> Exporter<I> for Elasticsearch<I> {
+    fn export<'a>(&'a mut self, item: &'a I) -> BoxFuture<'a, Result<(), ExportError>> {
+        // Serialized up front, items aren't required to be `Sync`
+        let buffered = self.buffer(item);
+        Box::pin(async move {
+            buffered?;
+            if self.pending.len() >= self.batch_size {
+                self.flush().await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn finish(&mut self) -> BoxFuture<'_, Result<(), ExportError>> {
+        Box::pin(self.flush())
+    }
+}
+
+#[derive(Deserialize)]
+struct BulkResponse {
+    #[serde(default)]
+    items: Vec<BulkItem>,
+}
+
+/// The result of a single action, keyed by the action's name.
+#[derive(Deserialize)]
+struct BulkItem(std::collections::HashMap<String, BulkResult>);
+
+#[derive(Deserialize)]
+struct BulkResult {
+    status: u16,
+    error: Option<serde_json::Value>,
+}
+
+impl BulkItem {
+    fn status(&self) -> u16 {
+        self.0
+            .values()
+            .next()
+            .map(|result| result.status)
+            .unwrap_or(0)
+    }
+
+    /// Describes why the action failed, unless it succeeded or was throttled.
+    fn failure(&self) -> Option<String> {
+        let result = self.0.values().next()?;
+        if result.status == StatusCode::TOO_MANY_REQUESTS.as_u16() {
+            return None;
+        }
+        result
+            .error
+            .as_ref()
+            .map(|error| format!("an item was rejected with {}: {}", result.status, error))
+    }
+}
+
+/// Convert days since the Unix epoch to a (year, month, day) date.
+fn civil_date(days: i64) -> (i64, u32, u32) {
+    // Howard Hinnant's days_from_civil inverse
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn backend<E: ToString>(err: E) -> ExportError {
+    ExportError::Backend(err.to_string())
+}
@@ -0,0 +1,49 @@
+use super::{ExportError, Exporter};
+use crate::item::ScrapeItem;
+use futures::future::{self, BoxFuture};
+use std::io::Write;
+
+/// Writes every item as a line of JSON.
+///
+/// ```no_run
+/// # fn example() -> std::io::Result<()> {
+/// use scrappy_do::export::JsonLines;
+/// use std::{fs::File, io::BufWriter};
+///
+/// let exporter = JsonLines::new(BufWriter::new(File::create("items.jsonl")?));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct JsonLines<W> {
+    writer: W,
+}
+
+impl<W: Write + Send> JsonLines<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Unwrap the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write<I: ScrapeItem>(&mut self, item: &I) -> Result<(), ExportError> {
+        serde_json::to_writer(&mut self.writer, item)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+impl<I: ScrapeItem, W: Write + Send> Exporter<I> for JsonLines<W> {
+    fn export<'a>(&'a mut self, item: &'a I) -> BoxFuture<'a, Result<(), ExportError>> {
+        Box::pin(future::ready(self.write(item)))
+    }
+
+    fn finish(&mut self) -> BoxFuture<'_, Result<(), ExportError>> {
+        Box::pin(future::ready(
+            self.writer.flush().map_err(ExportError::from),
+        ))
+    }
+}
@@ -0,0 +1,59 @@
+//! Writes the items of a crawl out of the process.
+//!
+//! An [Exporter](Exporter) receives every item of a crawl through
+//! [Web::export](crate::Web::export). Items opt into exporters by implementing
+//! [ScrapeItem](crate::ScrapeItem). A [JsonLinesReader](JsonLinesReader) instead reads the items
+//! of a crawl as an [AsyncRead](tokio::io::AsyncRead), to pipe them into a socket or a
+//! subprocess.
+
+use crate::item::ScrapeItem;
+use futures::future::{self, BoxFuture};
+use std::io;
+use thiserror::Error;
+
+mod elasticsearch;
+mod json_lines;
+#[cfg(feature = "mongodb")]
+mod mongodb;
+#[cfg(feature = "parquet")]
+mod parquet;
+mod pretty;
+mod reader;
+#[cfg(feature = "redis")]
+mod redis;
+mod rotate;
+mod router;
+
+#[cfg(feature = "mongodb")]
+pub use self::mongodb::MongoDb;
+#[cfg(feature = "parquet")]
+pub use self::parquet::Parquet;
+#[cfg(feature = "redis")]
+pub use self::redis::Redis;
+pub use elasticsearch::Elasticsearch;
+pub use json_lines::JsonLines;
+pub use pretty::{Pretty, PrettyFormat};
+pub use reader::JsonLinesReader;
+pub use rotate::{ChunkWriter, Compression, Rotating};
+pub use router::Router;
+
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("the item could not be written: {0}")]
+    Io(#[from] io::Error),
+    #[error("the item could not be serialized: {0}")]
+    Serialize(#[from] serde_json::Error),
+    #[error("the export backend failed: {0}")]
+    Backend(String),
+}
+
+/// Writes items somewhere, like a file or a database.
+pub trait Exporter<I: ScrapeItem>: Send {
+    /// Write a single item.
+    fn export<'a>(&'a mut self, item: &'a I) -> BoxFuture<'a, Result<(), ExportError>>;
+
+    /// Called once after the last item, to flush buffered items.
+    fn finish(&mut self) -> BoxFuture<'_, Result<(), ExportError>> {
+        Box::pin(future::ready(Ok(())))
+    }
+}
@@ -0,0 +1,123 @@
+use super::{ExportError, Exporter};
+use crate::item::ScrapeItem;
+use futures::future::{self, BoxFuture};
+use mongodb::bson::{self, doc, Document};
+use mongodb::options::{InsertManyOptions, ReplaceOptions};
+use mongodb::Collection;
+use std::mem;
+
+/// Writes the items to a MongoDB collection, one document per item. Only available with the
+/// `mongodb` feature.
+///
+/// Items are buffered and written in batches. By default they are inserted with `insert_many`, use
+/// [upsert_by](MongoDb::upsert_by) to replace the document with the same key instead, so
+/// recrawled items update their previous version.
+///
+/// ```no_run
+/// # async fn example() -> mongodb::error::Result<()> {
+/// use mongodb::{bson::Document, Client};
+/// use scrappy_do::export::MongoDb;
+///
+/// let client = Client::with_uri_str("mongodb://localhost:27017").await?;
+/// let exporter = MongoDb::new(client.database("crawl").collection::<Document>("products"))
+///     .upsert_by("sku")
+///     .batch_size(500);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct MongoDb {
+    collection: Collection<Document>,
+    key: Option<String>,
+    batch_size: usize,
+    pending: Vec<Document>,
+}
+
+impl MongoDb {
+    pub fn new(collection: Collection<Document>) -> Self {
+        Self {
+            collection,
+            key: None,
+            batch_size: 100,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Replace the document whose `key` field matches the item's, inserting it if there is none.
+    /// Every item must serialize a `key` field.
+    pub fn upsert_by<S: Into<String>>(mut self, key: S) -> Self {
+        self.key = Some(key.into());
+        self
+    }
+
+    /// The number of items written per batch. Defaults to 100.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    fn buffer<I: ScrapeItem>(&mut self, item: &I) -> Result<(), ExportError> {
+        let document = bson::to_document(item).map_err(backend)?;
+        if let Some(key) = &self.key {
+            if !document.contains_key(key) {
+                return Err(ExportError::Backend(format!(
+                    "the item has no {} field to upsert by",
+                    key
+                )));
+            }
+        }
+        self.pending.push(document);
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), ExportError> {
+        let documents = mem::take(&mut self.pending);
+        if documents.is_empty() {
+            return Ok(());
+        }
+        match &self.key {
+            None => {
+                // Unordered, a rejected document doesn't prevent the rest from being written
+                let options = InsertManyOptions::builder().ordered(false).build();
+                self.collection
+                    .insert_many(documents, options)
+                    .await
+                    .map_err(backend)?;
+            }
+            Some(key) => {
+                // The driver has no bulk upsert, the replacements are sent concurrently instead
+                let upserts = documents.iter().map(|document| {
+                    let filter = doc! { key.as_str(): document.get(key).cloned() };
+                    let options = ReplaceOptions::builder().upsert(true).build();
+                    self.collection.replace_one(filter, document, options)
+                });
+                for result in future::join_all(upserts).await {
+                    result.map_err(backend)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<I: ScrapeItem> Exporter<I> for MongoDb {
+    fn export<'a>(&'a mut self, item: &'a I) -> BoxFuture<'a, Result<(), ExportError>> {
+        // Serialized up front, items aren't required to be `Sync`
+        let buffered = self.buffer(item);
+        Box::pin(async move {
+            buffered?;
+            if self.pending.len() >= self.batch_size {
+                self.flush().await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn finish(&mut self) -> BoxFuture<'_, Result<(), ExportError>> {
+        Box::pin(self.flush())
+    }
+}
+
+fn backend<E: ToString>(err: E) -> ExportError {
+    ExportError::Backend(err.to_string())
+}
@@ -0,0 +1,159 @@
+use super::{ExportError, Exporter};
+use crate::item::ScrapeItem;
+use arrow_json::reader::{infer_json_schema_from_iterator, Decoder, ReaderBuilder};
+use arrow_schema::SchemaRef;
+use futures::future::{self, BoxFuture};
+use parquet::arrow::ArrowWriter;
+use parquet::file::properties::WriterProperties;
+use serde_json::Value;
+use std::fmt::{self, Debug};
+use std::io::Write;
+use std::mem;
+use std::sync::Arc;
+
+/// Writes the items to a Parquet file, one row per item. Only available with the `parquet`
+/// feature.
+///
+/// The columns follow the serde representation of the items: fields become columns and nested
+/// structs become struct columns. Unless it is set with [schema](Parquet::schema), the schema is
+/// inferred from the items of the first row group, fields first seen afterwards are dropped.
+///
+/// ```no_run
+/// # fn example() -> std::io::Result<()> {
+/// use scrappy_do::export::Parquet;
+/// use std::fs::File;
+///
+/// let exporter = Parquet::new(File::create("items.parquet")?).row_group_size(50_000);
+/// # Ok(())
+/// # }
+/// ```
+pub struct Parquet<W: Write + Send> {
+    state: State<W>,
+    schema: Option<SchemaRef>,
+    row_group_size: usize,
+    rows: Vec<Value>,
+}
+
+enum State<W: Write + Send> {
+    Pending(W),
+    Writing(Box<ArrowWriter<W>>, Decoder),
+    Finished(W),
+    // A write failed halfway and took the writer with it
+    Failed,
+}
+
+impl<W: Write + Send> Parquet<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            state: State::Pending(writer),
+            schema: None,
+            row_group_size: 10_000,
+            rows: Vec::new(),
+        }
+    }
+
+    /// Use `schema` instead of inferring it from the items.
+    pub fn schema(mut self, schema: SchemaRef) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// The number of items buffered in memory and written together as a row group. Defaults to
+    /// 10,000.
+    pub fn row_group_size(mut self, row_group_size: usize) -> Self {
+        self.row_group_size = row_group_size.max(1);
+        self
+    }
+
+    /// Unwrap the underlying writer. Call [finish](Exporter::finish) first, or the file is
+    /// incomplete.
+    pub fn into_inner(self) -> Result<W, ExportError> {
+        match self.state {
+            State::Pending(writer) | State::Finished(writer) => Ok(writer),
+            State::Writing(writer, _) => writer.into_inner().map_err(backend),
+            State::Failed => Err(ExportError::Backend(
+                "the writer was lost to an earlier error".to_string(),
+            )),
+        }
+    }
+
+    fn write<I: ScrapeItem>(&mut self, item: &I) -> Result<(), ExportError> {
+        self.rows.push(serde_json::to_value(item)?);
+        if self.rows.len() >= self.row_group_size {
+            self.write_row_group()?;
+        }
+        Ok(())
+    }
+
+    fn write_row_group(&mut self) -> Result<(), ExportError> {
+        if self.rows.is_empty() && self.schema.is_none() {
+            return Ok(());
+        }
+        if let State::Pending(_) = self.state {
+            if let State::Pending(writer) = mem::replace(&mut self.state, State::Failed) {
+                self.state = self.start(writer)?;
+            }
+        }
+        let rows = mem::take(&mut self.rows);
+        if let State::Writing(writer, decoder) = &mut self.state {
+            decoder.serialize(&rows).map_err(backend)?;
+            if let Some(batch) = decoder.flush().map_err(backend)? {
+                writer.write(&batch).map_err(backend)?;
+            }
+            writer.flush().map_err(backend)?;
+        }
+        Ok(())
+    }
+
+    fn start(&self, writer: W) -> Result<State<W>, ExportError> {
+        let schema = match &self.schema {
+            Some(schema) => schema.clone(),
+            None => Arc::new(
+                infer_json_schema_from_iterator(self.rows.iter().map(Ok)).map_err(backend)?,
+            ),
+        };
+        let properties = WriterProperties::builder()
+            .set_max_row_group_size(self.row_group_size)
+            .build();
+        let decoder = ReaderBuilder::new(schema.clone())
+            .with_batch_size(self.row_group_size)
+            .build_decoder()
+            .map_err(backend)?;
+        let writer = ArrowWriter::try_new(writer, schema, Some(properties)).map_err(backend)?;
+        Ok(State::Writing(Box::new(writer), decoder))
+    }
+
+    fn close(&mut self) -> Result<(), ExportError> {
+        self.write_row_group()?;
+        if let State::Writing(..) = self.state {
+            if let State::Writing(writer, _) = mem::replace(&mut self.state, State::Failed) {
+                self.state = State::Finished(writer.into_inner().map_err(backend)?);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write + Send> Debug for Parquet<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Parquet")
+            .field("schema", &self.schema)
+            .field("row_group_size", &self.row_group_size)
+            .field("buffered_rows", &self.rows.len())
+            .finish()
+    }
+}
+
+impl<I: ScrapeItem, W: Write + Send> Exporter<I> for Parquet<W> {
+    fn export<'a>(&'a mut self, item: &'a I) -> BoxFuture<'a, Result<(), ExportError>> {
+        Box::pin(future::ready(self.write(item)))
+    }
+
+    fn finish(&mut self) -> BoxFuture<'_, Result<(), ExportError>> {
+        Box::pin(future::ready(self.close()))
+    }
+}
+
+fn backend<E: ToString>(err: E) -> ExportError {
+    ExportError::Backend(err.to_string())
+}
@@ -0,0 +1,160 @@
+use super::{ExportError, Exporter};
+use crate::item::ScrapeItem;
+use futures::future::{self, BoxFuture};
+use serde_json::Value;
+use std::io::{self, Stdout, Write};
+
+/// How [Pretty](Pretty) prints the items.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrettyFormat {
+    /// The multi-line `{:#?}` representation.
+    Debug,
+    /// Indented JSON.
+    Json,
+    /// An aligned table with one column per field. Nested fields are selected with dotted paths,
+    /// like `price.amount`.
+    Table(Vec<String>),
+}
+
+/// Prints the items for people rather than programs, for quick interactive runs.
+///
+/// Tables are printed a page at a time so the columns can be aligned, long values are truncated.
+///
+/// ```no_run
+/// use scrappy_do::export::{Pretty, PrettyFormat};
+///
+/// let exporter = Pretty::new(PrettyFormat::Table(vec!["sku".into(), "price.amount".into()]))
+///     .page_size(50);
+/// ```
+#[derive(Debug)]
+pub struct Pretty<W = Stdout> {
+    writer: W,
+    format: PrettyFormat,
+    page_size: usize,
+    max_width: usize,
+    rows: Vec<Vec<String>>,
+}
+
+impl Pretty<Stdout> {
+    /// Print the items to the standard output.
+    pub fn new(format: PrettyFormat) -> Self {
+        Self::with_writer(format, io::stdout())
+    }
+}
+
+impl<W: Write + Send> Pretty<W> {
+    /// Print the items to `writer`.
+    pub fn with_writer(format: PrettyFormat, writer: W) -> Self {
+        Self {
+            writer,
+            format,
+            page_size: 20,
+            max_width: 40,
+            rows: Vec::new(),
+        }
+    }
+
+    /// The number of rows printed under each table header. Defaults to 20.
+    pub fn page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size.max(1);
+        self
+    }
+
+    /// The maximum number of characters of a table cell. Defaults to 40.
+    pub fn max_width(mut self, max_width: usize) -> Self {
+        self.max_width = max_width.max(1);
+        self
+    }
+
+    /// Unwrap the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write<I: ScrapeItem>(&mut self, item: &I) -> Result<(), ExportError> {
+        match &self.format {
+            PrettyFormat::Debug => writeln!(self.writer, "{:#?}", item)?,
+            PrettyFormat::Json => {
+                serde_json::to_writer_pretty(&mut self.writer, item)?;
+                writeln!(self.writer)?;
+            }
+            PrettyFormat::Table(fields) => {
+                let value = serde_json::to_value(item)?;
+                let row = fields
+                    .iter()
+                    .map(|field| truncate(cell(&value, field), self.max_width))
+                    .collect();
+                self.rows.push(row);
+                if self.rows.len() >= self.page_size {
+                    self.write_page()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_page(&mut self) -> Result<(), ExportError> {
+        let fields = match &self.format {
+            PrettyFormat::Table(fields) if !self.rows.is_empty() => fields,
+            _ => return Ok(()),
+        };
+        let widths: Vec<usize> = fields
+            .iter()
+            .enumerate()
+            .map(|(column, field)| {
+                self.rows
+                    .iter()
+                    .map(|row| row[column].chars().count())
+                    .chain(std::iter::once(field.chars().count()))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let header: Vec<String> = fields.iter().map(|field| field.to_string()).collect();
+        let separator: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+        for row in [header, separator].iter().chain(self.rows.iter()) {
+            let line: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            writeln!(self.writer, "{}", line.join("  ").trim_end())?;
+        }
+        writeln!(self.writer)?;
+        self.rows.clear();
+        Ok(())
+    }
+}
+
+impl<I: ScrapeItem, W: Write + Send> Exporter<I> for Pretty<W> {
+    fn export<'a>(&'a mut self, item: &'a I) -> BoxFuture<'a, Result<(), ExportError>> {
+        Box::pin(future::ready(self.write(item)))
+    }
+
+    fn finish(&mut self) -> BoxFuture<'_, Result<(), ExportError>> {
+        let result = self
+            .write_page()
+            .and_then(|_| self.writer.flush().map_err(ExportError::from));
+        Box::pin(future::ready(result))
+    }
+}
+
+/// The text of the field at the dotted `path` of `value`. Strings are printed without quotes.
+fn cell(value: &Value, path: &str) -> String {
+    match path.split('.').try_fold(value, |value, key| value.get(key)) {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(text)) => text.clone(),
+        Some(value) => value.to_string(),
+    }
+}
+
+fn truncate(text: String, max_width: usize) -> String {
+    // Table rows must stay on a single line
+    let text = text.replace(['\n', '\r', '\t'], " ");
+    if text.chars().count() <= max_width {
+        return text;
+    }
+    let mut truncated: String = text.chars().take(max_width - 1).collect();
+    truncated.push('…');
+    truncated
+}
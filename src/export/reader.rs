@@ -0,0 +1,124 @@
+use crate::item::ScrapeItem;
+use futures::Stream;
+use pin_project::pin_project;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncBufRead, AsyncRead, ReadBuf};
+
+/// Reads a stream of items, like a [Crawl](crate::Crawl), as lines of JSON. The items are
+/// serialized as they are read, so the crawl is paced by the reader: a slow HTTP client or
+/// subprocess holds the crawl back rather than the items piling up in memory.
+///
+/// An item that fails to serialize ends the reader with an
+/// [InvalidData](std::io::ErrorKind::InvalidData) error.
+///
+/// ```
+/// # #[tokio::main]
+/// # async fn main() -> std::io::Result<()> {
+/// use futures::stream;
+/// use scrappy_do::export::JsonLinesReader;
+/// use scrappy_do::ScrapeItem;
+/// use serde::Serialize;
+/// use tokio::io::AsyncReadExt;
+///
+/// #[derive(Debug, Serialize, ScrapeItem)]
+/// struct Quote {
+///     text: String,
+/// }
+///
+/// // A crawl, like `web.crawl().await`, is read the same way
+/// let quotes = stream::iter(vec![
+///     Quote { text: "Hello".to_string() },
+///     Quote { text: "World".to_string() },
+/// ]);
+/// let mut ndjson = String::new();
+/// JsonLinesReader::new(quotes).read_to_string(&mut ndjson).await?;
+/// assert_eq!(ndjson, "{\"text\":\"Hello\"}\n{\"text\":\"World\"}\n");
+/// # Ok(())
+/// # }
+/// ```
+#[pin_project]
+#[derive(Debug)]
+pub struct JsonLinesReader<S> {
+    #[pin]
+    items: S,
+    // The serialized item being read, and how much of it was read
+    line: Vec<u8>,
+    position: usize,
+    done: bool,
+}
+
+impl<S> JsonLinesReader<S> {
+    pub fn new(items: S) -> Self {
+        Self {
+            items,
+            line: Vec::new(),
+            position: 0,
+            done: false,
+        }
+    }
+
+    /// Unwrap the underlying stream, like a [Crawl](crate::Crawl) whose
+    /// [summary](crate::Crawl::summary) is available once the reader reached its end. The line
+    /// being read, if any, is lost.
+    pub fn into_inner(self) -> S {
+        self.items
+    }
+}
+
+impl<I, S> AsyncBufRead for JsonLinesReader<S>
+where
+    I: ScrapeItem,
+    S: Stream<Item = I>,
+{
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let mut this = self.project();
+        while *this.position == this.line.len() && !*this.done {
+            match this.items.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    this.line.clear();
+                    *this.position = 0;
+                    if let Err(err) = serde_json::to_writer(&mut *this.line, &item) {
+                        *this.done = true;
+                        this.line.clear();
+                        return Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidData, err)));
+                    }
+                    this.line.push(b'\n');
+                }
+                Poll::Ready(None) => *this.done = true,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(&this.line[*this.position..]))
+    }
+
+    fn consume(self: Pin<&mut Self>, amount: usize) {
+        let this = self.project();
+        *this.position = (*this.position + amount).min(this.line.len());
+    }
+}
+
+impl<I, S> AsyncRead for JsonLinesReader<S>
+where
+    I: ScrapeItem,
+    S: Stream<Item = I>,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let read = match self.as_mut().poll_fill_buf(cx) {
+            Poll::Ready(Ok(line)) => {
+                let read = line.len().min(buf.remaining());
+                buf.put_slice(&line[..read]);
+                read
+            }
+            Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+            Poll::Pending => return Poll::Pending,
+        };
+        self.consume(read);
+        Poll::Ready(Ok(()))
+    }
+}
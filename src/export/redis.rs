@@ -0,0 +1,121 @@
+use super::{ExportError, Exporter};
+use crate::item::ScrapeItem;
+use futures::future::BoxFuture;
+use redis::aio::ConnectionManager;
+use std::fmt::{self, Debug};
+use std::mem;
+
+/// Pushes the items to a Redis list or stream as JSON. Only available with the `redis` feature.
+///
+/// Items pushed to a list with `LPUSH` are consumed in order with `RPOP`/`BRPOP`, like the item
+/// queues of scrapy-redis deployments. Items added to a stream with `XADD` are stored in the
+/// `item` field of their entry.
+///
+/// The connection manager can be cloned and shared with [RedisStore](crate::pipeline::RedisStore)
+/// so a crawl uses a single connection.
+///
+/// ```no_run
+/// # async fn example() -> redis::RedisResult<()> {
+/// use redis::{aio::ConnectionManager, Client};
+/// use scrappy_do::export::Redis;
+///
+/// let connection = ConnectionManager::new(Client::open("redis://localhost")?).await?;
+/// let exporter = Redis::stream(connection, "products:items").max_len(1_000_000);
+/// # Ok(())
+/// # }
+/// ```
+pub struct Redis {
+    connection: ConnectionManager,
+    key: String,
+    stream: bool,
+    max_len: Option<usize>,
+    batch_size: usize,
+    pending: Vec<String>,
+}
+
+impl Redis {
+    /// Push the items to the list at `key`.
+    pub fn list<S: Into<String>>(connection: ConnectionManager, key: S) -> Self {
+        Self::new(connection, key.into(), false)
+    }
+
+    /// Add the items to the stream at `key`.
+    pub fn stream<S: Into<String>>(connection: ConnectionManager, key: S) -> Self {
+        Self::new(connection, key.into(), true)
+    }
+
+    fn new(connection: ConnectionManager, key: String, stream: bool) -> Self {
+        Self {
+            connection,
+            key,
+            stream,
+            max_len: None,
+            batch_size: 100,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Trim the stream to about `max_len` entries. Ignored for lists.
+    pub fn max_len(mut self, max_len: usize) -> Self {
+        self.max_len = Some(max_len);
+        self
+    }
+
+    /// The number of items sent per round trip. Defaults to 100.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    async fn flush(&mut self) -> Result<(), ExportError> {
+        let items = mem::take(&mut self.pending);
+        if items.is_empty() {
+            return Ok(());
+        }
+        let mut pipe = redis::pipe();
+        if self.stream {
+            for item in &items {
+                let command = pipe.cmd("XADD").arg(&self.key);
+                if let Some(max_len) = self.max_len {
+                    command.arg("MAXLEN").arg("~").arg(max_len);
+                }
+                command.arg("*").arg("item").arg(item).ignore();
+            }
+        } else {
+            pipe.cmd("LPUSH").arg(&self.key).arg(&items).ignore();
+        }
+        pipe.query_async::<_, ()>(&mut self.connection)
+            .await
+            .map_err(|err| ExportError::Backend(err.to_string()))
+    }
+}
+
+impl Debug for Redis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Redis")
+            .field("key", &self.key)
+            .field("stream", &self.stream)
+            .field("max_len", &self.max_len)
+            .field("batch_size", &self.batch_size)
+            .field("pending", &self.pending.len())
+            .finish()
+    }
+}
+
+impl<I: ScrapeItem> Exporter<I> for Redis {
+    fn export<'a>(&'a mut self, item: &'a I) -> BoxFuture<'a, Result<(), ExportError>> {
+        // Serialized up front, items aren't required to be `Sync`
+        let serialized = serde_json::to_string(item);
+        Box::pin(async move {
+            self.pending.push(serialized?);
+            if self.pending.len() >= self.batch_size {
+                self.flush().await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn finish(&mut self) -> BoxFuture<'_, Result<(), ExportError>> {
+        Box::pin(self.flush())
+    }
+}
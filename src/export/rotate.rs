@@ -0,0 +1,281 @@
+use super::{ExportError, Exporter};
+use crate::item::ScrapeItem;
+use futures::future::{self, BoxFuture};
+use std::fmt::{self, Debug};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::task::JoinHandle;
+
+/// How [Rotating](Rotating) compresses the chunks it completed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Gzip, appending `.gz` to the name of the chunk.
+    Gzip,
+    /// Zstandard, appending `.zst` to the name of the chunk. Only available with the `zstd`
+    /// feature.
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+/// The file of a single chunk, handed to the exporter writing it.
+#[derive(Debug)]
+pub struct ChunkWriter {
+    file: BufWriter<File>,
+    written: Arc<AtomicU64>,
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.file.write(buf)?;
+        self.written.fetch_add(written as u64, Ordering::Relaxed);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Splits the output of a file exporter into chunks, for crawls running long enough that a single
+/// file becomes unwieldy.
+///
+/// A new chunk is started, with a new exporter created by the factory, once the current one
+/// exceeds [max_bytes](Rotating::max_bytes) or [max_age](Rotating::max_age). Both limits are
+/// checked before an item is written, so chunks always end on an item boundary. Completed chunks
+/// can be [compressed](Rotating::compress) in the background.
+///
+/// The chunks are named after `pattern`, where `{n}` is replaced by the number of the chunk and
+/// `{time}` by the Unix time it was started at. Without `{n}` the number is appended to the name.
+///
+/// ```no_run
+/// use scrappy_do::export::{Compression, JsonLines, Rotating};
+/// use std::time::Duration;
+///
+/// let exporter = Rotating::new("items-{n}.jsonl", JsonLines::new)
+///     .max_bytes(256 * 1024 * 1024)
+///     .max_age(Duration::from_secs(3600))
+///     .compress(Compression::Gzip);
+/// ```
+pub struct Rotating<E, F> {
+    pattern: String,
+    factory: F,
+    max_bytes: Option<u64>,
+    max_age: Option<Duration>,
+    compression: Option<Compression>,
+    next: u64,
+    current: Option<Chunk<E>>,
+    // The compressions of the completed chunks, until the exporter finishes
+    compressions: Vec<JoinHandle<io::Result<()>>>,
+}
+
+struct Chunk<E> {
+    exporter: E,
+    path: PathBuf,
+    written: Arc<AtomicU64>,
+    started: Instant,
+}
+
+impl<E, F> Rotating<E, F>
+where
+    F: FnMut(ChunkWriter) -> E,
+{
+    pub fn new<S: Into<String>>(pattern: S, factory: F) -> Self {
+        Self {
+            pattern: pattern.into(),
+            factory,
+            max_bytes: None,
+            max_age: None,
+            compression: None,
+            next: 0,
+            current: None,
+            compressions: Vec::new(),
+        }
+    }
+
+    /// Start a new chunk once the current one holds `max_bytes`, before compression.
+    pub fn max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Start a new chunk once the current one was started `max_age` ago.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Compress every completed chunk, replacing the uncompressed file.
+    pub fn compress(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    fn is_due(&self, chunk: &Chunk<E>) -> bool {
+        let max_bytes = self
+            .max_bytes
+            .map(|max| chunk.written.load(Ordering::Relaxed) >= max)
+            .unwrap_or(false);
+        let max_age = self
+            .max_age
+            .map(|max| chunk.started.elapsed() >= max)
+            .unwrap_or(false);
+        max_bytes || max_age
+    }
+
+    fn chunk_path(&self) -> PathBuf {
+        let number = format!("{:05}", self.next);
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            .to_string();
+        let mut name = self.pattern.replace("{time}", &time);
+        if name.contains("{n}") {
+            name = name.replace("{n}", &number);
+        } else {
+            let path = Path::new(&name);
+            name = match (path.file_stem(), path.extension()) {
+                (Some(stem), Some(extension)) => path
+                    .with_file_name(format!(
+                        "{}-{}.{}",
+                        stem.to_string_lossy(),
+                        number,
+                        extension.to_string_lossy()
+                    ))
+                    .to_string_lossy()
+                    .into_owned(),
+                _ => format!("{}-{}", name, number),
+            };
+        }
+        PathBuf::from(name)
+    }
+
+    fn open(&mut self) -> io::Result<Chunk<E>> {
+        let path = self.chunk_path();
+        let written = Arc::new(AtomicU64::new(0));
+        let writer = ChunkWriter {
+            file: BufWriter::new(File::create(&path)?),
+            written: written.clone(),
+        };
+        self.next += 1;
+        Ok(Chunk {
+            exporter: (self.factory)(writer),
+            path,
+            written,
+            started: Instant::now(),
+        })
+    }
+}
+
+impl<E, F> Debug for Rotating<E, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Rotating")
+            .field("pattern", &self.pattern)
+            .field("max_bytes", &self.max_bytes)
+            .field("max_age", &self.max_age)
+            .field("compression", &self.compression)
+            .field("current", &self.current.as_ref().map(|chunk| &chunk.path))
+            .finish()
+    }
+}
+
+impl<I, E, F> Exporter<I> for Rotating<E, F>
+where
+    I: ScrapeItem,
+    E: Exporter<I>,
+    F: FnMut(ChunkWriter) -> E + Send,
+{
+    fn export<'a>(&'a mut self, item: &'a I) -> BoxFuture<'a, Result<(), ExportError>> {
+        // The completed chunk is moved out so only the exporter's future borrows `self`
+        let (completed, current) = match self.current.take() {
+            Some(chunk) if !self.is_due(&chunk) => (None, chunk),
+            completed => match self.open() {
+                Ok(chunk) => (completed, chunk),
+                Err(err) => {
+                    // Kept open, to try rotating again with the next item
+                    self.current = completed;
+                    return Box::pin(future::ready(Err(err.into())));
+                }
+            },
+        };
+        let compression = self.compression;
+        let compressions = &mut self.compressions;
+        let export = self.current.insert(current).exporter.export(item);
+        Box::pin(async move {
+            if let Some(chunk) = completed {
+                complete(chunk, compression, compressions).await?;
+            }
+            export.await
+        })
+    }
+
+    fn finish(&mut self) -> BoxFuture<'_, Result<(), ExportError>> {
+        Box::pin(async move {
+            if let Some(chunk) = self.current.take() {
+                complete(chunk, self.compression, &mut self.compressions).await?;
+            }
+            for compression in std::mem::take(&mut self.compressions) {
+                compression
+                    .await
+                    .map_err(|err| ExportError::Backend(err.to_string()))??;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Finish writing `chunk` and start compressing it.
+async fn complete<I, E>(
+    mut chunk: Chunk<E>,
+    compression: Option<Compression>,
+    compressions: &mut Vec<JoinHandle<io::Result<()>>>,
+) -> Result<(), ExportError>
+where
+    I: ScrapeItem,
+    E: Exporter<I>,
+{
+    chunk.exporter.finish().await?;
+    // Closes the file
+    drop(chunk.exporter);
+    if let Some(compression) = compression {
+        let path = chunk.path;
+        let handle = tokio::task::spawn_blocking(move || compress(&path, compression));
+        compressions.push(handle);
+    }
+    Ok(())
+}
+
+fn compress(path: &Path, compression: Compression) -> io::Result<()> {
+    let extension = match compression {
+        Compression::Gzip => "gz",
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => "zst",
+    };
+    let mut compressed_path = path.as_os_str().to_owned();
+    compressed_path.push(".");
+    compressed_path.push(extension);
+
+    let mut source = File::open(path)?;
+    let destination = BufWriter::new(File::create(&compressed_path)?);
+    match compression {
+        Compression::Gzip => {
+            let mut encoder =
+                flate2::write::GzEncoder::new(destination, flate2::Compression::default());
+            io::copy(&mut source, &mut encoder)?;
+            encoder.finish()?.flush()?;
+        }
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => {
+            let mut encoder = zstd::stream::Encoder::new(destination, 0)?;
+            io::copy(&mut source, &mut encoder)?;
+            encoder.finish()?.flush()?;
+        }
+    }
+    fs::remove_file(path)
+}
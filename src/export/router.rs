@@ -0,0 +1,164 @@
+use super::{ExportError, Exporter};
+use crate::item::ScrapeItem;
+use futures::future::{self, BoxFuture};
+use std::fmt::{self, Debug};
+use std::marker::PhantomData;
+
+/// Sends every kind of item to its own exporter, for crawls producing several item types wrapped
+/// in an enum.
+///
+/// Every item is written by the first route whose selector matches it, and by the
+/// [fallback](Router::fallback) if none does. Items matching no route are dropped when there is no
+/// fallback.
+///
+/// ```no_run
+/// # fn example() -> std::io::Result<()> {
+/// use scrappy_do::export::{JsonLines, Router};
+/// use scrappy_do::ScrapeItem;
+/// use serde::Serialize;
+/// use std::fs::File;
+///
+/// #[derive(Debug, Serialize, ScrapeItem)]
+/// struct Product {
+///     sku: String,
+/// }
+///
+/// #[derive(Debug, Serialize, ScrapeItem)]
+/// struct PriceSnapshot {
+///     sku: String,
+///     price: f64,
+/// }
+///
+/// #[derive(Debug, Serialize, ScrapeItem)]
+/// enum Item {
+///     Product(Product),
+///     PriceSnapshot(PriceSnapshot),
+/// }
+///
+/// fn product(item: &Item) -> Option<&Product> {
+///     match item {
+///         Item::Product(product) => Some(product),
+///         _ => None,
+///     }
+/// }
+///
+/// fn price_snapshot(item: &Item) -> Option<&PriceSnapshot> {
+///     match item {
+///         Item::PriceSnapshot(snapshot) => Some(snapshot),
+///         _ => None,
+///     }
+/// }
+///
+/// let exporter = Router::new()
+///     .route(product, JsonLines::new(File::create("products.jsonl")?))
+///     .route(price_snapshot, JsonLines::new(File::create("prices.jsonl")?));
+/// # Ok(())
+/// # }
+/// ```
+pub struct Router<I> {
+    routes: Vec<Box<dyn Route<I>>>,
+    fallback: Option<Box<dyn Exporter<I>>>,
+}
+
+impl<I: ScrapeItem> Router<I> {
+    pub fn new() -> Self {
+        Self {
+            routes: Vec::new(),
+            fallback: None,
+        }
+    }
+
+    /// Write the items for which `select` returns a value with `exporter`.
+    pub fn route<T, E, F>(mut self, select: F, exporter: E) -> Self
+    where
+        T: ScrapeItem,
+        E: Exporter<T> + 'static,
+        F: Fn(&I) -> Option<&T> + Send + 'static,
+    {
+        self.routes.push(Box::new(Typed {
+            select,
+            exporter,
+            item: PhantomData,
+        }));
+        self
+    }
+
+    /// Write the items matching no route with `exporter`.
+    pub fn fallback<E: Exporter<I> + 'static>(mut self, exporter: E) -> Self {
+        self.fallback = Some(Box::new(exporter));
+        self
+    }
+}
+
+impl<I: ScrapeItem> Default for Router<I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I> Debug for Router<I> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Router")
+            .field("routes", &self.routes.len())
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+impl<I: ScrapeItem> Exporter<I> for Router<I> {
+    fn export<'a>(&'a mut self, item: &'a I) -> BoxFuture<'a, Result<(), ExportError>> {
+        for route in self.routes.iter_mut() {
+            if let Some(export) = route.export(item) {
+                return export;
+            }
+        }
+        match &mut self.fallback {
+            Some(fallback) => fallback.export(item),
+            None => Box::pin(future::ready(Ok(()))),
+        }
+    }
+
+    fn finish(&mut self) -> BoxFuture<'_, Result<(), ExportError>> {
+        Box::pin(async move {
+            // Every exporter gets to flush even if another one failed
+            let mut result = Ok(());
+            for route in self.routes.iter_mut() {
+                result = result.and(route.finish().await);
+            }
+            if let Some(fallback) = &mut self.fallback {
+                result = result.and(fallback.finish().await);
+            }
+            result
+        })
+    }
+}
+
+/// A route erasing the type of the items it exports.
+trait Route<I>: Send {
+    /// Export `item` if it belongs to the route.
+    fn export<'a>(&'a mut self, item: &'a I) -> Option<BoxFuture<'a, Result<(), ExportError>>>;
+
+    fn finish(&mut self) -> BoxFuture<'_, Result<(), ExportError>>;
+}
+
+struct Typed<T, E, F> {
+    select: F,
+    exporter: E,
+    item: PhantomData<fn() -> T>,
+}
+
+impl<I, T, E, F> Route<I> for Typed<T, E, F>
+where
+    T: ScrapeItem,
+    E: Exporter<T>,
+    F: Fn(&I) -> Option<&T> + Send,
+{
+    fn export<'a>(&'a mut self, item: &'a I) -> Option<BoxFuture<'a, Result<(), ExportError>>> {
+        let item = (self.select)(item)?;
+        Some(self.exporter.export(item))
+    }
+
+    fn finish(&mut self) -> BoxFuture<'_, Result<(), ExportError>> {
+        self.exporter.finish()
+    }
+}
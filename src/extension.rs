@@ -0,0 +1,133 @@
+use crate::crawl::CrawlSummary;
+use crate::response::ScrapedResponse;
+use reqwest::Request;
+use slog::{info, Logger};
+use std::fmt::Debug;
+use std::num::NonZeroUsize;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+use url::Url;
+use uuid::Uuid;
+
+/// Adds cross-cutting behavior to a crawl by hooking into its lifecycle.
+///
+/// Extensions are registered with [extension](crate::WebBuilder::extension) and called in the
+/// order they were registered. Every hook defaults to doing nothing. Hooks are called from the
+/// crawl's tasks, so they should return quickly.
+///
+/// ```
+/// use scrappy_do::{CrawlSummary, Extension};
+/// use std::sync::atomic::{AtomicU64, Ordering};
+///
+/// #[derive(Debug, Default)]
+/// struct CountItems(AtomicU64);
+///
+/// impl Extension for CountItems {
+///     fn item_scraped(&self, _item: &dyn std::fmt::Debug) {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///     }
+///
+///     fn crawl_finished(&self, _summary: &CrawlSummary) {
+///         println!("{} items", self.0.load(Ordering::Relaxed));
+///     }
+/// }
+/// ```
+pub trait Extension: Send + Sync + Debug {
+    /// Called once before the first request is sent.
+    fn crawl_started(&self, _run_id: Uuid, _logger: &Logger) {}
+
+    /// Called when a request is about to be sent.
+    fn request_scheduled(&self, _request: &Request) {}
+
+    /// Called when a response was received, before it is handed to its handler.
+    fn response_received(&self, _response: &ScrapedResponse) {}
+
+    /// Called for every item produced by a handler, before it is queued.
+    fn item_scraped(&self, _item: &dyn Debug) {}
+
+    /// Called once the handler named `handler` finished processing the response to a request for
+    /// `url`, with the number of items and callbacks it produced.
+    fn response_handled(&self, _handler: &str, _url: &Url, _items: u64, _callbacks: u64) {}
+
+    /// Called once after every callback has finished.
+    fn crawl_finished(&self, _summary: &CrawlSummary) {}
+}
+
+/// Lets the caller keep a handle on an extension, to read its state during or after the crawl.
+impl<E: Extension + ?Sized> Extension for Arc<E> {
+    fn crawl_started(&self, run_id: Uuid, logger: &Logger) {
+        (**self).crawl_started(run_id, logger)
+    }
+
+    fn request_scheduled(&self, request: &Request) {
+        (**self).request_scheduled(request)
+    }
+
+    fn response_received(&self, response: &ScrapedResponse) {
+        (**self).response_received(response)
+    }
+
+    fn item_scraped(&self, item: &dyn Debug) {
+        (**self).item_scraped(item)
+    }
+
+    fn response_handled(&self, handler: &str, url: &Url, items: u64, callbacks: u64) {
+        (**self).response_handled(handler, url, items, callbacks)
+    }
+
+    fn crawl_finished(&self, summary: &CrawlSummary) {
+        (**self).crawl_finished(summary)
+    }
+}
+
+/// Periodically logs the progress of a crawl at the info level.
+#[derive(Debug)]
+pub struct LogStats {
+    every: u64,
+    logger: Mutex<Option<Logger>>,
+    requests: AtomicU64,
+    responses: AtomicU64,
+    items: AtomicU64,
+}
+
+impl LogStats {
+    /// Log the progress every `every` responses.
+    pub fn new(every: NonZeroUsize) -> Self {
+        Self {
+            every: every.get() as u64,
+            logger: Mutex::new(None),
+            requests: AtomicU64::new(0),
+            responses: AtomicU64::new(0),
+            items: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Extension for LogStats {
+    fn crawl_started(&self, _run_id: Uuid, logger: &Logger) {
+        *self.logger.lock().expect("logger lock") = Some(logger.clone());
+    }
+
+    fn request_scheduled(&self, _request: &Request) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn response_received(&self, _response: &ScrapedResponse) {
+        let responses = self.responses.fetch_add(1, Ordering::Relaxed) + 1;
+        if !responses.is_multiple_of(self.every) {
+            return;
+        }
+        if let Some(logger) = &*self.logger.lock().expect("logger lock") {
+            info!(logger, "Crawl progress";
+                  "requests" => self.requests.load(Ordering::Relaxed),
+                  "responses" => responses,
+                  "items" => self.items.load(Ordering::Relaxed));
+        }
+    }
+
+    fn item_scraped(&self, _item: &dyn Debug) {
+        self.items.fetch_add(1, Ordering::Relaxed);
+    }
+}
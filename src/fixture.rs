@@ -0,0 +1,412 @@
+//! Regression tests for handlers, replayed from captured responses.
+//!
+//! A fixture is a JSON golden file holding a request, the response captured for it, and what a
+//! handler produced from that response: the items, serialized to JSON, and the callbacks.
+//! [Fixtures::import_har](Fixtures::import_har) turns the exchanges of a HAR archive, like the
+//! ones saved by a [FailureCapture](crate::FailureCapture) or exported by a browser, into
+//! fixtures. [Fixtures::check](Fixtures::check) runs a handler over the response of a fixture and
+//! compares its output with the golden output, so a refactored handler can be checked against
+//! the pages it used to parse.
+//!
+//! The golden output is recorded the first time a fixture is checked, and again whenever the
+//! fixtures are [updated](Fixtures::update), after reviewing an intended change of output.
+//!
+//! ```no_run
+//! # #![feature(generators)]
+//! # use scrappy_do::{handle, wrap, ScrapedResponse};
+//! # use reqwest::Client;
+//! # use serde::Serialize;
+//! # use slog::Logger;
+//! use scrappy_do::fixture::Fixtures;
+//!
+//! #[derive(Debug, Serialize)]
+//! struct Quote {
+//!     text: String,
+//! }
+//!
+//! #[handle(item = Quote)]
+//! fn parse_quotes(client: Client, response: ScrapedResponse, context: (), logger: Logger) {
+//!     // ...
+//! }
+//!
+//! #[tokio::test]
+//! async fn parse_quotes_is_stable() {
+//!     let fixtures = Fixtures::new("tests/fixtures");
+//!     for name in fixtures.names().unwrap() {
+//!         fixtures.check(&name, wrap!(parse_quotes), ()).await.unwrap();
+//!     }
+//! }
+//! ```
+
+use crate::callback::Indeterminate;
+use crate::download::DownloadError;
+use crate::handler::Handler;
+use crate::replay::{self, Recorded, ReplayError};
+use crate::response::{CallbackInfo, FetchMetrics, ScrapedResponse};
+use crate::throttle::{Scheduler, Throttles};
+use crate::util::ParsePool;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use slog::{o, Discard, Logger};
+use std::fmt::Debug;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use thiserror::Error;
+use url::Url;
+
+/// Updates the golden outputs when set, like `SCRAPPY_DO_UPDATE_FIXTURES=1 cargo test`.
+pub const UPDATE_VARIABLE: &str = "SCRAPPY_DO_UPDATE_FIXTURES";
+
+#[derive(Error, Debug)]
+pub enum FixtureError {
+    #[error("the fixture could not be read or written: {0}")]
+    Io(#[from] io::Error),
+    #[error("the fixture is malformed: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("the archive could not be imported: {0}")]
+    Archive(#[from] ReplayError),
+    #[error("the fixture holds an invalid {0} (given: {1})")]
+    Invalid(&'static str, String),
+    #[error("the output of the handler changed for the fixture {0}: {1}")]
+    Changed(String, String),
+    #[error("the handler failed: {0}")]
+    Handler(DownloadError),
+}
+
+/// The outcome of a successful [check](Fixtures::check).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checked {
+    /// The handler produced the golden output.
+    Matched,
+    /// The output of the handler was recorded as the golden output.
+    Recorded,
+}
+
+/// What a handler produced from the response of a fixture.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Output {
+    /// The items, serialized to JSON.
+    pub items: Vec<serde_json::Value>,
+    /// The callbacks, in the order they were produced.
+    pub callbacks: Vec<ProducedCallback>,
+}
+
+/// A callback produced by a handler.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProducedCallback {
+    /// The name of the handler of the callback.
+    pub handler: String,
+    /// The method of the request.
+    pub method: String,
+    /// The URL of the request.
+    pub url: String,
+    /// The [Debug](std::fmt::Debug) representation of the context.
+    pub context: String,
+}
+
+/// A golden file.
+#[derive(Debug, Serialize, Deserialize)]
+struct Fixture {
+    request: FixtureRequest,
+    response: FixtureResponse,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    output: Option<Output>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FixtureRequest {
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FixtureResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    // Base64 encoded when the body isn't text
+    body: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    base64: bool,
+}
+
+/// The fixtures stored in a directory, one JSON file each.
+#[derive(Debug, Clone)]
+pub struct Fixtures {
+    directory: PathBuf,
+    update: bool,
+}
+
+impl Fixtures {
+    /// Use the fixtures stored in `directory`. The golden outputs are updated when the
+    /// [UPDATE_VARIABLE](UPDATE_VARIABLE) environment variable is set.
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Self {
+        Self {
+            directory: directory.into(),
+            update: std::env::var_os(UPDATE_VARIABLE).is_some(),
+        }
+    }
+
+    /// Record the output of the handlers as the golden output, instead of comparing them.
+    pub fn update(mut self, update: bool) -> Self {
+        self.update = update;
+        self
+    }
+
+    /// The names of the fixtures, sorted.
+    pub fn names(&self) -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                if let Some(name) = path.file_stem().and_then(|name| name.to_str()) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Add a fixture for every exchange of a HAR archive, named after `prefix` and the path of
+    /// the request. Existing fixtures of the same name are replaced.
+    ///
+    /// # Returns
+    /// The names of the fixtures.
+    pub fn import_har<R: Read>(
+        &self,
+        prefix: &str,
+        reader: R,
+    ) -> Result<Vec<String>, FixtureError> {
+        fs::create_dir_all(&self.directory)?;
+        let mut names = Vec::new();
+        for (index, exchange) in replay::read_har(reader)?.into_iter().enumerate() {
+            let name = fixture_name(prefix, index, &exchange.url);
+            let (body, base64) = match String::from_utf8(exchange.response.body) {
+                Ok(text) => (text, false),
+                Err(err) => (base64::encode(err.into_bytes()), true),
+            };
+            let fixture = Fixture {
+                request: FixtureRequest {
+                    method: exchange.method.to_string(),
+                    url: exchange.url.to_string(),
+                    headers: header_pairs(&exchange.headers),
+                },
+                response: FixtureResponse {
+                    status: exchange.response.status.as_u16(),
+                    headers: header_pairs(&exchange.response.headers),
+                    body,
+                    base64,
+                },
+                output: None,
+            };
+            self.write(&name, &fixture)?;
+            names.push(name);
+        }
+        Ok(names)
+    }
+
+    /// Run `handler` over the response of the fixture `name`, with `context`, and compare what it
+    /// produced with the golden output. The callbacks are compared but not executed.
+    ///
+    /// # Returns
+    /// Whether the output matched or was recorded, or
+    /// [FixtureError::Changed](FixtureError::Changed) describing the first difference.
+    /// [Failures](crate::Indeterminate::Failed) of the handler are returned as
+    /// [FixtureError::Handler](FixtureError::Handler).
+    pub async fn check<H, I, C>(
+        &self,
+        name: &str,
+        handler: H,
+        context: C,
+    ) -> Result<Checked, FixtureError>
+    where
+        H: Handler<I, C> + 'static,
+        I: Debug + Serialize,
+        C: Debug,
+    {
+        let mut fixture: Fixture = serde_json::from_reader(File::open(self.path(name))?)?;
+        let output = run(&fixture, handler, context).await?;
+        match &fixture.output {
+            Some(golden) if !self.update => match difference(golden, &output) {
+                None => Ok(Checked::Matched),
+                Some(difference) => Err(FixtureError::Changed(name.to_string(), difference)),
+            },
+            _ => {
+                fixture.output = Some(output);
+                self.write(name, &fixture)?;
+                Ok(Checked::Recorded)
+            }
+        }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.directory.join(format!("{}.json", name))
+    }
+
+    fn write(&self, name: &str, fixture: &Fixture) -> Result<(), FixtureError> {
+        let mut json = serde_json::to_vec_pretty(fixture)?;
+        json.push(b'\n');
+        fs::write(self.path(name), json)?;
+        Ok(())
+    }
+}
+
+/// `<prefix>-<index>-<path>`, keeping the name safe to use in a path.
+fn fixture_name(prefix: &str, index: usize, url: &Url) -> String {
+    let path: String = url
+        .path()
+        .trim_matches('/')
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .take(60)
+        .collect();
+    let name = format!("{}-{:03}-{}", prefix, index, path);
+    name.trim_end_matches('-').to_string()
+}
+
+fn header_pairs(headers: &[(HeaderName, HeaderValue)]) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            )
+        })
+        .collect()
+}
+
+fn parse_headers(
+    pairs: &[(String, String)],
+) -> Result<Vec<(HeaderName, HeaderValue)>, FixtureError> {
+    pairs
+        .iter()
+        .map(|(name, value)| {
+            match (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(value),
+            ) {
+                (Ok(name), Ok(value)) => Ok((name, value)),
+                _ => Err(FixtureError::Invalid(
+                    "header",
+                    format!("{}: {}", name, value),
+                )),
+            }
+        })
+        .collect()
+}
+
+/// Run `handler` over the response of `fixture`.
+async fn run<H, I, C>(fixture: &Fixture, handler: H, context: C) -> Result<Output, FixtureError>
+where
+    H: Handler<I, C> + 'static,
+    I: Debug + Serialize,
+    C: Debug,
+{
+    let request = &fixture.request;
+    let method = Method::from_bytes(request.method.as_bytes())
+        .map_err(|_| FixtureError::Invalid("method", request.method.clone()))?;
+    let url =
+        Url::parse(&request.url).map_err(|_| FixtureError::Invalid("URL", request.url.clone()))?;
+    let body = if fixture.response.base64 {
+        base64::decode(&fixture.response.body)
+            .map_err(|err| FixtureError::Invalid("body", err.to_string()))?
+    } else {
+        fixture.response.body.clone().into_bytes()
+    };
+    let recorded = Recorded {
+        status: StatusCode::from_u16(fixture.response.status)
+            .map_err(|_| FixtureError::Invalid("status", fixture.response.status.to_string()))?,
+        headers: parse_headers(&fixture.response.headers)?,
+        body,
+    };
+    let response = recorded.to_response(url.clone());
+    let info = CallbackInfo {
+        url: url.clone(),
+        method,
+        headers: parse_headers(&request.headers)?
+            .into_iter()
+            .collect::<HeaderMap>(),
+        depth: 0,
+        retries: 0,
+        parent: None,
+    };
+    let metrics = FetchMetrics::new(&response, Duration::ZERO);
+    let scheduler = Scheduler::new(
+        Arc::new(Throttles::default()),
+        url.host_str().unwrap_or_default(),
+        Arc::new(Mutex::new(None)),
+        None,
+    );
+    let response = ScrapedResponse::new(
+        response,
+        info,
+        metrics,
+        false,
+        scheduler,
+        None,
+        ParsePool::default(),
+    );
+
+    let logger = Logger::root(Discard, o!());
+    let mut produced = Box::new(handler).handle(Client::new(), response, context, logger);
+    let mut output = Output::default();
+    while let Some(indeterminate) = produced.recv().await {
+        match indeterminate {
+            Indeterminate::Item(item) => output.items.push(serde_json::to_value(&item)?),
+            Indeterminate::Callback(callback) => output.callbacks.push(ProducedCallback {
+                handler: callback.handler_name(),
+                method: callback.target().method().to_string(),
+                url: callback.target().url().to_string(),
+                context: format!("{:?}", callback.context()),
+            }),
+            Indeterminate::Failed(error, _) => return Err(FixtureError::Handler(error)),
+        }
+    }
+    Ok(output)
+}
+
+/// Describes the first difference between the golden output and `output`.
+fn difference(golden: &Output, output: &Output) -> Option<String> {
+    let items = golden.items.iter().zip(&output.items).enumerate();
+    for (index, (expected, produced)) in items {
+        if expected != produced {
+            return Some(format!(
+                "item {} is {}, expected {}",
+                index, produced, expected
+            ));
+        }
+    }
+    if golden.items.len() != output.items.len() {
+        return Some(format!(
+            "{} items were produced, expected {}",
+            output.items.len(),
+            golden.items.len()
+        ));
+    }
+    let callbacks = golden.callbacks.iter().zip(&output.callbacks).enumerate();
+    for (index, (expected, produced)) in callbacks {
+        if expected != produced {
+            return Some(format!(
+                "callback {} is {:?}, expected {:?}",
+                index, produced, expected
+            ));
+        }
+    }
+    if golden.callbacks.len() != output.callbacks.len() {
+        return Some(format!(
+            "{} callbacks were produced, expected {}",
+            output.callbacks.len(),
+            golden.callbacks.len()
+        ));
+    }
+    None
+}
@@ -0,0 +1,154 @@
+use crate::download::{local_response, DownloadError, DownloadHandler};
+use futures::future::BoxFuture;
+use percent_encoding::percent_decode_str;
+use reqwest::{Client, Request, Response, StatusCode};
+use std::net::SocketAddr;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use url::Url;
+
+/// Fetches `ftp://` URLs using passive mode. URLs ending with a `/` are listed, everything else is
+/// retrieved. Credentials are taken from the URL, logging in anonymously otherwise. Files the
+/// server refuses to serve produce a `404` response.
+///
+/// Register it with [scheme_handler](crate::WebBuilder::scheme_handler).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FtpDownloadHandler;
+
+impl DownloadHandler for FtpDownloadHandler {
+    fn download(
+        &self,
+        _client: Client,
+        request: Request,
+    ) -> BoxFuture<'static, Result<Response, DownloadError>> {
+        let url = request.url().clone();
+        Box::pin(fetch(url))
+    }
+}
+
+async fn fetch(url: Url) -> Result<Response, DownloadError> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| DownloadError::Ftp(format!("no host in {}", url)))?;
+    let stream = TcpStream::connect((host, url.port().unwrap_or(21))).await?;
+    let server = stream.peer_addr()?;
+    let mut control = Control {
+        stream: BufReader::new(stream),
+        last_reply: (0, String::new()),
+    };
+    control.expect(&[220]).await?;
+
+    let user = match decode(url.username()) {
+        user if user.is_empty() => "anonymous".to_string(),
+        user => user,
+    };
+    let password = decode(url.password().unwrap_or("anonymous@"));
+    if control.command(&format!("USER {}", user)).await?.0 == 331 {
+        control.command(&format!("PASS {}", password)).await?;
+    }
+    control.check(&[230, 202])?;
+    control.command("TYPE I").await?;
+    control.check(&[200])?;
+
+    let (_, reply) = control.command("PASV").await?;
+    control.check(&[227])?;
+    // The advertised address is often wrong behind NAT, only its port is used
+    let data_port = passive_port(&reply)
+        .ok_or_else(|| DownloadError::Ftp(format!("malformed passive reply {:?}", reply)))?;
+    let mut data = TcpStream::connect(SocketAddr::new(server.ip(), data_port)).await?;
+
+    let path = decode(url.path());
+    let listing = path.ends_with('/');
+    let command = if listing { "LIST" } else { "RETR" };
+    let (code, _) = control.command(&format!("{} {}", command, path)).await?;
+    if code == 550 {
+        return Ok(local_response(url, StatusCode::NOT_FOUND, Vec::new(), None));
+    }
+    control.check(&[125, 150])?;
+    let mut body = Vec::new();
+    data.read_to_end(&mut body).await?;
+    drop(data);
+    control.expect(&[226, 250]).await?;
+    // The transfer is complete, failing to say goodbye doesn't matter
+    let _ = control.command("QUIT").await;
+
+    let content_type = if listing { Some("text/plain") } else { None };
+    Ok(local_response(url, StatusCode::OK, body, content_type))
+}
+
+/// The control connection of an FTP session.
+struct Control {
+    stream: BufReader<TcpStream>,
+    last_reply: (u16, String),
+}
+
+impl Control {
+    /// Send a command and read the reply.
+    async fn command(&mut self, command: &str) -> Result<(u16, String), DownloadError> {
+        self.stream
+            .get_mut()
+            .write_all(format!("{}\r\n", command).as_bytes())
+            .await?;
+        self.read_reply().await
+    }
+
+    /// Read a reply and check its code.
+    async fn expect(&mut self, codes: &[u16]) -> Result<(), DownloadError> {
+        self.read_reply().await?;
+        self.check(codes)
+    }
+
+    /// Check the code of the last reply.
+    fn check(&self, codes: &[u16]) -> Result<(), DownloadError> {
+        let (code, reply) = &self.last_reply;
+        if codes.contains(code) {
+            Ok(())
+        } else {
+            Err(DownloadError::Ftp(reply.clone()))
+        }
+    }
+
+    async fn read_reply(&mut self) -> Result<(u16, String), DownloadError> {
+        let mut reply = String::new();
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.stream.read_line(&mut line).await? == 0 {
+                return Err(DownloadError::Ftp("the connection was closed".to_string()));
+            }
+            reply.push_str(&line);
+            // Multiline replies start with the code followed by a `-` and end with a line
+            // starting with the code followed by a space
+            let code = reply.get(..3).unwrap_or_default();
+            if line.starts_with(code) && line.as_bytes().get(3) != Some(&b'-') {
+                break;
+            }
+        }
+        let code = reply
+            .get(..3)
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| DownloadError::Ftp(format!("malformed reply {:?}", reply)))?;
+        self.last_reply = (code, reply.trim_end().to_string());
+        Ok(self.last_reply.clone())
+    }
+}
+
+/// Extract the port from a `227 Entering Passive Mode (h1,h2,h3,h4,p1,p2)` reply.
+fn passive_port(reply: &str) -> Option<u16> {
+    let start = reply.find('(')?;
+    let end = reply[start..].find(')')? + start;
+    let numbers: Vec<u16> = reply[start + 1..end]
+        .split(',')
+        .map(|number| number.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    match numbers[..] {
+        [_, _, _, _, high, low] if high < 256 && low < 256 => Some(high * 256 + low),
+        _ => None,
+    }
+}
+
+fn decode(component: &str) -> String {
+    percent_decode_str(component)
+        .decode_utf8_lossy()
+        .into_owned()
+}
@@ -1,5 +1,7 @@
 use crate::callback::Indeterminate;
-use reqwest::{Client, Response};
+use crate::download::DownloadError;
+use crate::response::ScrapedResponse;
+use reqwest::{Client, Request};
 use slog::Logger;
 use std::fmt::{self, Debug, Display};
 use tokio::sync::mpsc::Receiver;
@@ -17,8 +19,8 @@ use tokio::sync::mpsc::Receiver;
 /// // Needed for the yield keyword to work
 /// #![feature(generators)]
 ///
-/// use reqwest::{Client, Response};
-/// use scrappy_do::handle;
+/// use reqwest::Client;
+/// use scrappy_do::{handle, ScrapedResponse};
 /// use slog::Logger;
 ///
 /// // This is what we are trying to create from the web pages
@@ -32,7 +34,7 @@ use tokio::sync::mpsc::Receiver;
 /// #[handle(item = SomeItem)]
 /// fn handler_foo(
 ///     client: Client,
-///     response: Response,
+///     response: ScrapedResponse,
 ///     context: SomeContext,
 ///     logger: Logger,
 /// ) {
@@ -59,8 +61,8 @@ use tokio::sync::mpsc::Receiver;
 /// // Needed for the yield keyword to work
 /// #![feature(generators)]
 ///
-/// use reqwest::{Client, Response};
-/// use scrappy_do::{Handler, handle};
+/// use reqwest::Client;
+/// use scrappy_do::{Handler, handle, ScrapedResponse};
 /// use slog::Logger;
 /// use std::fmt;
 ///
@@ -87,7 +89,7 @@ use tokio::sync::mpsc::Receiver;
 ///     #[handle(item = SomeItem)]
 ///     fn handle(self: Box<Self>,
 ///               client: Client,
-///               response: Response,
+///               response: ScrapedResponse,
 ///               context: SomeContext,
 ///               logger: Logger) {
 ///
@@ -102,26 +104,69 @@ pub trait Handler<I: Debug, C>: Send + Sync + Debug + Display {
     fn handle(
         self: Box<Self>,
         client: Client,
-        response: Response,
+        response: ScrapedResponse,
+        context: C,
+        logger: Logger,
+    ) -> Receiver<Indeterminate<I, C>>;
+}
+
+/// Converts the failure of a request into items or HTTP requests, like a fallback request to a
+/// mirror or an item recording the failure. Attached to a callback with
+/// [Callback::errback](crate::Callback::errback).
+///
+/// Error handlers are defined like handlers, with the request and the error it failed with
+/// instead of the response:
+///
+/// ```
+/// // Needed for the yield keyword to work
+/// #![feature(generators)]
+///
+/// use reqwest::{Client, Request};
+/// use scrappy_do::{handle, DownloadError};
+/// use slog::Logger;
+///
+/// #[derive(Debug)]
+/// enum SomeItem {
+///     Page(String),
+///     Failure { url: String, error: String },
+/// }
+///
+/// #[handle(item = SomeItem)]
+/// fn on_error(
+///     client: Client,
+///     request: Request,
+///     error: DownloadError,
+///     context: (),
+///     logger: Logger,
+/// ) {
+///     yield SomeItem::Failure {
+///         url: request.url().to_string(),
+///         error: error.to_string(),
+///     };
+/// }
+/// ```
+///
+/// and wrapped with `wrap!(on_error)` as well.
+pub trait ErrorHandler<I: Debug, C>: Send + Sync + Debug + Display {
+    fn handle_error(
+        self: Box<Self>,
+        client: Client,
+        request: Request,
+        error: DownloadError,
         context: C,
         logger: Logger,
     ) -> Receiver<Indeterminate<I, C>>;
 }
 
 #[doc(hidden)]
+#[derive(Clone, Copy)]
 pub struct HandlerImpl<F> {
     function: F,
     function_name: &'static str,
 }
 
 impl<F> HandlerImpl<F> {
-    pub fn new<I: Debug, C>(function: F, function_name: &'static str) -> Self
-    where
-        F: FnOnce(Client, Response, C, Logger) -> Receiver<Indeterminate<I, C>>
-            + Send
-            + Sync
-            + Copy,
-    {
+    pub fn new(function: F, function_name: &'static str) -> Self {
         Self {
             function,
             function_name,
@@ -145,15 +190,37 @@ impl<F> Display for HandlerImpl<F> {
 
 impl<I: Debug, C, F> Handler<I, C> for HandlerImpl<F>
 where
-    F: FnOnce(Client, Response, C, Logger) -> Receiver<Indeterminate<I, C>> + Send + Sync + Copy,
+    F: FnOnce(Client, ScrapedResponse, C, Logger) -> Receiver<Indeterminate<I, C>>
+        + Send
+        + Sync
+        + Copy,
 {
     fn handle(
         self: Box<Self>,
         client: Client,
-        response: Response,
+        response: ScrapedResponse,
         context: C,
         logger: Logger,
     ) -> Receiver<Indeterminate<I, C>> {
         (self.function)(client, response, context, logger)
     }
 }
+
+impl<I: Debug, C, F> ErrorHandler<I, C> for HandlerImpl<F>
+where
+    F: FnOnce(Client, Request, DownloadError, C, Logger) -> Receiver<Indeterminate<I, C>>
+        + Send
+        + Sync
+        + Copy,
+{
+    fn handle_error(
+        self: Box<Self>,
+        client: Client,
+        request: Request,
+        error: DownloadError,
+        context: C,
+        logger: Logger,
+    ) -> Receiver<Indeterminate<I, C>> {
+        (self.function)(client, request, error, context, logger)
+    }
+}
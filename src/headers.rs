@@ -0,0 +1,74 @@
+use crate::spider::matches_host;
+use reqwest::header::{HeaderName, HeaderValue};
+use reqwest::Request;
+
+/// Headers added to every request of a crawl, with per domain overrides.
+///
+/// Values are templates: `{host}`, `{scheme}`, and `{origin}` are replaced by the parts of the URL
+/// of the request, e.g. `{origin}/` for a `Referer`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct HeaderTemplates {
+    defaults: Vec<(HeaderName, HeaderValue)>,
+    // Checked in order, the first matching pattern wins
+    domains: Vec<(String, Vec<(HeaderName, HeaderValue)>)>,
+}
+
+impl HeaderTemplates {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.defaults.is_empty() && self.domains.is_empty()
+    }
+
+    pub(crate) fn add_default(&mut self, name: HeaderName, template: HeaderValue) {
+        set(&mut self.defaults, name, template);
+    }
+
+    pub(crate) fn add_domain(&mut self, pattern: String, name: HeaderName, template: HeaderValue) {
+        match self
+            .domains
+            .iter_mut()
+            .find(|(existing, _)| *existing == pattern)
+        {
+            Some((_, headers)) => set(headers, name, template),
+            None => self.domains.push((pattern, vec![(name, template)])),
+        }
+    }
+
+    /// Add the headers to `request`. Headers set on the request itself are kept.
+    pub(crate) fn apply(&self, request: &mut Request) {
+        let url = request.url().clone();
+        let host = url.host_str().unwrap_or_default();
+        let domain = self
+            .domains
+            .iter()
+            .find(|(pattern, _)| matches_host(pattern, host))
+            .map(|(_, headers)| headers.as_slice())
+            .unwrap_or_default();
+        // Domain overrides come first so they win over the defaults
+        for (name, template) in domain.iter().chain(&self.defaults) {
+            if request.headers().contains_key(name) {
+                continue;
+            }
+            // Values that aren't text can't hold placeholders
+            let value = match template.to_str() {
+                Ok(template) => HeaderValue::from_str(
+                    &template
+                        .replace("{origin}", &url.origin().ascii_serialization())
+                        .replace("{scheme}", url.scheme())
+                        .replace("{host}", host),
+                ),
+                Err(_) => Ok(template.clone()),
+            };
+            // The template was valid, only a substituted part can make it invalid
+            if let Ok(value) = value {
+                request.headers_mut().insert(name.clone(), value);
+            }
+        }
+    }
+}
+
+fn set(headers: &mut Vec<(HeaderName, HeaderValue)>, name: HeaderName, template: HeaderValue) {
+    match headers.iter_mut().find(|(existing, _)| *existing == name) {
+        Some((_, existing)) => *existing = template,
+        None => headers.push((name, template)),
+    }
+}
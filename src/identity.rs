@@ -0,0 +1,170 @@
+use crate::spider::matches_host;
+use reqwest::{Client, ClientBuilder};
+use std::collections::{hash_map::DefaultHasher, HashMap};
+use std::hash::{Hash, Hasher};
+use std::net::IpAddr;
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+use tokio::sync::Notify;
+use url::Url;
+
+/// Determines how requests are distributed across the clients of a [Web](crate::Web).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    /// Every request uses the next client in line.
+    RoundRobin,
+    /// Requests for the same domain always use the same client.
+    StickyPerDomain,
+}
+
+/// The set of clients ("identities") requests are sent through.
+#[derive(Debug, Clone)]
+pub(crate) struct Identities {
+    clients: Arc<Vec<Client>>,
+    rotation: Rotation,
+    next: Arc<AtomicUsize>,
+    // Clients dedicated to the hosts matching a pattern, bypassing the rotation
+    domain_clients: Arc<Vec<(String, Client)>>,
+    // How many clients to skip for the hosts that were moved to another client
+    offsets: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl Identities {
+    pub(crate) fn new(clients: Vec<Client>, rotation: Rotation) -> Self {
+        assert!(!clients.is_empty(), "at least one client is required");
+        Self {
+            clients: Arc::new(clients),
+            rotation,
+            next: Arc::new(AtomicUsize::new(0)),
+            domain_clients: Arc::new(Vec::new()),
+            offsets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Send the requests to hosts matching a pattern through its client instead.
+    pub(crate) fn with_domain_clients(mut self, domain_clients: Vec<(String, Client)>) -> Self {
+        self.domain_clients = Arc::new(domain_clients);
+        self
+    }
+
+    pub(crate) fn single(client: Client) -> Self {
+        Self::new(vec![client], Rotation::RoundRobin)
+    }
+
+    /// Build one client per local address so requests leave through that address, configured by
+    /// the builders of `client_builder`.
+    pub(crate) fn bound_to<A, F>(
+        addresses: A,
+        rotation: Rotation,
+        client_builder: F,
+    ) -> Result<Self, reqwest::Error>
+    where
+        A: IntoIterator<Item = IpAddr>,
+        F: Fn() -> ClientBuilder,
+    {
+        let clients = addresses
+            .into_iter()
+            .map(|address| client_builder().local_address(address).build())
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(clients, rotation))
+    }
+
+    /// Pick the client used to request `url`.
+    pub(crate) fn select(&self, url: &Url) -> Client {
+        let host = url.host_str().unwrap_or_default();
+        if let Some((_, client)) = self
+            .domain_clients
+            .iter()
+            .find(|(pattern, _)| matches_host(pattern, host))
+        {
+            return client.clone();
+        }
+        let offset = self
+            .offsets
+            .lock()
+            .expect("identities lock")
+            .get(host)
+            .copied()
+            .unwrap_or(0);
+        let index = match self.rotation {
+            Rotation::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed),
+            Rotation::StickyPerDomain => {
+                let mut hasher = DefaultHasher::new();
+                url.host_str().hash(&mut hasher);
+                hasher.finish() as usize
+            }
+        };
+        self.clients[index.wrapping_add(offset) % self.clients.len()].clone()
+    }
+
+    /// Move the requests to `host` to the next client.
+    pub(crate) fn rotate(&self, host: &str) {
+        *self
+            .offsets
+            .lock()
+            .expect("identities lock")
+            .entry(host.to_string())
+            .or_insert(0) += 1;
+    }
+}
+
+/// The identity of a chain of connection-affine callbacks, see
+/// [Callback::affine](crate::Callback::affine). The callbacks of the chain go through the same
+/// client for each host, one at a time, so they reuse its pooled connection.
+#[derive(Debug, Default)]
+pub(crate) struct Affinity {
+    // The client picked for the first callback of the chain to each host
+    clients: Mutex<HashMap<String, Client>>,
+    // Whether a callback of the chain is executing
+    executing: AtomicBool,
+    // Notified once the executing callback of the chain finished
+    turn_ended: Notify,
+}
+
+impl Affinity {
+    /// The client of the chain for `url`, picked from `identities` the first time.
+    pub(crate) fn select(&self, identities: &Identities, url: &Url) -> Client {
+        self.clients
+            .lock()
+            .expect("affinity lock")
+            .entry(url.host_str().unwrap_or_default().to_string())
+            .or_insert_with(|| identities.select(url))
+            .clone()
+    }
+
+    /// Take the turn of the chain, unless another callback of the chain is executing.
+    pub(crate) fn try_turn(self: &Arc<Self>) -> Option<Turn> {
+        self.executing
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .ok()
+            .map(|_| Turn(self.clone()))
+    }
+
+    /// Wait until no callback of the chain is executing.
+    pub(crate) async fn turn_ended(&self) {
+        loop {
+            let notified = self.turn_ended.notified();
+            tokio::pin!(notified);
+            // Registered before checking, so a turn ending meanwhile isn't missed
+            notified.as_mut().enable();
+            if !self.executing.load(Ordering::Acquire) {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// The turn of the executing callback of a connection-affine chain, ended once dropped.
+#[derive(Debug)]
+pub(crate) struct Turn(Arc<Affinity>);
+
+impl Drop for Turn {
+    fn drop(&mut self) {
+        self.0.executing.store(false, Ordering::Release);
+        // Every waiting callback goes back to the queue, the first one dispatched takes the turn
+        self.0.turn_ended.notify_waiters();
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// The number of strings an interner holds before it first drops the unused ones.
+const MIN_SWEEP: usize = 1024;
+
+/// The hosts requested by the crawls of the process, shared by the statistics, throttles,
+/// backoffs, bans and circuits so that each host is stored once.
+static HOSTS: OnceLock<Mutex<Interner>> = OnceLock::new();
+
+/// Stores each string once, handing out shared copies. The strings nothing else holds are
+/// dropped each time the interner doubled in size.
+#[derive(Debug, Default)]
+pub(crate) struct Interner {
+    strings: HashSet<Arc<str>>,
+    // The number of strings left after the last sweep
+    swept: usize,
+}
+
+impl Interner {
+    /// The shared copy of `string`.
+    pub(crate) fn intern(&mut self, string: &str) -> Arc<str> {
+        if let Some(interned) = self.strings.get(string) {
+            return interned.clone();
+        }
+        if self.strings.len() >= (2 * self.swept).max(MIN_SWEEP) {
+            self.strings
+                .retain(|interned| Arc::strong_count(interned) > 1);
+            self.swept = self.strings.len();
+        }
+        let interned: Arc<str> = Arc::from(string);
+        self.strings.insert(interned.clone());
+        interned
+    }
+}
+
+/// The shared copy of `host`.
+pub(crate) fn host(host: &str) -> Arc<str> {
+    HOSTS
+        .get_or_init(Mutex::default)
+        .lock()
+        .expect("hosts lock")
+        .intern(host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shares_equal_strings() {
+        let mut interner = Interner::default();
+        let first = interner.intern("example.com");
+        let second = interner.intern("example.com");
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(!Arc::ptr_eq(&first, &interner.intern("example.org")));
+    }
+
+    #[test]
+    fn drops_unused_strings() {
+        let mut interner = Interner::default();
+        let kept = interner.intern("kept.example.com");
+        for index in 0..MIN_SWEEP {
+            interner.intern(&format!("{}.example.com", index));
+        }
+        assert_eq!(interner.strings.len(), 2);
+        assert!(Arc::ptr_eq(&kept, &interner.intern("kept.example.com")));
+    }
+}
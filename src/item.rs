@@ -0,0 +1,18 @@
+use serde::Serialize;
+use std::fmt::Debug;
+
+/// Marks the items that can go through [exporters](crate::export::Exporter).
+///
+/// Implement it with `#[derive(ScrapeItem)]`, next to the `Serialize` derive:
+///
+/// ```
+/// use scrappy_do::ScrapeItem;
+/// use serde::Serialize;
+///
+/// #[derive(Debug, Serialize, ScrapeItem)]
+/// struct Quote {
+///     text: String,
+///     tags: Vec<String>,
+/// }
+/// ```
+pub trait ScrapeItem: Debug + Send + Serialize + 'static {}
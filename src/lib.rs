@@ -17,8 +17,8 @@
 //! #![feature(generators)]
 //!
 //! use futures::stream::StreamExt; // Provides friendly methods for streams
-//! use reqwest::{Client, Response};
-//! use scrappy_do::{handle, wrap};
+//! use reqwest::Client;
+//! use scrappy_do::{handle, wrap, ScrapedResponse};
 //! use slog::Logger;
 //! use url::Url;
 //!
@@ -33,7 +33,7 @@
 //! #[handle(item = SomeItem)]
 //! fn handler_foo(
 //!     client: Client,
-//!     response: Response,
+//!     response: ScrapedResponse,
 //!     context: SomeContext,
 //!     logger: Logger,
 //! ) {
@@ -91,8 +91,8 @@
 //! #![feature(generators)]
 //!
 //! use futures::stream::StreamExt; // Provides friendly methods for streams
-//! use reqwest::{Client, Response};
-//! use scrappy_do::{handle, wrap};
+//! use reqwest::Client;
+//! use scrappy_do::{handle, wrap, ScrapedResponse};
 //! use slog::Logger;
 //! use url::Url;
 //!
@@ -108,7 +108,7 @@
 //! #[handle(item = SomeItem)]
 //! fn handler_foo(
 //!     client: Client,
-//!     response: Response,
+//!     response: ScrapedResponse,
 //!     context: SomeContext,
 //!     logger: Logger,
 //! ) {
@@ -135,7 +135,7 @@
 //! #[handle(item = SomeItem)]
 //! fn handler_bar(
 //!     client: Client,
-//!     response: Response,
+//!     response: ScrapedResponse,
 //!     context: SomeContext,
 //!     logger: Logger,
 //! ) {
@@ -189,16 +189,100 @@
 //!
 pub use scrappy_do_codegen::*;
 
+mod auth;
+mod auto_throttle;
+mod backoff;
+mod ban;
+mod breaker;
 mod callback;
+mod capture;
+mod content;
+mod contract;
+mod coverage;
+mod crawl;
+mod crawl_set;
+mod dedup;
+mod dns;
+mod download;
+mod encoding;
+pub mod export;
+mod extension;
+pub mod fixture;
+mod ftp;
 mod handler;
+mod headers;
+mod identity;
+mod intern;
+mod item;
+mod near_duplicate;
+mod partial;
+pub mod pipeline;
+mod probe;
+mod proxy;
+mod queue;
+mod replay;
+mod response;
+mod retry;
+mod robots;
+mod router;
+pub mod runner;
+mod runtime;
+mod scope;
+mod service;
+mod settings;
+mod shard;
+#[cfg(feature = "shell")]
+pub mod shell;
 mod spider;
+mod stats;
+mod throttle;
+mod tracker;
 pub mod util;
+mod yield_rate;
+pub use auth::{CredentialStore, FormLogin};
+pub use auto_throttle::AutoThrottle;
+pub use backoff::Backoff;
+pub use ban::BanDetector;
+pub use breaker::CircuitBreaker;
 pub use callback::{Callback, Indeterminate};
-pub use handler::{Handler, HandlerImpl};
-pub use spider::{Spider, Web, WebBuilder};
+pub use capture::FailureCapture;
+pub use content::ContentRouter;
+pub use contract::{Contract, ContractReport, ContractViolation};
+pub use coverage::{CoverageReport, FilterReason, UrlCoverage, UrlOutcome};
+pub use crawl::{Crawl, CrawlHandle, CrawlSummary, StopReason};
+pub use crawl_set::{CrawlSet, CrawlSetError, CrawlState, Quota};
+pub use dedup::DedupFilter;
+pub use dns::IpPreference;
+pub use download::{DownloadError, DownloadHandler, FileDownloadHandler, HttpDownloadHandler};
+pub use encoding::ContentCoding;
+pub use extension::{Extension, LogStats};
+pub use ftp::FtpDownloadHandler;
+pub use handler::{ErrorHandler, Handler, HandlerImpl};
+pub use identity::Rotation;
+pub use item::ScrapeItem;
+pub use near_duplicate::NearDuplicateAction;
+pub use partial::{PartialItem, PartialItems};
+pub use probe::Probe;
+pub use replay::{Replay, ReplayError};
+pub use response::{CallbackInfo, FetchMetrics, JsonError, ScrapedResponse};
+pub use retry::{FailureKind, RetryPolicy};
+pub use router::UrlRouter;
+pub use runtime::{Runtime, Tokio};
+pub use scope::{JoinError, SubCrawl, SubCrawlItems};
+pub use service::{ServiceError, WebService};
+pub use shard::{ParseShardError, Shard, ShardKey};
+pub use spider::{Spider, SpiderBuilder, Web, WebBuilder};
+pub use stats::{CrawlGauges, CrawlStats, HandlerStats, Histogram, RequestTags};
+pub use throttle::Scheduler;
+pub use tracker::{InFlightCallback, QueuedCallback, SchedulerDump};
+pub use yield_rate::{YieldAlert, YieldMonitor};
 
+pub use uuid::Uuid;
+
+#[doc(hidden)]
+pub use runtime::spawn;
+#[doc(hidden)]
+pub use slog;
+// The channels of tokio don't depend on its runtime
 #[doc(hidden)]
-pub use tokio::{
-    spawn,
-    sync::mpsc::{channel, Receiver},
-};
+pub use tokio::sync::mpsc::{channel, Receiver};
@@ -0,0 +1,126 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+
+/// The number of words hashed together when fingerprinting a page.
+const SHINGLE_SIZE: usize = 3;
+
+/// What to do with a page that is nearly identical to an already processed page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NearDuplicateAction {
+    /// Don't run the handler for the page.
+    Skip,
+    /// Run the handler, [ScrapedResponse::is_near_duplicate](crate::ScrapedResponse::is_near_duplicate)
+    /// returns `true` for the page.
+    Flag,
+}
+
+/// Detects pages whose text is nearly identical to the text of an already processed page, like
+/// print views or URLs only differing by a session ID.
+///
+/// Pages are fingerprinted with a simhash of their word shingles.
+#[derive(Debug)]
+pub(crate) struct NearDuplicates {
+    max_distance: u32,
+    action: NearDuplicateAction,
+    fingerprints: Mutex<Vec<u64>>,
+}
+
+impl NearDuplicates {
+    /// `similarity` is the fraction (`0.0..=1.0`) of matching fingerprint bits at which pages
+    /// count as duplicates.
+    pub(crate) fn new(similarity: f64, action: NearDuplicateAction) -> Self {
+        let max_distance = ((1.0 - similarity.clamp(0.0, 1.0)) * 64.0).floor() as u32;
+        Self {
+            max_distance,
+            action,
+            fingerprints: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn action(&self) -> NearDuplicateAction {
+        self.action
+    }
+
+    /// Check whether `body` is a near duplicate of a previously checked page. Pages that aren't
+    /// duplicates are remembered.
+    pub(crate) fn check(&self, body: &str) -> bool {
+        let fingerprint = match simhash(body) {
+            Some(fingerprint) => fingerprint,
+            // Pages without text can't be compared
+            None => return false,
+        };
+        let mut fingerprints = self.fingerprints.lock().expect("fingerprints lock");
+        let duplicate = fingerprints
+            .iter()
+            .any(|seen| (seen ^ fingerprint).count_ones() <= self.max_distance);
+        if !duplicate {
+            fingerprints.push(fingerprint);
+        }
+        duplicate
+    }
+}
+
+/// Compute the simhash of the text in an HTML document.
+fn simhash(body: &str) -> Option<u64> {
+    let text = strip_tags(body);
+    let words: Vec<&str> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+    if words.is_empty() {
+        return None;
+    }
+
+    let mut weights = [0i64; 64];
+    for shingle in words.windows(SHINGLE_SIZE.min(words.len())) {
+        let mut hasher = DefaultHasher::new();
+        for word in shingle {
+            word.to_lowercase().hash(&mut hasher);
+        }
+        let hash = hasher.finish();
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            if hash & (1 << bit) == 0 {
+                *weight -= 1;
+            } else {
+                *weight += 1;
+            }
+        }
+    }
+
+    Some(
+        weights
+            .iter()
+            .enumerate()
+            .filter(|(_, weight)| **weight > 0)
+            .fold(0, |fingerprint, (bit, _)| fingerprint | (1 << bit)),
+    )
+}
+
+/// Replace the tags of an HTML document with spaces, dropping scripts and styles entirely.
+fn strip_tags(body: &str) -> String {
+    let mut text = String::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        text.push(' ');
+        rest = &rest[start..];
+        let tag_end = rest
+            .find('>')
+            .map(|end| end + 1)
+            .unwrap_or_else(|| rest.len());
+        let tag = rest[..tag_end].to_ascii_lowercase();
+        rest = &rest[tag_end..];
+        for element in &["script", "style"] {
+            if tag.starts_with(&format!("<{}", element)) {
+                let close = format!("</{}", element);
+                rest = match rest.to_ascii_lowercase().find(&close) {
+                    Some(close_start) => &rest[close_start..],
+                    None => "",
+                };
+            }
+        }
+    }
+    text.push_str(rest);
+    text
+}
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// An item assembled from parts contributed by several handlers.
+#[derive(Debug)]
+pub struct PartialItem<T> {
+    item: T,
+    missing: Vec<String>,
+    started: Instant,
+}
+
+impl<T> PartialItem<T> {
+    /// Returns the item, as filled by the parts received.
+    pub fn item(&self) -> &T {
+        &self.item
+    }
+
+    /// Consumes the partial item, returning the item.
+    pub fn into_item(self) -> T {
+        self.item
+    }
+
+    /// Returns the required parts that never arrived, empty once the item is complete.
+    pub fn missing(&self) -> &[String] {
+        &self.missing
+    }
+
+    /// Returns whether every required part arrived.
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+/// Assembles items whose fields come from several pages, like a product built from its listing
+/// page, its detail page and a price API, without keeping the partial items in the context.
+///
+/// Shared between the handlers through the context, each handler contributes a part of the item
+/// identified by a key, like the product ID. The item is handed back to the handler that
+/// contributed its last required part, to be yielded. Items still missing parts after the
+/// [timeout](PartialItems::timeout) are handed back, incomplete, to the next handler that
+/// contributes a part, and the items left once the crawl finished are returned by
+/// [drain](PartialItems::drain).
+///
+/// ```
+/// use scrappy_do::PartialItems;
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// #[derive(Debug, Default)]
+/// struct Product {
+///     name: String,
+///     price: Option<u64>,
+/// }
+///
+/// let products: Arc<PartialItems<u64, Product>> = Arc::new(
+///     PartialItems::new(["listing", "price"]).timeout(Duration::from_secs(300)),
+/// );
+///
+/// // In the handler of the listing page
+/// let assembled = products.contribute(42, "listing", |product| product.name = "Kettle".into());
+/// assert!(assembled.is_empty());
+/// // In the handler of the price API
+/// let assembled = products.contribute(42, "price", |product| product.price = Some(1999));
+/// assert!(assembled[0].is_complete());
+/// assert_eq!(assembled[0].item().price, Some(1999));
+/// ```
+#[derive(Debug)]
+pub struct PartialItems<K, T> {
+    required: Vec<String>,
+    timeout: Option<Duration>,
+    partials: Mutex<HashMap<K, PartialItem<T>>>,
+}
+
+impl<K: Eq + Hash, T: Default> PartialItems<K, T> {
+    /// Assemble items made of the `required` parts.
+    pub fn new<S, P>(required: P) -> Self
+    where
+        S: Into<String>,
+        P: IntoIterator<Item = S>,
+    {
+        Self {
+            required: required.into_iter().map(Into::into).collect(),
+            timeout: None,
+            partials: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Give up waiting for the missing parts of an item `timeout` after its first part arrived.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Fill the part `part` of the item identified by `key` with `fill`. Parts that aren't
+    /// required can be contributed as well, they don't complete the item.
+    ///
+    /// # Returns
+    /// The item, if `part` was its last missing part, followed by the items that timed out.
+    pub fn contribute<F>(&self, key: K, part: &str, fill: F) -> Vec<PartialItem<T>>
+    where
+        F: FnOnce(&mut T),
+    {
+        let mut partials = self.partials.lock().expect("partial items lock");
+        let partial = partials.entry(key).or_insert_with(|| PartialItem {
+            item: T::default(),
+            missing: self.required.clone(),
+            started: Instant::now(),
+        });
+        fill(&mut partial.item);
+        partial.missing.retain(|missing| missing != part);
+
+        let now = Instant::now();
+        let (mut assembled, waiting): (Vec<_>, Vec<_>) =
+            partials.drain().partition(|(_, partial)| {
+                partial.is_complete()
+                    || self
+                        .timeout
+                        .is_some_and(|timeout| now - partial.started >= timeout)
+            });
+        partials.extend(waiting);
+        // The completed item first
+        assembled.sort_by_key(|(_, partial)| !partial.is_complete());
+        assembled.into_iter().map(|(_, partial)| partial).collect()
+    }
+
+    /// Returns the number of items still missing parts.
+    pub fn len(&self) -> usize {
+        self.partials.lock().expect("partial items lock").len()
+    }
+
+    /// Returns true if no item is missing parts.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Take the items still missing parts, like once the crawl finished.
+    pub fn drain(&self) -> Vec<PartialItem<T>> {
+        self.partials
+            .lock()
+            .expect("partial items lock")
+            .drain()
+            .map(|(_, partial)| partial)
+            .collect()
+    }
+}
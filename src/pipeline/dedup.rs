@@ -0,0 +1,272 @@
+use super::{Pipeline, PipelineError};
+use futures::future::{self, BoxFuture};
+use std::collections::HashSet;
+use std::fmt::{self, Debug};
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Remembers the fingerprints of the keys already seen by a [Dedup](Dedup) pipeline.
+pub trait DedupStore: Send + Sync + Debug {
+    /// Record `fingerprint`. Returns true if it wasn't recorded before.
+    fn insert(&self, fingerprint: u64) -> BoxFuture<'_, Result<bool, PipelineError>>;
+
+    /// Write the recorded fingerprints so they can be loaded by a later run. Stores persisted
+    /// some other way write nothing.
+    fn save(&self, _writer: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Remembers every fingerprint. Memory grows with the number of unique items.
+#[derive(Debug, Default)]
+pub struct ExactStore {
+    seen: Mutex<HashSet<u64>>,
+}
+
+impl ExactStore {
+    /// Load the fingerprints saved by a previous run.
+    pub fn load<R: Read>(reader: R) -> io::Result<Self> {
+        let mut reader = BufReader::new(reader);
+        let mut seen = HashSet::new();
+        let mut fingerprint = [0; 8];
+        loop {
+            match reader.read_exact(&mut fingerprint) {
+                Ok(()) => {
+                    seen.insert(u64::from_le_bytes(fingerprint));
+                }
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(Self {
+            seen: Mutex::new(seen),
+        })
+    }
+
+    /// Load the fingerprints saved at `path`, starting empty if the file doesn't exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        match File::open(path) {
+            Ok(file) => Self::load(file),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl DedupStore for ExactStore {
+    fn insert(&self, fingerprint: u64) -> BoxFuture<'_, Result<bool, PipelineError>> {
+        let new = self.seen.lock().expect("store lock").insert(fingerprint);
+        Box::pin(future::ready(Ok(new)))
+    }
+
+    fn save(&self, writer: &mut dyn Write) -> io::Result<()> {
+        for fingerprint in self.seen.lock().expect("store lock").iter() {
+            writer.write_all(&fingerprint.to_le_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// Remembers fingerprints in a Bloom filter of fixed size. Some unique items are wrongly dropped
+/// as duplicates, at the configured rate once the expected number of items has been seen.
+pub struct BloomStore {
+    bits: Mutex<Vec<u64>>,
+    num_bits: u64,
+    hashes: u32,
+}
+
+impl BloomStore {
+    /// Size the filter for `expected_items` items with a `false_positive_rate` (`0.0..1.0`).
+    pub fn new(expected_items: usize, false_positive_rate: f64) -> Self {
+        let expected_items = expected_items.max(1) as f64;
+        let rate = false_positive_rate.clamp(f64::MIN_POSITIVE, 0.5);
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-(expected_items * rate.ln()) / (ln2 * ln2))
+            .ceil()
+            .max(64.0) as u64;
+        let hashes = ((num_bits as f64 / expected_items) * ln2).round().max(1.0) as u32;
+        Self::with_bits(vec![0; num_bits.div_ceil(64) as usize], num_bits, hashes)
+    }
+
+    fn with_bits(bits: Vec<u64>, num_bits: u64, hashes: u32) -> Self {
+        Self {
+            bits: Mutex::new(bits),
+            num_bits,
+            hashes,
+        }
+    }
+
+    /// Load a filter saved by a previous run.
+    pub fn load<R: Read>(reader: R) -> io::Result<Self> {
+        let mut reader = BufReader::new(reader);
+        let mut word = [0; 8];
+        reader.read_exact(&mut word)?;
+        let num_bits = u64::from_le_bytes(word);
+        let mut hashes = [0; 4];
+        reader.read_exact(&mut hashes)?;
+        let hashes = u32::from_le_bytes(hashes);
+        let mut bits = vec![0; num_bits.div_ceil(64) as usize];
+        for bits in bits.iter_mut() {
+            reader.read_exact(&mut word)?;
+            *bits = u64::from_le_bytes(word);
+        }
+        Ok(Self::with_bits(bits, num_bits, hashes))
+    }
+
+    /// Load the filter saved at `path`, or create a new one with [new](BloomStore::new) if the
+    /// file doesn't exist.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        expected_items: usize,
+        false_positive_rate: f64,
+    ) -> io::Result<Self> {
+        match File::open(path) {
+            Ok(file) => Self::load(file),
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                Ok(Self::new(expected_items, false_positive_rate))
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl Debug for BloomStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BloomStore")
+            .field("num_bits", &self.num_bits)
+            .field("hashes", &self.hashes)
+            .finish()
+    }
+}
+
+impl DedupStore for BloomStore {
+    fn insert(&self, fingerprint: u64) -> BoxFuture<'_, Result<bool, PipelineError>> {
+        // Double hashing derives the bit positions from two halves of the fingerprint
+        let first = fingerprint & 0xffff_ffff;
+        let second = (fingerprint >> 32) | 1;
+        let mut bits = self.bits.lock().expect("store lock");
+        let mut new = false;
+        for index in 0..self.hashes as u64 {
+            let bit = first.wrapping_add(index.wrapping_mul(second)) % self.num_bits;
+            let (word, mask) = ((bit / 64) as usize, 1 << (bit % 64));
+            if bits[word] & mask == 0 {
+                bits[word] |= mask;
+                new = true;
+            }
+        }
+        Box::pin(future::ready(Ok(new)))
+    }
+
+    fn save(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writer.write_all(&self.num_bits.to_le_bytes())?;
+        writer.write_all(&self.hashes.to_le_bytes())?;
+        for word in self.bits.lock().expect("store lock").iter() {
+            writer.write_all(&word.to_le_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// Drops items whose key was already seen, like the SKU of a product listed on several category
+/// pages.
+///
+/// Keys are reduced to 64 bit fingerprints with a hash that is stable across runs, so stores can
+/// be persisted with [persist](Dedup::persist) and loaded again by the next run.
+///
+/// ```no_run
+/// # fn example() -> std::io::Result<()> {
+/// use scrappy_do::pipeline::{Dedup, ExactStore};
+///
+/// #[derive(Debug)]
+/// struct Product {
+///     sku: String,
+/// }
+///
+/// let dedup = Dedup::new(|product: &Product| product.sku.clone(), ExactStore::open("seen.bin")?)
+///     .persist("seen.bin");
+/// # Ok(())
+/// # }
+/// ```
+pub struct Dedup<F, S> {
+    key: F,
+    store: S,
+    path: Option<PathBuf>,
+}
+
+impl<F, S> Dedup<F, S> {
+    /// Deduplicate items on the key returned by `key`, remembering the keys in `store`.
+    pub fn new(key: F, store: S) -> Self {
+        Self {
+            key,
+            store,
+            path: None,
+        }
+    }
+
+    /// Save the store to `path` once the crawl is finished.
+    pub fn persist<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+}
+
+impl<F, S: Debug> Debug for Dedup<F, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Dedup")
+            .field("store", &self.store)
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl<I, K, F, S> Pipeline<I> for Dedup<F, S>
+where
+    I: Send + 'static,
+    K: Hash,
+    F: Fn(&I) -> K + Send + Sync,
+    S: DedupStore,
+{
+    fn process(&self, item: I) -> BoxFuture<'_, Result<Option<I>, PipelineError>> {
+        let mut hasher = Fnv64::default();
+        (self.key)(&item).hash(&mut hasher);
+        let insert = self.store.insert(hasher.finish());
+        Box::pin(async move { Ok(if insert.await? { Some(item) } else { None }) })
+    }
+
+    fn finish(&self) -> BoxFuture<'_, Result<(), PipelineError>> {
+        let result = match &self.path {
+            Some(path) => File::create(path).and_then(|file| {
+                let mut writer = BufWriter::new(file);
+                self.store.save(&mut writer)?;
+                writer.flush()
+            }),
+            None => Ok(()),
+        };
+        Box::pin(future::ready(result.map_err(PipelineError::from)))
+    }
+}
+
+/// FNV-1a, unlike the standard library's hasher its output is stable across releases.
+pub(crate) struct Fnv64(u64);
+
+impl Default for Fnv64 {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv64 {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
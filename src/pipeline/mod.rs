@@ -0,0 +1,365 @@
+//! Processes the items of a crawl before they are handed to the consumer.
+//!
+//! [Pipelines](Pipeline) are registered with [Web::pipeline](crate::Web::pipeline), or wrapped in
+//! a [Stage](Stage) and registered with [Web::stage](crate::Web::stage) to control how they are
+//! ordered and what happens when they fail.
+//!
+//! Stages run by ascending [order](Stage::order), then in the order they were registered. A
+//! chained stage receives the output of the previous chained stage and may transform or drop the
+//! item. A [fan-out](Stage::fan_out) stage receives a copy of the item, like a sink, and doesn't
+//! affect what the following stages receive. Consecutive fan-out stages run concurrently.
+//!
+//! ```no_run
+//! # use scrappy_do::pipeline::{Dedup, ErrorPolicy, ExactStore, Pipeline, Stage};
+//! # use std::time::Duration;
+//! #[derive(Debug, Clone)]
+//! struct Product {
+//!     sku: String,
+//! }
+//!
+//! # fn example<P: Pipeline<Product> + 'static>(web: scrappy_do::Web<Product, ()>, store: P) {
+//! let web = web
+//!     .stage(
+//!         Stage::new(store)
+//!             .order(10)
+//!             .fan_out()
+//!             .retry(3, Duration::from_secs(1))
+//!             .on_error(ErrorPolicy::FailCrawl),
+//!     )
+//!     .pipeline(Dedup::new(|product: &Product| product.sku.clone(), ExactStore::default()));
+//! # }
+//! ```
+
+use futures::future::{self, BoxFuture};
+use slog::{crit, warn, Logger};
+use std::fmt::{self, Debug};
+use std::io;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+use std::time::Duration;
+use thiserror::Error;
+
+mod dedup;
+#[cfg(feature = "redis")]
+mod redis;
+mod sample;
+mod source;
+
+#[cfg(feature = "redis")]
+pub use self::redis::RedisStore;
+pub(crate) use dedup::Fnv64;
+pub use dedup::{BloomStore, Dedup, DedupStore, ExactStore};
+pub use sample::Sample;
+pub use source::Source;
+
+#[derive(Error, Debug)]
+pub enum PipelineError {
+    #[error("the pipeline could not read or write its data: {0}")]
+    Io(#[from] io::Error),
+    #[error("the pipeline failed: {0}")]
+    Failed(String),
+}
+
+/// Transforms, filters, or stores items.
+pub trait Pipeline<I>: Send + Sync + Debug {
+    /// Process an item.
+    ///
+    /// # Returns
+    /// The item to hand to the next pipeline, or `None` to drop it.
+    fn process(&self, item: I) -> BoxFuture<'_, Result<Option<I>, PipelineError>>;
+
+    /// Process an item along with the response it was scraped from. Only called for the stages
+    /// given the [source](Stage::source) of their items, defaults to [process](Pipeline::process).
+    fn process_with_source(
+        &self,
+        item: I,
+        _source: Arc<Source>,
+    ) -> BoxFuture<'_, Result<Option<I>, PipelineError>> {
+        self.process(item)
+    }
+
+    /// Called once after the last item has been processed.
+    fn finish(&self) -> BoxFuture<'_, Result<(), PipelineError>> {
+        Box::pin(future::ready(Ok(())))
+    }
+}
+
+/// What happens to an item once a stage failed to process it, after any [retries](Stage::retry).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Drop the item and keep crawling. For fan-out stages the item still reaches the following
+    /// stages.
+    Drop,
+    /// Drop the item and stop the crawl. Callbacks that are already executing are allowed to
+    /// finish but their items are dropped.
+    FailCrawl,
+}
+
+/// A [Pipeline](Pipeline) along with how it is scheduled and how its errors are handled.
+pub struct Stage<I> {
+    name: String,
+    pipeline: Box<dyn Pipeline<I>>,
+    order: i32,
+    fan_out: bool,
+    source: bool,
+    retries: usize,
+    backoff: Duration,
+    on_error: ErrorPolicy,
+    // Copies items for fan-out stages and retries
+    copy: Option<fn(&I) -> I>,
+}
+
+impl<I> Stage<I> {
+    /// Wrap `pipeline` in a chained stage with an order of 0 that drops the items it fails on.
+    pub fn new<P: Pipeline<I> + 'static>(pipeline: P) -> Self {
+        let name = std::any::type_name::<P>();
+        // Keep the type name readable in the summary, `scrappy_do::pipeline::Dedup<..>` -> `Dedup`
+        let name = name.split('<').next().unwrap_or(name);
+        Self {
+            name: name.rsplit("::").next().unwrap_or(name).to_string(),
+            pipeline: Box::new(pipeline),
+            order: 0,
+            fan_out: false,
+            source: false,
+            retries: 0,
+            backoff: Duration::ZERO,
+            on_error: ErrorPolicy::Drop,
+            copy: None,
+        }
+    }
+
+    /// The name of the stage in logs and in the [summary](crate::CrawlSummary::pipelines).
+    /// Defaults to the name of the pipeline's type.
+    pub fn name<S: Into<String>>(mut self, name: S) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Stages run by ascending order. Stages with the same order run in the order they were
+    /// registered.
+    pub fn order(mut self, order: i32) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Hand the stage the response each item was scraped from, through
+    /// [process_with_source](Pipeline::process_with_source). The body of every response is kept
+    /// in memory until the items of its handler went through the pipelines.
+    pub fn source(mut self) -> Self {
+        self.source = true;
+        self
+    }
+
+    /// What happens to an item the stage failed to process. Defaults to
+    /// [Drop](ErrorPolicy::Drop).
+    pub fn on_error(mut self, policy: ErrorPolicy) -> Self {
+        self.on_error = policy;
+        self
+    }
+}
+
+impl<I: Clone> Stage<I> {
+    /// Hand the stage a copy of every item instead of chaining it. What the stage returns is
+    /// ignored, the following stages receive the item it was given.
+    pub fn fan_out(mut self) -> Self {
+        self.fan_out = true;
+        self.copy = Some(I::clone);
+        self
+    }
+
+    /// Retry an item up to `retries` times, waiting `backoff` before the first retry and doubling
+    /// the wait every time, before applying the [error policy](Stage::on_error).
+    pub fn retry(mut self, retries: usize, backoff: Duration) -> Self {
+        self.retries = retries;
+        self.backoff = backoff;
+        self.copy = Some(I::clone);
+        self
+    }
+}
+
+impl<I> Debug for Stage<I> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Stage")
+            .field("name", &self.name)
+            .field("pipeline", &self.pipeline)
+            .field("order", &self.order)
+            .field("fan_out", &self.fan_out)
+            .field("source", &self.source)
+            .field("retries", &self.retries)
+            .field("backoff", &self.backoff)
+            .field("on_error", &self.on_error)
+            .finish()
+    }
+}
+
+/// The statistics collected for a single stage.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StageStats {
+    /// The name of the stage.
+    pub name: String,
+    /// The number of items the stage received.
+    pub processed: u64,
+    /// The number of items the stage dropped, excluding the items it failed on.
+    pub dropped: u64,
+    /// The number of items the stage failed on after exhausting its retries.
+    pub errors: u64,
+    /// The number of retries.
+    pub retries: u64,
+}
+
+/// The pipelines of a crawl.
+#[derive(Debug)]
+pub(crate) struct Pipelines<I> {
+    stages: Vec<(Stage<I>, Counters)>,
+    failure: Mutex<Option<String>>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    processed: AtomicU64,
+    dropped: AtomicU64,
+    errors: AtomicU64,
+    retries: AtomicU64,
+}
+
+impl<I> Pipelines<I> {
+    pub(crate) fn new(mut stages: Vec<Stage<I>>) -> Self {
+        // Stable, so registration breaks ties
+        stages.sort_by_key(|stage| stage.order);
+        Self {
+            stages: stages
+                .into_iter()
+                .map(|stage| (stage, Counters::default()))
+                .collect(),
+            failure: Mutex::new(None),
+        }
+    }
+
+    /// Returns why a stage failed the crawl, if one did.
+    pub(crate) fn failure(&self) -> Option<String> {
+        self.failure.lock().expect("failure lock").clone()
+    }
+
+    pub(crate) fn stats(&self) -> Vec<StageStats> {
+        self.stages
+            .iter()
+            .map(|(stage, counters)| StageStats {
+                name: stage.name.clone(),
+                processed: counters.processed.load(Ordering::Relaxed),
+                dropped: counters.dropped.load(Ordering::Relaxed),
+                errors: counters.errors.load(Ordering::Relaxed),
+                retries: counters.retries.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    /// Whether a stage is given the source of the items.
+    pub(crate) fn keep_source(&self) -> bool {
+        self.stages.iter().any(|(stage, _)| stage.source)
+    }
+
+    /// Run `item`, scraped from `source`, through every stage. Returns `None` if the item was
+    /// dropped.
+    pub(crate) async fn process(
+        &self,
+        mut item: I,
+        source: Option<&Arc<Source>>,
+        logger: &Logger,
+    ) -> Option<I> {
+        let mut index = 0;
+        while index < self.stages.len() {
+            if self.failure().is_some() {
+                return None;
+            }
+            let (stage, _) = &self.stages[index];
+            if !stage.fan_out {
+                item = self.run(index, item, source, logger).await?;
+                index += 1;
+                continue;
+            }
+            let copy = stage.copy.expect("fan-out stages copy items");
+            let mut fan_out = Vec::new();
+            while index < self.stages.len() && self.stages[index].0.fan_out {
+                fan_out.push(self.run(index, copy(&item), source, logger));
+                index += 1;
+            }
+            future::join_all(fan_out).await;
+        }
+        if self.failure().is_some() {
+            return None;
+        }
+        Some(item)
+    }
+
+    async fn run(
+        &self,
+        index: usize,
+        item: I,
+        source: Option<&Arc<Source>>,
+        logger: &Logger,
+    ) -> Option<I> {
+        let (stage, counters) = &self.stages[index];
+        counters.processed.fetch_add(1, Ordering::Relaxed);
+        let mut backoff = stage.backoff;
+        let mut attempt = 0;
+        let mut item = Some(item);
+        loop {
+            // Keep a copy around only while the item may still be retried
+            let input = match (stage.copy, attempt < stage.retries) {
+                (Some(copy), true) => copy(item.as_ref().expect("item")),
+                _ => item.take().expect("item"),
+            };
+            let processed = match source.filter(|_| stage.source) {
+                Some(source) => stage.pipeline.process_with_source(input, source.clone()),
+                None => stage.pipeline.process(input),
+            };
+            let err = match processed.await {
+                Ok(Some(output)) => return Some(output),
+                Ok(None) => {
+                    counters.dropped.fetch_add(1, Ordering::Relaxed);
+                    return None;
+                }
+                Err(err) => err,
+            };
+            if item.is_none() {
+                counters.errors.fetch_add(1, Ordering::Relaxed);
+                self.fail(stage, &err, logger);
+                return None;
+            }
+            warn!(logger, "A pipeline failed, retrying the item";
+                  "stage" => &stage.name, "error" => %err, "backoff" => ?backoff);
+            counters.retries.fetch_add(1, Ordering::Relaxed);
+            attempt += 1;
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+
+    /// Apply the error policy of `stage`.
+    fn fail(&self, stage: &Stage<I>, err: &PipelineError, logger: &Logger) {
+        match stage.on_error {
+            ErrorPolicy::Drop => {
+                warn!(logger, "A pipeline failed"; "stage" => &stage.name, "error" => %err);
+            }
+            ErrorPolicy::FailCrawl => {
+                let mut failure = self.failure.lock().expect("failure lock");
+                if failure.is_none() {
+                    crit!(logger, "Stopping the crawl, a pipeline failed";
+                          "stage" => &stage.name, "error" => %err);
+                    *failure = Some(format!("{}: {}", stage.name, err));
+                }
+            }
+        }
+    }
+
+    pub(crate) async fn finish(&self, logger: &Logger) {
+        for (stage, counters) in &self.stages {
+            if let Err(err) = stage.pipeline.finish().await {
+                counters.errors.fetch_add(1, Ordering::Relaxed);
+                self.fail(stage, &err, logger);
+            }
+        }
+    }
+}
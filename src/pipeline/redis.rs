@@ -0,0 +1,66 @@
+use super::{DedupStore, PipelineError};
+use futures::future::BoxFuture;
+use redis::aio::ConnectionManager;
+use std::fmt::{self, Debug};
+
+/// Remembers fingerprints in a Redis set, shared by every crawl using the same key. Only
+/// available with the `redis` feature.
+///
+/// Redis persists the set itself, so nothing is written by [persist](super::Dedup::persist). The
+/// connection manager can be cloned and shared with [export::Redis](crate::export::Redis).
+///
+/// ```no_run
+/// # async fn example() -> redis::RedisResult<()> {
+/// use redis::{aio::ConnectionManager, Client};
+/// use scrappy_do::pipeline::{Dedup, RedisStore};
+///
+/// # #[derive(Debug)]
+/// # struct Product {
+/// #     sku: String,
+/// # }
+/// let connection = ConnectionManager::new(Client::open("redis://localhost")?).await?;
+/// let dedup = Dedup::new(
+///     |product: &Product| product.sku.clone(),
+///     RedisStore::new(connection, "products:seen"),
+/// );
+/// # Ok(())
+/// # }
+/// ```
+pub struct RedisStore {
+    connection: ConnectionManager,
+    key: String,
+}
+
+impl RedisStore {
+    /// Store the fingerprints in the set at `key`.
+    pub fn new<S: Into<String>>(connection: ConnectionManager, key: S) -> Self {
+        Self {
+            connection,
+            key: key.into(),
+        }
+    }
+}
+
+impl Debug for RedisStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RedisStore")
+            .field("key", &self.key)
+            .finish()
+    }
+}
+
+impl DedupStore for RedisStore {
+    fn insert(&self, fingerprint: u64) -> BoxFuture<'_, Result<bool, PipelineError>> {
+        // The manager multiplexes its clones over the same connection
+        let mut connection = self.connection.clone();
+        Box::pin(async move {
+            redis::cmd("SADD")
+                .arg(&self.key)
+                .arg(fingerprint)
+                .query_async::<_, u64>(&mut connection)
+                .await
+                .map(|added| added == 1)
+                .map_err(|err| PipelineError::Failed(err.to_string()))
+        })
+    }
+}
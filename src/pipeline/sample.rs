@@ -0,0 +1,79 @@
+use super::{Fnv64, Pipeline, PipelineError};
+use futures::future::{self, BoxFuture};
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+use std::num::NonZeroU64;
+
+/// Keeps a sample of the items, chosen by hashing a key of each item. The same keys are sampled
+/// on every run, so a QA run can check the parsers on a fraction of a large site while the whole
+/// site is still crawled.
+///
+/// Registered with [Web::sample_items](crate::Web::sample_items), the items left out never reach
+/// the other stages.
+///
+/// ```
+/// use scrappy_do::pipeline::Sample;
+/// use std::num::NonZeroU64;
+///
+/// #[derive(Debug)]
+/// struct Product {
+///     sku: String,
+/// }
+///
+/// // Keep the products of about one SKU in 100
+/// let sample = Sample::one_in(NonZeroU64::new(100).unwrap(), |product: &Product| {
+///     product.sku.clone()
+/// });
+/// // Or of 2.5% of the SKUs
+/// let sample = Sample::percent(2.5, |product: &Product| product.sku.clone());
+/// ```
+pub struct Sample<F> {
+    key: F,
+    // Items are kept when their hash modulo `out_of` is below `kept`
+    kept: u64,
+    out_of: u64,
+}
+
+impl<F> Sample<F> {
+    /// Keep one item out of `n`, by the key returned by `key`.
+    pub fn one_in(n: NonZeroU64, key: F) -> Self {
+        Self {
+            key,
+            kept: 1,
+            out_of: n.get(),
+        }
+    }
+
+    /// Keep `percent` percent of the items, by the key returned by `key`. The percentage is
+    /// rounded to a hundredth of a percent and clamped between 0 and 100.
+    pub fn percent(percent: f64, key: F) -> Self {
+        Self {
+            key,
+            kept: (percent.clamp(0.0, 100.0) * 100.0).round() as u64,
+            out_of: 10_000,
+        }
+    }
+}
+
+impl<F> Debug for Sample<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Sample")
+            .field("kept", &self.kept)
+            .field("out_of", &self.out_of)
+            .finish()
+    }
+}
+
+impl<I, K, F> Pipeline<I> for Sample<F>
+where
+    I: Send + 'static,
+    K: Hash,
+    F: Fn(&I) -> K + Send + Sync,
+{
+    fn process(&self, item: I) -> BoxFuture<'_, Result<Option<I>, PipelineError>> {
+        let mut hasher = Fnv64::default();
+        (self.key)(&item).hash(&mut hasher);
+        let kept = hasher.finish() % self.out_of < self.kept;
+        Box::pin(future::ready(Ok(kept.then_some(item))))
+    }
+}
@@ -0,0 +1,93 @@
+use crate::response::CallbackInfo;
+use bytes::Bytes;
+use reqwest::{header::HeaderMap, StatusCode};
+use scraper::Html;
+use std::borrow::Cow;
+use url::Url;
+
+/// The response an item was scraped from, handed to the stages given the
+/// [source](super::Stage::source) of their items. Enrichment steps, like extracting the canonical
+/// URL of the page or hashing its content, read it instead of the handler copying the data into
+/// every item.
+///
+/// ```no_run
+/// use futures::future::BoxFuture;
+/// use scraper::Selector;
+/// use scrappy_do::pipeline::{Pipeline, PipelineError, Source};
+/// use std::sync::Arc;
+///
+/// #[derive(Debug)]
+/// struct Article {
+///     title: String,
+///     canonical: Option<String>,
+/// }
+///
+/// #[derive(Debug)]
+/// struct Canonical;
+///
+/// impl Pipeline<Article> for Canonical {
+///     fn process(&self, item: Article) -> BoxFuture<'_, Result<Option<Article>, PipelineError>> {
+///         Box::pin(async move { Ok(Some(item)) })
+///     }
+///
+///     fn process_with_source(
+///         &self,
+///         mut item: Article,
+///         source: Arc<Source>,
+///     ) -> BoxFuture<'_, Result<Option<Article>, PipelineError>> {
+///         // The document isn't `Send`, it is dropped before the future is created
+///         let selector = Selector::parse("link[rel=canonical]").unwrap();
+///         item.canonical = source
+///             .html()
+///             .select(&selector)
+///             .find_map(|link| link.value().attr("href"))
+///             .map(str::to_string);
+///         Box::pin(async move { Ok(Some(item)) })
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Source {
+    pub(crate) info: CallbackInfo,
+    pub(crate) url: Url,
+    pub(crate) status: StatusCode,
+    pub(crate) headers: HeaderMap,
+    pub(crate) body: Bytes,
+}
+
+impl Source {
+    /// Information about the callback the response was produced for.
+    pub fn info(&self) -> &CallbackInfo {
+        &self.info
+    }
+
+    /// The URL of the response, after any redirects.
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// The status of the response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The headers of the response.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The decoded body of the response.
+    pub fn body(&self) -> &Bytes {
+        &self.body
+    }
+
+    /// The body of the response as text, replacing the invalid UTF-8 sequences.
+    pub fn text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.body)
+    }
+
+    /// Parse the body of the response as an HTML document.
+    pub fn html(&self) -> Html {
+        Html::parse_document(&self.text())
+    }
+}
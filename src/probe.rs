@@ -0,0 +1,146 @@
+use regex::Regex;
+use reqwest::header::{HeaderValue, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, RANGE};
+use reqwest::{Method, Request, Response};
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+/// Checks a resource with a cheap request before fetching it, to skip the links to large files,
+/// unwanted content types, or content already fetched under another URL.
+///
+/// The GET requests whose URL matches the rule are preceded by a `HEAD` request, or by a `GET`
+/// of the first byte for servers that don't answer `HEAD`. The full request is only sent if the
+/// headers of the probe pass every check. Probes that fail, or aren't answered with a success,
+/// don't prevent the full request.
+///
+/// ```
+/// use regex::Regex;
+/// use scrappy_do::Probe;
+///
+/// // Only download the attachments that are PDFs smaller than 10MB
+/// let probe = Probe::new(Regex::new("/attachments/").unwrap())
+///     .content_type("application/pdf")
+///     .max_length(10_000_000)
+///     .skip_seen_etags(true);
+/// ```
+#[derive(Debug)]
+pub struct Probe {
+    pattern: Regex,
+    ranged: bool,
+    content_types: Vec<String>,
+    max_length: Option<u64>,
+    skip_seen_etags: bool,
+    seen_etags: Mutex<HashSet<Vec<u8>>>,
+}
+
+impl Probe {
+    /// Probe the GET requests whose URL matches `pattern`.
+    pub fn new(pattern: Regex) -> Self {
+        Self {
+            pattern,
+            ranged: false,
+            content_types: Vec::new(),
+            max_length: None,
+            skip_seen_etags: false,
+            seen_etags: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Probe with a `GET` of the first byte of the resource instead of a `HEAD` request.
+    pub fn ranged(mut self, ranged: bool) -> Self {
+        self.ranged = ranged;
+        self
+    }
+
+    /// Only fetch resources whose content type starts with `content_type`, like `text/html`.
+    /// Every allowed content type is added with its own call. Resources without a content type
+    /// are fetched.
+    pub fn content_type<S: Into<String>>(mut self, content_type: S) -> Self {
+        self.content_types
+            .push(content_type.into().to_ascii_lowercase());
+        self
+    }
+
+    /// Only fetch resources of at most `max_length` bytes. Resources of unknown length are
+    /// fetched.
+    pub fn max_length(mut self, max_length: u64) -> Self {
+        self.max_length = Some(max_length);
+        self
+    }
+
+    /// Skip the resources whose `ETag` was already seen by a probe of the crawl.
+    pub fn skip_seen_etags(mut self, skip_seen_etags: bool) -> Self {
+        self.skip_seen_etags = skip_seen_etags;
+        self
+    }
+
+    pub(crate) fn matches(&self, request: &Request) -> bool {
+        request.method() == Method::GET && self.pattern.is_match(request.url().as_str())
+    }
+
+    /// The probe request for `request`.
+    pub(crate) fn request(&self, request: &Request) -> Request {
+        let method = if self.ranged {
+            Method::GET
+        } else {
+            Method::HEAD
+        };
+        let mut probe = Request::new(method, request.url().clone());
+        *probe.headers_mut() = request.headers().clone();
+        if self.ranged {
+            probe
+                .headers_mut()
+                .insert(RANGE, HeaderValue::from_static("bytes=0-0"));
+        }
+        *probe.timeout_mut() = request.timeout().copied();
+        probe
+    }
+
+    /// Describes why the resource shouldn't be fetched, judging by the response to its probe.
+    pub(crate) fn reject(&self, response: &Response) -> Option<String> {
+        if !response.status().is_success() {
+            return None;
+        }
+        let headers = response.headers();
+        if let Some(content_type) = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+        {
+            let content_type = content_type.to_ascii_lowercase();
+            if !self.content_types.is_empty()
+                && !self
+                    .content_types
+                    .iter()
+                    .any(|allowed| content_type.starts_with(allowed.as_str()))
+            {
+                return Some(format!("the content type is {}", content_type));
+            }
+        }
+        if let (Some(max_length), Some(length)) = (self.max_length, self.length(response)) {
+            if length > max_length {
+                return Some(format!("the resource is {} bytes long", length));
+            }
+        }
+        if self.skip_seen_etags {
+            if let Some(etag) = headers.get(ETAG) {
+                let mut seen_etags = self.seen_etags.lock().expect("probe lock");
+                if !seen_etags.insert(etag.as_bytes().to_vec()) {
+                    return Some(format!("the ETag {:?} was already seen", etag));
+                }
+            }
+        }
+        None
+    }
+
+    /// The length of the whole resource, as announced by the headers of the probe.
+    fn length(&self, response: &Response) -> Option<u64> {
+        let headers = response.headers();
+        let length = if self.ranged {
+            // `bytes 0-0/<length>`, the length may be `*` when unknown
+            let range = headers.get(CONTENT_RANGE)?.to_str().ok()?;
+            range.rsplit('/').next()?
+        } else {
+            headers.get(CONTENT_LENGTH)?.to_str().ok()?
+        };
+        length.trim().parse().ok()
+    }
+}
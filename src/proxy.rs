@@ -0,0 +1,180 @@
+use crate::spider::matches_host;
+use std::env;
+use std::net::IpAddr;
+use url::Url;
+
+/// Selects the proxy of each request of the clients built by the
+/// [SpiderBuilder](crate::SpiderBuilder): the rules given to the builder first, then the proxies
+/// of the environment.
+#[derive(Debug, Clone)]
+pub(crate) struct ProxyRules {
+    // Checked in order, the first matching pattern wins. `None` connects directly
+    rules: Vec<(String, Option<Url>)>,
+    env: Option<EnvProxies>,
+}
+
+impl ProxyRules {
+    pub(crate) fn new(rules: Vec<(String, Option<Url>)>, env: Option<EnvProxies>) -> Self {
+        Self { rules, env }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.rules.is_empty() && self.env.as_ref().is_none_or(EnvProxies::is_empty)
+    }
+
+    /// The proxy to send the request to `url` through, `None` to connect directly.
+    pub(crate) fn select(&self, url: &Url) -> Option<Url> {
+        let host = url.host_str().unwrap_or_default();
+        match self
+            .rules
+            .iter()
+            .find(|(pattern, _)| matches_host(pattern, host))
+        {
+            Some((_, proxy)) => proxy.clone(),
+            None => self.env.as_ref()?.select(url),
+        }
+    }
+}
+
+/// The proxies configured by the `HTTP_PROXY`, `HTTPS_PROXY`, `ALL_PROXY` and `NO_PROXY`
+/// environment variables, or their lowercase variants which take precedence like they do for
+/// curl.
+#[derive(Debug, Clone)]
+pub(crate) struct EnvProxies {
+    http: Option<Url>,
+    https: Option<Url>,
+    all: Option<Url>,
+    no_proxy: Vec<NoProxy>,
+}
+
+impl EnvProxies {
+    pub(crate) fn from_env() -> Self {
+        // Under CGI, `HTTP_PROXY` can be set by the `Proxy` header of the incoming request
+        let http = if env::var_os("REQUEST_METHOD").is_some() {
+            var(&["http_proxy"])
+        } else {
+            var(&["http_proxy", "HTTP_PROXY"])
+        };
+        Self {
+            http: http.as_deref().and_then(proxy_url),
+            https: var(&["https_proxy", "HTTPS_PROXY"])
+                .as_deref()
+                .and_then(proxy_url),
+            all: var(&["all_proxy", "ALL_PROXY"])
+                .as_deref()
+                .and_then(proxy_url),
+            no_proxy: var(&["no_proxy", "NO_PROXY"])
+                .map(|hosts| hosts.split(',').filter_map(NoProxy::parse).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.http.is_none() && self.https.is_none() && self.all.is_none()
+    }
+
+    fn select(&self, url: &Url) -> Option<Url> {
+        let host = url.host_str().unwrap_or_default();
+        if self.no_proxy.iter().any(|entry| entry.matches(host)) {
+            return None;
+        }
+        let proxy = match url.scheme() {
+            "http" => self.http.as_ref(),
+            "https" => self.https.as_ref(),
+            _ => None,
+        };
+        proxy.or(self.all.as_ref()).cloned()
+    }
+}
+
+/// The value of the first of `names` set to a non empty value.
+fn var(names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.trim().is_empty())
+}
+
+/// Parse a proxy of the environment, which may leave out the `http://` scheme.
+fn proxy_url(proxy: &str) -> Option<Url> {
+    let proxy = proxy.trim();
+    if proxy.contains("://") {
+        Url::parse(proxy).ok()
+    } else {
+        Url::parse(&format!("http://{}", proxy)).ok()
+    }
+}
+
+/// An entry of `NO_PROXY`.
+#[derive(Debug, Clone)]
+enum NoProxy {
+    // `*`, every host
+    All,
+    // A domain and its subdomains
+    Domain(String),
+    // An address, or a range of addresses with a prefix length
+    Network(IpAddr, u8),
+}
+
+impl NoProxy {
+    fn parse(entry: &str) -> Option<Self> {
+        let entry = entry.trim().to_ascii_lowercase();
+        if entry.is_empty() {
+            return None;
+        }
+        if entry == "*" {
+            return Some(NoProxy::All);
+        }
+        let (address, prefix) = match entry.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (entry.as_str(), None),
+        };
+        if let Ok(ip) = address
+            .trim_matches(|c| c == '[' || c == ']')
+            .parse::<IpAddr>()
+        {
+            let bits = if ip.is_ipv4() { 32 } else { 128 };
+            let prefix = match prefix {
+                Some(prefix) => prefix.parse().ok().filter(|prefix| *prefix <= bits)?,
+                None => bits,
+            };
+            return Some(NoProxy::Network(ip, prefix));
+        }
+        // Ports are ignored, the entry applies to the whole host
+        let domain = entry.split(':').next().unwrap_or_default();
+        let domain = domain.trim_start_matches('*').trim_start_matches('.');
+        Some(NoProxy::Domain(domain.to_string()))
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        match self {
+            NoProxy::All => true,
+            NoProxy::Domain(domain) => {
+                host == domain
+                    || host
+                        .strip_suffix(domain.as_str())
+                        .is_some_and(|subdomain| subdomain.ends_with('.'))
+            }
+            NoProxy::Network(network, prefix) => {
+                let ip = match host
+                    .trim_matches(|c| c == '[' || c == ']')
+                    .parse::<IpAddr>()
+                {
+                    Ok(ip) => ip,
+                    Err(_) => return false,
+                };
+                match (network, ip) {
+                    (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                        let mask = u32::MAX.checked_shl(32 - u32::from(*prefix)).unwrap_or(0);
+                        u32::from(*network) & mask == u32::from(ip) & mask
+                    }
+                    (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                        let mask = u128::MAX.checked_shl(128 - u32::from(*prefix)).unwrap_or(0);
+                        u128::from(*network) & mask == u128::from(ip) & mask
+                    }
+                    _ => false,
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+
+/// What the task queue orders its tasks by, the highest first.
+pub(crate) trait Prioritized {
+    fn priority(&self) -> i32;
+}
+
+/// The queue of the callbacks of a crawl, dispatching them by priority and, within a priority, in
+/// the order they were queued.
+///
+/// The callbacks are sent through a bounded channel, and moved into a bounded heap as the manager
+/// takes them out. Both hold at most `capacity` callbacks, which keeps the memory of the queue
+/// bounded while the heap orders the callbacks waiting for a slot.
+///
+/// The priorities only order the callbacks in the heap. Once it is full, the callbacks waiting in
+/// the channel stay in the order they were sent, and join the heap as it makes room for them, so
+/// a callback of a high priority can wait behind `capacity` callbacks of a lower one.
+#[derive(Debug)]
+pub(crate) struct TaskQueue<T> {
+    incoming: Receiver<T>,
+    heap: BinaryHeap<Queued<T>>,
+    capacity: usize,
+    // Orders the tasks of a same priority
+    sequence: u64,
+}
+
+impl<T: Prioritized> TaskQueue<T> {
+    /// A queue holding at most about `size` tasks, with the sender tasks are queued with.
+    pub(crate) fn new(size: usize) -> (Sender<T>, Self) {
+        let capacity = (size / 2).max(1);
+        let (sender, incoming) = channel(capacity);
+        let queue = Self {
+            incoming,
+            heap: BinaryHeap::new(),
+            capacity,
+            sequence: 0,
+        };
+        (sender, queue)
+    }
+
+    /// Take out the task of the highest priority, waiting for one if the queue is empty.
+    ///
+    /// # Returns
+    /// `None` once every sender was dropped and the queue is empty.
+    pub(crate) async fn recv(&mut self) -> Option<T> {
+        if self.heap.is_empty() {
+            let task = self.incoming.recv().await?;
+            self.push(task);
+        }
+        self.fill();
+        self.heap.pop().map(|queued| queued.task)
+    }
+
+    /// Take out the task of the highest priority, if one is queued.
+    pub(crate) fn try_recv(&mut self) -> Option<T> {
+        self.fill();
+        self.heap.pop().map(|queued| queued.task)
+    }
+
+    /// Stop accepting tasks. The queued tasks can still be taken out.
+    pub(crate) fn close(&mut self) {
+        self.incoming.close();
+    }
+
+    /// Returns the number of queued tasks.
+    pub(crate) fn len(&self) -> usize {
+        self.incoming.len() + self.heap.len()
+    }
+
+    // Move the sent tasks into the heap, as long as it has room for them
+    fn fill(&mut self) {
+        while self.heap.len() < self.capacity {
+            match self.incoming.try_recv() {
+                Ok(task) => self.push(task),
+                Err(_) => break,
+            }
+        }
+    }
+
+    fn push(&mut self, task: T) {
+        self.sequence += 1;
+        self.heap.push(Queued {
+            rank: (task.priority(), Reverse(self.sequence)),
+            task,
+        });
+    }
+}
+
+#[derive(Debug)]
+struct Queued<T> {
+    // The priority, then the earliest queued
+    rank: (i32, Reverse<u64>),
+    task: T,
+}
+
+impl<T> PartialEq for Queued<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.rank == other.rank
+    }
+}
+
+impl<T> Eq for Queued<T> {}
+
+impl<T> PartialOrd for Queued<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Queued<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.rank.cmp(&other.rank)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Task(i32, &'static str);
+
+    impl Prioritized for Task {
+        fn priority(&self) -> i32 {
+            self.0
+        }
+    }
+
+    fn drain(queue: &mut TaskQueue<Task>) -> Vec<&'static str> {
+        std::iter::from_fn(|| queue.try_recv())
+            .map(|task| task.1)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn dispatches_by_priority_then_in_order() {
+        let (sender, mut queue) = TaskQueue::new(16);
+        for task in [
+            Task(0, "page 2"),
+            Task(1, "detail a"),
+            Task(-1, "sitemap"),
+            Task(0, "page 3"),
+            Task(1, "detail b"),
+        ] {
+            sender.send(task).await.unwrap();
+        }
+        assert_eq!(queue.len(), 5);
+        assert_eq!(queue.recv().await, Some(Task(1, "detail a")));
+        assert_eq!(
+            drain(&mut queue),
+            ["detail b", "page 2", "page 3", "sitemap"]
+        );
+    }
+
+    #[tokio::test]
+    async fn orders_the_heap_window_only() {
+        // Two tasks fit in the heap, two more in the channel
+        let (sender, mut queue) = TaskQueue::new(4);
+        sender.send(Task(0, "a")).await.unwrap();
+        sender.send(Task(0, "b")).await.unwrap();
+        assert_eq!(queue.recv().await, Some(Task(0, "a")));
+        sender.send(Task(0, "c")).await.unwrap();
+        sender.send(Task(5, "urgent")).await.unwrap();
+        // The heap only had room for "c" when "b" was taken out
+        assert_eq!(drain(&mut queue), ["b", "urgent", "c"]);
+    }
+
+    #[tokio::test]
+    async fn ends_once_closed_and_empty() {
+        let (sender, mut queue) = TaskQueue::new(4);
+        sender.send(Task(0, "a")).await.unwrap();
+        queue.close();
+        assert!(sender.send(Task(0, "b")).await.is_err());
+        assert_eq!(queue.recv().await, Some(Task(0, "a")));
+        assert_eq!(queue.recv().await, None);
+    }
+}
@@ -0,0 +1,416 @@
+use crate::download::{DownloadError, DownloadHandler};
+use flate2::read::{DeflateDecoder, GzDecoder, MultiGzDecoder};
+use futures::future::{self, BoxFuture};
+use reqwest::{
+    header::{HeaderName, HeaderValue, CONTENT_ENCODING, CONTENT_LENGTH, TRANSFER_ENCODING},
+    Client, Method, Request, Response, ResponseBuilderExt, StatusCode,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read};
+use std::sync::Arc;
+use thiserror::Error;
+use url::Url;
+
+#[derive(Error, Debug)]
+pub enum ReplayError {
+    #[error("the archive could not be read: {0}")]
+    Io(#[from] io::Error),
+    #[error("the HAR archive is malformed: {0}")]
+    Har(#[from] serde_json::Error),
+    #[error("the WARC archive is malformed: {0}")]
+    Warc(String),
+}
+
+/// A response recorded in an archive.
+#[derive(Debug, Clone)]
+pub(crate) struct Recorded {
+    pub(crate) status: StatusCode,
+    pub(crate) headers: Vec<(HeaderName, HeaderValue)>,
+    pub(crate) body: Vec<u8>,
+}
+
+impl Recorded {
+    /// The response to a request for `url`.
+    pub(crate) fn to_response(&self, url: Url) -> Response {
+        let mut builder = http::Response::builder().status(self.status).url(url);
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        let response = builder
+            .body(self.body.clone())
+            .expect("valid recorded response");
+        Response::from(response)
+    }
+}
+
+/// A request recorded in a HAR archive, along with its response.
+#[derive(Debug)]
+pub(crate) struct Exchange {
+    pub(crate) method: Method,
+    pub(crate) url: Url,
+    pub(crate) headers: Vec<(HeaderName, HeaderValue)>,
+    pub(crate) response: Recorded,
+}
+
+/// Read the exchanges recorded in a HAR archive, skipping the requests that can't be replayed.
+pub(crate) fn read_har<R: Read>(reader: R) -> Result<Vec<Exchange>, ReplayError> {
+    let har: Har = serde_json::from_reader(reader)?;
+    let mut exchanges = Vec::new();
+    for entry in har.log.entries {
+        let (method, url) = match (
+            Method::from_bytes(entry.request.method.as_bytes()),
+            Url::parse(&entry.request.url),
+        ) {
+            (Ok(method), Ok(url)) => (method, url),
+            _ => continue,
+        };
+        let body = match (entry.response.content.text, entry.response.content.encoding) {
+            (Some(text), Some(encoding)) if encoding == "base64" => base64::decode(text)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            (Some(text), _) => text.into_bytes(),
+            (None, _) => Vec::new(),
+        };
+        // HAR archives store the decoded body
+        let headers = entry
+            .response
+            .headers
+            .into_iter()
+            .filter_map(|header| parse_header(&header.name, &header.value))
+            .filter(|(name, _)| !is_framing_header(name))
+            .collect();
+        exchanges.push(Exchange {
+            method,
+            url,
+            headers: entry
+                .request
+                .headers
+                .into_iter()
+                .filter_map(|header| parse_header(&header.name, &header.value))
+                .collect(),
+            response: Recorded {
+                status: StatusCode::from_u16(entry.response.status).unwrap_or(StatusCode::OK),
+                headers,
+                body,
+            },
+        });
+    }
+    Ok(exchanges)
+}
+
+/// Serves responses out of previously recorded WARC or HAR archives instead of the network, so
+/// handlers can be re-run over archived captures.
+///
+/// Requests are matched on their method and URL. Requests that weren't recorded fail with
+/// [DownloadError::NotRecorded](crate::DownloadError::NotRecorded).
+///
+/// ```no_run
+/// # fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// use scrappy_do::Replay;
+/// use std::fs::File;
+///
+/// let replay = Replay::default()
+///     .with_har(File::open("capture.har")?)?
+///     .with_warc(File::open("crawl.warc.gz")?)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Replay {
+    responses: Arc<HashMap<(Method, Url), Recorded>>,
+}
+
+impl Replay {
+    /// Add the responses recorded in a HAR archive.
+    pub fn with_har<R: Read>(self, reader: R) -> Result<Self, ReplayError> {
+        let mut responses = self.into_responses();
+        for exchange in read_har(reader)? {
+            responses.insert((exchange.method, exchange.url), exchange.response);
+        }
+        Ok(Self::from_responses(responses))
+    }
+
+    /// Add the responses recorded in a WARC archive. Gzipped archives are decompressed
+    /// automatically.
+    pub fn with_warc<R: Read>(self, reader: R) -> Result<Self, ReplayError> {
+        let mut reader = BufReader::new(reader);
+        let gzipped = reader.fill_buf()?.starts_with(&[0x1f, 0x8b]);
+        let mut archive: Box<dyn BufRead> = if gzipped {
+            Box::new(BufReader::new(MultiGzDecoder::new(reader)))
+        } else {
+            Box::new(reader)
+        };
+
+        // Methods are stored in the request records, which point to their response record
+        let mut methods = HashMap::new();
+        let mut recorded = Vec::new();
+        while let Some(record) = read_warc_record(&mut archive)? {
+            match record.header("WARC-Type") {
+                Some("response") => {
+                    let url = match record.header("WARC-Target-URI").map(parse_warc_uri) {
+                        Some(Ok(url)) => url,
+                        _ => continue,
+                    };
+                    let id = record
+                        .header("WARC-Record-ID")
+                        .unwrap_or_default()
+                        .to_string();
+                    recorded.push((id, url, parse_http_response(&record.block)?));
+                }
+                Some("request") => {
+                    if let Some(concurrent_to) = record.header("WARC-Concurrent-To") {
+                        let method = record
+                            .block
+                            .split(|byte| *byte == b' ')
+                            .next()
+                            .and_then(|method| Method::from_bytes(method).ok());
+                        if let Some(method) = method {
+                            methods.insert(concurrent_to.to_string(), method);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut responses = self.into_responses();
+        for (id, url, response) in recorded {
+            let method = methods.remove(&id).unwrap_or(Method::GET);
+            responses.insert((method, url), response);
+        }
+        Ok(Self::from_responses(responses))
+    }
+
+    /// The number of recorded responses.
+    pub fn len(&self) -> usize {
+        self.responses.len()
+    }
+
+    /// Returns true if no responses have been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.responses.is_empty()
+    }
+
+    fn into_responses(self) -> HashMap<(Method, Url), Recorded> {
+        Arc::try_unwrap(self.responses).unwrap_or_else(|responses| (*responses).clone())
+    }
+
+    fn from_responses(responses: HashMap<(Method, Url), Recorded>) -> Self {
+        Self {
+            responses: Arc::new(responses),
+        }
+    }
+
+    fn respond(&self, request: &Request) -> Result<Response, DownloadError> {
+        let key = (request.method().clone(), request.url().clone());
+        let recorded = self
+            .responses
+            .get(&key)
+            .ok_or_else(|| DownloadError::NotRecorded(key.0.clone(), key.1.clone()))?;
+        Ok(recorded.to_response(key.1))
+    }
+}
+
+impl DownloadHandler for Replay {
+    fn download(
+        &self,
+        _client: Client,
+        request: Request,
+    ) -> BoxFuture<'static, Result<Response, DownloadError>> {
+        Box::pin(future::ready(self.respond(&request)))
+    }
+}
+
+#[derive(Deserialize)]
+struct Har {
+    log: HarLog,
+}
+
+#[derive(Deserialize)]
+struct HarLog {
+    entries: Vec<HarEntry>,
+}
+
+#[derive(Deserialize)]
+struct HarEntry {
+    request: HarRequest,
+    response: HarResponse,
+}
+
+#[derive(Deserialize)]
+struct HarRequest {
+    method: String,
+    url: String,
+    #[serde(default)]
+    headers: Vec<HarHeader>,
+}
+
+#[derive(Deserialize)]
+struct HarResponse {
+    status: u16,
+    headers: Vec<HarHeader>,
+    content: HarContent,
+}
+
+#[derive(Deserialize)]
+struct HarHeader {
+    name: String,
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct HarContent {
+    text: Option<String>,
+    encoding: Option<String>,
+}
+
+struct WarcRecord {
+    headers: Vec<(String, String)>,
+    block: Vec<u8>,
+}
+
+impl WarcRecord {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn read_warc_record<R: BufRead + ?Sized>(
+    reader: &mut R,
+) -> Result<Option<WarcRecord>, ReplayError> {
+    // Skip the blank lines separating records
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if !line.trim().is_empty() {
+            break;
+        }
+    }
+    if !line.starts_with("WARC/") {
+        return Err(ReplayError::Warc(format!(
+            "expected a record version line, found {:?}",
+            line.trim()
+        )));
+    }
+
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    let length = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("Content-Length"))
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .ok_or_else(|| ReplayError::Warc("record without a Content-Length".to_string()))?;
+    let mut block = vec![0; length];
+    reader.read_exact(&mut block)?;
+    Ok(Some(WarcRecord { headers, block }))
+}
+
+fn parse_warc_uri(uri: &str) -> Result<Url, url::ParseError> {
+    // WARC/1.0 wraps the URI in angle brackets
+    Url::parse(uri.trim_start_matches('<').trim_end_matches('>'))
+}
+
+fn parse_http_response(message: &[u8]) -> Result<Recorded, ReplayError> {
+    let malformed = || ReplayError::Warc("malformed HTTP response".to_string());
+    let split = message
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(malformed)?;
+    let head = String::from_utf8_lossy(&message[..split]);
+    let mut body = message[split + 4..].to_vec();
+
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|status_line| status_line.split(' ').nth(1))
+        .and_then(|status| status.parse::<u16>().ok())
+        .and_then(|status| StatusCode::from_u16(status).ok())
+        .ok_or_else(malformed)?;
+    let mut headers: Vec<(HeaderName, HeaderValue)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .filter_map(|(name, value)| parse_header(name.trim(), value.trim()))
+        .collect();
+
+    // The archive stores the body as it was sent over the wire
+    let has_value = |headers: &[(HeaderName, HeaderValue)], name: &HeaderName, value: &str| {
+        headers.iter().any(|(header, header_value)| {
+            header == name
+                && header_value
+                    .to_str()
+                    .map(|header_value| header_value.eq_ignore_ascii_case(value))
+                    .unwrap_or(false)
+        })
+    };
+    if has_value(&headers, &TRANSFER_ENCODING, "chunked") {
+        body = dechunk(&body).ok_or_else(malformed)?;
+    }
+    if has_value(&headers, &CONTENT_ENCODING, "gzip") {
+        body = decode(GzDecoder::new(&body[..]))?;
+    } else if has_value(&headers, &CONTENT_ENCODING, "deflate") {
+        body = decode(DeflateDecoder::new(&body[..]))?;
+    } else if headers.iter().any(|(name, _)| name == CONTENT_ENCODING) {
+        // Leave encodings that can't be decoded untouched
+        return Ok(Recorded {
+            status,
+            headers,
+            body,
+        });
+    }
+    headers.retain(|(name, _)| !is_framing_header(name));
+
+    Ok(Recorded {
+        status,
+        headers,
+        body,
+    })
+}
+
+fn dechunk(mut chunked: &[u8]) -> Option<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = chunked.windows(2).position(|window| window == b"\r\n")?;
+        let size = std::str::from_utf8(&chunked[..line_end]).ok()?;
+        // Chunk extensions follow the size
+        let size = size.split(';').next()?.trim();
+        let size = usize::from_str_radix(size, 16).ok()?;
+        chunked = &chunked[line_end + 2..];
+        if size == 0 {
+            return Some(body);
+        }
+        body.extend_from_slice(chunked.get(..size)?);
+        chunked = chunked.get(size + 2..)?;
+    }
+}
+
+fn decode<R: Read>(mut decoder: R) -> Result<Vec<u8>, ReplayError> {
+    let mut body = Vec::new();
+    decoder.read_to_end(&mut body)?;
+    Ok(body)
+}
+
+fn parse_header(name: &str, value: &str) -> Option<(HeaderName, HeaderValue)> {
+    Some((
+        HeaderName::from_bytes(name.as_bytes()).ok()?,
+        HeaderValue::from_str(value).ok()?,
+    ))
+}
+
+/// Headers describing how the recorded body was sent, which no longer apply to the stored body.
+fn is_framing_header(name: &HeaderName) -> bool {
+    name == CONTENT_ENCODING || name == CONTENT_LENGTH || name == TRANSFER_ENCODING
+}
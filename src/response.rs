@@ -0,0 +1,335 @@
+use crate::encoding::ContentCoding;
+use crate::stats::Stats;
+use crate::throttle::Scheduler;
+use crate::util::ParsePool;
+use bytes::Bytes;
+use hyper::client::connect::HttpInfo;
+use reqwest::{
+    header::{HeaderMap, TRANSFER_ENCODING},
+    Method, Response, ResponseBuilderExt, Version,
+};
+use scraper::Html;
+use serde::de::DeserializeOwned;
+use std::net::SocketAddr;
+use std::ops::Deref;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use url::Url;
+
+#[derive(Error, Debug)]
+pub enum JsonError {
+    #[error("the body could not be read: {0}")]
+    Body(#[from] reqwest::Error),
+    #[error("the body is not valid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Describes the callback a response was produced for.
+///
+/// The [url](reqwest::Response::url) of a response reflects the state after redirects, this keeps
+/// what was actually scheduled.
+#[derive(Debug, Clone)]
+pub struct CallbackInfo {
+    pub(crate) url: Url,
+    pub(crate) method: Method,
+    pub(crate) headers: HeaderMap,
+    pub(crate) depth: usize,
+    pub(crate) retries: usize,
+    pub(crate) parent: Option<Arc<Url>>,
+}
+
+impl CallbackInfo {
+    /// The URL of the scheduled request.
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// The method of the scheduled request.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// The headers of the scheduled request.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// How many callbacks separate this callback from the start of the crawl. The initial
+    /// callback has a depth of 0.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// How many times the request has been retried.
+    pub fn retries(&self) -> usize {
+        self.retries
+    }
+
+    /// The URL requested by the callback that produced this callback. `None` for the initial
+    /// callback.
+    pub fn parent(&self) -> Option<&Url> {
+        self.parent.as_deref()
+    }
+}
+
+/// Describes how a response was fetched. It is cheap to copy, so it can be kept around after the
+/// body has been consumed.
+#[derive(Debug, Clone, Copy)]
+pub struct FetchMetrics {
+    pub(crate) duration: Duration,
+    pub(crate) content_length: Option<u64>,
+    pub(crate) remote_addr: Option<SocketAddr>,
+    pub(crate) local_addr: Option<SocketAddr>,
+    pub(crate) connection_reused: Option<bool>,
+    pub(crate) version: Version,
+    pub(crate) content_coding: ContentCoding,
+    pub(crate) chunked: bool,
+    pub(crate) identity_fallback: bool,
+}
+
+impl FetchMetrics {
+    pub(crate) fn new(response: &Response, duration: Duration) -> Self {
+        let chunked = response
+            .headers()
+            .get_all(TRANSFER_ENCODING)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .any(|value| value.to_ascii_lowercase().contains("chunked"));
+        Self {
+            duration,
+            content_length: response.content_length(),
+            remote_addr: response.remote_addr(),
+            local_addr: response
+                .extensions()
+                .get::<HttpInfo>()
+                .map(HttpInfo::local_addr),
+            connection_reused: None,
+            version: response.version(),
+            content_coding: ContentCoding::of(response.headers()),
+            chunked,
+            identity_fallback: false,
+        }
+    }
+
+    /// Mark the response as the uncompressed re-request of a response that failed to decode.
+    pub(crate) fn identity_fallback(mut self) -> Self {
+        self.identity_fallback = true;
+        self
+    }
+
+    /// Find out whether the response came over a connection an earlier response came over.
+    pub(crate) fn track_connection(mut self, stats: &Stats) -> Self {
+        if let (Some(local_addr), Some(remote_addr)) = (self.local_addr, self.remote_addr) {
+            self.connection_reused = Some(stats.record_connection(local_addr, remote_addr));
+        }
+        self
+    }
+
+    /// The time between sending the request and receiving the response headers.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// The size of the body in bytes, if the server announced it.
+    pub fn content_length(&self) -> Option<u64> {
+        self.content_length
+    }
+
+    /// The address of the server that sent the response. `None` for responses that didn't come
+    /// from the network.
+    pub fn remote_addr(&self) -> Option<SocketAddr> {
+        self.remote_addr
+    }
+
+    /// The local address of the connection the response came over. `None` for responses that
+    /// didn't come from the network.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    /// Whether the response came over a pooled connection an earlier response of the crawl came
+    /// over, rather than a new connection. A crawl opening a connection for most of its requests
+    /// to a host is churning connections, for instance because the server closes them or the
+    /// pool is too small. `None` when the connection isn't known.
+    ///
+    /// Connections are told apart by their local address, so a new connection reusing the local
+    /// port of a closed connection to the same server is reported as reused.
+    pub fn connection_reused(&self) -> Option<bool> {
+        self.connection_reused
+    }
+
+    /// The HTTP version of the response. HTTP/2 responses share a single connection to the host.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// The content coding the body was sent with, before the crawl decoded it. The length
+    /// announced by the server is the length of the encoded body.
+    pub fn content_coding(&self) -> ContentCoding {
+        self.content_coding
+    }
+
+    /// Whether the body was sent with the chunked transfer encoding.
+    pub fn is_chunked(&self) -> bool {
+        self.chunked
+    }
+
+    /// Whether the compressed response failed to decode, and this is the response to the same
+    /// request sent again asking for an uncompressed body.
+    pub fn is_identity_fallback(&self) -> bool {
+        self.identity_fallback
+    }
+}
+
+/// The response handed to a [Handler](crate::Handler).
+///
+/// It dereferences to the underlying [Response](reqwest::Response) and forwards the methods
+/// consuming the body.
+#[derive(Debug)]
+pub struct ScrapedResponse {
+    response: Response,
+    info: CallbackInfo,
+    metrics: FetchMetrics,
+    near_duplicate: bool,
+    scheduler: Scheduler,
+    // Counts the bytes of the body, `None` outside of a crawl
+    stats: Option<Arc<Stats>>,
+    parse_pool: ParsePool,
+}
+
+impl ScrapedResponse {
+    pub(crate) fn new(
+        response: Response,
+        info: CallbackInfo,
+        metrics: FetchMetrics,
+        near_duplicate: bool,
+        scheduler: Scheduler,
+        stats: Option<Arc<Stats>>,
+        parse_pool: ParsePool,
+    ) -> Self {
+        Self {
+            response,
+            info,
+            metrics,
+            near_duplicate,
+            scheduler,
+            stats,
+            parse_pool,
+        }
+    }
+
+    /// Returns the [Scheduler](crate::Scheduler) the handler can slow down the crawl with.
+    pub fn scheduler(&self) -> &Scheduler {
+        &self.scheduler
+    }
+
+    /// Returns information about the callback the response was produced for.
+    pub fn info(&self) -> &CallbackInfo {
+        &self.info
+    }
+
+    /// Returns how the response was fetched.
+    pub fn metrics(&self) -> FetchMetrics {
+        self.metrics
+    }
+
+    /// The value of the cookie `name` set by the response, to attach it to the callbacks
+    /// continuing the flow with [Callback::cookie](crate::Callback::cookie). Every cookie set by
+    /// the response is listed by [cookies](reqwest::Response::cookies).
+    ///
+    /// ```no_run
+    /// # #![feature(generators)]
+    /// # use scrappy_do::{handle, wrap, Callback, ScrapedResponse};
+    /// # use reqwest::Client;
+    /// # use slog::Logger;
+    /// #[handle(item = String)]
+    /// fn search(client: Client, response: ScrapedResponse, context: (), logger: Logger) {
+    ///     let token = response.cookie("flow_token");
+    ///     let next = response.url().join("/results").unwrap();
+    ///     let mut callback = Callback::new(wrap!(search), client.get(next).build().unwrap(), ());
+    ///     if let Some(token) = token {
+    ///         callback = callback.cookie("flow_token", token);
+    ///     }
+    ///     yield callback;
+    /// }
+    /// ```
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.response
+            .cookies()
+            .find(|cookie| cookie.name() == name)
+            .map(|cookie| cookie.value().to_string())
+    }
+
+    /// Returns true if the page is nearly identical to a page processed earlier in the crawl.
+    /// Only detected when enabled with
+    /// [near_duplicates](crate::WebBuilder::near_duplicates).
+    pub fn is_near_duplicate(&self) -> bool {
+        self.near_duplicate
+    }
+
+    /// Get the full response text. See [Response::text](reqwest::Response::text).
+    pub async fn text(self) -> reqwest::Result<String> {
+        self.text_with_charset("utf-8").await
+    }
+
+    /// Get the full response text with the given fallback encoding. See
+    /// [Response::text_with_charset](reqwest::Response::text_with_charset).
+    pub async fn text_with_charset(self, default_encoding: &str) -> reqwest::Result<String> {
+        let mut builder = http::Response::builder()
+            .status(self.response.status())
+            .version(self.response.version())
+            .url(self.response.url().clone());
+        for (name, value) in self.response.headers() {
+            builder = builder.header(name, value);
+        }
+        let body = self.bytes().await?;
+        // Decoded by reqwest, from the charset of the response
+        let buffered = builder.body(body).expect("valid buffered response");
+        Response::from(buffered)
+            .text_with_charset(default_encoding)
+            .await
+    }
+
+    /// Parse the full response text as an HTML document and pass it to `extract` on the
+    /// [ParsePool](crate::util::ParsePool) of the crawl, sized by
+    /// [parse_workers](crate::WebBuilder::parse_workers), keeping the runtime free to drive the
+    /// requests. The document isn't `Send`, `extract` returns the data needed from it.
+    pub async fn parse_document<F, T>(self, extract: F) -> reqwest::Result<T>
+    where
+        F: FnOnce(Html) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let pool = self.parse_pool.clone();
+        let text = self.text().await?;
+        Ok(pool.parse_document(text, extract).await)
+    }
+
+    /// Get the full response body as bytes. See [Response::bytes](reqwest::Response::bytes).
+    pub async fn bytes(self) -> reqwest::Result<Bytes> {
+        let body = self.response.bytes().await?;
+        if let Some(stats) = &self.stats {
+            stats.record_downloaded(body.len());
+        }
+        Ok(body)
+    }
+
+    /// Deserialize the full response body as JSON.
+    pub async fn json<T: DeserializeOwned>(self) -> Result<T, JsonError> {
+        let body = self.bytes().await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// Unwrap the underlying response.
+    pub fn into_inner(self) -> Response {
+        self.response
+    }
+}
+
+impl Deref for ScrapedResponse {
+    type Target = Response;
+
+    fn deref(&self) -> &Response {
+        &self.response
+    }
+}
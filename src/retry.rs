@@ -0,0 +1,191 @@
+use crate::download::DownloadError;
+use reqwest::StatusCode;
+use std::time::Duration;
+
+/// The failures of a request that a [RetryPolicy](RetryPolicy) retries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum FailureKind {
+    /// The request timed out.
+    Timeout,
+    /// The connection to the host couldn't be established.
+    Connect,
+    /// The request failed after the connection was established, like a connection reset by the
+    /// host.
+    Request,
+    /// The request was aborted as an [outlier](crate::WebBuilder::outlier_limit).
+    Outlier,
+}
+
+/// Retries the callbacks whose request failed transiently, like a timeout, a connection reset or
+/// a `503 Service Unavailable` response, instead of losing them.
+///
+/// A failed callback goes back to the queue after a delay doubling with every retry, up to a
+/// maximum, without its handler seeing the error. Unlike a [Backoff](crate::Backoff), which
+/// pauses the whole domain once it keeps failing, the policy applies to each callback alone.
+/// Failures the `Backoff` of the web recognizes are left to it. Retries are counted in
+/// [CrawlStats::retries](crate::CrawlStats::retries).
+///
+/// ```
+/// use reqwest::StatusCode;
+/// use scrappy_do::{FailureKind, RetryPolicy};
+/// use std::time::Duration;
+///
+/// let policy = RetryPolicy::new()
+///     .max_retries(5)
+///     .backoff(Duration::from_millis(500), Duration::from_secs(30))
+///     .statuses(vec![StatusCode::TOO_MANY_REQUESTS, StatusCode::SERVICE_UNAVAILABLE])
+///     .errors(vec![FailureKind::Timeout, FailureKind::Request]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_retries: usize,
+    initial_delay: Duration,
+    max_delay: Duration,
+    statuses: Vec<StatusCode>,
+    errors: Vec<FailureKind>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RetryPolicy {
+    /// Retry a callback up to 3 times, after 1 second at first and up to 1 minute. Every
+    /// [FailureKind](FailureKind), and the `429`, `500`, `502`, `503` and `504` statuses are
+    /// retried.
+    pub fn new() -> Self {
+        Self {
+            max_retries: 3,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            statuses: vec![
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            errors: vec![
+                FailureKind::Timeout,
+                FailureKind::Connect,
+                FailureKind::Request,
+                FailureKind::Outlier,
+            ],
+        }
+    }
+
+    /// Give up on a callback that failed more than `max_retries` times, counting it as a failed
+    /// request.
+    pub fn max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Wait `initial_delay` before the first retry of a callback, doubled for every later retry
+    /// up to `max_delay`.
+    pub fn backoff(mut self, initial_delay: Duration, max_delay: Duration) -> Self {
+        self.initial_delay = initial_delay;
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Retry the responses with one of `statuses`, instead of the default ones.
+    pub fn statuses<S: IntoIterator<Item = StatusCode>>(mut self, statuses: S) -> Self {
+        self.statuses = statuses.into_iter().collect();
+        self
+    }
+
+    /// Retry the requests failing with one of `errors`, instead of every kind.
+    pub fn errors<E: IntoIterator<Item = FailureKind>>(mut self, errors: E) -> Self {
+        self.errors = errors.into_iter().collect();
+        self
+    }
+
+    pub(crate) fn retry_limit(&self) -> usize {
+        self.max_retries
+    }
+
+    /// Describes why the request failed, if the policy retries the error.
+    pub(crate) fn check_error(&self, error: &DownloadError) -> Option<String> {
+        let kind = match error {
+            DownloadError::Request(err) if err.is_timeout() => FailureKind::Timeout,
+            DownloadError::Request(err) if err.is_connect() => FailureKind::Connect,
+            DownloadError::Request(err) if err.is_request() || err.is_body() => {
+                FailureKind::Request
+            }
+            DownloadError::Outlier(_) => FailureKind::Outlier,
+            _ => return None,
+        };
+        self.errors.contains(&kind).then(|| error.to_string())
+    }
+
+    /// Describes why the response failed, if the policy retries its status.
+    pub(crate) fn check_status(&self, status: StatusCode) -> Option<String> {
+        self.statuses
+            .contains(&status)
+            .then(|| format!("the response status is {}", status))
+    }
+
+    /// How long to wait before the `retry`th retry of a callback, counting from 1.
+    pub(crate) fn delay(&self, retry: usize) -> Duration {
+        let exponent = retry.saturating_sub(1).min(u32::MAX as usize) as u32;
+        self.initial_delay
+            .saturating_mul(2u32.saturating_pow(exponent))
+            .min(self.max_delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::download::connect_error;
+
+    #[test]
+    fn doubles_the_delay_up_to_the_maximum() {
+        let policy = RetryPolicy::new().backoff(Duration::from_millis(500), Duration::from_secs(3));
+        let delays: Vec<Duration> = (1..=5).map(|retry| policy.delay(retry)).collect();
+        assert_eq!(
+            delays,
+            [500, 1000, 2000, 3000, 3000]
+                .map(Duration::from_millis)
+                .to_vec()
+        );
+        assert_eq!(policy.delay(usize::MAX), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn retries_the_configured_statuses() {
+        let default = RetryPolicy::new();
+        assert!(default
+            .check_status(StatusCode::TOO_MANY_REQUESTS)
+            .is_some());
+        assert!(default.check_status(StatusCode::NOT_FOUND).is_none());
+        let policy = RetryPolicy::new().statuses(vec![StatusCode::NOT_FOUND]);
+        assert!(policy.check_status(StatusCode::NOT_FOUND).is_some());
+        assert!(policy.check_status(StatusCode::TOO_MANY_REQUESTS).is_none());
+    }
+
+    #[test]
+    fn retries_the_configured_errors() {
+        let outlier = DownloadError::Outlier(Duration::from_secs(5));
+        assert!(RetryPolicy::new().check_error(&outlier).is_some());
+        let policy = RetryPolicy::new().errors(vec![FailureKind::Timeout]);
+        assert!(policy.check_error(&outlier).is_none());
+        let banned = DownloadError::Banned("captcha".to_string());
+        assert!(RetryPolicy::new().check_error(&banned).is_none());
+        assert_eq!(RetryPolicy::new().max_retries(5).retry_limit(), 5);
+    }
+
+    #[tokio::test]
+    async fn classifies_connection_failures() {
+        let err = connect_error().await;
+        assert!(RetryPolicy::new().check_error(&err).is_some());
+        let policy = RetryPolicy::new().errors(vec![FailureKind::Connect]);
+        assert!(policy.check_error(&err).is_some());
+        let policy = RetryPolicy::new().errors(vec![FailureKind::Timeout]);
+        assert!(policy.check_error(&err).is_none());
+    }
+}
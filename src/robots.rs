@@ -0,0 +1,223 @@
+use crate::throttle::Gate;
+use reqwest::Client;
+use slog::{debug, warn, Logger};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::OnceCell;
+use url::Url;
+
+/// The part of a robots.txt file that is read, larger files are truncated.
+const MAX_SIZE: usize = 500 * 1024;
+
+/// The robots.txt files of the hosts of a running crawl, fetched before the first request to
+/// each host.
+#[derive(Debug)]
+pub(crate) struct Robots {
+    // The product token matched against the `User-agent` lines
+    user_agent: String,
+    // By origin, filled once the file was fetched
+    origins: Mutex<HashMap<String, Arc<OnceCell<Arc<RobotsTxt>>>>>,
+    // The gates enforcing the `Crawl-delay` of the origins that have one
+    gates: Mutex<HashMap<String, Arc<Gate>>>,
+}
+
+impl Robots {
+    pub(crate) fn new(user_agent: String) -> Self {
+        Self {
+            user_agent,
+            origins: Mutex::new(HashMap::new()),
+            gates: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn origin(&self, url: &Url) -> Arc<OnceCell<Arc<RobotsTxt>>> {
+        let mut origins = self.origins.lock().expect("robots lock");
+        origins
+            .entry(url.origin().ascii_serialization())
+            .or_default()
+            .clone()
+    }
+
+    /// The rules of the host of `url`, if they were fetched.
+    pub(crate) fn rules(&self, url: &Url) -> Option<Arc<RobotsTxt>> {
+        self.origin(url).get().cloned()
+    }
+
+    /// Fetch the robots.txt of the host of `url` with `client`, unless it was already fetched or
+    /// is being fetched, in which case wait for it.
+    pub(crate) async fn fetch(&self, client: Client, url: &Url, logger: &Logger) {
+        let origin = self.origin(url);
+        origin
+            .get_or_init(|| async {
+                let location = match url.join("/robots.txt") {
+                    Ok(location) => location,
+                    Err(_) => return Arc::new(RobotsTxt::allow_all()),
+                };
+                let rules = match download(client, location.clone()).await {
+                    Ok(Some(body)) => RobotsTxt::parse(&body, &self.user_agent),
+                    // The site doesn't restrict crawlers
+                    Ok(None) => RobotsTxt::allow_all(),
+                    Err(err) => {
+                        warn!(logger, "Failed to fetch robots.txt, disallowing the host";
+                              "url" => %location, "error" => %err);
+                        RobotsTxt::disallow_all()
+                    }
+                };
+                debug!(logger, "Fetched robots.txt"; "url" => %location,
+                       "crawl_delay" => ?rules.crawl_delay);
+                Arc::new(rules)
+            })
+            .await;
+    }
+
+    /// The gate enforcing the `Crawl-delay` of the host of `url`, if it has one.
+    pub(crate) fn gate(&self, url: &Url, rules: &RobotsTxt) -> Option<Arc<Gate>> {
+        let delay = rules.crawl_delay?;
+        let mut gates = self.gates.lock().expect("robots lock");
+        let gate = gates
+            .entry(url.origin().ascii_serialization())
+            .or_insert_with(|| Arc::new(Gate::permanent(delay)));
+        Some(gate.clone())
+    }
+}
+
+/// Download the robots.txt at `location`.
+///
+/// # Returns
+/// The file, or `None` if the site doesn't have one. Server errors are errors, the site may be
+/// unable to tell what it allows.
+async fn download(client: Client, location: Url) -> Result<Option<String>, String> {
+    let response = client
+        .get(location)
+        .send()
+        .await
+        .map_err(|err| err.to_string())?;
+    let status = response.status();
+    if status.is_client_error() {
+        return Ok(None);
+    }
+    if !status.is_success() {
+        return Err(format!("status {}", status));
+    }
+    let body = response.bytes().await.map_err(|err| err.to_string())?;
+    let body = &body[..body.len().min(MAX_SIZE)];
+    Ok(Some(String::from_utf8_lossy(body).into_owned()))
+}
+
+/// The rules of a robots.txt file that apply to the crawler, as specified by RFC 9309.
+#[derive(Debug, Default)]
+pub(crate) struct RobotsTxt {
+    // The path patterns and whether they allow the paths they match
+    rules: Vec<(String, bool)>,
+    crawl_delay: Option<Duration>,
+}
+
+impl RobotsTxt {
+    fn allow_all() -> Self {
+        Self::default()
+    }
+
+    fn disallow_all() -> Self {
+        Self {
+            rules: vec![("/".to_string(), false)],
+            crawl_delay: None,
+        }
+    }
+
+    /// Parse the rules of `text` applying to `user_agent`: those of the groups naming the
+    /// longest prefix of it, or else those of the `*` groups.
+    fn parse(text: &str, user_agent: &str) -> Self {
+        let user_agent = user_agent.to_ascii_lowercase();
+        // The most specific group naming the crawler so far, and its rules
+        let mut best: Option<usize> = None;
+        let mut selected = RobotsTxt::default();
+        let mut wildcard = RobotsTxt::default();
+        // How well the group being read matches, `Some(0)` for `*`
+        let mut group: Option<usize> = None;
+        let mut in_agents = false;
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim().to_ascii_lowercase(), value.trim()),
+                None => continue,
+            };
+            if key == "user-agent" {
+                if !in_agents {
+                    group = None;
+                    in_agents = true;
+                }
+                let agent = value.to_ascii_lowercase();
+                let score = if agent == "*" {
+                    Some(0)
+                } else if !agent.is_empty() && user_agent.starts_with(&agent) {
+                    Some(agent.len())
+                } else {
+                    None
+                };
+                group = group.max(score);
+                // A more specific group replaces the rules of the previous ones
+                if group.is_some_and(|group| group > 0 && best.is_none_or(|best| group > best)) {
+                    best = group;
+                    selected = RobotsTxt::default();
+                }
+                continue;
+            }
+            in_agents = false;
+            let target = match group {
+                Some(0) => &mut wildcard,
+                Some(_) if group == best => &mut selected,
+                _ => continue,
+            };
+            match key.as_str() {
+                "allow" | "disallow" if !value.is_empty() => {
+                    target.rules.push((value.to_string(), key == "allow"));
+                }
+                "crawl-delay" => {
+                    target.crawl_delay = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|delay| delay.is_finite() && *delay >= 0.0)
+                        .map(Duration::from_secs_f64);
+                }
+                _ => {}
+            }
+        }
+        match best {
+            Some(_) => selected,
+            None => wildcard,
+        }
+    }
+
+    /// Whether `url` may be crawled. The longest matching pattern wins, `Allow` when an `Allow`
+    /// and a `Disallow` pattern are as long.
+    pub(crate) fn allowed(&self, url: &Url) -> bool {
+        let mut path = url.path().to_string();
+        if path == "/robots.txt" {
+            return true;
+        }
+        if let Some(query) = url.query() {
+            path.push('?');
+            path.push_str(query);
+        }
+        self.rules
+            .iter()
+            .filter(|(pattern, _)| matches_path(pattern.as_bytes(), path.as_bytes()))
+            .max_by_key(|(pattern, allow)| (pattern.len(), *allow))
+            .is_none_or(|(_, allow)| *allow)
+    }
+}
+
+/// Match `path` against the prefix `pattern`, where `*` stands for any sequence of characters
+/// and a final `$` anchors the end of the path.
+fn matches_path(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => true,
+        Some((b'$', [])) => path.is_empty(),
+        Some((b'*', rest)) => (0..=path.len()).any(|skip| matches_path(rest, &path[skip..])),
+        Some((expected, rest)) => match path.split_first() {
+            Some((actual, path)) if actual == expected => matches_path(rest, path),
+            _ => false,
+        },
+    }
+}
@@ -0,0 +1,189 @@
+use crate::callback::Indeterminate;
+use crate::handler::Handler;
+use crate::response::ScrapedResponse;
+use regex::Regex;
+use reqwest::Client;
+use slog::{trace, warn, Logger};
+use std::fmt::{self, Debug, Display};
+use std::sync::Arc;
+use tokio::sync::mpsc::{channel, Receiver};
+use url::Url;
+
+/// Creates a handler for a response.
+type Factory<I, C> = Arc<dyn Fn() -> Box<dyn Handler<I, C>> + Send + Sync>;
+
+/// A handler handing each response to the handler registered for the URL it was requested from,
+/// for crawls following rules rather than chaining handlers by hand.
+///
+/// Routes are regular expressions searched in the whole URL, checked in the order they were
+/// registered. URLs matching no route go to the fallback handler, if there is one.
+///
+/// ```no_run
+/// # #![feature(generators)]
+/// # use scrappy_do::{handle, wrap, Callback, ScrapedResponse, UrlRouter};
+/// # use reqwest::Client;
+/// # use slog::Logger;
+/// #[handle(item = String)]
+/// fn listing(client: Client, response: ScrapedResponse, context: (), logger: Logger) {
+///     // Links are followed without naming their handler
+///     let link = response.url().join("/product/1").unwrap();
+///     yield Callback::follow(client.get(link).build().unwrap(), ());
+/// }
+///
+/// #[handle(item = String)]
+/// fn product(client: Client, response: ScrapedResponse, context: (), logger: Logger) {
+///     yield response.text().await.unwrap();
+/// }
+///
+/// # fn example() -> Result<(), regex::Error> {
+/// let router = UrlRouter::new()
+///     .route(r"/category/[^/]+/?$", wrap!(listing))?
+///     .route(r"/product/\d+", wrap!(product))?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The router is a handler itself, given to [WebBuilder::handler](crate::WebBuilder::handler) or
+/// to the [callbacks](crate::Callback::new) following links. Given to
+/// [WebBuilder::url_router](crate::WebBuilder::url_router), it also picks the handler of the
+/// callbacks created by [Callback::follow](crate::Callback::follow) as soon as they are produced,
+/// and the links matching no route are dropped without being requested.
+pub struct UrlRouter<I, C> {
+    // The URL patterns and their handlers, in the order they were registered
+    routes: Vec<(Regex, Factory<I, C>)>,
+    fallback: Option<Factory<I, C>>,
+    names: Vec<String>,
+}
+
+impl<I: Debug + 'static, C: 'static> Default for UrlRouter<I, C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I: Debug + 'static, C: 'static> UrlRouter<I, C> {
+    /// A router without routes, nor fallback.
+    pub fn new() -> Self {
+        Self {
+            routes: Vec::new(),
+            fallback: None,
+            names: Vec::new(),
+        }
+    }
+
+    /// Route the URLs matching the regular expression `pattern` to `handler`. The first matching
+    /// route is used.
+    ///
+    /// # Errors
+    /// Fails if `pattern` isn't a valid regular expression.
+    pub fn route<H>(mut self, pattern: &str, handler: H) -> Result<Self, regex::Error>
+    where
+        H: Handler<I, C> + Clone + 'static,
+    {
+        let pattern = Regex::new(pattern)?;
+        self.names.push(format!("{} => {}", pattern, handler));
+        self.routes.push((pattern, factory(handler)));
+        Ok(self)
+    }
+
+    /// Route the URLs matching no other route to `fallback`, instead of dropping them.
+    pub fn fallback<H>(mut self, fallback: H) -> Self
+    where
+        H: Handler<I, C> + Clone + 'static,
+    {
+        self.names.push(format!("* => {}", fallback));
+        self.fallback = Some(factory(fallback));
+        self
+    }
+
+    /// The handler of the response to the request of `url`, if a route matches it.
+    pub(crate) fn select(&self, url: &Url) -> Option<Box<dyn Handler<I, C>>> {
+        self.routes
+            .iter()
+            .find(|(pattern, _)| pattern.is_match(url.as_str()))
+            .map(|(_, factory)| factory)
+            .or(self.fallback.as_ref())
+            .map(|factory| factory())
+    }
+}
+
+fn factory<I: Debug + 'static, C: 'static, H>(handler: H) -> Factory<I, C>
+where
+    H: Handler<I, C> + Clone + 'static,
+{
+    Arc::new(move || Box::new(handler.clone()))
+}
+
+impl<I, C> Clone for UrlRouter<I, C> {
+    fn clone(&self) -> Self {
+        Self {
+            routes: self.routes.clone(),
+            fallback: self.fallback.clone(),
+            names: self.names.clone(),
+        }
+    }
+}
+
+impl<I, C> Debug for UrlRouter<I, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("UrlRouter")
+            .field("handlers", &self.names)
+            .finish()
+    }
+}
+
+impl<I, C> Display for UrlRouter<I, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "UrlRouter({})", self.names.join(", "))
+    }
+}
+
+impl<I: Debug + 'static, C: 'static> Handler<I, C> for UrlRouter<I, C> {
+    fn handle(
+        self: Box<Self>,
+        client: Client,
+        response: ScrapedResponse,
+        context: C,
+        logger: Logger,
+    ) -> Receiver<Indeterminate<I, C>> {
+        match self.select(response.info().url()) {
+            Some(handler) => {
+                trace!(logger, "Routing the response by its URL"; "handler" => %handler);
+                handler.handle(client, response, context, logger)
+            }
+            None => {
+                trace!(logger, "No route matches the URL of the response";
+                       "url" => %response.info().url());
+                // Nothing is ever sent, so the router produces nothing
+                let (_, empty) = channel(1);
+                empty
+            }
+        }
+    }
+}
+
+/// The handler of a callback following a link, until the [UrlRouter](UrlRouter) of the web
+/// replaces it.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Unrouted;
+
+impl Display for Unrouted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unrouted")
+    }
+}
+
+impl<I: Debug, C> Handler<I, C> for Unrouted {
+    fn handle(
+        self: Box<Self>,
+        _client: Client,
+        response: ScrapedResponse,
+        _context: C,
+        logger: Logger,
+    ) -> Receiver<Indeterminate<I, C>> {
+        warn!(logger, "The followed link has no handler, the web has no URL router";
+              "url" => %response.info().url());
+        let (_, empty) = channel(1);
+        empty
+    }
+}
@@ -0,0 +1,354 @@
+//! Operational plumbing for binaries embedding the crate.
+//!
+//! A [Runner](Runner) reads the common settings from the command line and the environment,
+//! applies them to a [WebBuilder](crate::WebBuilder), writes the items to the selected output,
+//! stops on `Ctrl-C` or `SIGTERM`, writes a [SchedulerDump](crate::SchedulerDump) to the standard
+//! error on `SIGUSR1`, and turns the [CrawlSummary](crate::CrawlSummary) into an exit
+//! code.
+//!
+//! ```no_run
+//! # #![feature(generators)]
+//! # use scrappy_do::{handle, wrap, ScrapedResponse, Spider, runner::Runner};
+//! # use reqwest::Client;
+//! # use slog::Logger;
+//! # #[handle(item = String)]
+//! # fn handler(client: Client, response: ScrapedResponse, context: (), logger: Logger) {}
+//! use std::process::ExitCode;
+//!
+//! #[tokio::main]
+//! async fn main() -> ExitCode {
+//!     let runner = match Runner::from_env() {
+//!         Ok(runner) => runner,
+//!         Err(err) => {
+//!             eprintln!("{}\n\n{}", err, scrappy_do::runner::USAGE);
+//!             return ExitCode::from(scrappy_do::runner::EXIT_USAGE);
+//!         }
+//!     };
+//!     let client = Client::new();
+//!     let web = runner
+//!         .configure(Spider::new(client.clone(), None).web())
+//!         .start(client.get("http://quotes.toscrape.com").build().unwrap())
+//!         .handler(wrap!(handler))
+//!         .context(())
+//!         .build();
+//!     runner.run(web).await
+//! }
+//! ```
+
+use crate::crawl::StopReason;
+use crate::shard::Shard;
+use crate::spider::{Web, WebBuilder};
+use futures::StreamExt;
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+use thiserror::Error;
+
+/// The exit code when the crawl finished within its limits.
+pub const EXIT_SUCCESS: u8 = 0;
+/// The exit code when the crawl exceeded its failure limits, was failed by a pipeline, broke a
+/// [contract](crate::Contract), or the items couldn't be written.
+pub const EXIT_FAILURE: u8 = 1;
+/// The exit code when the arguments are invalid.
+pub const EXIT_USAGE: u8 = 2;
+/// The exit code when the crawl was stopped by a signal.
+pub const EXIT_INTERRUPTED: u8 = 130;
+
+/// The arguments understood by [Runner::from_env](Runner::from_env).
+pub const USAGE: &str = "\
+Options:
+    --concurrency <n>           Maximum number of concurrent requests [env: SCRAPPY_DO_CONCURRENCY]
+    --latency-budget-ms <ms>    Per domain p95 latency budget [env: SCRAPPY_DO_LATENCY_BUDGET_MS]
+    --output <stdout|discard|path>
+                                Where items are written, one per line [env: SCRAPPY_DO_OUTPUT]
+    --max-failed-requests <n>   Exit with a failure above this many failed requests
+                                [env: SCRAPPY_DO_MAX_FAILED_REQUESTS]
+    --shard <index>/<count>     Only crawl the hosts assigned to this shard, like 0/4
+                                [env: SCRAPPY_DO_SHARD]
+    --contract-run <true|false> Only check the contracts of the handlers, exit with a failure
+                                if one is broken [env: SCRAPPY_DO_CONTRACT_RUN]";
+
+#[derive(Error, Debug)]
+pub enum RunnerError {
+    #[error("unknown argument (given: {0})")]
+    UnknownArgument(String),
+    #[error("missing value for {0}")]
+    MissingValue(String),
+    #[error("invalid value for {0} (given: {1})")]
+    InvalidValue(String, String),
+}
+
+/// Where the items of a crawl are written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Output {
+    /// Write the items to the standard output.
+    Stdout,
+    /// Write the items to a file, replacing its contents.
+    File(PathBuf),
+    /// Drop the items, for crawls run for their side effects.
+    Discard,
+}
+
+/// Runs a crawl with settings read from the command line and the environment.
+#[derive(Debug, Clone)]
+pub struct Runner {
+    concurrent_requests: Option<NonZeroUsize>,
+    domain_latency_budget: Option<Duration>,
+    output: Output,
+    max_failed_requests: Option<u64>,
+    shard: Option<Shard>,
+    contract_run: bool,
+}
+
+impl Runner {
+    /// Read the settings from the process arguments and environment. Arguments take precedence
+    /// over environment variables, see [USAGE](USAGE).
+    pub fn from_env() -> Result<Self, RunnerError> {
+        Self::parse(std::env::args().skip(1), |name| std::env::var(name).ok())
+    }
+
+    /// Read the settings from `args`, which shouldn't include the program name, and the
+    /// environment variables returned by `env`.
+    pub fn parse<A, E>(args: A, env: E) -> Result<Self, RunnerError>
+    where
+        A: IntoIterator<Item = String>,
+        E: Fn(&str) -> Option<String>,
+    {
+        let mut values: Vec<(&str, Option<String>)> = OPTIONS
+            .iter()
+            .map(|(name, variable)| (*name, env(variable)))
+            .collect();
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let slot = values
+                .iter_mut()
+                .find(|(option, _)| *option == name)
+                .ok_or_else(|| RunnerError::UnknownArgument(name.clone()))?;
+            let value = value
+                .or_else(|| args.next())
+                .ok_or(RunnerError::MissingValue(name))?;
+            slot.1 = Some(value);
+        }
+
+        let value = |name: &str| {
+            values
+                .iter()
+                .find(|(option, _)| *option == name)
+                .and_then(|(_, value)| value.clone())
+        };
+        Ok(Self {
+            concurrent_requests: parse_value("--concurrency", value("--concurrency"))?,
+            domain_latency_budget: parse_value::<u64>(
+                "--latency-budget-ms",
+                value("--latency-budget-ms"),
+            )?
+            .map(Duration::from_millis),
+            output: match value("--output").as_deref() {
+                None | Some("stdout") | Some("-") => Output::Stdout,
+                Some("discard") => Output::Discard,
+                Some(path) => Output::File(PathBuf::from(path)),
+            },
+            max_failed_requests: parse_value(
+                "--max-failed-requests",
+                value("--max-failed-requests"),
+            )?,
+            shard: parse_value("--shard", value("--shard"))?,
+            contract_run: parse_value("--contract-run", value("--contract-run"))?.unwrap_or(false),
+        })
+    }
+
+    /// Where the items are written.
+    pub fn output(&self) -> &Output {
+        &self.output
+    }
+
+    /// The shard this process crawls, to split the seeds with
+    /// [Shard::filter](crate::Shard::filter).
+    pub fn shard(&self) -> Option<Shard> {
+        self.shard
+    }
+
+    /// Apply the settings to `builder`. The callbacks are tracked for the scheduler dumps
+    /// written on `SIGUSR1`.
+    pub fn configure<H, C>(&self, mut builder: WebBuilder<H, C>) -> WebBuilder<H, C>
+    where
+        C: Debug + Send + Unpin + 'static,
+    {
+        builder = builder.track_callbacks(true);
+        if let Some(concurrent_requests) = self.concurrent_requests {
+            builder = builder.concurrent_requests(concurrent_requests);
+        }
+        if let Some(budget) = self.domain_latency_budget {
+            builder = builder.domain_latency_budget(budget);
+        }
+        if let Some(shard) = self.shard {
+            builder = builder.shard(shard);
+        }
+        if self.contract_run {
+            builder = builder.contract_run(true);
+        }
+        builder
+    }
+
+    /// Crawl `web`, writing the items to the output until the crawl finishes or a signal is
+    /// received. The first signal lets the executing callbacks finish and writes their items, the
+    /// second aborts the crawl.
+    ///
+    /// # Returns
+    /// The exit code summarizing the crawl, one of the `EXIT_` constants.
+    pub async fn run<I, C>(&self, web: Web<I, C>) -> ExitCode
+    where
+        I: Debug + Send + Unpin + 'static,
+        C: Debug + Send + Unpin + 'static,
+    {
+        let mut output: Box<dyn Write> = match &self.output {
+            Output::Stdout => Box::new(io::stdout()),
+            Output::File(path) => match File::create(path) {
+                Ok(file) => Box::new(BufWriter::new(file)),
+                Err(err) => {
+                    eprintln!("could not create {}: {}", path.display(), err);
+                    return ExitCode::from(EXIT_FAILURE);
+                }
+            },
+            Output::Discard => Box::new(io::sink()),
+        };
+
+        let mut crawl = web.crawl().await;
+        let handle = crawl.handle();
+        let shutdown = shutdown_signal();
+        tokio::pin!(shutdown);
+        let mut dump_signal = DumpSignal::new();
+        loop {
+            tokio::select! {
+                item = crawl.next() => match item {
+                    Some(item) => {
+                        if let Err(err) = writeln!(output, "{:?}", item) {
+                            eprintln!("could not write an item: {}", err);
+                            return ExitCode::from(EXIT_FAILURE);
+                        }
+                    }
+                    None => break,
+                },
+                _ = &mut shutdown => {
+                    if handle.is_shutting_down() {
+                        handle.abort();
+                        return ExitCode::from(EXIT_INTERRUPTED);
+                    }
+                    eprintln!("finishing the pending requests, interrupt again to abort");
+                    handle.graceful_shutdown();
+                    shutdown.set(shutdown_signal());
+                }
+                _ = dump_signal.recv() => eprint!("{}", handle.scheduler_dump()),
+            }
+        }
+        if let Err(err) = output.flush() {
+            eprintln!("could not write the items: {}", err);
+            return ExitCode::from(EXIT_FAILURE);
+        }
+        // Crawls stopping on their own limits aren't interrupted
+        if handle.stop_reason() == Some(StopReason::Shutdown) {
+            return ExitCode::from(EXIT_INTERRUPTED);
+        }
+
+        match (crawl.summary(), self.max_failed_requests) {
+            (None, _) => ExitCode::from(EXIT_FAILURE),
+            (Some(summary), _) if summary.pipeline_failure.is_some() => {
+                ExitCode::from(EXIT_FAILURE)
+            }
+            (Some(summary), _)
+                if summary
+                    .contracts
+                    .as_ref()
+                    .is_some_and(|contracts| !contracts.violations.is_empty()) =>
+            {
+                ExitCode::from(EXIT_FAILURE)
+            }
+            (Some(summary), Some(max)) if summary.stats.failed_requests > max => {
+                ExitCode::from(EXIT_FAILURE)
+            }
+            _ => ExitCode::from(EXIT_SUCCESS),
+        }
+    }
+}
+
+/// The options and the environment variables they can be read from.
+const OPTIONS: &[(&str, &str)] = &[
+    ("--concurrency", "SCRAPPY_DO_CONCURRENCY"),
+    ("--latency-budget-ms", "SCRAPPY_DO_LATENCY_BUDGET_MS"),
+    ("--output", "SCRAPPY_DO_OUTPUT"),
+    ("--max-failed-requests", "SCRAPPY_DO_MAX_FAILED_REQUESTS"),
+    ("--shard", "SCRAPPY_DO_SHARD"),
+    ("--contract-run", "SCRAPPY_DO_CONTRACT_RUN"),
+];
+
+fn parse_value<T: std::str::FromStr>(
+    name: &str,
+    value: Option<String>,
+) -> Result<Option<T>, RunnerError> {
+    value
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| RunnerError::InvalidValue(name.to_string(), value))
+        })
+        .transpose()
+}
+
+/// Resolves once the process is asked to stop.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = interrupt() => {}
+                _ = terminate.recv() => {}
+            }
+            return;
+        }
+    }
+    interrupt().await
+}
+
+async fn interrupt() {
+    // Without a signal handler the crawl can only finish on its own
+    if tokio::signal::ctrl_c().await.is_err() {
+        futures::future::pending::<()>().await;
+    }
+}
+
+/// Receives the requests to dump the state of the scheduler, sent with `SIGUSR1`.
+struct DumpSignal {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl DumpSignal {
+    fn new() -> Self {
+        Self {
+            #[cfg(unix)]
+            signal: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::user_defined1())
+                .ok(),
+        }
+    }
+
+    /// Resolves on the next request.
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.signal {
+            if signal.recv().await.is_some() {
+                return;
+            }
+        }
+        futures::future::pending::<()>().await
+    }
+}
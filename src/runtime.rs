@@ -0,0 +1,100 @@
+use futures::future::BoxFuture;
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::future::Future;
+use std::sync::Arc;
+
+/// Spawns the tasks of a crawl: the task managing the queue, a task per callback, and the task
+/// running each handler.
+///
+/// The default, [Tokio](Tokio), spawns onto the ambient tokio runtime. Implement this trait to
+/// spawn onto a specific runtime instead, for instance when the application runs on async-std or
+/// smol, or forbids spawning onto whatever runtime happens to be current.
+///
+/// The requests themselves are made with reqwest, which needs a tokio reactor to drive its
+/// sockets and timers. A runtime that isn't tokio must poll the tasks within a tokio context,
+/// for instance with async-std's `tokio1` feature or by wrapping them with `async-compat`.
+/// Spawning onto a tokio runtime running on background threads, through its
+/// [Handle](tokio::runtime::Handle), is the simplest option:
+///
+/// ```no_run
+/// # #![feature(generators)]
+/// # use scrappy_do::{handle, wrap, ScrapedResponse, Spider};
+/// # use reqwest::Client;
+/// # use slog::Logger;
+/// # #[handle(item = String)]
+/// # fn handler(client: Client, response: ScrapedResponse, context: (), logger: Logger) {}
+/// use futures::StreamExt;
+///
+/// let background = tokio::runtime::Builder::new_multi_thread()
+///     .enable_all()
+///     .build()
+///     .unwrap();
+/// let spider = Spider::builder().build().unwrap();
+/// let web = spider
+///     .web()
+///     .runtime(background.handle().clone())
+///     .start(spider.client().get("http://quotes.toscrape.com").build().unwrap())
+///     .handler(wrap!(handler))
+///     .context(())
+///     .build();
+///
+/// // The items can be consumed from any executor
+/// let items: Vec<String> = futures::executor::block_on(async {
+///     web.crawl().await.collect().await
+/// });
+/// ```
+pub trait Runtime: Send + Sync + Debug {
+    /// Run `future` to completion in the background.
+    fn spawn(&self, future: BoxFuture<'static, ()>);
+}
+
+/// Spawns onto the ambient tokio runtime, the one the crawl was started from.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tokio;
+
+impl Runtime for Tokio {
+    fn spawn(&self, future: BoxFuture<'static, ()>) {
+        tokio::spawn(future);
+    }
+}
+
+impl Runtime for tokio::runtime::Handle {
+    fn spawn(&self, future: BoxFuture<'static, ()>) {
+        tokio::runtime::Handle::spawn(self, future);
+    }
+}
+
+thread_local! {
+    // The runtime of the crawl calling a handler
+    static CURRENT: RefCell<Option<Arc<dyn Runtime>>> = RefCell::new(None);
+}
+
+/// Call `f` with `runtime` as the runtime handlers spawn onto.
+pub(crate) fn enter<R, F: FnOnce() -> R>(runtime: &Arc<dyn Runtime>, f: F) -> R {
+    let previous = CURRENT.with(|current| current.replace(Some(runtime.clone())));
+    // Restore the previous runtime even if `f` panics
+    struct Restore(Option<Arc<dyn Runtime>>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            CURRENT.with(|current| *current.borrow_mut() = previous);
+        }
+    }
+    let _restore = Restore(previous);
+    f()
+}
+
+/// Spawn the task of a handler onto the runtime of the crawl calling it, or onto the ambient tokio
+/// runtime when the handler is called directly.
+#[doc(hidden)]
+pub fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let runtime = CURRENT.with(|current| current.borrow().clone());
+    match runtime {
+        Some(runtime) => runtime.spawn(Box::pin(future)),
+        None => Tokio.spawn(Box::pin(future)),
+    }
+}
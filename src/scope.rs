@@ -0,0 +1,382 @@
+use crate::callback::Callback;
+use crate::coverage::{FilterReason, UrlOutcome};
+use crate::settings::Settings;
+use crate::spider::{PendingCallback, Shared};
+use crate::throttle::Branch;
+use futures::{future, Stream};
+use std::any::Any;
+use std::fmt::Debug;
+use std::num::NonZeroU64;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::task::{Context, Poll};
+use thiserror::Error;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use url::Url;
+
+/// The number of items of a sub-request buffered until the handler collects them.
+const SCOPE_QUEUE_SIZE: usize = 16;
+
+#[derive(Error, Debug)]
+pub enum JoinError {
+    #[error("the response wasn't produced by a running crawl")]
+    Unavailable,
+    #[error("the crawl stopped before the sub-requests were queued")]
+    Stopped,
+}
+
+/// The limits of a sub-crawl started by a handler with
+/// [Scheduler::sub_crawl](crate::Scheduler::sub_crawl). Callbacks past the limits are dropped.
+///
+/// ```
+/// use scrappy_do::SubCrawl;
+/// use std::num::NonZeroU64;
+///
+/// // The first 3 pages of comments, and at most 50 comments
+/// let limits = SubCrawl::new()
+///     .max_depth(2)
+///     .max_items(NonZeroU64::new(50).unwrap());
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SubCrawl {
+    max_depth: Option<usize>,
+    max_items: Option<NonZeroU64>,
+}
+
+impl SubCrawl {
+    /// A sub-crawl without limits, following callbacks until none are left.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Follow callbacks at most `max_depth` callbacks away from the sub-requests. With a depth
+    /// of 0 only the sub-requests are executed.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Stop the sub-crawl once it produced `max_items` items.
+    pub fn max_items(mut self, max_items: NonZeroU64) -> Self {
+        self.max_items = Some(max_items);
+        self
+    }
+}
+
+/// The limits of a sub-crawl, shared by all of its callbacks.
+#[derive(Debug)]
+pub(crate) struct Budget {
+    limits: SubCrawl,
+    // The depth of the sub-requests in the crawl
+    root_depth: usize,
+    items: AtomicU64,
+}
+
+impl Budget {
+    /// Whether a callback at `depth` in the crawl is within the limits.
+    pub(crate) fn follows(&self, depth: usize) -> bool {
+        self.limits
+            .max_depth
+            .is_none_or(|max_depth| depth.saturating_sub(self.root_depth) <= max_depth)
+    }
+
+    /// Count an item of the sub-crawl.
+    ///
+    /// # Returns
+    /// Whether the item is within the limits.
+    pub(crate) fn take_item(&self) -> bool {
+        let max_items = self.limits.max_items;
+        self.items
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |items| {
+                max_items
+                    .is_none_or(|max_items| items < max_items.get())
+                    .then_some(items + 1)
+            })
+            .is_ok()
+    }
+
+    /// Whether the sub-crawl produced all the items it may.
+    pub(crate) fn exhausted(&self) -> bool {
+        self.limits
+            .max_items
+            .is_some_and(|max_items| self.items.load(Ordering::Acquire) >= max_items.get())
+    }
+}
+
+/// The items of a sub-crawl, in the order they are produced. The stream ends once the sub-crawl
+/// finished, and the sub-crawl stops once the stream is dropped.
+#[derive(Debug)]
+pub struct SubCrawlItems<I> {
+    items: Receiver<I>,
+    // `None` once the sub-crawl finished
+    _lent: Option<LentPermit>,
+}
+
+impl<I> Stream for SubCrawlItems<I> {
+    type Item = I;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<I>> {
+        self.get_mut().items.poll_recv(cx)
+    }
+}
+
+/// A type erased [Scope](Scope), handed to the [Scheduler](crate::Scheduler) of the response.
+/// Only valid while the handler runs.
+pub(crate) type ScopeHandle = Weak<dyn Any + Send + Sync>;
+
+/// Lets the handler of a response queue sub-requests in the crawl and collect their items.
+#[derive(Debug)]
+pub(crate) struct Scope<I, C> {
+    task_sender: Sender<PendingCallback<I, C>>,
+    shared: Arc<Shared>,
+    // The callback of the response, which the sub-requests descend from
+    parent: Arc<Url>,
+    depth: usize,
+    branch: Branch,
+}
+
+impl<I, C> Scope<I, C>
+where
+    I: Debug + Send + 'static,
+    C: Debug + Send + 'static,
+{
+    pub(crate) fn new(
+        task_sender: Sender<PendingCallback<I, C>>,
+        shared: Arc<Shared>,
+        parent: Arc<Url>,
+        depth: usize,
+        branch: Branch,
+    ) -> Self {
+        Self {
+            task_sender,
+            shared,
+            parent,
+            depth,
+            branch,
+        }
+    }
+
+    /// Queue `callbacks` and wait until they, and the callbacks they produce, finished.
+    ///
+    /// # Returns
+    /// The items produced for every callback, in the order of the callbacks.
+    pub(crate) async fn join(
+        &self,
+        callbacks: Vec<Callback<I, C>>,
+    ) -> Result<Vec<Vec<I>>, JoinError> {
+        let mut receivers = Vec::with_capacity(callbacks.len());
+        for callback in callbacks {
+            let (item_sender, item_receiver) = channel(SCOPE_QUEUE_SIZE);
+            self.queue(callback, item_sender, None).await?;
+            receivers.push(item_receiver);
+        }
+        // The sub-requests may need the permit the handler holds
+        let lent = LentPermit::new(self.shared.settings.clone());
+        let items = future::join_all(receivers.into_iter().map(collect)).await;
+        lent.take_back().await;
+        Ok(items)
+    }
+
+    /// Queue `callbacks` as a sub-crawl within `limits`.
+    ///
+    /// # Returns
+    /// The items produced by the sub-crawl.
+    pub(crate) async fn sub_crawl(
+        &self,
+        limits: SubCrawl,
+        callbacks: Vec<Callback<I, C>>,
+    ) -> Result<SubCrawlItems<I>, JoinError> {
+        let budget = Arc::new(Budget {
+            limits,
+            root_depth: self.depth + 1,
+            items: AtomicU64::new(0),
+        });
+        let (item_sender, items) = channel(SCOPE_QUEUE_SIZE);
+        for callback in callbacks {
+            self.queue(callback, item_sender.clone(), Some(budget.clone()))
+                .await?;
+        }
+        drop(item_sender);
+        let lent = LentPermit::new(self.shared.settings.clone());
+        if !self.shared.settings.deterministic() {
+            return Ok(SubCrawlItems {
+                items,
+                _lent: Some(lent),
+            });
+        }
+        // The handler would otherwise run alongside the sub-crawl
+        let collected = collect(items).await;
+        lent.take_back().await;
+        let (item_sender, items) = channel(collected.len().max(1));
+        for item in collected {
+            item_sender.try_send(item).expect("room for the items");
+        }
+        Ok(SubCrawlItems { items, _lent: None })
+    }
+
+    /// Queue `callback` as a sub-request of the response, sending its items to `item_sender`.
+    async fn queue(
+        &self,
+        mut callback: Callback<I, C>,
+        item_sender: Sender<I>,
+        budget: Option<Arc<Budget>>,
+    ) -> Result<(), JoinError> {
+        let shared = &self.shared;
+        callback.descend_from(&self.parent, self.depth);
+        callback.set_gate(self.branch.lock().expect("branch lock").clone());
+        let routed = shared.route(&mut callback);
+        if let Some(coverage) = &shared.coverage {
+            coverage.discovered(callback.target().url().as_str(), callback.handler_name());
+        }
+        if !routed {
+            shared.stats.record_dropped_callback();
+            shared.record_coverage(
+                callback.target().url(),
+                UrlOutcome::Filtered(FilterReason::NoRoute),
+            );
+            return Ok(());
+        }
+        let domain = callback.domain().to_string();
+        shared.stats.record_enqueued(&domain);
+        let pending = PendingCallback::scoped(
+            shared.tracker.queued(&callback),
+            callback,
+            self.task_sender.clone(),
+            item_sender,
+            budget,
+        );
+        if let Err(err) = self.task_sender.send(pending).await {
+            shared.stats.record_dequeued(&domain);
+            shared.tracker.finished(err.0.id());
+            return Err(JoinError::Stopped);
+        }
+        Ok(())
+    }
+}
+
+/// Collect the items of a sub-request, until it and the callbacks it produced finished.
+pub(crate) async fn collect<I>(mut items: Receiver<I>) -> Vec<I> {
+    let mut collected = Vec::new();
+    while let Some(item) = items.recv().await {
+        collected.push(item);
+    }
+    collected
+}
+
+/// A permit lent to the crawl while a handler waits for its sub-requests, taken back once
+/// dropped.
+#[derive(Debug)]
+struct LentPermit(Option<Arc<Settings>>);
+
+impl LentPermit {
+    fn new(settings: Arc<Settings>) -> Self {
+        settings.lend_permit();
+        Self(Some(settings))
+    }
+
+    /// Take the permit back, once it is free if the crawl is deterministic.
+    async fn take_back(mut self) {
+        if let Some(settings) = self.0.take() {
+            settings.take_back_permit().await;
+        }
+    }
+}
+
+impl Drop for LentPermit {
+    fn drop(&mut self) {
+        if let Some(settings) = self.0.take() {
+            settings.reclaim_permit();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    fn budget(limits: SubCrawl) -> Budget {
+        Budget {
+            limits,
+            root_depth: 3,
+            items: AtomicU64::new(0),
+        }
+    }
+
+    #[test]
+    fn follows_callbacks_within_the_depth() {
+        let one_page = budget(SubCrawl::new().max_depth(1));
+        assert!(one_page.follows(3));
+        assert!(one_page.follows(4));
+        assert!(!one_page.follows(5));
+
+        let sub_requests = budget(SubCrawl::new().max_depth(0));
+        assert!(sub_requests.follows(3));
+        assert!(!sub_requests.follows(4));
+        assert!(budget(SubCrawl::new()).follows(100));
+    }
+
+    #[test]
+    fn takes_items_up_to_the_limit() {
+        let two_items = budget(SubCrawl::new().max_items(NonZeroU64::new(2).unwrap()));
+        assert!(!two_items.exhausted());
+        assert!(two_items.take_item());
+        assert!(two_items.take_item());
+        assert!(two_items.exhausted());
+        assert!(!two_items.take_item());
+
+        let unlimited = budget(SubCrawl::new());
+        assert!((0..1000).all(|_| unlimited.take_item()));
+        assert!(!unlimited.exhausted());
+    }
+
+    #[tokio::test]
+    async fn lends_a_permit_while_waiting() {
+        let settings = Arc::new(Settings::new(1, None, None, false));
+        let held = settings.acquire().await;
+        assert!(settings.acquire().now_or_never().is_none());
+
+        let lent = LentPermit::new(settings.clone());
+        let sub_request = settings.acquire().now_or_never().expect("lent permit");
+        // Taken back once the sub-request using it finishes
+        drop(lent);
+        settings.release(sub_request);
+        assert!(settings.acquire().now_or_never().is_none());
+
+        settings.release(held);
+        let next = settings.acquire().now_or_never().expect("returned permit");
+        assert!(settings.acquire().now_or_never().is_none());
+        drop(next);
+    }
+
+    #[tokio::test]
+    async fn takes_back_an_unused_permit() {
+        let settings = Arc::new(Settings::new(1, None, None, false));
+        let held = settings.acquire().await;
+        drop(LentPermit::new(settings.clone()));
+        assert!(settings.acquire().now_or_never().is_none());
+        settings.release(held);
+        assert!(settings.acquire().now_or_never().is_some());
+    }
+
+    #[tokio::test]
+    async fn hands_the_permit_over_while_deterministic() {
+        let settings = Arc::new(Settings::new(1, None, None, true));
+        settings.set_concurrent_requests(4);
+        assert_eq!(settings.concurrent_requests(), 1);
+        let held = settings.acquire().await;
+
+        let lent = LentPermit::new(settings.clone());
+        let sub_request = settings.acquire().now_or_never().expect("lent permit");
+        let mut taking_back = Box::pin(lent.take_back());
+        // The handler only resumes once the sub-request finished
+        assert!((&mut taking_back).now_or_never().is_none());
+        settings.release(sub_request);
+        assert!(taking_back.now_or_never().is_some());
+        assert!(settings.acquire().now_or_never().is_none());
+
+        settings.release(held);
+        assert!(settings.acquire().now_or_never().is_some());
+    }
+}
@@ -0,0 +1,181 @@
+use crate::callback::Callback;
+use crate::coverage::{FilterReason, UrlOutcome};
+use crate::crawl::CrawlHandle;
+use crate::scope::collect;
+use crate::spider::{PendingCallback, Shared};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use std::fmt::{self, Debug};
+use std::future::Future;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::mpsc::{channel, Sender};
+
+/// The number of items of a call buffered until they are collected.
+const CALL_QUEUE_SIZE: usize = 16;
+
+#[derive(Error, Debug)]
+pub enum ServiceError {
+    #[error("the crawl of the service stopped before the callback was queued")]
+    Stopped,
+}
+
+/// Executes callbacks on demand through the full stack of a [Web](crate::Web): its download
+/// handlers, headers and credentials, its retries, bans and backoffs, its throttles and its
+/// concurrency limit. Useful to embed the fetch and parse machinery of a crawler in an
+/// application making one-off lookups.
+///
+/// Every call returns the items produced by its callback and by the callbacks it produced, once
+/// they all finished. The items don't go through the [pipelines](crate::WebBuilder::pipeline) of
+/// the web, and the request of a call is made even if the web
+/// [skips duplicate requests](crate::WebBuilder::dedup). A request that fails produces no items.
+///
+/// The service is started by [WebBuilder::service](crate::WebBuilder::service) and shares one
+/// crawl between its clones, which ends once the last clone is dropped. With the `tower` feature
+/// the service implements `tower::Service`.
+///
+/// ```no_run
+/// # #![feature(generators)]
+/// # use scrappy_do::{handle, wrap, Callback, ContentRouter, ScrapedResponse, Spider};
+/// # use reqwest::Client;
+/// # use slog::Logger;
+/// #[handle(item = String)]
+/// fn title(client: Client, response: ScrapedResponse, context: (), logger: Logger) {
+///     yield response.text().await.unwrap();
+/// }
+///
+/// # async fn example(spider: Spider) {
+/// // The handler of each call is given with its request
+/// let service = spider
+///     .web::<ContentRouter<String, ()>, String, ()>()
+///     .domain_delay(std::time::Duration::from_secs(1))
+///     .service()
+///     .await;
+/// let request = spider.client().get("http://quotes.toscrape.com").build().unwrap();
+/// let titles = service
+///     .call(Callback::new(wrap!(title), request, ()))
+///     .await
+///     .unwrap();
+/// # }
+/// ```
+pub struct WebService<I, C> {
+    // Keeps the crawl running while the service is alive
+    task_sender: Sender<PendingCallback<I, C>>,
+    shared: Arc<Shared>,
+    handle: CrawlHandle,
+}
+
+impl<I, C> WebService<I, C>
+where
+    I: Debug + Send + 'static,
+    C: Debug + Send + 'static,
+{
+    pub(crate) fn new(
+        task_sender: Sender<PendingCallback<I, C>>,
+        shared: Arc<Shared>,
+        handle: CrawlHandle,
+    ) -> Self {
+        Self {
+            task_sender,
+            shared,
+            handle,
+        }
+    }
+
+    /// Execute `callback`, and the callbacks it produces. The call doesn't borrow the service,
+    /// several calls can run at once.
+    ///
+    /// # Returns
+    /// The items they produced, in the order they were produced.
+    pub fn call(
+        &self,
+        callback: Callback<I, C>,
+    ) -> impl Future<Output = Result<Vec<I>, ServiceError>> + Send + 'static {
+        self.execute(callback)
+    }
+
+    /// Returns a handle to control the crawl of the service, like its concurrency.
+    pub fn handle(&self) -> CrawlHandle {
+        self.handle.clone()
+    }
+
+    fn execute(
+        &self,
+        mut callback: Callback<I, C>,
+    ) -> BoxFuture<'static, Result<Vec<I>, ServiceError>> {
+        let task_sender = self.task_sender.clone();
+        let shared = self.shared.clone();
+        async move {
+            let routed = shared.route(&mut callback);
+            if let Some(coverage) = &shared.coverage {
+                coverage.discovered(callback.target().url().as_str(), callback.handler_name());
+            }
+            if !routed {
+                shared.stats.record_dropped_callback();
+                shared.record_coverage(
+                    callback.target().url(),
+                    UrlOutcome::Filtered(FilterReason::NoRoute),
+                );
+                return Ok(Vec::new());
+            }
+            let domain = callback.domain().to_string();
+            shared.stats.record_enqueued(&domain);
+            let (item_sender, items) = channel(CALL_QUEUE_SIZE);
+            let pending = PendingCallback::scoped(
+                shared.tracker.queued(&callback),
+                callback,
+                task_sender.clone(),
+                item_sender,
+                None,
+            );
+            if let Err(err) = task_sender.send(pending).await {
+                shared.stats.record_dequeued(&domain);
+                shared.tracker.finished(err.0.id());
+                return Err(ServiceError::Stopped);
+            }
+            Ok(collect(items).await)
+        }
+        .boxed()
+    }
+}
+
+impl<I, C> Clone for WebService<I, C> {
+    fn clone(&self) -> Self {
+        Self {
+            task_sender: self.task_sender.clone(),
+            shared: self.shared.clone(),
+            handle: self.handle.clone(),
+        }
+    }
+}
+
+impl<I, C> Debug for WebService<I, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WebService")
+            .field("handle", &self.handle)
+            .finish()
+    }
+}
+
+#[cfg(feature = "tower")]
+impl<I, C> tower_service::Service<Callback<I, C>> for WebService<I, C>
+where
+    I: Debug + Send + 'static,
+    C: Debug + Send + 'static,
+{
+    type Response = Vec<I>;
+    type Error = ServiceError;
+    type Future = BoxFuture<'static, Result<Vec<I>, ServiceError>>;
+
+    fn poll_ready(
+        &mut self,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), ServiceError>> {
+        // Calls wait for room in the queue of the crawl
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, callback: Callback<I, C>) -> Self::Future {
+        self.execute(callback)
+    }
+}
@@ -0,0 +1,247 @@
+use crate::crawl::StopReason;
+use std::num::NonZeroU64;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+use std::time::{Duration, Instant};
+use tokio::sync::{watch, OwnedSemaphorePermit, Semaphore};
+
+/// How far a crawl is from stopping, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Shutdown {
+    Running,
+    // Callbacks are no longer executed, the executing ones finish
+    Draining,
+    // The executing callbacks are cancelled
+    Aborted,
+}
+
+/// The settings of a crawl that can be changed while it is running.
+#[derive(Debug)]
+pub(crate) struct Settings {
+    // Each executing callback holds a permit for its whole lifetime
+    permits: Arc<Semaphore>,
+    concurrent_requests: Mutex<usize>,
+    // Permits still held by executing callbacks after the limit was lowered
+    excess_permits: AtomicUsize,
+    domain_latency_budget: Mutex<Option<Duration>>,
+    bandwidth: Mutex<Option<Pacer>>,
+    shutdown: watch::Sender<Shutdown>,
+    // Why the crawl stopped, or is stopping
+    stop_reason: Mutex<Option<StopReason>>,
+    // Executes a single callback at a time, whatever changes the limit
+    deterministic: bool,
+}
+
+/// Paces the requests of a crawl to its bandwidth, a token bucket holding up to a second worth
+/// of bytes.
+#[derive(Debug)]
+struct Pacer {
+    bytes_per_second: NonZeroU64,
+    tokens: f64,
+    refilled: Instant,
+    // The bytes downloaded by the crawl when the bucket was last debited, `None` until then
+    downloaded: Option<u64>,
+}
+
+impl Pacer {
+    fn new(bytes_per_second: NonZeroU64) -> Self {
+        Self {
+            bytes_per_second,
+            tokens: bytes_per_second.get() as f64,
+            refilled: Instant::now(),
+            downloaded: None,
+        }
+    }
+
+    fn delay(&mut self, downloaded: u64) -> Option<Duration> {
+        let rate = self.bytes_per_second.get() as f64;
+        let now = Instant::now();
+        let refill = now.duration_since(self.refilled).as_secs_f64() * rate;
+        self.tokens = (self.tokens + refill).min(rate);
+        self.refilled = now;
+        let spent = downloaded.saturating_sub(self.downloaded.unwrap_or(downloaded));
+        self.downloaded = Some(downloaded);
+        self.tokens -= spent as f64;
+        (self.tokens < 0.0).then(|| Duration::from_secs_f64(-self.tokens / rate))
+    }
+}
+
+impl Settings {
+    pub(crate) fn new(
+        concurrent_requests: usize,
+        domain_latency_budget: Option<Duration>,
+        bandwidth: Option<NonZeroU64>,
+        deterministic: bool,
+    ) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(concurrent_requests)),
+            concurrent_requests: Mutex::new(concurrent_requests),
+            excess_permits: AtomicUsize::new(0),
+            domain_latency_budget: Mutex::new(domain_latency_budget),
+            bandwidth: Mutex::new(bandwidth.map(Pacer::new)),
+            shutdown: watch::Sender::new(Shutdown::Running),
+            stop_reason: Mutex::new(None),
+            deterministic,
+        }
+    }
+
+    /// Wait until another callback is allowed to execute.
+    pub(crate) async fn acquire(&self) -> OwnedSemaphorePermit {
+        self.permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("open semaphore")
+    }
+
+    /// Return the permit of a finished callback.
+    pub(crate) fn release(&self, permit: OwnedSemaphorePermit) {
+        let retired = self
+            .excess_permits
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |excess| {
+                excess.checked_sub(1)
+            })
+            .is_ok();
+        if retired {
+            permit.forget();
+        }
+    }
+
+    /// Allow one more callback to execute, while an executing callback waits on others. While
+    /// deterministic the waiting callback hands its own permit over instead, and only takes it
+    /// back [once it is free](Settings::take_back_permit).
+    pub(crate) fn lend_permit(&self) {
+        self.permits.add_permits(1);
+    }
+
+    /// Take back a permit given by [lend_permit](Settings::lend_permit). While deterministic
+    /// this waits until no other callback uses it, so the waiting callback never resumes
+    /// alongside another.
+    pub(crate) async fn take_back_permit(&self) {
+        if self.deterministic {
+            self.acquire().await.forget();
+        } else {
+            self.reclaim_permit();
+        }
+    }
+
+    /// Take back a permit given by [lend_permit](Settings::lend_permit) without waiting, when
+    /// the callback that waited is dropped.
+    pub(crate) fn reclaim_permit(&self) {
+        if self.permits.forget_permits(1) == 0 {
+            // Retired once the callback using it finishes
+            self.excess_permits.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+
+    pub(crate) fn concurrent_requests(&self) -> usize {
+        *self.concurrent_requests.lock().expect("settings lock")
+    }
+
+    pub(crate) fn deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    /// Change the limit of concurrent callbacks, unless the crawl is deterministic.
+    pub(crate) fn set_concurrent_requests(&self, concurrent_requests: usize) {
+        if self.deterministic {
+            return;
+        }
+        let mut current = self.concurrent_requests.lock().expect("settings lock");
+        if concurrent_requests > *current {
+            let mut added = concurrent_requests - *current;
+            // Cancel out permits that were waiting to be retired first
+            while added > 0
+                && self
+                    .excess_permits
+                    .fetch_update(Ordering::AcqRel, Ordering::Acquire, |excess| {
+                        excess.checked_sub(1)
+                    })
+                    .is_ok()
+            {
+                added -= 1;
+            }
+            self.permits.add_permits(added);
+        } else {
+            let removed = *current - concurrent_requests;
+            // Permits held by executing callbacks are retired once they finish
+            let forgotten = self.permits.forget_permits(removed);
+            self.excess_permits
+                .fetch_add(removed - forgotten, Ordering::AcqRel);
+        }
+        *current = concurrent_requests;
+    }
+
+    pub(crate) fn domain_latency_budget(&self) -> Option<Duration> {
+        *self.domain_latency_budget.lock().expect("settings lock")
+    }
+
+    pub(crate) fn set_domain_latency_budget(&self, budget: Option<Duration>) {
+        *self.domain_latency_budget.lock().expect("settings lock") = budget;
+    }
+
+    pub(crate) fn bandwidth(&self) -> Option<NonZeroU64> {
+        self.bandwidth
+            .lock()
+            .expect("settings lock")
+            .as_ref()
+            .map(|pacer| pacer.bytes_per_second)
+    }
+
+    pub(crate) fn set_bandwidth(&self, bandwidth: Option<NonZeroU64>) {
+        let mut pacer = self.bandwidth.lock().expect("settings lock");
+        match (&mut *pacer, bandwidth) {
+            (Some(pacer), Some(bandwidth)) => pacer.bytes_per_second = bandwidth,
+            (pacer, bandwidth) => *pacer = bandwidth.map(Pacer::new),
+        }
+    }
+
+    /// How long to wait before the next request, for the crawl to stay within its bandwidth
+    /// after downloading `downloaded` bytes in total.
+    pub(crate) fn pace(&self, downloaded: u64) -> Option<Duration> {
+        self.bandwidth
+            .lock()
+            .expect("settings lock")
+            .as_mut()
+            .and_then(|pacer| pacer.delay(downloaded))
+    }
+
+    pub(crate) fn shutdown(&self) -> Shutdown {
+        *self.shutdown.borrow()
+    }
+
+    /// Move the crawl on to `shutdown` because of `reason`. A crawl never moves back, an aborted
+    /// crawl stays aborted.
+    pub(crate) fn stop(&self, shutdown: Shutdown, reason: StopReason) {
+        self.shutdown.send_if_modified(|current| {
+            let moved = shutdown > *current;
+            if moved {
+                *current = shutdown;
+                *self.stop_reason.lock().expect("settings lock") = Some(reason);
+            }
+            moved
+        });
+    }
+
+    pub(crate) fn stop_reason(&self) -> Option<StopReason> {
+        *self.stop_reason.lock().expect("settings lock")
+    }
+
+    /// Record why the crawl stopped, once it did, unless it was asked to stop.
+    pub(crate) fn finish(&self, reason: StopReason) -> StopReason {
+        *self
+            .stop_reason
+            .lock()
+            .expect("settings lock")
+            .get_or_insert(reason)
+    }
+
+    /// Wait until the crawl moved on to `shutdown`, or further.
+    pub(crate) async fn stopping(&self, shutdown: Shutdown) {
+        let mut changes = self.shutdown.subscribe();
+        // The sender lives as long as the settings
+        let _ = changes.wait_for(|current| *current >= shutdown).await;
+    }
+}
@@ -0,0 +1,125 @@
+use crate::pipeline::Fnv64;
+use std::hash::Hasher;
+use std::str::FromStr;
+use thiserror::Error;
+use url::Url;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ParseShardError {
+    #[error("expected <index>/<count> (given: {0})")]
+    Format(String),
+    #[error("the shard index must be lower than the shard count (given: {0})")]
+    OutOfRange(String),
+}
+
+/// What is hashed to assign a URL to a shard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShardKey {
+    /// The host of the URL, every page of a site is crawled by the same process which keeps the
+    /// per host settings, like politeness, meaningful.
+    Host,
+    /// The whole URL, to spread a single large site across processes.
+    Url,
+}
+
+/// One of `count` shards URLs are assigned to by hashing their host, so several processes can
+/// split a crawl without overlapping work. Each process is given the same seeds and the index of
+/// its shard, and only crawls the URLs of its shard.
+///
+/// The assignment only depends on the host (or URL) and the shard count, so it is the same
+/// across processes and machines. Given to [WebBuilder::shard](crate::WebBuilder::shard), the
+/// callbacks of other shards are dropped when handlers produce them.
+///
+/// ```
+/// use scrappy_do::Shard;
+/// use url::Url;
+///
+/// let shard: Shard = "1/4".parse().unwrap();
+/// let seeds = vec![
+///     Url::parse("https://example.com/").unwrap(),
+///     Url::parse("https://example.org/").unwrap(),
+///     Url::parse("https://example.net/").unwrap(),
+/// ];
+/// for seed in shard.filter(seeds) {
+///     println!("crawling {}", seed);
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    index: usize,
+    count: usize,
+    key: ShardKey,
+}
+
+impl Shard {
+    /// The shard `index` (starting at 0) out of `count`, assigning URLs by host.
+    ///
+    /// # Panics
+    /// Panics if `index` isn't lower than `count`.
+    pub fn new(index: usize, count: usize) -> Self {
+        assert!(
+            index < count,
+            "the shard index must be lower than the count"
+        );
+        Self {
+            index,
+            count,
+            key: ShardKey::Host,
+        }
+    }
+
+    /// Set what is hashed to assign URLs to shards.
+    pub fn key(mut self, key: ShardKey) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// The index of the shard.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The number of shards.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// The index of the shard `url` is assigned to.
+    pub fn of(&self, url: &Url) -> usize {
+        let mut hasher = Fnv64::default();
+        match self.key {
+            ShardKey::Host => hasher.write(url.host_str().unwrap_or_default().as_bytes()),
+            ShardKey::Url => hasher.write(url.as_str().as_bytes()),
+        }
+        (hasher.finish() % self.count as u64) as usize
+    }
+
+    /// Whether `url` is assigned to this shard.
+    pub fn owns(&self, url: &Url) -> bool {
+        self.of(url) == self.index
+    }
+
+    /// Keep the URLs of `urls` assigned to this shard, like the part of a seed list this process
+    /// crawls.
+    pub fn filter<U: IntoIterator<Item = Url>>(self, urls: U) -> impl Iterator<Item = Url> {
+        urls.into_iter().filter(move |url| self.owns(url))
+    }
+}
+
+impl FromStr for Shard {
+    type Err = ParseShardError;
+
+    /// Parse a shard written as `<index>/<count>`, like `0/4`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (index, count) = value
+            .split_once('/')
+            .and_then(|(index, count)| {
+                Some((index.trim().parse().ok()?, count.trim().parse().ok()?))
+            })
+            .ok_or_else(|| ParseShardError::Format(value.to_string()))?;
+        if index >= count {
+            return Err(ParseShardError::OutOfRange(value.to_string()));
+        }
+        Ok(Self::new(index, count))
+    }
+}
@@ -0,0 +1,188 @@
+use reqwest::{Client, StatusCode};
+use scraper::{ElementRef, Html, Selector};
+use std::io::{self, BufRead, Write};
+use thiserror::Error;
+use url::Url;
+
+const HELP: &str = "\
+<selector>              print the HTML of the elements matching the CSS selector
+text <selector>         print the text of the matching elements
+attr <name> <selector>  print the attribute of the matching elements
+fetch <url>             fetch another page, relative to the current one
+info                    print the URL and status of the current page
+body                    print the body of the current page
+help                    print this message
+quit                    leave the shell";
+
+#[derive(Error, Debug)]
+pub enum ShellError {
+    #[error("the page could not be fetched: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("the selector is invalid (given: {0})")]
+    Selector(String),
+    #[error("the URL is invalid: {0}")]
+    Url(#[from] url::ParseError),
+    #[error("the terminal could not be read or written: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Evaluates CSS selectors against a live page, the equivalent of `scrapy shell` for developing
+/// handlers. Only available with the `shell` feature.
+///
+/// A shell can be driven programmatically or interactively from the terminal:
+///
+/// ```no_run
+/// # async fn example() -> Result<(), scrappy_do::shell::ShellError> {
+/// use reqwest::Client;
+/// use scrappy_do::shell::Shell;
+///
+/// let client = Client::new();
+/// let shell = Shell::fetch(&client, "http://quotes.toscrape.com".parse()?).await?;
+/// println!("{}", shell.evaluate("text .quote .text")?);
+/// shell.interact(&client).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Shell {
+    url: Url,
+    status: StatusCode,
+    body: String,
+    document: Html,
+}
+
+impl Shell {
+    /// Fetch `url` with `client` and load the response.
+    pub async fn fetch(client: &Client, url: Url) -> Result<Self, ShellError> {
+        let response = client.get(url).send().await?;
+        let url = response.url().clone();
+        let status = response.status();
+        let body = response.text().await?;
+        Ok(Self::load(url, status, body))
+    }
+
+    /// Load a page that was retrieved some other way, like a saved fixture.
+    pub fn from_body(url: Url, body: String) -> Self {
+        Self::load(url, StatusCode::OK, body)
+    }
+
+    fn load(url: Url, status: StatusCode, body: String) -> Self {
+        let document = Html::parse_document(&body);
+        Self {
+            url,
+            status,
+            body,
+            document,
+        }
+    }
+
+    /// The URL of the page, after redirects.
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// The status of the response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The body of the page.
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+
+    /// The parsed page.
+    pub fn document(&self) -> &Html {
+        &self.document
+    }
+
+    /// Returns the elements matching the CSS `selector`.
+    pub fn select(&self, selector: &str) -> Result<Vec<ElementRef<'_>>, ShellError> {
+        let selector =
+            Selector::parse(selector).map_err(|_| ShellError::Selector(selector.to_string()))?;
+        Ok(self.document.select(&selector).collect())
+    }
+
+    /// Evaluate a shell command, like `text h1`, and return its output. `fetch` isn't supported,
+    /// fetch a new shell instead.
+    pub fn evaluate(&self, command: &str) -> Result<String, ShellError> {
+        let command = command.trim();
+        let (name, argument) = command
+            .split_once(char::is_whitespace)
+            .map(|(name, argument)| (name, argument.trim()))
+            .unwrap_or((command, ""));
+        let output = match name {
+            "help" => HELP.to_string(),
+            "info" => format!("{} {}", self.status, self.url),
+            "body" => self.body.clone(),
+            "text" => numbered(
+                self.select(argument)?
+                    .iter()
+                    .map(|element| element.text().collect::<String>().trim().to_string()),
+            ),
+            "attr" => {
+                let (attr, selector) = argument
+                    .split_once(char::is_whitespace)
+                    .ok_or_else(|| ShellError::Selector(argument.to_string()))?;
+                numbered(
+                    self.select(selector.trim())?
+                        .iter()
+                        .filter_map(|element| element.value().attr(attr))
+                        .map(|value| value.to_string()),
+                )
+            }
+            _ => numbered(self.select(command)?.iter().map(|element| element.html())),
+        };
+        Ok(output)
+    }
+
+    /// Read commands from the terminal until `quit` or the end of the input. Type `help` for the
+    /// list of commands.
+    ///
+    /// The terminal is read synchronously, this is meant to be the only thing running.
+    pub async fn interact(mut self, client: &Client) -> Result<(), ShellError> {
+        let stdin = io::stdin();
+        let mut stdout = io::stdout();
+        writeln!(stdout, "{} {}, type `help` for help", self.status, self.url)?;
+        let mut line = String::new();
+        loop {
+            write!(stdout, "> ")?;
+            stdout.flush()?;
+            line.clear();
+            if stdin.lock().read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let command = line.trim();
+            let result = match command {
+                "" => continue,
+                "quit" | "exit" => return Ok(()),
+                _ => match command.strip_prefix("fetch ") {
+                    Some(target) => match self.url.join(target.trim()) {
+                        Ok(url) => Self::fetch(client, url).await.map(|shell| {
+                            self = shell;
+                            format!("{} {}", self.status, self.url)
+                        }),
+                        Err(err) => Err(err.into()),
+                    },
+                    None => self.evaluate(command),
+                },
+            };
+            match result {
+                Ok(output) => writeln!(stdout, "{}", output)?,
+                Err(err) => writeln!(stdout, "error: {}", err)?,
+            }
+        }
+    }
+}
+
+fn numbered<I: Iterator<Item = String>>(values: I) -> String {
+    let lines: Vec<String> = values
+        .enumerate()
+        .map(|(index, value)| format!("[{}] {}", index, value))
+        .collect();
+    if lines.is_empty() {
+        "no match".to_string()
+    } else {
+        lines.join("\n")
+    }
+}
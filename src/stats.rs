@@ -0,0 +1,573 @@
+use crate::intern;
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{
+    atomic::{AtomicU64, AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+use std::time::{Duration, Instant};
+
+/// How many of the most recent latencies are kept per domain.
+const LATENCY_WINDOW: usize = 100;
+/// The number of samples needed before percentiles are reported for a domain.
+const MIN_LATENCY_SAMPLES: usize = 10;
+/// How long a latency counts toward the percentiles of its domain.
+const LATENCY_MAX_AGE: Duration = Duration::from_secs(60);
+
+/// The most tags the request histograms are kept for, the requests with further tags are
+/// recorded under the [overflow tags](RequestTags::overflow).
+const MAX_REQUEST_TAGS: usize = 1000;
+
+/// The number of buckets in a [Histogram](Histogram).
+const HISTOGRAM_BUCKETS: usize = 48;
+
+/// A histogram with exponentially growing buckets. Bucket `0` counts zeros and bucket `n` counts
+/// the values in `[2^(n - 1), 2^n)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    buckets: Vec<u64>,
+    count: u64,
+    sum: u64,
+    max: u64,
+}
+
+impl Histogram {
+    fn record(&mut self, value: u64) {
+        let bucket = (64 - value.leading_zeros() as usize).min(HISTOGRAM_BUCKETS - 1);
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum = self.sum.saturating_add(value);
+        self.max = self.max.max(value);
+    }
+
+    /// The number of recorded values.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The sum of the recorded values.
+    pub fn sum(&self) -> u64 {
+        self.sum
+    }
+
+    /// The largest recorded value.
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    /// The number of values recorded in each bucket.
+    pub fn buckets(&self) -> &[u64] {
+        &self.buckets
+    }
+
+    /// An upper bound of the percentile (`0.0..=1.0`) of the recorded values. Returns `None` if
+    /// nothing has been recorded.
+    pub fn percentile(&self, percentile: f64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = (self.count as f64 * percentile.clamp(0.0, 1.0))
+            .ceil()
+            .max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let upper_bound = (1u64 << bucket) - 1;
+                return Some(upper_bound.min(self.max));
+            }
+        }
+        Some(self.max)
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: vec![0; HISTOGRAM_BUCKETS],
+            count: 0,
+            sum: 0,
+            max: 0,
+        }
+    }
+}
+
+/// Identifies the requests a [Histogram](Histogram) was recorded for.
+///
+/// The histograms are kept for the first 1000 tags only, so crawls of many domains don't grow
+/// them without bound. The requests with further tags are recorded under the
+/// [overflow tags](RequestTags::overflow).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RequestTags {
+    /// The domain requested.
+    pub domain: String,
+    /// The name of the handler processing the response.
+    pub handler: String,
+}
+
+impl RequestTags {
+    /// The tags the requests are recorded under once the histograms are kept for 1000 tags,
+    /// with `*` as their domain and handler.
+    pub fn overflow() -> Self {
+        Self {
+            domain: "*".to_string(),
+            handler: "*".to_string(),
+        }
+    }
+}
+
+/// The statistics collected for a single handler.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HandlerStats {
+    /// The number of items the handler produced.
+    pub items: u64,
+    /// The number of callbacks the handler produced.
+    pub callbacks: u64,
+    /// The number of times the handler's callbacks failed.
+    pub errors: u64,
+    /// The time the handler took to process each response, until it produced its last item or
+    /// callback, in microseconds. Includes the time its items waited for the pipelines.
+    pub handling_time: Histogram,
+}
+
+/// A snapshot of the statistics collected during a crawl.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CrawlStats {
+    /// The number of requests sent.
+    pub requests: u64,
+    /// The number of requests that failed before a response was received.
+    pub failed_requests: u64,
+    /// The number of items produced by handlers.
+    pub items: u64,
+    /// The number of items dropped by pipelines.
+    pub dropped_items: u64,
+    /// The number of callbacks produced by handlers.
+    pub callbacks: u64,
+    /// The number of callbacks dropped without being executed.
+    pub dropped_callbacks: u64,
+    /// The number of pages skipped as near duplicates of earlier pages.
+    pub near_duplicates: u64,
+    /// The number of callbacks skipped because their request was already made, see
+    /// [WebBuilder::dedup](crate::WebBuilder::dedup).
+    pub duplicate_requests: u64,
+    /// The number of responses recognized as bans by the
+    /// [BanDetector](crate::BanDetector).
+    pub bans: u64,
+    /// The number of callbacks retried by the [RetryPolicy](crate::RetryPolicy) of the web.
+    pub retries: u64,
+    /// The number of resources skipped after their [Probe](crate::Probe) rejected them.
+    pub probe_rejections: u64,
+    /// The number of compressed responses that failed to decode and were requested again
+    /// uncompressed.
+    pub decode_fallbacks: u64,
+    /// The number of connections responses came over, see
+    /// [FetchMetrics::connection_reused](crate::FetchMetrics::connection_reused).
+    pub connections: u64,
+    /// The number of responses that came over a connection an earlier response came over.
+    pub reused_connections: u64,
+    /// The number of bytes of the response bodies read by the handlers, once decoded. The bodies
+    /// read from the [inner response](crate::ScrapedResponse::into_inner) aren't counted.
+    pub downloaded_bytes: u64,
+    /// The time until the response headers were received, in microseconds. Covers resolving the
+    /// domain and connecting to the host for the requests opening a new connection, the phases
+    /// of a request aren't timed separately. Compare with
+    /// [reused_connections](CrawlStats::reused_connections) to tell the cost of new connections.
+    pub latency: HashMap<RequestTags, Histogram>,
+    /// The response sizes in bytes, for the responses that announced their length.
+    pub response_size: HashMap<RequestTags, Histogram>,
+    /// The statistics of every handler, by handler name.
+    pub handlers: HashMap<String, HandlerStats>,
+}
+
+/// The live state of the queues of a running crawl.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CrawlGauges {
+    /// The number of callbacks waiting to be executed.
+    pub frontier: usize,
+    /// The number of callbacks waiting to be executed, by domain.
+    pub domain_frontier: HashMap<String, usize>,
+    /// The number of callbacks currently executing.
+    pub in_flight: usize,
+    /// The number of items waiting to be consumed.
+    pub queued_items: usize,
+}
+
+/// Collects statistics about a crawl while it is running.
+#[derive(Debug, Default)]
+pub(crate) struct Stats {
+    in_flight: AtomicUsize,
+    domain_frontier: Mutex<HashMap<Arc<str>, usize>>,
+    requests: AtomicU64,
+    failed_requests: AtomicU64,
+    items: AtomicU64,
+    dropped_items: AtomicU64,
+    callbacks: AtomicU64,
+    dropped_callbacks: AtomicU64,
+    near_duplicates: AtomicU64,
+    duplicate_requests: AtomicU64,
+    bans: AtomicU64,
+    retries: AtomicU64,
+    probe_rejections: AtomicU64,
+    decode_fallbacks: AtomicU64,
+    connections: AtomicU64,
+    reused_connections: AtomicU64,
+    downloaded_bytes: AtomicU64,
+    // The remote address of every connection, by local address. There can only be so many local
+    // addresses, one per local port and IP.
+    remote_addrs: Mutex<HashMap<SocketAddr, SocketAddr>>,
+    domain_latencies: Mutex<DomainLatencies>,
+    histograms: Mutex<RequestHistograms>,
+    handlers: Mutex<HashMap<String, HandlerStats>>,
+}
+
+/// The recent latencies of the domains requested in the last minute.
+#[derive(Debug, Default)]
+struct DomainLatencies {
+    // With when they were recorded
+    windows: HashMap<Arc<str>, VecDeque<(Instant, Duration)>>,
+    // When the domains whose latencies all expired were last evicted
+    evicted: Option<Instant>,
+}
+
+impl DomainLatencies {
+    // Drop the domains whose latencies all expired, at most once per expiry period
+    fn evict_expired(&mut self) {
+        if self
+            .evicted
+            .is_some_and(|evicted| evicted.elapsed() < LATENCY_MAX_AGE)
+        {
+            return;
+        }
+        self.windows.retain(|_, window| {
+            window
+                .back()
+                .is_some_and(|(recorded, _)| recorded.elapsed() <= LATENCY_MAX_AGE)
+        });
+        self.evicted = Some(Instant::now());
+    }
+}
+
+#[derive(Debug, Default)]
+struct RequestHistograms {
+    latency: HashMap<RequestTags, Histogram>,
+    response_size: HashMap<RequestTags, Histogram>,
+}
+
+impl Stats {
+    /// Record a callback for `domain` entering the task queue.
+    pub(crate) fn record_enqueued(&self, domain: &str) {
+        let mut domain_frontier = self.domain_frontier.lock().expect("stats lock");
+        match domain_frontier.get_mut(domain) {
+            Some(queued) => *queued += 1,
+            None => {
+                domain_frontier.insert(intern::host(domain), 1);
+            }
+        }
+    }
+
+    /// Record a callback for `domain` leaving the task queue.
+    pub(crate) fn record_dequeued(&self, domain: &str) {
+        let mut domain_frontier = self.domain_frontier.lock().expect("stats lock");
+        if let Some(queued) = domain_frontier.get_mut(domain) {
+            *queued -= 1;
+            if *queued == 0 {
+                domain_frontier.remove(domain);
+            }
+        }
+    }
+
+    pub(crate) fn record_started(&self) {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_finished(&self) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Read the current state of the queues. The item queue isn't tracked here, it is filled in by
+    /// the caller.
+    pub(crate) fn gauges(&self) -> CrawlGauges {
+        let domain_frontier: HashMap<_, _> = self
+            .domain_frontier
+            .lock()
+            .expect("stats lock")
+            .iter()
+            .map(|(domain, &queued)| (domain.to_string(), queued))
+            .collect();
+        CrawlGauges {
+            frontier: domain_frontier.values().sum(),
+            domain_frontier,
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            queued_items: 0,
+        }
+    }
+
+    pub(crate) fn record_request(&self) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_failed_request(&self) {
+        self.failed_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_item(&self, handler: &str) {
+        self.items.fetch_add(1, Ordering::Relaxed);
+        self.update_handler(handler, |stats| stats.items += 1);
+    }
+
+    pub(crate) fn record_dropped_item(&self) {
+        self.dropped_items.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_callback(&self, handler: &str) {
+        self.callbacks.fetch_add(1, Ordering::Relaxed);
+        self.update_handler(handler, |stats| stats.callbacks += 1);
+    }
+
+    pub(crate) fn record_handler_error(&self, handler: &str) {
+        self.update_handler(handler, |stats| stats.errors += 1);
+    }
+
+    /// Record `handler` finishing to process a response, after `duration`.
+    pub(crate) fn record_handled(&self, handler: &str, duration: Duration) {
+        self.update_handler(handler, |stats| {
+            stats.handling_time.record(duration.as_micros() as u64)
+        });
+    }
+
+    fn update_handler<F: FnOnce(&mut HandlerStats)>(&self, handler: &str, update: F) {
+        let mut handlers = self.handlers.lock().expect("stats lock");
+        match handlers.get_mut(handler) {
+            Some(stats) => update(stats),
+            None => update(handlers.entry(handler.to_string()).or_default()),
+        }
+    }
+
+    pub(crate) fn record_dropped_callback(&self) {
+        self.dropped_callbacks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_duplicate_request(&self) {
+        self.duplicate_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_near_duplicate(&self) {
+        self.near_duplicates.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_ban(&self) {
+        self.bans.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_retry(&self) {
+        self.retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_probe_rejection(&self) {
+        self.probe_rejections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_decode_fallback(&self) {
+        self.decode_fallbacks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_downloaded(&self, bytes: usize) {
+        self.downloaded_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn downloaded_bytes(&self) -> u64 {
+        self.downloaded_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Record a response received over the connection from `local_addr` to `remote_addr`.
+    ///
+    /// # Returns
+    /// Whether an earlier response came over the connection.
+    pub(crate) fn record_connection(
+        &self,
+        local_addr: SocketAddr,
+        remote_addr: SocketAddr,
+    ) -> bool {
+        let mut remote_addrs = self.remote_addrs.lock().expect("stats lock");
+        let reused = remote_addrs.insert(local_addr, remote_addr) == Some(remote_addr);
+        if reused {
+            self.reused_connections.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.connections.fetch_add(1, Ordering::Relaxed);
+        }
+        reused
+    }
+
+    /// Record a response received for a request to `tags.domain` processed by `tags.handler`.
+    pub(crate) fn record_response(&self, tags: RequestTags, latency: Duration, size: Option<u64>) {
+        self.record_latency(&tags.domain, latency);
+        let mut histograms = self.histograms.lock().expect("stats lock");
+        // Every recorded response has a latency, its histograms hold every tag
+        let tags = if histograms.latency.len() < MAX_REQUEST_TAGS
+            || histograms.latency.contains_key(&tags)
+        {
+            tags
+        } else {
+            RequestTags::overflow()
+        };
+        if let Some(size) = size {
+            histograms
+                .response_size
+                .entry(tags.clone())
+                .or_default()
+                .record(size);
+        }
+        histograms
+            .latency
+            .entry(tags)
+            .or_default()
+            .record(latency.as_micros() as u64);
+    }
+
+    /// Copy the current values of the counters.
+    pub(crate) fn snapshot(&self) -> CrawlStats {
+        let histograms = self.histograms.lock().expect("stats lock");
+        CrawlStats {
+            requests: self.requests.load(Ordering::Relaxed),
+            failed_requests: self.failed_requests.load(Ordering::Relaxed),
+            items: self.items.load(Ordering::Relaxed),
+            dropped_items: self.dropped_items.load(Ordering::Relaxed),
+            callbacks: self.callbacks.load(Ordering::Relaxed),
+            dropped_callbacks: self.dropped_callbacks.load(Ordering::Relaxed),
+            near_duplicates: self.near_duplicates.load(Ordering::Relaxed),
+            duplicate_requests: self.duplicate_requests.load(Ordering::Relaxed),
+            bans: self.bans.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            probe_rejections: self.probe_rejections.load(Ordering::Relaxed),
+            decode_fallbacks: self.decode_fallbacks.load(Ordering::Relaxed),
+            connections: self.connections.load(Ordering::Relaxed),
+            reused_connections: self.reused_connections.load(Ordering::Relaxed),
+            downloaded_bytes: self.downloaded_bytes.load(Ordering::Relaxed),
+            latency: histograms.latency.clone(),
+            response_size: histograms.response_size.clone(),
+            handlers: self.handlers.lock().expect("stats lock").clone(),
+        }
+    }
+
+    /// Record how long a request to `domain` took to produce a response.
+    pub(crate) fn record_latency(&self, domain: &str, latency: Duration) {
+        let mut domain_latencies = self.domain_latencies.lock().expect("stats lock");
+        domain_latencies.evict_expired();
+        let windows = &mut domain_latencies.windows;
+        if !windows.contains_key(domain) {
+            windows.insert(
+                intern::host(domain),
+                VecDeque::with_capacity(LATENCY_WINDOW),
+            );
+        }
+        let window = windows.get_mut(domain).expect("window of the domain");
+        if window.len() == LATENCY_WINDOW {
+            window.pop_front();
+        }
+        window.push_back((Instant::now(), latency));
+    }
+
+    /// The latency percentile (`0.0..=1.0`) of the recent requests made to `domain`. Returns `None`
+    /// until enough requests have been made to the domain, or once its latencies are over a
+    /// minute old, so a domain that stopped being requested is tried again.
+    pub(crate) fn latency_percentile(&self, domain: &str, percentile: f64) -> Option<Duration> {
+        let mut domain_latencies = self.domain_latencies.lock().expect("stats lock");
+        let window = domain_latencies.windows.get_mut(domain)?;
+        while window
+            .front()
+            .is_some_and(|(recorded, _)| recorded.elapsed() > LATENCY_MAX_AGE)
+        {
+            window.pop_front();
+        }
+        if window.is_empty() {
+            domain_latencies.windows.remove(domain);
+            return None;
+        }
+        if window.len() < MIN_LATENCY_SAMPLES {
+            return None;
+        }
+        let mut sorted: Vec<Duration> = window.iter().map(|(_, latency)| *latency).collect();
+        sorted.sort_unstable();
+        let rank = ((sorted.len() - 1) as f64 * percentile.clamp(0.0, 1.0)).round() as usize;
+        Some(sorted[rank])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(domain: &str) -> RequestTags {
+        RequestTags {
+            domain: domain.to_string(),
+            handler: "parse".to_string(),
+        }
+    }
+
+    #[test]
+    fn evicts_the_domains_whose_latencies_expired() {
+        let stats = Stats::default();
+        let expired = Instant::now() - LATENCY_MAX_AGE - Duration::from_secs(1);
+        stats.record_latency("stale.example.com", Duration::ZERO);
+        stats.record_latency("fresh.example.com", Duration::ZERO);
+        {
+            let mut domain_latencies = stats.domain_latencies.lock().unwrap();
+            let stale = domain_latencies
+                .windows
+                .get_mut("stale.example.com")
+                .unwrap();
+            stale[0].0 = expired;
+            domain_latencies.evicted = Some(expired);
+        }
+        stats.record_latency("new.example.com", Duration::ZERO);
+        let domain_latencies = stats.domain_latencies.lock().unwrap();
+        let mut domains: Vec<&str> = domain_latencies
+            .windows
+            .keys()
+            .map(|domain| &**domain)
+            .collect();
+        domains.sort_unstable();
+        assert_eq!(domains, ["fresh.example.com", "new.example.com"]);
+    }
+
+    #[test]
+    fn forgets_a_domain_once_its_latencies_expired() {
+        let stats = Stats::default();
+        for _ in 0..MIN_LATENCY_SAMPLES {
+            stats.record_latency("example.com", Duration::from_secs(1));
+        }
+        assert_eq!(
+            stats.latency_percentile("example.com", 0.95),
+            Some(Duration::from_secs(1))
+        );
+        let expired = Instant::now() - LATENCY_MAX_AGE - Duration::from_secs(1);
+        for sample in stats
+            .domain_latencies
+            .lock()
+            .unwrap()
+            .windows
+            .get_mut("example.com")
+            .unwrap()
+        {
+            sample.0 = expired;
+        }
+        assert_eq!(stats.latency_percentile("example.com", 0.95), None);
+        assert!(stats.domain_latencies.lock().unwrap().windows.is_empty());
+    }
+
+    #[test]
+    fn request_tags_are_capped() {
+        let stats = Stats::default();
+        for n in 0..MAX_REQUEST_TAGS + 5 {
+            stats.record_response(tags(&format!("{}.example.com", n)), Duration::ZERO, Some(1));
+        }
+        stats.record_response(tags("0.example.com"), Duration::ZERO, None);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.latency.len(), MAX_REQUEST_TAGS + 1);
+        assert_eq!(snapshot.latency[&RequestTags::overflow()].count(), 5);
+        assert_eq!(snapshot.latency[&tags("0.example.com")].count(), 2);
+        assert_eq!(snapshot.response_size[&RequestTags::overflow()].count(), 5);
+    }
+}
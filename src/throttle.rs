@@ -0,0 +1,353 @@
+use crate::callback::Callback;
+use crate::intern;
+use crate::scope::{JoinError, Scope, ScopeHandle, SubCrawl, SubCrawlItems};
+use crate::spider::matches_host;
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Spaces out the callbacks it applies to by a delay, until it expires.
+#[derive(Debug)]
+pub(crate) struct Gate {
+    delay: Mutex<Duration>,
+    expires: Option<Instant>,
+    // When the next callback may be dispatched
+    next: Mutex<Instant>,
+}
+
+impl Gate {
+    fn new(delay: Duration, duration: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            delay: Mutex::new(delay),
+            expires: Some(now + duration),
+            next: Mutex::new(now),
+        }
+    }
+
+    /// A gate that never expires.
+    pub(crate) fn permanent(delay: Duration) -> Self {
+        Self {
+            delay: Mutex::new(delay),
+            expires: None,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Returns the delay between the callbacks.
+    pub(crate) fn delay(&self) -> Duration {
+        *self.delay.lock().expect("gate lock")
+    }
+
+    /// Space out the next callbacks by `delay`.
+    pub(crate) fn set_delay(&self, delay: Duration) {
+        *self.delay.lock().expect("gate lock") = delay;
+    }
+
+    fn expired(&self, now: Instant) -> bool {
+        self.expires.is_some_and(|expires| now >= expires)
+    }
+
+    /// Reserve the next slot for a callback.
+    ///
+    /// # Returns
+    /// When the callback may be dispatched, if it has to wait.
+    pub(crate) fn reserve(&self, now: Instant) -> Option<Instant> {
+        if self.expired(now) {
+            return None;
+        }
+        let mut next = self.next.lock().expect("gate lock");
+        let slot = (*next).max(now);
+        *next = slot + self.delay();
+        if slot > now {
+            Some(slot)
+        } else {
+            None
+        }
+    }
+}
+
+/// The throttles of the hosts, shared by every [Web](crate::Web) of a
+/// [Spider](crate::Spider) so webs crawling the same host are limited together.
+#[derive(Debug, Default)]
+pub(crate) struct Throttles {
+    // Applied by handlers, until they expire
+    domains: Mutex<HashMap<Arc<str>, Arc<Gate>>>,
+    // The delay between the requests to the hosts matching a pattern, and to the other hosts
+    host_delays: Vec<(String, Duration)>,
+    host_delay: Option<Duration>,
+    // The gates enforcing the host delays, by host
+    hosts: Mutex<HashMap<Arc<str>, Arc<Gate>>>,
+}
+
+impl Throttles {
+    pub(crate) fn new(host_delay: Option<Duration>, host_delays: Vec<(String, Duration)>) -> Self {
+        Self {
+            host_delay,
+            host_delays,
+            ..Self::default()
+        }
+    }
+
+    /// The gate enforcing the configured delay of `host`, if it has one.
+    pub(crate) fn host(&self, host: &str) -> Option<Arc<Gate>> {
+        let delay = self
+            .host_delays
+            .iter()
+            .find(|(pattern, _)| matches_host(pattern, host))
+            .map(|(_, delay)| *delay)
+            .or(self.host_delay)?;
+        let mut hosts = self.hosts.lock().expect("throttles lock");
+        if let Some(gate) = hosts.get(host) {
+            return Some(gate.clone());
+        }
+        let gate = Arc::new(Gate::permanent(delay));
+        hosts.insert(intern::host(host), gate.clone());
+        Some(gate)
+    }
+
+    /// The active throttle of `domain`, if any.
+    pub(crate) fn domain(&self, domain: &str) -> Option<Arc<Gate>> {
+        let mut domains = self.domains.lock().expect("throttles lock");
+        match domains.get(domain) {
+            Some(gate) if gate.expired(Instant::now()) => {
+                domains.remove(domain);
+                None
+            }
+            gate => gate.cloned(),
+        }
+    }
+}
+
+/// The throttle of the branch a callback belongs to, shared with the handler of its response.
+pub(crate) type Branch = Arc<Mutex<Option<Arc<Gate>>>>;
+
+/// Lets a handler slow down the crawl while it is running, for instance after a page asks the
+/// crawler to slow down. Obtained through
+/// [ScrapedResponse::scheduler](crate::ScrapedResponse::scheduler).
+///
+/// Throttled callbacks wait in the queue without holding a slot of the
+/// [concurrent requests](crate::WebBuilder::concurrent_requests), the rest of the crawl carries
+/// on.
+///
+/// ```no_run
+/// # #![feature(generators)]
+/// # use scrappy_do::{handle, ScrapedResponse};
+/// # use reqwest::Client;
+/// # use slog::Logger;
+/// use std::time::Duration;
+///
+/// #[handle(item = String)]
+/// fn handler(client: Client, response: ScrapedResponse, context: (), logger: Logger) {
+///     let scheduler = response.scheduler().clone();
+///     let body = response.text().await.unwrap();
+///     if body.contains("Please slow down") {
+///         // One request every 5 seconds to this site for the next 10 minutes
+///         scheduler.throttle_domain(Duration::from_secs(5), Duration::from_secs(600));
+///     }
+///     yield body;
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    throttles: Arc<Throttles>,
+    domain: Arc<str>,
+    branch: Branch,
+    // The sub-requests of the response, `None` outside of a crawl
+    scope: Option<ScopeHandle>,
+}
+
+impl Scheduler {
+    pub(crate) fn new(
+        throttles: Arc<Throttles>,
+        domain: &str,
+        branch: Branch,
+        scope: Option<ScopeHandle>,
+    ) -> Self {
+        Self {
+            throttles,
+            domain: intern::host(domain),
+            branch,
+            scope,
+        }
+    }
+
+    /// The domain of the response.
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    /// Space the requests to the domain of the response by at least `delay`, for the next
+    /// `duration`. Replaces the previous throttle of the domain. The throttle applies to every
+    /// [Web](crate::Web) of the [Spider](crate::Spider), not only the crawl of the handler.
+    pub fn throttle_domain(&self, delay: Duration, duration: Duration) {
+        self.throttles
+            .domains
+            .lock()
+            .expect("throttles lock")
+            .insert(self.domain.clone(), Arc::new(Gate::new(delay, duration)));
+    }
+
+    /// Space the callbacks the handler produces from now on, and the callbacks they produce in
+    /// turn, by at least `delay`, for the next `duration`. Other branches of the crawl, even on
+    /// the same domain, aren't affected. Replaces the throttle inherited from the parent
+    /// callbacks.
+    pub fn throttle_branch(&self, delay: Duration, duration: Duration) {
+        *self.branch.lock().expect("branch lock") = Some(Arc::new(Gate::new(delay, duration)));
+    }
+
+    /// Queue `callbacks` as sub-requests of the response and wait for the items their handlers
+    /// produce, to combine them into the item of the response. The sub-requests go through the
+    /// scheduler like any callback, so they are subject to the throttles, bans and the limit of
+    /// [concurrent requests](crate::WebBuilder::concurrent_requests). The callbacks the
+    /// sub-requests produce are followed as well, their items are returned with the items of the
+    /// sub-request that produced them.
+    ///
+    /// Items of sub-requests go back to the handler only, neither the pipelines nor the consumer
+    /// of the crawl receive them. A sub-request that fails produces no item, the error is logged.
+    ///
+    /// The handler doesn't hold its slot of the concurrent requests while it waits.
+    ///
+    /// # Returns
+    /// The items produced for every callback, in the order of the callbacks, or an error if the
+    /// response wasn't produced by a running crawl, like a [fixture](crate::fixture).
+    ///
+    /// ```no_run
+    /// # #![feature(generators)]
+    /// # use scrappy_do::{handle, wrap, Callback, ScrapedResponse};
+    /// # use reqwest::Client;
+    /// # use slog::Logger;
+    /// #[derive(Debug)]
+    /// enum Page {
+    ///     Product { name: String, reviews: Vec<String> },
+    ///     Review(String),
+    /// }
+    ///
+    /// #[handle(item = Page)]
+    /// fn product(client: Client, response: ScrapedResponse, context: (), logger: Logger) {
+    ///     let scheduler = response.scheduler().clone();
+    ///     let reviews = response.url().join("reviews").unwrap();
+    ///     let name = response.text().await.unwrap();
+    ///     let review_pages = vec![Callback::new(
+    ///         wrap!(review),
+    ///         client.get(reviews).build().unwrap(),
+    ///         (),
+    ///     )];
+    ///     let reviews = scheduler
+    ///         .join(review_pages)
+    ///         .await
+    ///         .unwrap()
+    ///         .into_iter()
+    ///         .flatten()
+    ///         .filter_map(|page| match page {
+    ///             Page::Review(review) => Some(review),
+    ///             _ => None,
+    ///         })
+    ///         .collect();
+    ///     yield Page::Product { name, reviews };
+    /// }
+    ///
+    /// #[handle(item = Page)]
+    /// fn review(client: Client, response: ScrapedResponse, context: (), logger: Logger) {
+    ///     yield Page::Review(response.text().await.unwrap());
+    /// }
+    /// ```
+    pub async fn join<I, C>(&self, callbacks: Vec<Callback<I, C>>) -> Result<Vec<Vec<I>>, JoinError>
+    where
+        I: Debug + Send + 'static,
+        C: Debug + Send + 'static,
+    {
+        self.scope()?.join(callbacks).await
+    }
+
+    /// Start a sub-crawl from `callbacks`, within `limits`, and stream its items back to the
+    /// handler, like the paginated comments of a page that are folded into the item of the page.
+    /// The callbacks of the sub-crawl go through the scheduler like any callback, the callbacks
+    /// they produce are followed within the limits.
+    ///
+    /// Items of the sub-crawl go back to the handler only, neither the pipelines nor the consumer
+    /// of the crawl receive them. The sub-crawl stops once the stream is dropped, and the handler
+    /// doesn't hold its slot of the concurrent requests while the stream is alive. In a
+    /// [deterministic](crate::WebBuilder::deterministic) crawl the sub-crawl finishes before its
+    /// items are returned.
+    ///
+    /// # Returns
+    /// The items of the sub-crawl, or an error if the response wasn't produced by a running
+    /// crawl, like a [fixture](crate::fixture).
+    ///
+    /// ```no_run
+    /// # #![feature(generators)]
+    /// # use scrappy_do::{handle, wrap, Callback, ScrapedResponse, SubCrawl};
+    /// # use reqwest::Client;
+    /// # use slog::Logger;
+    /// use futures::StreamExt;
+    /// use std::num::NonZeroU64;
+    ///
+    /// #[derive(Debug)]
+    /// enum Page {
+    ///     Article { body: String, comments: Vec<String> },
+    ///     Comment(String),
+    /// }
+    ///
+    /// #[handle(item = Page)]
+    /// fn article(client: Client, response: ScrapedResponse, context: (), logger: Logger) {
+    ///     let scheduler = response.scheduler().clone();
+    ///     let first_url = response.url().join("comments?page=1").unwrap();
+    ///     let body = response.text().await.unwrap();
+    ///     let first_page = Callback::new(
+    ///         wrap!(comments),
+    ///         client.get(first_url).build().unwrap(),
+    ///         (),
+    ///     );
+    ///     // The first 5 pages of comments, at most 100 comments
+    ///     let limits = SubCrawl::new()
+    ///         .max_depth(4)
+    ///         .max_items(NonZeroU64::new(100).unwrap());
+    ///     let comments = scheduler
+    ///         .sub_crawl(limits, vec![first_page])
+    ///         .await
+    ///         .unwrap()
+    ///         .filter_map(|page| async move {
+    ///             match page {
+    ///                 Page::Comment(comment) => Some(comment),
+    ///                 _ => None,
+    ///             }
+    ///         })
+    ///         .collect()
+    ///         .await;
+    ///     yield Page::Article { body, comments };
+    /// }
+    ///
+    /// #[handle(item = Page)]
+    /// fn comments(client: Client, response: ScrapedResponse, context: (), logger: Logger) {
+    ///     // Yields a `Page::Comment` for every comment, and a callback to the next page
+    ///     yield Page::Comment(response.text().await.unwrap());
+    /// }
+    /// ```
+    pub async fn sub_crawl<I, C>(
+        &self,
+        limits: SubCrawl,
+        callbacks: Vec<Callback<I, C>>,
+    ) -> Result<SubCrawlItems<I>, JoinError>
+    where
+        I: Debug + Send + 'static,
+        C: Debug + Send + 'static,
+    {
+        self.scope()?.sub_crawl(limits, callbacks).await
+    }
+
+    /// The sub-requests of the response, if it was produced by a running crawl.
+    fn scope<I, C>(&self) -> Result<Arc<Scope<I, C>>, JoinError>
+    where
+        I: Send + 'static,
+        C: Send + 'static,
+    {
+        self.scope
+            .as_ref()
+            .and_then(|scope| scope.upgrade())
+            .and_then(|scope: Arc<dyn Any + Send + Sync>| scope.downcast::<Scope<I, C>>().ok())
+            .ok_or(JoinError::Unavailable)
+    }
+}
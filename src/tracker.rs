@@ -0,0 +1,265 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// The number of queued callbacks listed by a [SchedulerDump](SchedulerDump).
+const QUEUE_SAMPLE: usize = 20;
+
+/// The state of the scheduler of a running crawl, to diagnose a crawl that stalls. Obtained
+/// through [CrawlHandle::scheduler_dump](crate::CrawlHandle::scheduler_dump), its
+/// [Display](std::fmt::Display) is meant for logs.
+///
+/// The queued and executing callbacks are only listed when the crawl tracks them, see
+/// [WebBuilder::track_callbacks](crate::WebBuilder::track_callbacks).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchedulerDump {
+    /// The maximum number of concurrent requests.
+    pub concurrent_requests: usize,
+    /// The number of callbacks waiting to be executed, by domain.
+    pub domain_queues: HashMap<String, usize>,
+    /// The callbacks that waited the longest to be executed, the oldest first.
+    pub queued: Vec<QueuedCallback>,
+    /// The callback taken out of the queue, waiting for an executing callback to finish.
+    pub awaiting_slot: Option<QueuedCallback>,
+    /// The executing callbacks, the oldest first.
+    pub in_flight: Vec<InFlightCallback>,
+}
+
+/// A callback waiting to be executed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueuedCallback {
+    /// The handler and URL of the callback.
+    pub callback: String,
+    /// How long ago the callback was queued.
+    pub waiting: Duration,
+    /// How long the callback still waits for a throttle or a ban pause before it is queued
+    /// again, if it does.
+    pub deferred: Option<Duration>,
+}
+
+/// A callback being executed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InFlightCallback {
+    /// The handler and URL of the callback.
+    pub callback: String,
+    /// How long ago the callback started executing.
+    pub age: Duration,
+}
+
+impl Display for SchedulerDump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let queued: usize = self.domain_queues.values().sum();
+        writeln!(
+            f,
+            "{} queued, {} in flight out of {}",
+            queued,
+            self.in_flight.len(),
+            self.concurrent_requests
+        )?;
+        let mut domains: Vec<_> = self.domain_queues.iter().collect();
+        domains.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (domain, queued) in domains {
+            writeln!(f, "  queue {}: {}", domain, queued)?;
+        }
+        if let Some(callback) = &self.awaiting_slot {
+            writeln!(
+                f,
+                "  waiting for a request slot for {:?}: {}",
+                callback.waiting, callback.callback
+            )?;
+        }
+        for callback in &self.in_flight {
+            writeln!(
+                f,
+                "  in flight for {:?}: {}",
+                callback.age, callback.callback
+            )?;
+        }
+        for callback in &self.queued {
+            match callback.deferred {
+                Some(deferred) => writeln!(
+                    f,
+                    "  queued for {:?}, deferred for {:?}: {}",
+                    callback.waiting, deferred, callback.callback
+                )?,
+                None => writeln!(
+                    f,
+                    "  queued for {:?}: {}",
+                    callback.waiting, callback.callback
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Follows the callbacks of a crawl from the moment they are queued until they finish.
+#[derive(Debug, Default)]
+pub(crate) struct Tracker {
+    next_id: AtomicU64,
+    // The callbacks queued, deferred, or executing
+    live: AtomicUsize,
+    // The state of every live callback, when the crawl tracks them for its scheduler dumps
+    callbacks: Option<Mutex<HashMap<u64, Tracked>>>,
+    // Notified once no callback is left
+    idle: Notify,
+}
+
+#[derive(Debug)]
+struct Tracked {
+    callback: String,
+    // When the callback was queued, or started executing
+    since: Instant,
+    state: State,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Queued,
+    Deferred(Instant),
+    AwaitingSlot,
+    InFlight,
+}
+
+impl Tracker {
+    /// A tracker counting the live callbacks, and keeping the state of each of them when
+    /// `detailed`.
+    pub(crate) fn new(detailed: bool) -> Self {
+        Self {
+            callbacks: detailed.then(Mutex::default),
+            ..Self::default()
+        }
+    }
+
+    /// Track a callback entering the task queue. The callback is only formatted when the
+    /// tracker keeps the state of the callbacks.
+    ///
+    /// # Returns
+    /// The ID the callback is tracked by.
+    pub(crate) fn queued(&self, callback: &dyn Display) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.live.fetch_add(1, Ordering::SeqCst);
+        if let Some(callbacks) = &self.callbacks {
+            let tracked = Tracked {
+                callback: callback.to_string(),
+                since: Instant::now(),
+                state: State::Queued,
+            };
+            callbacks.lock().expect("tracker lock").insert(id, tracked);
+        }
+        id
+    }
+
+    /// The callback waits outside the queue until `until`.
+    pub(crate) fn deferred(&self, id: u64, until: Instant) {
+        self.update(id, |tracked| tracked.state = State::Deferred(until));
+    }
+
+    /// The callback waits for an executing callback to finish.
+    pub(crate) fn awaiting_slot(&self, id: u64) {
+        self.update(id, |tracked| tracked.state = State::AwaitingSlot);
+    }
+
+    /// The callback started executing.
+    pub(crate) fn started(&self, id: u64) {
+        self.update(id, |tracked| {
+            tracked.since = Instant::now();
+            tracked.state = State::InFlight;
+        });
+    }
+
+    /// The callback finished executing, or was dropped.
+    pub(crate) fn finished(&self, id: u64) {
+        if let Some(callbacks) = &self.callbacks {
+            callbacks.lock().expect("tracker lock").remove(&id);
+        }
+        if self.live.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.idle.notify_waiters();
+        }
+    }
+
+    fn update(&self, id: u64, update: impl FnOnce(&mut Tracked)) {
+        if let Some(callbacks) = &self.callbacks {
+            if let Some(tracked) = callbacks.lock().expect("tracker lock").get_mut(&id) {
+                update(tracked);
+            }
+        }
+    }
+
+    /// Wait until no callback is queued, deferred, or executing.
+    pub(crate) async fn idle(&self) {
+        loop {
+            let notified = self.idle.notified();
+            tokio::pin!(notified);
+            // Registered before checking, so a callback finishing meanwhile isn't missed
+            notified.as_mut().enable();
+            if self.live.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    pub(crate) fn dump(
+        &self,
+        concurrent_requests: usize,
+        domain_queues: HashMap<String, usize>,
+    ) -> SchedulerDump {
+        let now = Instant::now();
+        let mut queued = Vec::new();
+        let mut awaiting_slot = None;
+        let mut in_flight = Vec::new();
+        let callbacks = match &self.callbacks {
+            Some(callbacks) => callbacks.lock().expect("tracker lock"),
+            None => {
+                return SchedulerDump {
+                    concurrent_requests,
+                    domain_queues,
+                    ..SchedulerDump::default()
+                }
+            }
+        };
+        for tracked in callbacks.values() {
+            let elapsed = now.saturating_duration_since(tracked.since);
+            match tracked.state {
+                State::Queued => queued.push(QueuedCallback {
+                    callback: tracked.callback.clone(),
+                    waiting: elapsed,
+                    deferred: None,
+                }),
+                State::Deferred(until) => queued.push(QueuedCallback {
+                    callback: tracked.callback.clone(),
+                    waiting: elapsed,
+                    // Past its slot, the callback is back in the queue
+                    deferred: Some(until.saturating_duration_since(now))
+                        .filter(|deferred| !deferred.is_zero()),
+                }),
+                State::AwaitingSlot => {
+                    awaiting_slot = Some(QueuedCallback {
+                        callback: tracked.callback.clone(),
+                        waiting: elapsed,
+                        deferred: None,
+                    })
+                }
+                State::InFlight => in_flight.push(InFlightCallback {
+                    callback: tracked.callback.clone(),
+                    age: elapsed,
+                }),
+            }
+        }
+        queued.sort_by_key(|callback| Reverse(callback.waiting));
+        queued.truncate(QUEUE_SAMPLE);
+        in_flight.sort_by_key(|callback| Reverse(callback.age));
+        SchedulerDump {
+            concurrent_requests,
+            domain_queues,
+            queued,
+            awaiting_slot,
+            in_flight,
+        }
+    }
+}
@@ -1,8 +1,23 @@
-use reqwest::{Client, Request};
+use crate::{Callback, Handler};
+use encoding_rs::{Encoding, UTF_8};
+use regex::Regex;
+use reqwest::{header::CONTENT_TYPE, Client, Request};
 use scraper::{Html, Selector};
-use std::collections::HashMap;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
 use thiserror::Error;
-use url::Url;
+use tokio::{sync::Semaphore, task::spawn_blocking};
+use url::{form_urlencoded, Url};
+
+pub mod date;
+#[cfg(feature = "pdf")]
+pub mod pdf;
+pub mod price;
+pub mod text;
+pub mod xml;
 
 #[derive(Error, Debug)]
 pub enum ParseError {
@@ -35,6 +50,7 @@ pub struct FormBuilder {
     name: Option<String>,
     fields: Vec<FormField>,
     body: Option<Html>,
+    charset: Option<String>,
 }
 
 impl FormBuilder {
@@ -68,8 +84,16 @@ impl FormBuilder {
         self
     }
 
+    /// Encode the submitted values with `charset`, like `Shift_JIS`, instead of the charset
+    /// declared by the page. Unknown charsets are ignored. This is optional.
+    pub fn charset<S: Into<String>>(mut self, charset: S) -> Self {
+        self.charset = Some(charset.into());
+        self
+    }
+
     /// Attempt to build a `Form`. Will return `None` if the form wasn't found in the supplied
-    /// body.
+    /// body. The inputs of the form are submitted under their `name` with the values of the page,
+    /// unless a field of the same name was set.
     pub fn build(self) -> Option<Form> {
         let body = self.body.expect("body is required to be set");
         let mut form_qualifiers = Vec::new();
@@ -92,33 +116,101 @@ impl FormBuilder {
             .map(|field| (field.name, field.value))
             .collect();
 
+        let forced_charset = self.charset;
+        // The charset of the page, which forms without `accept-charset` are submitted in
+        let page_charset = || {
+            let meta = Selector::parse("meta[charset], meta[http-equiv][content]").unwrap();
+            body.select(&meta).find_map(|meta| {
+                let meta = meta.value();
+                match meta.attr("charset") {
+                    Some(charset) => Encoding::for_label(charset.trim().as_bytes()),
+                    None if meta
+                        .attr("http-equiv")
+                        .is_some_and(|name| name.eq_ignore_ascii_case("content-type")) =>
+                    {
+                        let content = meta.attr("content")?.to_ascii_lowercase();
+                        let charset = content.split("charset=").nth(1)?;
+                        Encoding::for_label(
+                            charset
+                                .trim_matches(|c: char| c == '"' || c == ' ')
+                                .as_bytes(),
+                        )
+                    }
+                    None => None,
+                }
+            })
+        };
+
         body.select(&form_selector).next().map(|form| {
             let mut form_fields = HashMap::<String, String>::new();
             for field in form.select(&field_selector) {
                 let field_value = field.value();
-                let id = match field_value.attr("id") {
-                    Some(id) => id,
+                // Inputs are submitted under their name, those without one aren't submitted
+                let name = match field_value.attr("name") {
+                    Some(name) => name,
                     None => continue,
                 };
                 let value = field_value.attr("value").unwrap_or("");
-                form_fields.insert(id.to_string(), value.to_string());
+                form_fields.insert(name.to_string(), value.to_string());
             }
             form_fields.extend(fields);
 
             let path = form.value().attr("action").unwrap();
+            // The first charset of `accept-charset` that is known, like browsers do
+            let charset = forced_charset
+                .as_deref()
+                .and_then(|charset| Encoding::for_label(charset.trim().as_bytes()))
+                .or_else(|| {
+                    form.value()
+                        .attr("accept-charset")?
+                        .split([' ', ','])
+                        .find_map(|charset| Encoding::for_label(charset.as_bytes()))
+                })
+                .or_else(page_charset)
+                .unwrap_or(UTF_8);
             Form {
                 path: path.to_string(),
                 fields: form_fields,
+                charset: charset.output_encoding(),
             }
         })
     }
 }
 
 /// Simplifies submitting forms embedded in webpage bodies.
+///
+/// The values are encoded with the charset the form accepts, from its `accept-charset`
+/// attribute or else the charset of the page, as browsers do. Legacy sites often expect
+/// submissions in their own charset, like Shift-JIS, and can't read UTF-8 values.
+///
+/// ```
+/// use reqwest::Client;
+/// use scraper::Html;
+/// use scrappy_do::util::Form;
+/// use url::Url;
+///
+/// let page = Html::parse_document(
+///     r#"<form id="search" action="/search" accept-charset="Shift_JIS">
+///         <input id="q" name="q" value="">
+///     </form>"#,
+/// );
+/// let form = Form::builder()
+///     .id("search")
+///     .body(page)
+///     .add_field(scrappy_do::util::FormField::new("q", "日本"))
+///     .build()
+///     .unwrap();
+/// let request = form
+///     .generate_request(&Client::new(), Url::parse("https://example.com/").unwrap())
+///     .unwrap();
+/// let body = request.body().and_then(|body| body.as_bytes()).unwrap();
+/// assert_eq!(body, b"q=%93%FA%96%7B");
+/// ```
 #[derive(Debug)]
 pub struct Form {
     fields: HashMap<String, String>,
     path: String,
+    charset: &'static Encoding,
 }
 
 impl Form {
@@ -128,6 +220,7 @@ impl Form {
             name: None,
             body: None,
             fields: Vec::new(),
+            charset: None,
         }
     }
 
@@ -137,10 +230,720 @@ impl Form {
     /// - `client`: Used to generate the `Request` object.
     /// - `url`: The host that will recieve the form request upon execution.
     pub fn generate_request(&self, client: &Client, url: Url) -> Result<Request, reqwest::Error> {
+        let builder = client.post(url.join(&self.path).unwrap().as_str());
+        if self.charset == UTF_8 {
+            return builder.form(&self.fields).build();
+        }
+        // Characters missing from the charset are sent as numeric character references
+        let encode = |text: &str| {
+            let (encoded, _, _) = self.charset.encode(text);
+            form_urlencoded::byte_serialize(&encoded).collect::<String>()
+        };
+        let body = self
+            .fields
+            .iter()
+            .map(|(name, value)| format!("{}={}", encode(name), encode(value)))
+            .collect::<Vec<_>>()
+            .join("&");
+        builder
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(body)
+            .build()
+    }
+}
+
+/// The selectors tried in order to find the next page when none is configured.
+const NEXT_PAGE_SELECTORS: &[&str] = &[
+    "link[rel~=next][href]",
+    "a[rel~=next][href]",
+    ".next a[href]",
+    "a.next[href]",
+    ".pagination a[aria-label=Next][href]",
+];
+
+/// Follows "next page" links, keeping count of the pages visited in the context.
+///
+/// Without a configured selector the next page is found with `rel="next"` links and the common
+/// `.next` CSS patterns.
+///
+/// ```no_run
+/// # use reqwest::Client;
+/// # use scraper::Html;
+/// # use scrappy_do::{Callback, Handler, util::Pagination};
+/// # use std::num::NonZeroUsize;
+/// # use url::Url;
+/// # fn example<H: Handler<(), usize> + 'static>(
+/// #     handler: H, client: &Client, document: &Html, url: &Url, page: usize,
+/// # ) -> Option<Callback<(), usize>> {
+/// // Crawl at most 10 pages, `page` is the context of the current page
+/// Pagination::default()
+///     .max_pages(NonZeroUsize::new(10).unwrap())
+///     .follow(handler, client, document, url, page)
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Pagination {
+    // Tried in order
+    selectors: Vec<Selector>,
+    max_pages: Option<NonZeroUsize>,
+}
+
+impl Default for Pagination {
+    fn default() -> Self {
+        Self {
+            selectors: NEXT_PAGE_SELECTORS
+                .iter()
+                .filter_map(|selector| Selector::parse(selector).ok())
+                .collect(),
+            max_pages: None,
+        }
+    }
+}
+
+impl Pagination {
+    /// Find the next page with `selector` instead of the default patterns. The selector must match
+    /// elements with an `href` attribute, it is parsed by the caller with
+    /// [Selector::parse](scraper::Selector::parse).
+    pub fn selector(mut self, selector: Selector) -> Self {
+        self.selectors = vec![selector];
+        self
+    }
+
+    /// Stop following links once `max_pages` pages have been visited.
+    pub fn max_pages(mut self, max_pages: NonZeroUsize) -> Self {
+        self.max_pages = Some(max_pages);
+        self
+    }
+
+    /// Find the URL of the page following `document`, resolving relative links against `url`.
+    pub fn next_url(&self, document: &Html, url: &Url) -> Option<Url> {
+        let href = self
+            .selectors
+            .iter()
+            .find_map(|selector| first_href(document, selector))?;
+        url.join(href.trim()).ok()
+    }
+
+    /// Create the callback processing the page following `document` with `handler`.
+    ///
+    /// # Arguments
+    /// - `page`: The number of the current page, starting at 1. The callback's context is
+    ///   `page + 1`.
+    ///
+    /// # Returns
+    /// `None` if there is no next page or the maximum number of pages has been reached.
+    pub fn follow<I, H>(
+        &self,
+        handler: H,
+        client: &Client,
+        document: &Html,
+        url: &Url,
+        page: usize,
+    ) -> Option<Callback<I, usize>>
+    where
+        I: Debug,
+        H: Handler<I, usize> + 'static,
+    {
+        if let Some(max_pages) = self.max_pages {
+            if page >= max_pages.get() {
+                return None;
+            }
+        }
+        let next = self.next_url(document, url)?;
+        let request = client.get(next).build().ok()?;
+        Some(Callback::new(handler, request, page + 1))
+    }
+}
+
+/// Follows the pages of a JSON API paginated with a query parameter, like a cursor or an offset,
+/// keeping count of the pages visited in the context.
+///
+/// ```no_run
+/// # use reqwest::Client;
+/// # use scrappy_do::{Callback, Handler, util::ApiPagination};
+/// # use url::Url;
+/// # struct Page { results: Vec<String>, next_cursor: Option<String> }
+/// # fn example<H: Handler<String, usize> + 'static>(
+/// #     handler: H, client: &Client, url: &Url, page: &Page, page_number: usize,
+/// # ) -> Option<Callback<String, usize>> {
+/// // Cursor: the API returns the cursor of the next page
+/// ApiPagination::new("cursor").follow(handler, client, url, page_number, |_| {
+///     page.next_cursor.clone()
+/// })
+/// # }
+/// # fn offset<H: Handler<String, usize> + 'static>(
+/// #     handler: H, client: &Client, url: &Url, page: &Page, page_number: usize,
+/// # ) -> Option<Callback<String, usize>> {
+/// // Offset: the next page starts after the results of this one, until a page is empty
+/// ApiPagination::new("offset").follow(handler, client, url, page_number, |offset| {
+///     let offset: usize = offset.and_then(|offset| offset.parse().ok()).unwrap_or(0);
+///     if page.results.is_empty() {
+///         None
+///     } else {
+///         Some((offset + page.results.len()).to_string())
+///     }
+/// })
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ApiPagination {
+    param: String,
+    max_pages: Option<NonZeroUsize>,
+}
+
+impl ApiPagination {
+    /// Paginate with the query parameter `param`.
+    pub fn new<S: Into<String>>(param: S) -> Self {
+        Self {
+            param: param.into(),
+            max_pages: None,
+        }
+    }
+
+    /// Stop requesting pages once `max_pages` pages have been visited.
+    pub fn max_pages(mut self, max_pages: NonZeroUsize) -> Self {
+        self.max_pages = Some(max_pages);
+        self
+    }
+
+    /// Build the URL of the page following `url`.
+    ///
+    /// # Arguments
+    /// - `next`: Given the value of the parameter in `url`, returns its value for the next page.
+    ///   Returning `None` ends the pagination.
+    pub fn next_url<F>(&self, url: &Url, next: F) -> Option<Url>
+    where
+        F: FnOnce(Option<&str>) -> Option<String>,
+    {
+        let pairs: Vec<(String, String)> = url
+            .query_pairs()
+            .filter(|(name, _)| name != &self.param)
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect();
+        let current = url
+            .query_pairs()
+            .find(|(name, _)| name == &self.param)
+            .map(|(_, value)| value);
+        let value = next(current.as_deref())?;
+
+        let mut next_url = url.clone();
+        next_url
+            .query_pairs_mut()
+            .clear()
+            .extend_pairs(pairs)
+            .append_pair(&self.param, &value);
+        Some(next_url)
+    }
+
+    /// Create the callback processing the page following `url` with `handler`.
+    ///
+    /// # Arguments
+    /// - `page`: The number of the current page, starting at 1. The callback's context is
+    ///   `page + 1`.
+    /// - `next`: See [next_url](ApiPagination::next_url).
+    ///
+    /// # Returns
+    /// `None` if there is no next page or the maximum number of pages has been reached.
+    pub fn follow<I, H, F>(
+        &self,
+        handler: H,
+        client: &Client,
+        url: &Url,
+        page: usize,
+        next: F,
+    ) -> Option<Callback<I, usize>>
+    where
+        I: Debug,
+        H: Handler<I, usize> + 'static,
+        F: FnOnce(Option<&str>) -> Option<String>,
+    {
+        if let Some(max_pages) = self.max_pages {
+            if page >= max_pages.get() {
+                return None;
+            }
+        }
+        let request = client.get(self.next_url(url, next)?).build().ok()?;
+        Some(Callback::new(handler, request, page + 1))
+    }
+}
+
+/// The context of a page of an API paginated with POST requests: the number of the page, starting
+/// at 1, and the URL and payload it was posted with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostPage {
+    pub number: usize,
+    pub url: Url,
+    pub payload: Value,
+}
+
+/// Follows the pages of an API paginated with POST requests, like search APIs taking the cursor or
+/// the offset of the page in their JSON payload. Each page is posted with the payload of the
+/// previous page, updated with a value taken from the previous response, and the pages are
+/// counted in the [PostPage](PostPage) context.
+///
+/// ```
+/// use reqwest::Client;
+/// use scrappy_do::util::{PostPage, PostPagination};
+/// use serde_json::json;
+/// use url::Url;
+///
+/// let pagination = PostPagination::new("/paging/cursor");
+/// let page = PostPage {
+///     number: 1,
+///     url: Url::parse("https://example.com/api/search").unwrap(),
+///     payload: json!({"query": "shoes", "paging": {"size": 20}}),
+/// };
+/// // The cursor of the next page, as returned by the API
+/// let cursor = "c2hvZXM6MjA=";
+/// let payload = pagination
+///     .next_payload(&page.payload, |_| Some(cursor.into()))
+///     .unwrap();
+/// assert_eq!(
+///     payload,
+///     json!({"query": "shoes", "paging": {"size": 20, "cursor": "c2hvZXM6MjA="}})
+/// );
+///
+/// // The request of the first page
+/// let request = pagination.request(&Client::new(), &page).unwrap();
+/// assert_eq!(request.method(), "POST");
+/// ```
+///
+/// Offsets are computed from the value of the current page:
+///
+/// ```no_run
+/// # use reqwest::Client;
+/// # use scrappy_do::{Callback, Handler, util::{PostPage, PostPagination}};
+/// # struct Results { hits: Vec<String> }
+/// # fn example<H: Handler<String, PostPage> + 'static>(
+/// #     handler: H, client: &Client, results: &Results, page: &PostPage,
+/// # ) -> Option<Callback<String, PostPage>> {
+/// // The next page starts after the hits of this one, until a page is empty
+/// PostPagination::new("/from").follow(handler, client, page, |from| {
+///     let from = from.and_then(|from| from.as_u64()).unwrap_or(0);
+///     if results.hits.is_empty() {
+///         None
+///     } else {
+///         Some((from + results.hits.len() as u64).into())
+///     }
+/// })
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct PostPagination {
+    pointer: String,
+    max_pages: Option<NonZeroUsize>,
+}
+
+impl PostPagination {
+    /// Paginate with the value at `pointer` in the payload, a JSON pointer like `/paging/cursor`.
+    /// The objects missing on the way are created.
+    pub fn new<S: Into<String>>(pointer: S) -> Self {
+        Self {
+            pointer: pointer.into(),
+            max_pages: None,
+        }
+    }
+
+    /// Stop requesting pages once `max_pages` pages have been visited.
+    pub fn max_pages(mut self, max_pages: NonZeroUsize) -> Self {
+        self.max_pages = Some(max_pages);
+        self
+    }
+
+    /// Build the request posting the payload of `page` to its URL as JSON.
+    pub fn request(&self, client: &Client, page: &PostPage) -> Option<Request> {
+        let body = serde_json::to_vec(&page.payload).ok()?;
         client
-            .post(url.join(&self.path).unwrap().as_str())
-            .form(&self.fields)
+            .post(page.url.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .body(body)
             .build()
+            .ok()
+    }
+
+    /// Create the callback processing the first page, posting `payload` to `url`, with
+    /// `handler`.
+    pub fn start<I, H>(
+        &self,
+        handler: H,
+        client: &Client,
+        url: Url,
+        payload: Value,
+    ) -> Option<Callback<I, PostPage>>
+    where
+        I: Debug,
+        H: Handler<I, PostPage> + 'static,
+    {
+        let page = PostPage {
+            number: 1,
+            url,
+            payload,
+        };
+        let request = self.request(client, &page)?;
+        Some(Callback::new(handler, request, page))
+    }
+
+    /// Build the payload of the page following the page posted with `payload`.
+    ///
+    /// # Arguments
+    /// - `next`: Given the value at the pointer in `payload`, returns its value for the next
+    ///   page. Returning `None` ends the pagination.
+    ///
+    /// # Returns
+    /// `None` if there is no next page, or the pointer goes through a value that isn't an object
+    /// or an array.
+    pub fn next_payload<F>(&self, payload: &Value, next: F) -> Option<Value>
+    where
+        F: FnOnce(Option<&Value>) -> Option<Value>,
+    {
+        let value = next(payload.pointer(&self.pointer))?;
+        let mut next_payload = payload.clone();
+        *pointer_mut(&mut next_payload, &self.pointer)? = value;
+        Some(next_payload)
+    }
+
+    /// Create the callback processing the page following `page` with `handler`. The callback's
+    /// context is `page` with the next number and payload.
+    ///
+    /// # Arguments
+    /// - `next`: See [next_payload](PostPagination::next_payload).
+    ///
+    /// # Returns
+    /// `None` if there is no next page or the maximum number of pages has been reached.
+    pub fn follow<I, H, F>(
+        &self,
+        handler: H,
+        client: &Client,
+        page: &PostPage,
+        next: F,
+    ) -> Option<Callback<I, PostPage>>
+    where
+        I: Debug,
+        H: Handler<I, PostPage> + 'static,
+        F: FnOnce(Option<&Value>) -> Option<Value>,
+    {
+        if let Some(max_pages) = self.max_pages {
+            if page.number >= max_pages.get() {
+                return None;
+            }
+        }
+        let next_page = PostPage {
+            number: page.number + 1,
+            url: page.url.clone(),
+            payload: self.next_payload(&page.payload, next)?,
+        };
+        let request = self.request(client, &next_page)?;
+        Some(Callback::new(handler, request, next_page))
+    }
+}
+
+/// The value at the JSON `pointer` in `value`, creating the missing members of objects, and
+/// `null` values, on the way.
+fn pointer_mut<'a>(value: &'a mut Value, pointer: &str) -> Option<&'a mut Value> {
+    if pointer.is_empty() {
+        return Some(value);
+    }
+    let tokens = pointer.strip_prefix('/')?;
+    tokens.split('/').try_fold(value, |value, token| {
+        let token = token.replace("~1", "/").replace("~0", "~");
+        if value.is_null() {
+            *value = Value::Object(Map::new());
+        }
+        match value {
+            Value::Object(members) => Some(members.entry(token).or_insert(Value::Null)),
+            Value::Array(elements) => elements.get_mut(token.parse::<usize>().ok()?),
+            _ => None,
+        }
+    })
+}
+
+/// The elements and attributes links are extracted from when none are configured.
+const DEFAULT_LINK_SOURCES: &[(&str, &str)] = &[("a", "href"), ("area", "href")];
+
+/// Extracts the links of a page.
+///
+/// Links are resolved against the page's URL and their fragments are removed. Only links with the
+/// page's scheme or an HTTP scheme are kept, so `mailto:` and `javascript:` links are skipped.
+///
+/// ```
+/// use scraper::{Html, Selector};
+/// use scrappy_do::util::LinkExtractor;
+/// use url::Url;
+///
+/// let document = Html::parse_document(
+///     r#"<nav><a href="/">Home</a></nav>
+///        <div id="content"><a href="/a">A</a><a href="/a#top">A</a><iframe src="/b"></iframe></div>"#,
+/// );
+/// let links = LinkExtractor::default()
+///     .scope(Selector::parse("#content").unwrap())
+///     .source("a", "href")
+///     .source("iframe", "src")
+///     .extract(&document, &Url::parse("http://example.com").unwrap());
+/// assert_eq!(
+///     links.iter().map(Url::as_str).collect::<Vec<_>>(),
+///     ["http://example.com/a", "http://example.com/b"]
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct LinkExtractor {
+    scope: Option<Selector>,
+    sources: Vec<(String, String)>,
+    unique: bool,
+}
+
+impl Default for LinkExtractor {
+    fn default() -> Self {
+        Self {
+            scope: None,
+            sources: Vec::new(),
+            unique: true,
+        }
+    }
+}
+
+impl LinkExtractor {
+    /// Only extract links inside the elements matching `scope`, e.g. `#content`, so navigation and
+    /// footers are ignored.
+    pub fn scope(mut self, scope: Selector) -> Self {
+        self.scope = Some(scope);
+        self
+    }
+
+    /// Extract links from the `attr` attribute of `tag` elements, e.g. `iframe` and `src`. By
+    /// default links are extracted from `a@href` and `area@href`, adding a source replaces the
+    /// defaults.
+    pub fn source<T: Into<String>, A: Into<String>>(mut self, tag: T, attr: A) -> Self {
+        self.sources.push((tag.into(), attr.into()));
+        self
+    }
+
+    /// Whether duplicate links are removed, keeping the first occurrence. Defaults to `true`.
+    pub fn unique(mut self, unique: bool) -> Self {
+        self.unique = unique;
+        self
+    }
+
+    /// Extract the links of `document`, resolving relative links against `url`.
+    pub fn extract(&self, document: &Html, url: &Url) -> Vec<Url> {
+        let sources: Vec<(&str, &str)> = if self.sources.is_empty() {
+            DEFAULT_LINK_SOURCES.to_vec()
+        } else {
+            self.sources
+                .iter()
+                .map(|(tag, attr)| (tag.as_str(), attr.as_str()))
+                .collect()
+        };
+        let selector = sources
+            .iter()
+            .map(|(tag, attr)| format!("{}[{}]", tag, attr))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let selector = match Selector::parse(&selector) {
+            Ok(selector) => selector,
+            Err(_) => return Vec::new(),
+        };
+
+        let roots: Vec<_> = match &self.scope {
+            Some(scope) => document.select(scope).collect(),
+            None => vec![document.root_element()],
+        };
+        let mut seen = HashSet::new();
+        let mut links = Vec::new();
+        for element in roots.iter().flat_map(|root| root.select(&selector)) {
+            let value = element.value();
+            let href = sources
+                .iter()
+                .filter(|(tag, _)| value.name().eq_ignore_ascii_case(tag))
+                .find_map(|(_, attr)| value.attr(attr));
+            let mut link = match href.and_then(|href| url.join(href.trim()).ok()) {
+                Some(link) => link,
+                None => continue,
+            };
+            if !matches!(link.scheme(), "http" | "https") && link.scheme() != url.scheme() {
+                continue;
+            }
+            link.set_fragment(None);
+            if !self.unique || seen.insert(link.clone()) {
+                links.push(link);
+            }
+        }
+        links
+    }
+}
+
+/// Matches absolute and protocol relative URLs, and quoted root relative paths.
+const URL_CANDIDATE_PATTERN: &str =
+    r#"(?:https?:)?//[^\s"'<>\\]+|"(/[^"\s<>\\]*)"|'(/[^'\s<>\\]*)'"#;
+
+/// Discovers URLs in script bodies and JSON, which DOM link extraction can't see. Many single page
+/// applications only reference their detail pages in embedded JSON.
+///
+/// Candidates are absolute URLs, protocol relative URLs, and quoted paths starting with a `/`.
+/// Only candidates matching one of the allow patterns are kept, or every candidate if no pattern
+/// was added.
+///
+/// ```
+/// use regex::Regex;
+/// use scrappy_do::util::UrlDiscovery;
+/// use url::Url;
+///
+/// let json = r#"{"items": [{"url": "\/product\/1"}, {"url": "/cart"}]}"#;
+/// let urls = UrlDiscovery::default()
+///     .allow(Regex::new("/product/").unwrap())
+///     .scan_text(json, &Url::parse("http://example.com").unwrap());
+/// assert_eq!(
+///     urls.iter().map(Url::as_str).collect::<Vec<_>>(),
+///     ["http://example.com/product/1"]
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct UrlDiscovery {
+    candidates: Regex,
+    allow: Vec<Regex>,
+}
+
+impl Default for UrlDiscovery {
+    fn default() -> Self {
+        Self {
+            candidates: Regex::new(URL_CANDIDATE_PATTERN).expect("valid URL pattern"),
+            allow: Vec::new(),
+        }
+    }
+}
+
+impl UrlDiscovery {
+    /// Keep the URLs matching `pattern`. Patterns are matched against the resolved URL.
+    pub fn allow(mut self, pattern: Regex) -> Self {
+        self.allow.push(pattern);
+        self
+    }
+
+    /// Find the URLs in the inline `<script>` elements of `document`, resolving them against
+    /// `url`.
+    pub fn scan_scripts(&self, document: &Html, url: &Url) -> Vec<Url> {
+        let selector = Selector::parse("script:not([src])").expect("valid selector");
+        let scripts: String = document
+            .select(&selector)
+            .flat_map(|script| script.text())
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.scan_text(&scripts, url)
+    }
+
+    /// Find the URLs in `text`, like a JSON body, resolving them against `url`.
+    pub fn scan_text(&self, text: &str, url: &Url) -> Vec<Url> {
+        // JSON may escape slashes
+        let text = text.replace("\\/", "/").replace("\\u002F", "/");
+        let mut seen = HashSet::new();
+        let mut urls = Vec::new();
+        for captures in self.candidates.captures_iter(&text) {
+            let candidate = captures
+                .get(1)
+                .or_else(|| captures.get(2))
+                .or_else(|| captures.get(0))
+                .map(|candidate| candidate.as_str())
+                .unwrap_or_default();
+            let found = match url.join(candidate) {
+                Ok(found) => found,
+                Err(_) => continue,
+            };
+            let allowed = self.allow.is_empty()
+                || self
+                    .allow
+                    .iter()
+                    .any(|pattern| pattern.is_match(found.as_str()));
+            if allowed && seen.insert(found.clone()) {
+                urls.push(found);
+            }
+        }
+        urls
+    }
+}
+
+fn first_href(document: &Html, selector: &Selector) -> Option<String> {
+    document
+        .select(selector)
+        .find_map(|element| element.value().attr("href"))
+        .map(|href| href.to_string())
+}
+
+/// A bounded pool of blocking workers dedicated to parsing HTML.
+///
+/// Parsing large documents is CPU-heavy and blocks whichever thread it runs on. Running it in the
+/// pool keeps the runtime free to drive network requests and lets parsing throughput be tuned
+/// independently of the number of concurrent requests. The pool is cheap to clone, clones share
+/// the same workers. Handlers parse their responses on the pool of their crawl with
+/// [ScrapedResponse::parse_document](crate::ScrapedResponse::parse_document), sized by
+/// [WebBuilder::parse_workers](crate::WebBuilder::parse_workers).
+///
+/// Because [Html](scraper::Html) can't be sent between threads the data needed from the document
+/// has to be extracted on the worker:
+///
+/// ```no_run
+/// # async fn example(body: String) {
+/// use scraper::Selector;
+/// use scrappy_do::util::ParsePool;
+///
+/// let pool = ParsePool::default();
+/// let titles: Vec<String> = pool
+///     .parse_document(body, |html| {
+///         let selector = Selector::parse("h1").unwrap();
+///         html.select(&selector).map(|title| title.inner_html()).collect()
+///     })
+///     .await;
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ParsePool {
+    workers: Arc<Semaphore>,
+}
+
+impl ParsePool {
+    /// Create a pool that parses at most `workers` documents at once.
+    pub fn new(workers: NonZeroUsize) -> Self {
+        Self {
+            workers: Arc::new(Semaphore::new(workers.get())),
+        }
+    }
+
+    /// Parse `body` as a full HTML document and pass it to `extract` on one of the pool's workers.
+    pub async fn parse_document<F, T>(&self, body: String, extract: F) -> T
+    where
+        F: FnOnce(Html) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.run(move || extract(Html::parse_document(&body))).await
+    }
+
+    /// Parse `body` as an HTML fragment and pass it to `extract` on one of the pool's workers.
+    pub async fn parse_fragment<F, T>(&self, body: String, extract: F) -> T
+    where
+        F: FnOnce(Html) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.run(move || extract(Html::parse_fragment(&body))).await
+    }
+
+    async fn run<F, T>(&self, work: F) -> T
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let _permit = self.workers.acquire().await.expect("open parse pool");
+        match spawn_blocking(work).await {
+            Ok(parsed) => parsed,
+            // The panics of the parsing carry over to the caller
+            Err(err) => std::panic::resume_unwind(err.into_panic()),
+        }
+    }
+}
+
+impl Default for ParsePool {
+    /// A pool with one worker per available CPU.
+    fn default() -> Self {
+        Self::new(
+            std::thread::available_parallelism().unwrap_or_else(|_| NonZeroUsize::new(1).unwrap()),
+        )
     }
 }
 
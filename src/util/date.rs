@@ -0,0 +1,425 @@
+//! Parses the dates scraped from pages into timestamps: ISO 8601 and RSS dates, numeric dates,
+//! dates with the month spelled out in English, French, German, Spanish, Italian, Portuguese or
+//! Dutch, and relative dates like `2 days ago` or `il y a 3 heures`.
+//!
+//! ```
+//! use scrappy_do::util::date::DateParser;
+//! use std::time::{Duration, SystemTime, UNIX_EPOCH};
+//!
+//! let parser = DateParser::new().day_first(true);
+//! let now = UNIX_EPOCH + Duration::from_secs(1_709_636_400); // 2024-03-05 11:00 UTC
+//! let march_5 = UNIX_EPOCH + Duration::from_secs(1_709_596_800);
+//! assert_eq!(parser.parse("Published 5 March 2024", now), Some(march_5));
+//! assert_eq!(parser.parse("5. März 2024", now), Some(march_5));
+//! assert_eq!(parser.parse("05/03/2024", now), Some(march_5));
+//! assert_eq!(parser.parse("2024-03-05T01:00:00+01:00", now), Some(march_5));
+//! assert_eq!(parser.parse("Tue, 05 Mar 2024 00:00:00 GMT", now), Some(march_5));
+//! assert_eq!(
+//!     parser.parse("2 hours ago", now),
+//!     Some(now - Duration::from_secs(2 * 3600))
+//! );
+//! assert_eq!(
+//!     parser.parse("hace 3 días", now),
+//!     Some(now - Duration::from_secs(3 * 86_400))
+//! );
+//! ```
+
+use regex::{Captures, Regex};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The names of the months and their common abbreviations, by month.
+const MONTHS: &[&[&str]] = &[
+    &[
+        "january", "jan", "janvier", "janv", "enero", "ene", "januar", "jänner", "gennaio",
+        "janeiro", "januari",
+    ],
+    &[
+        "february",
+        "feb",
+        "février",
+        "fevrier",
+        "févr",
+        "fevr",
+        "fév",
+        "febrero",
+        "febr",
+        "februar",
+        "febbraio",
+        "fevereiro",
+        "fev",
+        "februari",
+    ],
+    &[
+        "march", "mar", "mars", "märz", "maerz", "mrz", "marzo", "março", "marco", "maart",
+    ],
+    &["april", "apr", "avril", "avr", "abril", "abr", "aprile"],
+    &["may", "mai", "mayo", "maggio", "maio", "mei"],
+    &[
+        "june", "jun", "juin", "junio", "juni", "giugno", "giu", "junho",
+    ],
+    &[
+        "july", "jul", "juillet", "juil", "julio", "juli", "luglio", "lug", "julho",
+    ],
+    &["august", "aug", "août", "aout", "agosto", "augustus"],
+    &[
+        "september",
+        "sep",
+        "sept",
+        "septembre",
+        "septiembre",
+        "setiembre",
+        "settembre",
+        "setembro",
+    ],
+    &[
+        "october", "oct", "octobre", "octubre", "oktober", "okt", "ottobre", "ott", "outubro",
+    ],
+    &["november", "nov", "novembre", "noviembre", "novembro"],
+    &[
+        "december",
+        "dec",
+        "décembre",
+        "decembre",
+        "déc",
+        "diciembre",
+        "dic",
+        "dezember",
+        "dez",
+        "dicembre",
+        "dezembro",
+    ],
+];
+
+/// The words marking a date as relative to the current time.
+const AGO: &[&str] = &["ago", "il y a", "vor", "hace", "fa", "há", "geleden"];
+
+/// The prefixes of the units of relative dates, and their length in seconds.
+const UNITS: &[(&str, u64)] = &[
+    ("sec", 1),
+    ("seg", 1),
+    ("sek", 1),
+    ("min", 60),
+    ("hour", 3_600),
+    ("hr", 3_600),
+    ("heure", 3_600),
+    ("stund", 3_600),
+    ("hora", 3_600),
+    ("ora", 3_600),
+    ("ore", 3_600),
+    ("uur", 3_600),
+    ("day", 86_400),
+    ("jour", 86_400),
+    ("tag", 86_400),
+    ("día", 86_400),
+    ("dia", 86_400),
+    ("giorn", 86_400),
+    ("dag", 86_400),
+    ("week", 604_800),
+    ("semaine", 604_800),
+    ("woche", 604_800),
+    ("semana", 604_800),
+    ("settiman", 604_800),
+    ("month", 2_592_000),
+    ("mois", 2_592_000),
+    ("monat", 2_592_000),
+    ("mes", 2_592_000),
+    ("maand", 2_592_000),
+    ("year", 31_536_000),
+    ("an", 31_536_000),
+    ("année", 31_536_000),
+    ("jahr", 31_536_000),
+    ("año", 31_536_000),
+    ("ano", 31_536_000),
+    ("jaar", 31_536_000),
+];
+
+/// The words standing for a single unit, like in `a day ago`.
+const ONE: &[&str] = &[
+    "a", "an", "one", "un", "une", "einem", "einer", "una", "uno", "een",
+];
+
+/// The words for the day before.
+const YESTERDAY: &[&str] = &[
+    "yesterday",
+    "hier",
+    "gestern",
+    "ayer",
+    "ieri",
+    "ontem",
+    "gisteren",
+];
+
+/// The words for the current time.
+const NOW: &[&str] = &[
+    "today",
+    "aujourd'hui",
+    "heute",
+    "hoy",
+    "oggi",
+    "hoje",
+    "vandaag",
+];
+
+/// Parses dates scraped from pages, see the [module](self) documentation.
+///
+/// Dates without a time are read as midnight and dates without a UTC offset as UTC. A month
+/// counts as 30 days and a year as 365 days in relative dates.
+#[derive(Debug, Clone)]
+pub struct DateParser {
+    day_first: bool,
+    iso: Regex,
+    numeric: Regex,
+    time: Regex,
+}
+
+impl Default for DateParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DateParser {
+    /// A parser reading ambiguous numeric dates like `05/03/2024` month first.
+    pub fn new() -> Self {
+        Self {
+            day_first: false,
+            iso: Regex::new(
+                r"(\d{4})-(\d{1,2})-(\d{1,2})(?:[t ](\d{1,2}):(\d{2})(?::(\d{2})(?:[.,]\d+)?)?\s*(z|[+-]\d{2}:?\d{2})?)?",
+            )
+            .expect("valid ISO date pattern"),
+            numeric: Regex::new(r"\b(\d{1,4})[./-](\d{1,2})[./-](\d{1,4})\b")
+                .expect("valid numeric date pattern"),
+            time: Regex::new(
+                r"\b(\d{1,2}):(\d{2})(?::(\d{2}))?(?:\s*([ap])\.?m\b\.?)?(?:\s*(?:gmt|utc)?\s*([+-]\d{2}:?\d{2})\b)?",
+            )
+            .expect("valid time pattern"),
+        }
+    }
+
+    /// Whether ambiguous numeric dates like `05/03/2024` are read day first, as in most of the
+    /// world, rather than month first. Numeric dates that can only be read one way are read that
+    /// way.
+    pub fn day_first(mut self, day_first: bool) -> Self {
+        self.day_first = day_first;
+        self
+    }
+
+    /// Parse the date in `text`, reading relative dates against `now`.
+    pub fn parse(&self, text: &str, now: SystemTime) -> Option<SystemTime> {
+        let text = text.to_lowercase();
+        self.parse_relative(&text, now)
+            .or_else(|| self.parse_iso(&text))
+            .or_else(|| self.parse_absolute(&text))
+    }
+
+    fn parse_relative(&self, text: &str, now: SystemTime) -> Option<SystemTime> {
+        let words: Vec<&str> = words(text).collect();
+        if words.iter().any(|word| YESTERDAY.contains(word)) {
+            return now.checked_sub(Duration::from_secs(86_400));
+        }
+        if text.trim() == "now"
+            || text.contains("just now")
+            || NOW.iter().any(|word| words.contains(word))
+        {
+            return Some(now);
+        }
+        let ago = AGO
+            .iter()
+            .any(|ago| words.contains(ago) || (ago.contains(' ') && text.contains(ago)));
+        if !ago {
+            return None;
+        }
+        words.windows(2).find_map(|pair| {
+            let count = match pair[0].parse::<u64>() {
+                Ok(count) => count,
+                Err(_) if ONE.contains(&pair[0]) => 1,
+                Err(_) => return None,
+            };
+            let unit = pair[1];
+            let (_, seconds) = UNITS.iter().find(|(prefix, _)| {
+                unit.starts_with(prefix) && (prefix.len() > 2 || unit.len() <= 3)
+            })?;
+            now.checked_sub(Duration::from_secs(count.checked_mul(*seconds)?))
+        })
+    }
+
+    fn parse_iso(&self, text: &str) -> Option<SystemTime> {
+        let captures = self.iso.captures(text)?;
+        let time = (
+            number(&captures, 4).unwrap_or(0),
+            number(&captures, 5).unwrap_or(0),
+            number(&captures, 6).unwrap_or(0),
+        );
+        let offset = match captures.get(7).map(|offset| offset.as_str()) {
+            None | Some("z") => 0,
+            Some(offset) => offset_minutes(offset)?,
+        };
+        timestamp(
+            (
+                number(&captures, 1)?,
+                number(&captures, 2)?,
+                number(&captures, 3)?,
+            ),
+            time,
+            offset,
+        )
+    }
+
+    fn parse_absolute(&self, text: &str) -> Option<SystemTime> {
+        let (time, offset, text) = match self.time.captures(text) {
+            Some(captures) => (
+                clock(&captures)?,
+                match captures.get(5) {
+                    Some(offset) => offset_minutes(offset.as_str())?,
+                    None => 0,
+                },
+                text.replacen(captures.get(0)?.as_str(), " ", 1),
+            ),
+            None => ((0, 0, 0), 0, text.to_string()),
+        };
+        let date = match month(&text) {
+            Some(month) => {
+                let numbers: Vec<&str> = text
+                    .split(|c: char| !c.is_ascii_digit())
+                    .filter(|number| !number.is_empty())
+                    .collect();
+                let year = numbers.iter().find(|number| number.len() == 4)?;
+                let day = numbers.iter().find(|number| number.len() <= 2)?;
+                (year.parse().ok()?, month, day.parse().ok()?)
+            }
+            None => self.numeric_date(&text)?,
+        };
+        timestamp(date, time, offset)
+    }
+
+    /// The year, month and day of a numeric date like `05/03/2024`.
+    fn numeric_date(&self, text: &str) -> Option<(i64, u32, u32)> {
+        let captures = self.numeric.captures(text)?;
+        if captures[1].len() == 4 {
+            return Some((
+                captures[1].parse().ok()?,
+                captures[2].parse().ok()?,
+                captures[3].parse().ok()?,
+            ));
+        }
+        let first: u32 = captures[1].parse().ok()?;
+        let second: u32 = captures[2].parse().ok()?;
+        let year: i64 = match captures[3].len() {
+            2 => {
+                let year: i64 = captures[3].parse().ok()?;
+                if year < 70 {
+                    2000 + year
+                } else {
+                    1900 + year
+                }
+            }
+            4 => captures[3].parse().ok()?,
+            _ => return None,
+        };
+        let day_first = first > 12 || (self.day_first && second <= 12);
+        Some(if day_first {
+            (year, second, first)
+        } else {
+            (year, first, second)
+        })
+    }
+}
+
+/// Parse the date in `text` with the default [DateParser].
+pub fn parse_date(text: &str) -> Option<SystemTime> {
+    DateParser::new().parse(text, SystemTime::now())
+}
+
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|word| !word.is_empty())
+}
+
+/// The number captured by the group `index`.
+fn number<T: FromStr>(captures: &Captures, index: usize) -> Option<T> {
+    captures.get(index)?.as_str().parse().ok()
+}
+
+/// The month spelled out in `text`.
+fn month(text: &str) -> Option<u32> {
+    words(text).find_map(|word| {
+        MONTHS
+            .iter()
+            .position(|names| names.contains(&word))
+            .map(|index| index as u32 + 1)
+    })
+}
+
+/// The hour, minute and second of a time of day.
+fn clock(captures: &Captures) -> Option<(u32, u32, u32)> {
+    let mut hour: u32 = captures[1].parse().ok()?;
+    let minute = captures[2].parse().ok()?;
+    let second = captures
+        .get(3)
+        .map_or(Some(0), |second| second.as_str().parse().ok())?;
+    match captures.get(4).map(|meridiem| meridiem.as_str()) {
+        Some("p") if hour < 12 => hour += 12,
+        Some("a") if hour == 12 => hour = 0,
+        _ => {}
+    }
+    Some((hour, minute, second))
+}
+
+/// The minutes from UTC of an offset like `+01:00` or `-0500`.
+fn offset_minutes(offset: &str) -> Option<i64> {
+    let digits: String = offset.chars().filter(char::is_ascii_digit).collect();
+    let minutes =
+        digits.get(..2)?.parse::<i64>().ok()? * 60 + digits.get(2..)?.parse::<i64>().ok()?;
+    Some(if offset.starts_with('-') {
+        -minutes
+    } else {
+        minutes
+    })
+}
+
+/// The timestamp of a date and time, at `offset` minutes from UTC.
+fn timestamp(
+    (year, month, day): (i64, u32, u32),
+    (hour, minute, second): (u32, u32, u32),
+    offset: i64,
+) -> Option<SystemTime> {
+    if !(1..=12).contains(&month)
+        || day == 0
+        || day > days_in_month(year, month)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+    let seconds = days_from_civil(year, month, day) * 86_400
+        + i64::from(hour * 3_600 + minute * 60 + second)
+        - offset * 60;
+    if seconds >= 0 {
+        UNIX_EPOCH.checked_add(Duration::from_secs(seconds as u64))
+    } else {
+        UNIX_EPOCH.checked_sub(Duration::from_secs(seconds.unsigned_abs()))
+    }
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// The number of days between 1970-01-01 and a date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    // Howard Hinnant's days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let shifted_month = i64::from(if month > 2 { month - 3 } else { month + 9 });
+    let day_of_year = (153 * shifted_month + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
@@ -0,0 +1,58 @@
+//! Extracts the text of PDF documents, which government and regulatory sites publish most of
+//! their data as.
+//!
+//! Documents are routed to their own handler by a [ContentRouter](crate::ContentRouter) route
+//! for `application/pdf`, and the links to documents that are too large or aren't PDFs can be
+//! skipped before they are downloaded with a [Probe](crate::Probe). Extraction is CPU bound, large
+//! documents are better extracted on a [ParsePool](crate::util::ParsePool).
+//!
+//! ```no_run
+//! # #![feature(generators)]
+//! # use scrappy_do::{handle, ScrapedResponse};
+//! # use reqwest::Client;
+//! # use slog::Logger;
+//! use scrappy_do::util::pdf;
+//!
+//! #[handle(item = String)]
+//! fn report(client: Client, response: ScrapedResponse, context: (), logger: Logger) {
+//!     let body = response.bytes().await.unwrap();
+//!     if pdf::is_pdf(&body) {
+//!         yield pdf::extract_text(&body).unwrap();
+//!     }
+//! }
+//! ```
+
+use std::panic::{self, AssertUnwindSafe};
+use thiserror::Error;
+
+/// The bytes every PDF document starts with.
+const MAGIC: &[u8] = b"%PDF-";
+
+#[derive(Error, Debug)]
+pub enum PdfError {
+    #[error("the document is not a PDF")]
+    NotPdf,
+    #[error("the text could not be extracted: {0}")]
+    Extract(#[from] pdf_extract::OutputError),
+    #[error("the document is malformed")]
+    Malformed,
+}
+
+/// Whether `bytes` is a PDF document, judging by its first bytes. Servers often send documents
+/// as `application/octet-stream`, or with the content type of the page linking to them.
+pub fn is_pdf(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Extract the text of the PDF document `bytes`, page after page.
+pub fn extract_text(bytes: &[u8]) -> Result<String, PdfError> {
+    if !is_pdf(bytes) {
+        return Err(PdfError::NotPdf);
+    }
+    // The extractor panics on some malformed documents
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        pdf_extract::extract_text_from_mem(bytes)
+    }))
+    .map_err(|_| PdfError::Malformed)?
+    .map_err(PdfError::Extract)
+}
@@ -0,0 +1,158 @@
+//! Parses the prices and numbers scraped from pages, whatever the conventions of the site's
+//! locale: `$1,299.99`, `1.299,99 €`, `CHF 1'299.50` and `1 299,99 zł` are all understood.
+//!
+//! ```
+//! use rust_decimal::Decimal;
+//! use scrappy_do::util::price::{parse_number, parse_price, Price};
+//!
+//! assert_eq!(
+//!     parse_price("Now only 1.299,99 €!"),
+//!     Some(Price {
+//!         amount: Decimal::new(129_999, 2),
+//!         currency: Some("EUR".to_string()),
+//!     })
+//! );
+//! assert_eq!(parse_price("R$ 49,90").unwrap().currency.as_deref(), Some("BRL"));
+//! assert_eq!(parse_number("12,345 reviews"), Some(Decimal::new(12_345, 0)));
+//! assert_eq!(parse_number("-0.5"), Some(Decimal::new(-5, 1)));
+//! ```
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// The currency symbols, the longest first, and the currencies they usually stand for.
+const SYMBOLS: &[(&str, &str)] = &[
+    ("CA$", "CAD"),
+    ("AU$", "AUD"),
+    ("US$", "USD"),
+    ("HK$", "HKD"),
+    ("NZ$", "NZD"),
+    ("MX$", "MXN"),
+    ("R$", "BRL"),
+    ("C$", "CAD"),
+    ("A$", "AUD"),
+    ("S$", "SGD"),
+    ("$", "USD"),
+    ("€", "EUR"),
+    ("£", "GBP"),
+    ("¥", "JPY"),
+    ("₹", "INR"),
+    ("₽", "RUB"),
+    ("₩", "KRW"),
+    ("₺", "TRY"),
+    ("₪", "ILS"),
+    ("zł", "PLN"),
+    ("Kč", "CZK"),
+];
+
+/// The currency codes recognized in prices.
+const CODES: &[&str] = &[
+    "AED", "AUD", "BGN", "BRL", "CAD", "CHF", "CNY", "CZK", "DKK", "EUR", "GBP", "HKD", "HUF",
+    "IDR", "ILS", "INR", "JPY", "KRW", "MXN", "MYR", "NOK", "NZD", "PHP", "PLN", "RON", "RUB",
+    "SAR", "SEK", "SGD", "THB", "TRY", "USD", "ZAR",
+];
+
+/// The characters grouping the thousands of a number, besides `.` and `,`.
+const GROUP_SEPARATORS: &[char] = &[' ', '\'', '’', '\u{A0}', '\u{202F}'];
+
+/// A price scraped from a page.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Price {
+    /// The amount of the price.
+    pub amount: Decimal,
+    /// The ISO 4217 code of the currency of the price, when the text names a currency by its code
+    /// or its symbol.
+    pub currency: Option<String>,
+}
+
+/// Parse the first price in `text`, and its currency. Ambiguous symbols are read as the most
+/// common currency using them, like `$` as US dollars. See [parse_number] for how the amount is
+/// read.
+pub fn parse_price(text: &str) -> Option<Price> {
+    Some(Price {
+        amount: parse_number(text)?,
+        currency: currency(text),
+    })
+}
+
+/// Parse the first number in `text`. Digits may be grouped with `,`, `.`, spaces or apostrophes,
+/// and the decimal separator is either `.` or `,`: when both are used the last one separates the
+/// decimals, and a single separator followed by exactly three digits groups thousands, so
+/// `1,299` and `1.299` are both read as 1299.
+pub fn parse_number(text: &str) -> Option<Decimal> {
+    let start = text.find(|c: char| c.is_ascii_digit())?;
+    let negative = text[..start].trim_end().ends_with(['-', '−']);
+
+    // The runs of digits of the number and the separators before them
+    let mut runs: Vec<(Option<char>, &str)> = Vec::new();
+    let mut rest = &text[start..];
+    let mut separator = None;
+    loop {
+        let end = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let digits = &rest[..end];
+        if digits.is_empty()
+            || separator
+                .is_some_and(|separator| GROUP_SEPARATORS.contains(&separator) && digits.len() != 3)
+        {
+            break;
+        }
+        runs.push((separator, digits));
+        rest = &rest[end..];
+        let mut chars = rest.chars();
+        separator = match chars.next() {
+            Some(next) if next == '.' || next == ',' || GROUP_SEPARATORS.contains(&next) => {
+                Some(next)
+            }
+            _ => break,
+        };
+        rest = chars.as_str();
+    }
+
+    let decimal = decimal_separator(&runs);
+    let mut number = String::new();
+    if negative {
+        number.push('-');
+    }
+    for (separator, digits) in runs {
+        if separator.is_some() && separator == decimal {
+            number.push('.');
+        }
+        number.push_str(digits);
+    }
+    Decimal::from_str(&number).ok()
+}
+
+/// The separator of the decimals of the number made of `runs`, if it has decimals.
+fn decimal_separator(runs: &[(Option<char>, &str)]) -> Option<char> {
+    let (separator, digits) = runs.iter().rev().find(|(separator, _)| {
+        separator.is_some_and(|separator| separator == '.' || separator == ',')
+    })?;
+    let count = runs.iter().filter(|(other, _)| other == separator).count();
+    let mixed = runs
+        .iter()
+        .any(|(other, _)| other.is_some() && other != separator);
+    let last = runs.last().map(|(_, last)| last) == Some(digits);
+    let grouping = count > 1 || !last || (!mixed && digits.len() == 3 && runs[0].1 != "0");
+    if grouping {
+        None
+    } else {
+        *separator
+    }
+}
+
+/// The currency named in `text`, by its code or its symbol.
+fn currency(text: &str) -> Option<String> {
+    let code = text
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .find(|word| CODES.contains(word));
+    match code {
+        Some(code) => Some(code.to_string()),
+        None => SYMBOLS
+            .iter()
+            .find(|(symbol, _)| text.contains(symbol))
+            .map(|(_, code)| code.to_string()),
+    }
+}
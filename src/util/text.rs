@@ -0,0 +1,119 @@
+//! Cleans up the strings extracted from pages: decoding HTML entities, stripping the tags of
+//! `inner_html`, normalizing unicode and collapsing whitespace.
+//!
+//! ```
+//! use scraper::{Html, Selector};
+//! use scrappy_do::util::text;
+//!
+//! let page = Html::parse_fragment(
+//!     "<div class=\"price\">\n  <b>Caf&eacute;</b>&nbsp;au lait<br>4.50 &euro;\n</div>",
+//! );
+//! let price = page
+//!     .select(&Selector::parse(".price").unwrap())
+//!     .next()
+//!     .unwrap();
+//! assert_eq!(text::strip_tags(&price.inner_html()), "Café au lait 4.50 €");
+//! assert_eq!(
+//!     text::clean("Fu&szlig;ball&nbsp; \u{FB01}nal &#8211;\n \u{FF14} goals "),
+//!     "Fußball final – 4 goals"
+//! );
+//! ```
+
+use html_escape::decode_html_entities;
+use scraper::{ElementRef, Html, Node};
+use std::borrow::Cow;
+use unicode_normalization::UnicodeNormalization;
+
+/// The elements whose boundaries separate words, like paragraphs and line breaks.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "td",
+    "th",
+    "tr",
+    "ul",
+];
+
+/// Decode the HTML entities of `text`, named like `&amp;` or numeric like `&#233;`. Text read
+/// through [scraper] is already decoded, but the text of attributes embedding HTML, JSON bodies
+/// and XML feeds often isn't.
+pub fn decode_entities(text: &str) -> Cow<'_, str> {
+    decode_html_entities(text)
+}
+
+/// Replace every run of whitespace in `text`, including line breaks and non-breaking spaces,
+/// with a single space, and trim it.
+pub fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Normalize `text` to the unicode compatibility composition form (NFKC), so strings compare
+/// equal however the site encoded them: accents are composed with their letter, and full width
+/// characters, ligatures and non-breaking spaces become their plain equivalents.
+pub fn normalize_unicode(text: &str) -> String {
+    text.nfkc().collect()
+}
+
+/// The text of the HTML `html`, like the `inner_html` of an element, without its tags and with
+/// collapsed whitespace. Block elements and line breaks separate words, and the content of
+/// `script` and `style` elements is dropped.
+pub fn strip_tags(html: &str) -> String {
+    let fragment = Html::parse_fragment(html);
+    let mut text = String::new();
+    push_text(fragment.root_element(), &mut text);
+    collapse_whitespace(&text)
+}
+
+fn push_text(element: ElementRef, text: &mut String) {
+    for child in element.children() {
+        match child.value() {
+            Node::Text(content) => text.push_str(content),
+            Node::Element(child_element) => {
+                if matches!(child_element.name(), "script" | "style") {
+                    continue;
+                }
+                let block = BLOCK_ELEMENTS.contains(&child_element.name());
+                if block {
+                    text.push(' ');
+                }
+                push_text(ElementRef::wrap(child).expect("element"), text);
+                if block {
+                    text.push(' ');
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Decode the HTML entities of `text`, normalize its unicode and collapse its whitespace, the
+/// cleanup most extracted strings need.
+pub fn clean(text: &str) -> String {
+    collapse_whitespace(&normalize_unicode(&decode_entities(text)))
+}
@@ -0,0 +1,182 @@
+//! Selects the elements and attributes of XML documents, like sitemaps, RSS feeds, SOAP
+//! responses and XML data feeds, as the [scraper] selectors do for HTML pages.
+//!
+//! Documents are parsed with [roxmltree]. A selector is a path of element names separated by
+//! `/`: its first element can be anywhere in the document, and each following element is a child
+//! of the previous one. Names without a prefix match the elements of any namespace, which suits
+//! documents declaring a default namespace like sitemaps. Prefixed names only match the elements
+//! of the prefix's namespace, bound on the selector or else declared by the document.
+//!
+//! ```
+//! use scrappy_do::util::{get_unique_element, xml::{self, XmlSelector}};
+//!
+//! let feed = xml::parse(
+//!     r#"<rss xmlns:media="http://search.yahoo.com/mrss/"><channel>
+//!         <title>News</title>
+//!         <item><title>First</title><media:content url="/1.jpg"/></item>
+//!         <item><title><![CDATA[Second & last]]></title></item>
+//!     </channel></rss>"#,
+//! )
+//! .unwrap();
+//! let titles = XmlSelector::parse("item/title").unwrap();
+//! assert_eq!(
+//!     titles.select(feed.root()).map(xml::text).collect::<Vec<_>>(),
+//!     ["First", "Second & last"]
+//! );
+//!
+//! let media = XmlSelector::parse("m:content")
+//!     .unwrap()
+//!     .namespace("m", "http://search.yahoo.com/mrss/");
+//! let content = get_unique_element(&mut media.select(feed.root())).unwrap();
+//! assert_eq!(media.attr(content, "url"), Some("/1.jpg"));
+//! ```
+
+use roxmltree::{Document, Node, ParsingOptions};
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// The namespace bound to the `xml` prefix by every document.
+const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+
+#[derive(Error, Debug)]
+pub enum XmlError {
+    #[error("the document is not well formed: {0}")]
+    Syntax(#[from] roxmltree::Error),
+    #[error("invalid selector (given: {0})")]
+    InvalidSelector(String),
+}
+
+/// Parse the XML document `text`. Unlike [Document::parse], documents with a `DOCTYPE`, like
+/// older RSS feeds, are accepted.
+pub fn parse(text: &str) -> Result<Document<'_>, XmlError> {
+    let options = ParsingOptions {
+        allow_dtd: true,
+        ..ParsingOptions::default()
+    };
+    Ok(Document::parse_with_options(text, options)?)
+}
+
+/// The text of `node` and its descendants, trimmed. The text of CDATA sections is included.
+pub fn text(node: Node) -> String {
+    node.descendants()
+        .filter(Node::is_text)
+        .filter_map(|text| text.text())
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// An element or attribute name, with an optional namespace prefix.
+#[derive(Debug, Clone)]
+struct Name {
+    prefix: Option<String>,
+    local: String,
+}
+
+impl Name {
+    fn parse(name: &str) -> Option<Self> {
+        let (prefix, local) = match name.split_once(':') {
+            Some((prefix, local)) => (Some(prefix), local),
+            None => (None, name),
+        };
+        let valid = |part: &str| !part.is_empty() && !part.contains(char::is_whitespace);
+        if !prefix.is_none_or(valid) || !valid(local) || local.contains(':') {
+            return None;
+        }
+        Some(Self {
+            prefix: prefix.map(str::to_string),
+            local: local.to_string(),
+        })
+    }
+}
+
+/// Selects the elements of an XML document by path, see the [module](self) documentation.
+#[derive(Debug, Clone)]
+pub struct XmlSelector {
+    steps: Vec<Name>,
+    // Namespaces by prefix
+    namespaces: HashMap<String, String>,
+}
+
+impl XmlSelector {
+    /// Parse the path `path`, like `url/loc` or `soap:Body/m:GetPriceResponse`. A `*` matches the
+    /// elements of any name.
+    pub fn parse(path: &str) -> Result<Self, XmlError> {
+        let steps = path
+            .trim()
+            .split('/')
+            .map(Name::parse)
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| XmlError::InvalidSelector(path.to_string()))?;
+        Ok(Self {
+            steps,
+            namespaces: HashMap::new(),
+        })
+    }
+
+    /// Bind `prefix` to the namespace `uri`, whatever prefix the document uses for it.
+    pub fn namespace<P: Into<String>, U: Into<String>>(mut self, prefix: P, uri: U) -> Self {
+        self.namespaces.insert(prefix.into(), uri.into());
+        self
+    }
+
+    /// The elements matching the selector under `root`, in document order. `root` is usually
+    /// [Document::root], or an element selected earlier to select inside of.
+    pub fn select<'a, 'input>(
+        &self,
+        root: Node<'a, 'input>,
+    ) -> impl Iterator<Item = Node<'a, 'input>> {
+        let mut steps = self.steps.iter();
+        let first = steps.next().expect("non empty path");
+        let mut matched: Vec<Node> = root
+            .descendants()
+            .filter(|node| self.matches(first, *node))
+            .collect();
+        for step in steps {
+            matched = matched
+                .iter()
+                .flat_map(Node::children)
+                .filter(|node| self.matches(step, *node))
+                .collect();
+        }
+        matched.into_iter()
+    }
+
+    /// The value of the attribute `name` of `element`, like `href` or `xml:lang`. Prefixed names
+    /// are resolved as in the path of the selector.
+    pub fn attr<'a>(&self, element: Node<'a, '_>, name: &str) -> Option<&'a str> {
+        let name = Name::parse(name)?;
+        match &name.prefix {
+            Some(prefix) => {
+                let namespace = self.resolve(prefix, element)?;
+                element.attribute((namespace, name.local.as_str()))
+            }
+            None => element.attribute(name.local.as_str()),
+        }
+    }
+
+    fn matches(&self, name: &Name, node: Node) -> bool {
+        if !node.is_element() {
+            return false;
+        }
+        let tag = node.tag_name();
+        if name.local != "*" && tag.name() != name.local {
+            return false;
+        }
+        match &name.prefix {
+            Some(prefix) => self
+                .resolve(prefix, node)
+                .is_some_and(|namespace| tag.namespace() == Some(namespace)),
+            None => true,
+        }
+    }
+
+    /// The namespace of `prefix`, bound on the selector or declared in scope of `node`.
+    fn resolve<'a>(&'a self, prefix: &str, node: Node<'a, '_>) -> Option<&'a str> {
+        match self.namespaces.get(prefix) {
+            Some(namespace) => Some(namespace),
+            None if prefix == "xml" => Some(XML_NAMESPACE),
+            None => node.lookup_namespace_uri(Some(prefix)),
+        }
+    }
+}
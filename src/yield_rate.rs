@@ -0,0 +1,183 @@
+use crate::extension::Extension;
+use slog::{info, warn, Logger};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Debug};
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use url::Url;
+use uuid::Uuid;
+
+/// Raised when the yield rate of a handler fell below its baseline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct YieldAlert {
+    /// The name of the handler.
+    pub handler: String,
+    /// The average number of items per response over the recent responses.
+    pub rate: f64,
+    /// The minimum average expected from the handler.
+    pub baseline: f64,
+    /// The number of responses the handler processed so far.
+    pub responses: u64,
+    /// The URL of the response that brought the rate below the baseline.
+    pub url: Url,
+}
+
+type AlertCallback = Arc<dyn Fn(&YieldAlert) + Send + Sync>;
+
+/// Watches how many items each handler yields per response, and raises an alert when the rate of
+/// a handler drops below its baseline in the middle of a crawl: the usual symptom of a site
+/// redesign breaking the selectors of a handler.
+///
+/// The rate is the average over a window of recent responses, so single empty pages don't raise
+/// alerts. An alert is logged as a warning, passed to the [on_alert](YieldMonitor::on_alert)
+/// callback, and kept for [alerts](YieldMonitor::alerts). A handler raises a new alert once its
+/// rate recovered and dropped again.
+///
+/// ```
+/// use scrappy_do::YieldMonitor;
+/// use std::num::NonZeroUsize;
+/// use std::sync::Arc;
+///
+/// let monitor = Arc::new(
+///     YieldMonitor::new(NonZeroUsize::new(20).unwrap())
+///         // Listing pages hold about 30 products
+///         .baseline("parse_listing", 10.0)
+///         .on_alert(|alert| eprintln!("{} stopped yielding items", alert.handler)),
+/// );
+/// // Registered with `WebBuilder::extension(monitor.clone())`, then after the crawl:
+/// assert!(monitor.alerts().is_empty());
+/// ```
+pub struct YieldMonitor {
+    window: usize,
+    baselines: HashMap<String, f64>,
+    default_baseline: Option<f64>,
+    on_alert: Option<AlertCallback>,
+    logger: Mutex<Option<Logger>>,
+    handlers: Mutex<HashMap<String, HandlerYield>>,
+    alerts: Mutex<Vec<YieldAlert>>,
+}
+
+/// The recent yield of a handler.
+#[derive(Debug, Default)]
+struct HandlerYield {
+    // The items produced for the last responses, the oldest first
+    recent: VecDeque<u64>,
+    responses: u64,
+    alerted: bool,
+}
+
+impl YieldMonitor {
+    /// Average the yield rate of each handler over its last `window` responses. No handler is
+    /// watched until a baseline is set.
+    pub fn new(window: NonZeroUsize) -> Self {
+        Self {
+            window: window.get(),
+            baselines: HashMap::new(),
+            default_baseline: None,
+            on_alert: None,
+            logger: Mutex::new(None),
+            handlers: Mutex::new(HashMap::new()),
+            alerts: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Expect the handler named `handler` to yield at least `items_per_response` items per
+    /// response on average.
+    pub fn baseline<S: Into<String>>(mut self, handler: S, items_per_response: f64) -> Self {
+        self.baselines.insert(handler.into(), items_per_response);
+        self
+    }
+
+    /// Expect the handlers without a [baseline](YieldMonitor::baseline) to yield at least
+    /// `items_per_response` items per response on average.
+    pub fn default_baseline(mut self, items_per_response: f64) -> Self {
+        self.default_baseline = Some(items_per_response);
+        self
+    }
+
+    /// Call `on_alert` for every alert, for instance to page someone or stop the crawl.
+    pub fn on_alert<F>(mut self, on_alert: F) -> Self
+    where
+        F: Fn(&YieldAlert) + Send + Sync + 'static,
+    {
+        self.on_alert = Some(Arc::new(on_alert));
+        self
+    }
+
+    /// The alerts raised so far.
+    pub fn alerts(&self) -> Vec<YieldAlert> {
+        self.alerts.lock().expect("yield monitor lock").clone()
+    }
+}
+
+impl Debug for YieldMonitor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("YieldMonitor")
+            .field("window", &self.window)
+            .field("baselines", &self.baselines)
+            .field("default_baseline", &self.default_baseline)
+            .field("alerts", &self.alerts)
+            .finish()
+    }
+}
+
+impl Extension for YieldMonitor {
+    fn crawl_started(&self, _run_id: Uuid, logger: &Logger) {
+        *self.logger.lock().expect("logger lock") = Some(logger.clone());
+    }
+
+    fn response_handled(&self, handler: &str, url: &Url, items: u64, _callbacks: u64) {
+        let baseline = match self
+            .baselines
+            .get(handler)
+            .or(self.default_baseline.as_ref())
+        {
+            Some(baseline) => *baseline,
+            None => return,
+        };
+        let alert = {
+            let mut handlers = self.handlers.lock().expect("yield monitor lock");
+            let handler_yield = handlers.entry(handler.to_string()).or_default();
+            handler_yield.responses += 1;
+            handler_yield.recent.push_back(items);
+            if handler_yield.recent.len() > self.window {
+                handler_yield.recent.pop_front();
+            }
+            if handler_yield.recent.len() < self.window {
+                return;
+            }
+            let rate = handler_yield.recent.iter().sum::<u64>() as f64 / self.window as f64;
+            if rate >= baseline {
+                if handler_yield.alerted {
+                    handler_yield.alerted = false;
+                    if let Some(logger) = &*self.logger.lock().expect("logger lock") {
+                        info!(logger, "The yield rate of the handler recovered";
+                              "handler" => handler, "rate" => rate, "baseline" => baseline);
+                    }
+                }
+                return;
+            }
+            if handler_yield.alerted {
+                return;
+            }
+            handler_yield.alerted = true;
+            YieldAlert {
+                handler: handler.to_string(),
+                rate,
+                baseline,
+                responses: handler_yield.responses,
+                url: url.clone(),
+            }
+        };
+
+        if let Some(logger) = &*self.logger.lock().expect("logger lock") {
+            warn!(logger, "The yield rate of the handler dropped below its baseline";
+                  "handler" => handler, "rate" => alert.rate, "baseline" => baseline,
+                  "url" => %url);
+        }
+        if let Some(on_alert) = &self.on_alert {
+            on_alert(&alert);
+        }
+        self.alerts.lock().expect("yield monitor lock").push(alert);
+    }
+}